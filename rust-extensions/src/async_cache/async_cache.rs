@@ -0,0 +1,850 @@
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use futures::FutureExt;
+use parking_lot::Mutex;
+
+use crate::{
+    date_time::DateTimeAsMicroseconds, StrOrString, TaskCompletion, TaskCompletionAwaiter,
+};
+
+use super::{
+    AsyncCacheEntry, AsyncCacheEviction, AsyncCacheLoadedEntry, AsyncCacheLoader, AsyncCacheResult,
+    ParkedLoad,
+};
+
+type RegisteredLoader<TKey, TValue, TErr> = Arc<dyn AsyncCacheLoader<TKey, TValue, TErr>>;
+
+/// The entries are looked up by key in the map. The loaded ones are also ranked in the
+/// eviction index - `(hits, tick)` for LFU, `(0, tick)` for LRU, where `tick` grows with
+/// every access - so the first one of the index is always the one to evict.
+///
+/// The callers parked on a load are kept by its `load_id` in `parked` rather than in the
+/// `Loading` entry: an invalidation removes the entry, but the load still has to hand its
+/// result to the callers who parked on it.
+struct AsyncCacheInner<TKey, TValue, TErr> {
+    items: HashMap<TKey, AsyncCacheEntry<TValue, TErr>>,
+    parked: HashMap<u64, Vec<ParkedLoad<TValue, TErr>>>,
+    eviction_index: BTreeMap<(u64, u64), TKey>,
+    loaded_amount: usize,
+    max_amount: usize,
+    eviction: AsyncCacheEviction,
+    /// Source of the load ids, the refresh ids and the access ticks.
+    next_id: u64,
+}
+
+impl<TKey: Clone + Hash + Eq, TValue, TErr> AsyncCacheInner<TKey, TValue, TErr> {
+    fn get_next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    fn get_rank(&mut self, hits: u64) -> (u64, u64) {
+        let tick = self.get_next_id();
+
+        match self.eviction {
+            AsyncCacheEviction::Lru => (0, tick),
+            AsyncCacheEviction::Lfu => (hits, tick),
+        }
+    }
+
+    fn get_loaded(&self, key: &TKey) -> Option<&AsyncCacheLoadedEntry<TValue, TErr>> {
+        match self.items.get(key)? {
+            AsyncCacheEntry::Loaded(loaded) => Some(loaded),
+            AsyncCacheEntry::Loading { .. } => None,
+        }
+    }
+
+    fn is_loading(&self, key: &TKey, load_id: u64) -> bool {
+        matches!(
+            self.items.get(key),
+            Some(AsyncCacheEntry::Loading { load_id: entry_load_id }) if *entry_load_id == load_id
+        )
+    }
+
+    /// Removes the entry whatever its state is. A `Loading` one still hands its result to
+    /// the callers parked on it - it is just not stored.
+    fn remove(&mut self, key: &TKey) -> bool {
+        match self.items.remove(key) {
+            Some(AsyncCacheEntry::Loaded(loaded)) => {
+                self.eviction_index.remove(&loaded.rank);
+                self.loaded_amount -= 1;
+                true
+            }
+            Some(AsyncCacheEntry::Loading { .. }) => true,
+            None => false,
+        }
+    }
+
+    /// Stores the result of `key`, making room for it first - so with LFU a fresh entry
+    /// is not evicted right away for having no hits yet.
+    fn insert_loaded(
+        &mut self,
+        key: TKey,
+        result: AsyncCacheResult<TValue, TErr>,
+        expires_at: Option<DateTimeAsMicroseconds>,
+    ) {
+        self.remove(&key);
+
+        if self.max_amount == 0 {
+            return;
+        }
+
+        while self.loaded_amount >= self.max_amount {
+            let Some((_, evicted)) = self.eviction_index.pop_first() else {
+                break;
+            };
+
+            self.items.remove(&evicted);
+            self.loaded_amount -= 1;
+        }
+
+        let rank = self.get_rank(1);
+        self.eviction_index.insert(rank, key.clone());
+        self.loaded_amount += 1;
+
+        self.items.insert(
+            key,
+            AsyncCacheEntry::Loaded(AsyncCacheLoadedEntry {
+                result,
+                expires_at,
+                rank,
+                hits: 1,
+                refresh_id: None,
+            }),
+        );
+    }
+
+    /// Counts a hit of the loaded `key`. Returns its result, and the id of the
+    /// refresh-ahead the caller has to start, if it is time for one.
+    fn hit(
+        &mut self,
+        key: &TKey,
+        now: DateTimeAsMicroseconds,
+        refresh_ahead: Option<Duration>,
+    ) -> Option<(AsyncCacheResult<TValue, TErr>, Option<u64>)> {
+        let hits = self.get_loaded(key)?.hits + 1;
+        let rank = self.get_rank(hits);
+        let refresh_id = self.get_next_id();
+
+        let Some(AsyncCacheEntry::Loaded(loaded)) = self.items.get_mut(key) else {
+            return None;
+        };
+
+        self.eviction_index.remove(&loaded.rank);
+        self.eviction_index.insert(rank, key.clone());
+        loaded.rank = rank;
+        loaded.hits = hits;
+
+        let refresh = match (loaded.expires_at, refresh_ahead) {
+            (Some(expires_at), Some(refresh_ahead)) => {
+                loaded.result.is_ok()
+                    && loaded.refresh_id.is_none()
+                    && expires_at.sub(refresh_ahead).unix_microseconds <= now.unix_microseconds
+            }
+            _ => false,
+        };
+
+        if refresh {
+            loaded.refresh_id = Some(refresh_id);
+        }
+
+        Some((loaded.result.clone(), refresh.then_some(refresh_id)))
+    }
+}
+
+fn is_expired(expires_at: Option<DateTimeAsMicroseconds>, now: DateTimeAsMicroseconds) -> bool {
+    match expires_at {
+        Some(expires_at) => expires_at.unix_microseconds <= now.unix_microseconds,
+        None => false,
+    }
+}
+
+fn get_expires_at(
+    ttl: Option<Duration>,
+    now: DateTimeAsMicroseconds,
+) -> Option<DateTimeAsMicroseconds> {
+    ttl.map(|ttl| now.add(ttl))
+}
+
+/// Async read-through cache: `get_or_load(key)` returns the cached value of `key`, loading
+/// it with the registered [`AsyncCacheLoader`] if it is not there.
+///
+/// - **single-flight** - concurrent `get_or_load`s of a key which is not loaded yet run
+///   the loader once: the first caller loads, the others park on it (the way
+///   [`IdempotencyCache`](crate::IdempotencyCache) parks retries) and get the very same
+///   result;
+/// - **TTL** - with [`AsyncCache::set_ttl`] a value is loaded again once it is older than
+///   that. The loader can set a TTL per entry with [`AsyncCacheLoader::get_ttl`], and
+///   [`AsyncCache::insert_with_ttl`] takes one too;
+/// - **refresh-ahead** - with [`AsyncCache::set_refresh_ahead`] a hit within that long
+///   before the expiration starts loading the value again in the background, while the
+///   callers keep getting the current one. A failed refresh keeps the current value until
+///   it expires;
+/// - **negative caching** - an `Err` of the loader is handed to the parked callers, but
+///   is not cached unless [`AsyncCache::set_negative_ttl`] is set;
+/// - **size bound** - at most `max_amount` loaded entries, the one to drop is picked by
+///   [`AsyncCacheEviction`] (LRU by default). Loads in flight do not count. An expired
+///   entry is dropped when it is read, when it is evicted, or by
+///   [`AsyncCache::remove_expired`] - which is for a timer;
+/// - **invalidation** - [`AsyncCache::invalidate`], [`AsyncCache::invalidate_where`] and
+///   [`AsyncCache::clear`]. Invalidating a key which is being loaded lets the load finish
+///   for the callers parked on it, but its result is not cached - the next
+///   `get_or_load` loads again.
+///
+/// If the loading caller's future is dropped or the loader panics, nothing is cached and
+/// the parked callers try again - one of them loads in turn. Refresh-ahead spawns a Tokio
+/// task, so it needs a Tokio runtime.
+///
+/// It is designed to live inside an `AppCtx` as a plain field - every method takes
+/// `&self`, no outer `Mutex` needed.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::{sync::Arc, time::Duration};
+/// use rust_extensions::{AsyncCache, AsyncCacheLoader};
+///
+/// struct PartnerLoader;
+///
+/// #[async_trait::async_trait]
+/// impl AsyncCacheLoader<String, String, String> for PartnerLoader {
+///     async fn load(&self, partner_id: &String) -> Result<String, String> {
+///         // a database round trip happens here
+///         Ok(format!("partner {}", partner_id))
+///     }
+/// }
+///
+/// pub struct AppCtx {
+///     pub partners: AsyncCache<String, String, String>,
+/// }
+///
+/// # async fn example() {
+/// let ctx = AppCtx {
+///     partners: AsyncCache::new("partners", 10_000)
+///         .set_ttl(Duration::from_secs(60))
+///         .set_refresh_ahead(Duration::from_secs(10)),
+/// };
+/// ctx.partners.register_loader(Arc::new(PartnerLoader));
+///
+/// let partner = ctx.partners.get_or_load("partner-1".to_string()).await;
+/// # }
+/// ```
+pub struct AsyncCache<
+    TKey: Clone + Hash + Eq + Send + Sync + 'static,
+    TValue: Send + Sync + 'static,
+    TErr: Send + Sync + 'static,
+> {
+    inner: Arc<Mutex<AsyncCacheInner<TKey, TValue, TErr>>>,
+    loader: OnceLock<RegisteredLoader<TKey, TValue, TErr>>,
+    ttl: Option<Duration>,
+    negative_ttl: Option<Duration>,
+    refresh_ahead: Option<Duration>,
+    name: Arc<String>,
+}
+
+impl<
+        TKey: Clone + Hash + Eq + Send + Sync + 'static,
+        TValue: Send + Sync + 'static,
+        TErr: Send + Sync + 'static,
+    > AsyncCache<TKey, TValue, TErr>
+{
+    /// Creates a cache which keeps at most `max_amount` loaded entries. `max_amount == 0`
+    /// is legal and means "de-duplicate concurrent loads, but do not cache anything".
+    pub fn new(name: impl Into<StrOrString<'static>>, max_amount: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(AsyncCacheInner {
+                items: HashMap::new(),
+                parked: HashMap::new(),
+                eviction_index: BTreeMap::new(),
+                loaded_amount: 0,
+                max_amount,
+                eviction: AsyncCacheEviction::default(),
+                next_id: 0,
+            })),
+            loader: OnceLock::new(),
+            ttl: None,
+            negative_ttl: None,
+            refresh_ahead: None,
+            name: Arc::new(name.into().to_string()),
+        }
+    }
+
+    /// Which entry is dropped once the cache is full. Default [`AsyncCacheEviction::Lru`].
+    ///
+    /// Builder style: `AsyncCache::new("partners", 1000).set_eviction(eviction)`.
+    pub fn set_eviction(self, eviction: AsyncCacheEviction) -> Self {
+        self.inner.lock().eviction = eviction;
+        self
+    }
+
+    /// Loads a value again once it is older than `ttl`. Builder style, as
+    /// [`Self::set_eviction`].
+    pub fn set_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Caches the errors of the loader for `negative_ttl`. Builder style, as
+    /// [`Self::set_eviction`].
+    pub fn set_negative_ttl(mut self, negative_ttl: Duration) -> Self {
+        self.negative_ttl = Some(negative_ttl);
+        self
+    }
+
+    /// Starts loading a value again in the background on a hit within `refresh_ahead`
+    /// before it expires. Builder style, as [`Self::set_eviction`].
+    pub fn set_refresh_ahead(mut self, refresh_ahead: Duration) -> Self {
+        self.refresh_ahead = Some(refresh_ahead);
+        self
+    }
+
+    /// Registers the loader. One-shot: a second call panics.
+    ///
+    /// It is a separate step (not a constructor argument) so the loader is free to hold
+    /// an `Arc` of the very `AppCtx` which owns this cache.
+    pub fn register_loader(&self, loader: RegisteredLoader<TKey, TValue, TErr>) {
+        if self.loader.set(loader).is_err() {
+            panic!(
+                "Loader is already registered for the async cache {}",
+                self.name
+            );
+        }
+    }
+
+    fn get_loader(&self) -> &RegisteredLoader<TKey, TValue, TErr> {
+        match self.loader.get() {
+            Some(loader) => loader,
+            None => panic!("Loader is not registered for the async cache {}", self.name),
+        }
+    }
+
+    /// Returns the cached result of `key`, loading it if it is not cached - see the type
+    /// documentation.
+    pub async fn get_or_load(&self, key: TKey) -> AsyncCacheResult<TValue, TErr> {
+        // Resolved before we claim the key: panicking after inserting the `Loading` entry
+        // would leave it stuck in the map.
+        let loader = self.get_loader();
+
+        let load_id = loop {
+            match self.get_load_action(&key) {
+                LoadAction::Hit(result, refresh_id) => {
+                    if let Some(refresh_id) = refresh_id {
+                        self.spawn_refresh(key, refresh_id);
+                    }
+
+                    return result;
+                }
+                LoadAction::Park(awaiter) => {
+                    // An error means the load ended without a result - the key is free by
+                    // now, so the next round loads it or parks on whoever was faster.
+                    if let Ok(result) = awaiter.get_result().await {
+                        return result;
+                    }
+                }
+                LoadAction::Load(load_id) => break load_id,
+            }
+        };
+
+        // From here on we own the load. If this future is dropped or the loader panics,
+        // the guard frees the key and releases the parked callers.
+        let mut guard = LoadOwnerGuard::new(&self.inner, key, load_id);
+
+        let loaded = loader.load(guard.get_key()).await;
+        let now = DateTimeAsMicroseconds::now();
+
+        let (result, ttl) = match loaded {
+            Ok(value) => {
+                let ttl = loader.get_ttl(guard.get_key(), &value).or(self.ttl);
+                (Ok(Arc::new(value)), Some(ttl))
+            }
+            Err(err) => (Err(Arc::new(err)), self.negative_ttl.map(Some)),
+        };
+
+        // `None` - do not cache.
+        let expires_at = ttl.map(|ttl| get_expires_at(ttl, now));
+        let parked = guard.commit(result.clone(), expires_at);
+
+        // Outside the lock. `try_*`: a parked caller could have been cancelled meanwhile.
+        for mut parked in parked {
+            let _ = parked.try_set_ok(result.clone());
+        }
+
+        result
+    }
+
+    fn get_load_action(&self, key: &TKey) -> LoadAction<TValue, TErr> {
+        let now = DateTimeAsMicroseconds::now();
+        let mut inner = self.inner.lock();
+
+        if let Some(loaded) = inner.get_loaded(key) {
+            if is_expired(loaded.expires_at, now) {
+                inner.remove(key);
+            }
+        }
+
+        if let Some((result, refresh_id)) = inner.hit(key, now, self.refresh_ahead) {
+            return LoadAction::Hit(result, refresh_id);
+        }
+
+        if let Some(AsyncCacheEntry::Loading { load_id }) = inner.items.get(key) {
+            let load_id = *load_id;
+
+            let mut task_completion = TaskCompletion::new();
+            task_completion.set_drop_error(());
+            let awaiter = task_completion.get_awaiter();
+
+            inner
+                .parked
+                .entry(load_id)
+                .or_default()
+                .push(task_completion);
+            return LoadAction::Park(awaiter);
+        }
+
+        let load_id = inner.get_next_id();
+        inner
+            .items
+            .insert(key.clone(), AsyncCacheEntry::Loading { load_id });
+        inner.parked.insert(load_id, Vec::new());
+
+        LoadAction::Load(load_id)
+    }
+
+    fn spawn_refresh(&self, key: TKey, refresh_id: u64) {
+        let inner = self.inner.clone();
+        let loader = self.get_loader().clone();
+        let ttl = self.ttl;
+
+        tokio::spawn(async move {
+            let loaded = AssertUnwindSafe(loader.load(&key)).catch_unwind().await;
+            let now = DateTimeAsMicroseconds::now();
+
+            let expires_at = match &loaded {
+                Ok(Ok(value)) => get_expires_at(loader.get_ttl(&key, value).or(ttl), now),
+                _ => None,
+            };
+
+            let mut inner = inner.lock();
+
+            // Invalidated or replaced meanwhile - the value we loaded may be stale already.
+            let Some(AsyncCacheEntry::Loaded(entry)) = inner.items.get_mut(&key) else {
+                return;
+            };
+
+            if entry.refresh_id != Some(refresh_id) {
+                return;
+            }
+
+            entry.refresh_id = None;
+
+            if let Ok(Ok(value)) = loaded {
+                entry.result = Ok(Arc::new(value));
+                entry.expires_at = expires_at;
+            }
+        });
+    }
+
+    /// Puts `value` into the cache with the TTL of the cache, replacing whatever is
+    /// there. A load of `key` in flight is not cached then.
+    pub fn insert(&self, key: TKey, value: TValue) {
+        self.insert_with_ttl(key, value, self.ttl);
+    }
+
+    /// Same as [`Self::insert`] with a TTL of its own. `None` - never expires.
+    pub fn insert_with_ttl(&self, key: TKey, value: TValue, ttl: Option<Duration>) {
+        let expires_at = get_expires_at(ttl, DateTimeAsMicroseconds::now());
+        self.inner
+            .lock()
+            .insert_loaded(key, Ok(Arc::new(value)), expires_at);
+    }
+
+    /// Peeks the cached result without loading anything and without counting a hit.
+    /// `None` means the key is not cached, expired or is being loaded right now.
+    pub fn get_if_loaded(&self, key: &TKey) -> Option<AsyncCacheResult<TValue, TErr>> {
+        let inner = self.inner.lock();
+        let loaded = inner.get_loaded(key)?;
+
+        if is_expired(loaded.expires_at, DateTimeAsMicroseconds::now()) {
+            return None;
+        }
+
+        Some(loaded.result.clone())
+    }
+
+    /// Drops `key` - see the type documentation for a key which is being loaded. Returns
+    /// `false` if the key was not there.
+    pub fn invalidate(&self, key: &TKey) -> bool {
+        self.inner.lock().remove(key)
+    }
+
+    /// Drops every key `predicate` returns `true` for. Returns the amount of the dropped
+    /// keys.
+    pub fn invalidate_where(&self, predicate: impl Fn(&TKey) -> bool) -> usize {
+        let mut inner = self.inner.lock();
+
+        let keys: Vec<TKey> = inner
+            .items
+            .keys()
+            .filter(|key| predicate(key))
+            .cloned()
+            .collect();
+
+        for key in keys.iter() {
+            inner.remove(key);
+        }
+
+        keys.len()
+    }
+
+    /// Drops every key.
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock();
+        inner.items.clear();
+        inner.eviction_index.clear();
+        inner.loaded_amount = 0;
+    }
+
+    /// Drops the expired entries. Returns the amount of the dropped ones.
+    pub fn remove_expired(&self) -> usize {
+        let now = DateTimeAsMicroseconds::now();
+        let mut inner = self.inner.lock();
+
+        let keys: Vec<TKey> = inner
+            .items
+            .iter()
+            .filter_map(|(key, entry)| match entry {
+                AsyncCacheEntry::Loaded(loaded) if is_expired(loaded.expires_at, now) => {
+                    Some(key.clone())
+                }
+                _ => None,
+            })
+            .collect();
+
+        for key in keys.iter() {
+            inner.remove(key);
+        }
+
+        keys.len()
+    }
+
+    /// Amount of loaded entries - never above `max_amount`.
+    pub fn get_loaded_amount(&self) -> usize {
+        self.inner.lock().loaded_amount
+    }
+
+    /// Amount of loads which are in flight right now.
+    pub fn get_loading_amount(&self) -> usize {
+        let inner = self.inner.lock();
+        inner.items.len() - inner.loaded_amount
+    }
+}
+
+enum LoadAction<TValue, TErr> {
+    /// The cached result, and the id of the refresh-ahead to start.
+    Hit(AsyncCacheResult<TValue, TErr>, Option<u64>),
+    Park(TaskCompletionAwaiter<AsyncCacheResult<TValue, TErr>, ()>),
+    Load(u64),
+}
+
+/// Owns the `Loading` entry for the duration of the load.
+///
+/// [`LoadOwnerGuard::commit`] replaces the entry with the loaded one; if that never
+/// happens (the owning future was dropped, or the loader panicked and we are unwinding),
+/// `Drop` removes the entry and drops the parked `TaskCompletion`s, which makes the
+/// parked callers try again.
+struct LoadOwnerGuard<'s, TKey: Clone + Hash + Eq, TValue, TErr> {
+    inner: &'s Mutex<AsyncCacheInner<TKey, TValue, TErr>>,
+    key: TKey,
+    load_id: u64,
+    committed: bool,
+}
+
+impl<'s, TKey: Clone + Hash + Eq, TValue, TErr> LoadOwnerGuard<'s, TKey, TValue, TErr> {
+    fn new(inner: &'s Mutex<AsyncCacheInner<TKey, TValue, TErr>>, key: TKey, load_id: u64) -> Self {
+        Self {
+            inner,
+            key,
+            load_id,
+            committed: false,
+        }
+    }
+
+    fn get_key(&self) -> &TKey {
+        &self.key
+    }
+
+    /// Caches `result` unless `expires_at` is `None` or the entry was invalidated
+    /// meanwhile, and hands back everybody who parked while we were loading.
+    fn commit(
+        &mut self,
+        result: AsyncCacheResult<TValue, TErr>,
+        expires_at: Option<Option<DateTimeAsMicroseconds>>,
+    ) -> Vec<ParkedLoad<TValue, TErr>> {
+        self.committed = true;
+
+        let mut inner = self.inner.lock();
+        let parked = inner.parked.remove(&self.load_id).unwrap_or_default();
+
+        if inner.is_loading(&self.key, self.load_id) {
+            match expires_at {
+                Some(expires_at) => inner.insert_loaded(self.key.clone(), result, expires_at),
+                None => {
+                    inner.items.remove(&self.key);
+                }
+            }
+        }
+
+        parked
+    }
+}
+
+impl<'s, TKey: Clone + Hash + Eq, TValue, TErr> Drop for LoadOwnerGuard<'s, TKey, TValue, TErr> {
+    fn drop(&mut self) {
+        if self.committed {
+            return;
+        }
+
+        let parked = {
+            let mut inner = self.inner.lock();
+
+            if inner.is_loading(&self.key, self.load_id) {
+                inner.items.remove(&self.key);
+            }
+
+            inner.parked.remove(&self.load_id)
+        };
+
+        // Outside the lock: dropping the parked `TaskCompletion`s wakes them up.
+        drop(parked);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    use super::*;
+
+    fn create_runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    /// Loads `"{key}:{call}"`, or fails while `fail` is set.
+    struct TestLoader {
+        calls: AtomicUsize,
+        delay: Duration,
+        fail: AtomicBool,
+    }
+
+    impl TestLoader {
+        fn new(delay: Duration) -> Arc<Self> {
+            Arc::new(Self {
+                calls: AtomicUsize::new(0),
+                delay,
+                fail: AtomicBool::new(false),
+            })
+        }
+
+        fn get_calls(&self) -> usize {
+            self.calls.load(Ordering::SeqCst)
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl AsyncCacheLoader<u32, String, String> for TestLoader {
+        async fn load(&self, key: &u32) -> Result<String, String> {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+            tokio::time::sleep(self.delay).await;
+
+            if self.fail.load(Ordering::SeqCst) {
+                return Err(format!("{}:failed", key));
+            }
+
+            Ok(format!("{}:{}", key, call))
+        }
+    }
+
+    fn create_cache(
+        max_amount: usize,
+        loader: &Arc<TestLoader>,
+    ) -> AsyncCache<u32, String, String> {
+        let cache = AsyncCache::new("test", max_amount);
+        cache.register_loader(loader.clone());
+        cache
+    }
+
+    fn get_value(result: AsyncCacheResult<String, String>) -> String {
+        result.unwrap().as_ref().clone()
+    }
+
+    #[test]
+    fn concurrent_loads_of_a_key_run_the_loader_once() {
+        create_runtime().block_on(async {
+            let loader = TestLoader::new(Duration::from_millis(20));
+            let cache = create_cache(10, &loader);
+
+            let results = futures::future::join_all((0..5).map(|_| cache.get_or_load(1))).await;
+
+            for result in results {
+                assert_eq!(get_value(result), "1:1");
+            }
+
+            assert_eq!(get_value(cache.get_or_load(1).await), "1:1");
+            assert_eq!(loader.get_calls(), 1);
+            assert_eq!(cache.get_loaded_amount(), 1);
+            assert_eq!(cache.get_loading_amount(), 0);
+        });
+    }
+
+    #[test]
+    fn expired_value_is_loaded_again() {
+        create_runtime().block_on(async {
+            let loader = TestLoader::new(Duration::ZERO);
+            let cache = create_cache(10, &loader).set_ttl(Duration::from_millis(30));
+
+            assert_eq!(get_value(cache.get_or_load(1).await), "1:1");
+            assert_eq!(get_value(cache.get_or_load(1).await), "1:1");
+
+            tokio::time::sleep(Duration::from_millis(40)).await;
+            assert!(cache.get_if_loaded(&1).is_none());
+            assert_eq!(get_value(cache.get_or_load(1).await), "1:2");
+
+            cache.insert_with_ttl(2, "manual".to_string(), Some(Duration::ZERO));
+            assert_eq!(cache.remove_expired(), 1);
+            assert_eq!(cache.get_loaded_amount(), 1);
+        });
+    }
+
+    #[test]
+    fn errors_are_cached_only_with_negative_ttl() {
+        create_runtime().block_on(async {
+            let loader = TestLoader::new(Duration::ZERO);
+            loader.fail.store(true, Ordering::SeqCst);
+
+            let cache = create_cache(10, &loader);
+            assert!(cache.get_or_load(1).await.is_err());
+            assert!(cache.get_or_load(1).await.is_err());
+            assert_eq!(loader.get_calls(), 2);
+
+            let cache = create_cache(10, &loader).set_negative_ttl(Duration::from_millis(30));
+            assert!(cache.get_or_load(1).await.is_err());
+            loader.fail.store(false, Ordering::SeqCst);
+            assert!(cache.get_or_load(1).await.is_err());
+            assert_eq!(loader.get_calls(), 3);
+
+            tokio::time::sleep(Duration::from_millis(40)).await;
+            assert_eq!(get_value(cache.get_or_load(1).await), "1:4");
+        });
+    }
+
+    #[test]
+    fn lru_evicts_the_least_recently_used() {
+        create_runtime().block_on(async {
+            let loader = TestLoader::new(Duration::ZERO);
+            let cache = create_cache(2, &loader);
+
+            cache.get_or_load(1).await.unwrap();
+            cache.get_or_load(2).await.unwrap();
+            cache.get_or_load(1).await.unwrap();
+            cache.get_or_load(3).await.unwrap();
+
+            assert!(cache.get_if_loaded(&1).is_some());
+            assert!(cache.get_if_loaded(&2).is_none());
+            assert!(cache.get_if_loaded(&3).is_some());
+            assert_eq!(cache.get_loaded_amount(), 2);
+        });
+    }
+
+    #[test]
+    fn lfu_evicts_the_least_frequently_used() {
+        create_runtime().block_on(async {
+            let loader = TestLoader::new(Duration::ZERO);
+            let cache = create_cache(2, &loader).set_eviction(AsyncCacheEviction::Lfu);
+
+            for _ in 0..3 {
+                cache.get_or_load(1).await.unwrap();
+            }
+
+            for _ in 0..2 {
+                cache.get_or_load(2).await.unwrap();
+            }
+
+            // 2 is the most recently used one, but 1 has more hits.
+            cache.get_or_load(3).await.unwrap();
+
+            assert!(cache.get_if_loaded(&1).is_some());
+            assert!(cache.get_if_loaded(&2).is_none());
+            assert!(cache.get_if_loaded(&3).is_some());
+        });
+    }
+
+    #[test]
+    fn refresh_ahead_serves_the_current_value_meanwhile() {
+        create_runtime().block_on(async {
+            let loader = TestLoader::new(Duration::from_millis(10));
+            let cache = create_cache(10, &loader)
+                .set_ttl(Duration::from_millis(100))
+                .set_refresh_ahead(Duration::from_millis(80));
+
+            assert_eq!(get_value(cache.get_or_load(1).await), "1:1");
+
+            tokio::time::sleep(Duration::from_millis(30)).await;
+            assert_eq!(get_value(cache.get_or_load(1).await), "1:1");
+            assert_eq!(get_value(cache.get_or_load(1).await), "1:1");
+
+            tokio::time::sleep(Duration::from_millis(30)).await;
+            assert_eq!(get_value(cache.get_or_load(1).await), "1:2");
+            assert_eq!(loader.get_calls(), 2);
+        });
+    }
+
+    #[test]
+    fn invalidated_load_is_not_cached() {
+        create_runtime().block_on(async {
+            let loader = TestLoader::new(Duration::from_millis(20));
+            let cache = create_cache(10, &loader);
+
+            let (loaded, invalidated) = futures::join!(cache.get_or_load(1), async {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                cache.invalidate(&1)
+            });
+
+            assert_eq!(get_value(loaded), "1:1");
+            assert!(invalidated);
+            assert!(cache.get_if_loaded(&1).is_none());
+            assert_eq!(get_value(cache.get_or_load(1).await), "1:2");
+
+            cache.get_or_load(2).await.unwrap();
+            cache.get_or_load(10).await.unwrap();
+            assert_eq!(cache.invalidate_where(|key| *key < 10), 2);
+            assert_eq!(cache.get_loaded_amount(), 1);
+        });
+    }
+
+    #[test]
+    fn parked_caller_loads_if_the_owner_is_dropped() {
+        create_runtime().block_on(async {
+            let loader = TestLoader::new(Duration::from_millis(30));
+            let cache = create_cache(10, &loader);
+
+            let (owner, parked) = futures::join!(
+                tokio::time::timeout(Duration::from_millis(10), cache.get_or_load(1)),
+                async {
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                    cache.get_or_load(1).await
+                }
+            );
+
+            assert!(owner.is_err());
+            assert_eq!(get_value(parked), "1:2");
+            assert_eq!(cache.get_loading_amount(), 0);
+        });
+    }
+}
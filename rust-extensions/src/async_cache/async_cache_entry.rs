@@ -0,0 +1,35 @@
+use std::sync::Arc;
+
+use crate::{date_time::DateTimeAsMicroseconds, TaskCompletion};
+
+/// What `get_or_load` gets back.
+///
+/// Both sides are `Arc` - a result is handed out to every caller of the same key, so
+/// neither `TValue` nor `TErr` has to be `Clone`.
+pub type AsyncCacheResult<TValue, TErr> = Result<Arc<TValue>, Arc<TErr>>;
+
+/// A caller parked on a load. The error is the drop error: the load ended without a
+/// result, and the caller tries again.
+pub(crate) type ParkedLoad<TValue, TErr> = TaskCompletion<AsyncCacheResult<TValue, TErr>, ()>;
+
+pub(crate) enum AsyncCacheEntry<TValue, TErr> {
+    /// Somebody is loading this key right now. The callers who came in meanwhile are
+    /// parked by `load_id` aside of the entry, so the load can hand them its result even
+    /// if the entry was invalidated meanwhile.
+    Loading {
+        load_id: u64,
+    },
+    Loaded(AsyncCacheLoadedEntry<TValue, TErr>),
+}
+
+pub(crate) struct AsyncCacheLoadedEntry<TValue, TErr> {
+    /// An `Err` is here only with a negative TTL set.
+    pub result: AsyncCacheResult<TValue, TErr>,
+    /// `None` - never expires.
+    pub expires_at: Option<DateTimeAsMicroseconds>,
+    /// The key of the entry in the eviction index.
+    pub rank: (u64, u64),
+    pub hits: u64,
+    /// Id of the refresh-ahead in flight.
+    pub refresh_id: Option<u64>,
+}
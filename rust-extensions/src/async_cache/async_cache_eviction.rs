@@ -0,0 +1,10 @@
+/// Which loaded entry [`AsyncCache`](super::AsyncCache) drops once it is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AsyncCacheEviction {
+    /// The least recently used one.
+    #[default]
+    Lru,
+    /// The least frequently used one - the one with the fewest hits since it was loaded,
+    /// the least recently used among those.
+    Lfu,
+}
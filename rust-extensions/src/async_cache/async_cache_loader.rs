@@ -0,0 +1,20 @@
+use std::time::Duration;
+
+/// Loads the value which sits behind a key of [`AsyncCache`](super::AsyncCache).
+///
+/// The cache makes sure concurrent `get_or_load`s of the same key run `load` once - the
+/// others park on it and get the very same result.
+#[async_trait::async_trait]
+pub trait AsyncCacheLoader<
+    TKey: Send + Sync + 'static,
+    TValue: Send + Sync + 'static,
+    TErr: Send + Sync + 'static,
+>: Send + Sync + 'static
+{
+    async fn load(&self, key: &TKey) -> Result<TValue, TErr>;
+
+    /// Per-entry TTL of a loaded value. `None` - the TTL of the cache.
+    fn get_ttl(&self, _key: &TKey, _value: &TValue) -> Option<Duration> {
+        None
+    }
+}
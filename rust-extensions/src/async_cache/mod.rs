@@ -0,0 +1,10 @@
+mod async_cache;
+mod async_cache_entry;
+mod async_cache_eviction;
+mod async_cache_loader;
+
+pub use async_cache::AsyncCache;
+pub use async_cache_entry::AsyncCacheResult;
+pub(crate) use async_cache_entry::{AsyncCacheEntry, AsyncCacheLoadedEntry, ParkedLoad};
+pub use async_cache_eviction::AsyncCacheEviction;
+pub use async_cache_loader::AsyncCacheLoader;
@@ -0,0 +1,99 @@
+use parking_lot::Mutex;
+
+use crate::TaskCompletion;
+
+struct BarrierState {
+    arrived: usize,
+    awaiters: Vec<TaskCompletion<(), ()>>,
+}
+
+/// Parks everybody who calls [`AsyncBarrier::wait`] until `parties` of them are there,
+/// then releases them all and starts over - reusable, unlike [`super::CountdownLatch`].
+///
+/// Not cancel-safe: a waiter which is dropped still counts as arrived.
+pub struct AsyncBarrier {
+    parties: usize,
+    state: Mutex<BarrierState>,
+}
+
+impl AsyncBarrier {
+    /// Panics if `parties` is zero.
+    pub fn new(parties: usize) -> Self {
+        if parties == 0 {
+            panic!("Barrier needs at least one party");
+        }
+
+        Self {
+            parties,
+            state: Mutex::new(BarrierState {
+                arrived: 0,
+                awaiters: Vec::new(),
+            }),
+        }
+    }
+
+    /// Returns `true` for exactly one waiter of each round - the last one to arrive.
+    pub async fn wait(&self) -> bool {
+        let awaiter = {
+            let mut state = self.state.lock();
+            state.arrived += 1;
+
+            if state.arrived == self.parties {
+                state.arrived = 0;
+
+                for mut task_completion in state.awaiters.drain(..) {
+                    let _ = task_completion.try_set_ok(());
+                }
+
+                return true;
+            }
+
+            let mut task_completion = TaskCompletion::new();
+            task_completion.set_drop_error(());
+            let awaiter = task_completion.get_awaiter();
+            state.awaiters.push(task_completion);
+            awaiter
+        };
+
+        let _ = awaiter.get_result().await;
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn releases_every_round_once_all_parties_arrive() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        rt.block_on(async {
+            let barrier = Arc::new(AsyncBarrier::new(3));
+
+            for _ in 0..2 {
+                let waiters: Vec<_> = (0..3)
+                    .map(|_| {
+                        let barrier = barrier.clone();
+                        tokio::spawn(async move { barrier.wait().await })
+                    })
+                    .collect();
+
+                let mut leaders = 0;
+
+                for waiter in waiters {
+                    if waiter.await.unwrap() {
+                        leaders += 1;
+                    }
+                }
+
+                assert_eq!(leaders, 1);
+            }
+        });
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+
+use crate::TaskCompletion;
+
+/// How an [`AsyncEvent`] behaves once it is set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AsyncEventReset {
+    /// Stays set - releasing every waiter - until [`AsyncEvent::reset`] is called.
+    Manual,
+    /// Releases exactly one waiter and resets itself. If nobody waits, it stays set until
+    /// the next waiter comes.
+    Auto,
+}
+
+struct EventState {
+    is_set: bool,
+    awaiters: VecDeque<TaskCompletion<(), ()>>,
+}
+
+/// An async event which can be set and reset any amount of times - unlike
+/// [`crate::IsInitialized`], which is set once and for all.
+pub struct AsyncEvent {
+    reset: AsyncEventReset,
+    state: Mutex<EventState>,
+}
+
+impl AsyncEvent {
+    pub fn new(reset: AsyncEventReset, is_set: bool) -> Self {
+        Self {
+            reset,
+            state: Mutex::new(EventState {
+                is_set,
+                awaiters: VecDeque::new(),
+            }),
+        }
+    }
+
+    pub fn new_manual_reset(is_set: bool) -> Self {
+        Self::new(AsyncEventReset::Manual, is_set)
+    }
+
+    pub fn new_auto_reset(is_set: bool) -> Self {
+        Self::new(AsyncEventReset::Auto, is_set)
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.state.lock().is_set
+    }
+
+    pub fn set(&self) {
+        let mut state = self.state.lock();
+
+        match self.reset {
+            AsyncEventReset::Manual => {
+                state.is_set = true;
+
+                for mut task_completion in state.awaiters.drain(..) {
+                    let _ = task_completion.try_set_ok(());
+                }
+            }
+            AsyncEventReset::Auto => {
+                // The first waiter which is still there takes it - the cancelled ones
+                // do not count.
+                while let Some(mut task_completion) = state.awaiters.pop_front() {
+                    if task_completion.try_set_ok(()).is_ok() {
+                        return;
+                    }
+                }
+
+                state.is_set = true;
+            }
+        }
+    }
+
+    pub fn reset(&self) {
+        self.state.lock().is_set = false;
+    }
+
+    pub async fn wait(&self) {
+        let awaiter = {
+            let mut state = self.state.lock();
+
+            if state.is_set {
+                if self.reset == AsyncEventReset::Auto {
+                    state.is_set = false;
+                }
+
+                return;
+            }
+
+            let mut task_completion = TaskCompletion::new();
+            task_completion.set_drop_error(());
+            let awaiter = task_completion.get_awaiter();
+            state.awaiters.push_back(task_completion);
+            awaiter
+        };
+
+        let _ = awaiter.get_result().await;
+    }
+
+    /// Returns `false` if the event is not set within `timeout`.
+    pub async fn wait_with_timeout(&self, timeout: Duration) -> bool {
+        tokio::time::timeout(timeout, self.wait()).await.is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::*;
+
+    fn create_runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn manual_reset_event_releases_everybody_until_reset() {
+        create_runtime().block_on(async {
+            let event = Arc::new(AsyncEvent::new_manual_reset(false));
+
+            let waiters: Vec<_> = (0..2)
+                .map(|_| {
+                    let event = event.clone();
+                    tokio::spawn(async move { event.wait().await })
+                })
+                .collect();
+
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            event.set();
+
+            for waiter in waiters {
+                waiter.await.unwrap();
+            }
+
+            event.wait().await;
+            event.reset();
+            assert!(!event.wait_with_timeout(Duration::from_millis(20)).await);
+        });
+    }
+
+    #[test]
+    fn auto_reset_event_releases_one_waiter_per_set() {
+        create_runtime().block_on(async {
+            let event = Arc::new(AsyncEvent::new_auto_reset(false));
+
+            let first = tokio::spawn({
+                let event = event.clone();
+                async move { event.wait().await }
+            });
+            let second = tokio::spawn({
+                let event = event.clone();
+                async move { event.wait().await }
+            });
+
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            event.set();
+            tokio::time::sleep(Duration::from_millis(20)).await;
+
+            assert_eq!(
+                [first.is_finished(), second.is_finished()]
+                    .iter()
+                    .filter(|finished| **finished)
+                    .count(),
+                1
+            );
+            assert!(!event.is_set());
+
+            event.set();
+            first.await.unwrap();
+            second.await.unwrap();
+
+            // Nobody waits - it stays set for the next waiter only.
+            event.set();
+            event.wait().await;
+            assert!(!event.is_set());
+        });
+    }
+}
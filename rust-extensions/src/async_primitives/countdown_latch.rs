@@ -0,0 +1,129 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use parking_lot::Mutex;
+
+use crate::TaskCompletion;
+
+/// Releases everybody who waits once [`CountdownLatch::count_down`] is called `count`
+/// times - e.g. "start serving once all 3 caches are loaded".
+///
+/// Same shape as [`crate::IsInitialized`]: the waiters park on `TaskCompletion`s, and once
+/// the latch is open `wait` flies through the atomic counter without locking.
+pub struct CountdownLatch {
+    count: AtomicUsize,
+    awaiters: Mutex<Vec<TaskCompletion<(), ()>>>,
+}
+
+impl CountdownLatch {
+    pub fn new(count: usize) -> Self {
+        Self {
+            count: AtomicUsize::new(count),
+            awaiters: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// How many `count_down` calls are still missing.
+    pub fn get_count(&self) -> usize {
+        self.count.load(Ordering::Acquire)
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.get_count() == 0
+    }
+
+    /// Counting down an open latch is a no-op.
+    pub fn count_down(&self) {
+        let mut awaiters = self.awaiters.lock();
+
+        let count = self.count.load(Ordering::Acquire);
+
+        if count == 0 {
+            return;
+        }
+
+        self.count.store(count - 1, Ordering::Release);
+
+        if count > 1 {
+            return;
+        }
+
+        for mut task_completion in awaiters.drain(..) {
+            // The awaiter may have been cancelled meanwhile.
+            let _ = task_completion.try_set_ok(());
+        }
+    }
+
+    pub async fn wait(&self) {
+        if self.is_open() {
+            return;
+        }
+
+        let awaiter = {
+            let mut awaiters = self.awaiters.lock();
+
+            // `count_down` opens the latch and drains the vec under the same lock.
+            if self.is_open() {
+                return;
+            }
+
+            let mut task_completion = TaskCompletion::new();
+            task_completion.set_drop_error(());
+            let awaiter = task_completion.get_awaiter();
+            awaiters.push(task_completion);
+            awaiter
+        };
+
+        let _ = awaiter.get_result().await;
+    }
+
+    /// Returns `false` if the latch is not open within `timeout`.
+    pub async fn wait_with_timeout(&self, timeout: Duration) -> bool {
+        tokio::time::timeout(timeout, self.wait()).await.is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::*;
+
+    fn create_runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn opens_after_the_last_count_down() {
+        create_runtime().block_on(async {
+            let latch = Arc::new(CountdownLatch::new(2));
+
+            let waiter = tokio::spawn({
+                let latch = latch.clone();
+                async move { latch.wait().await }
+            });
+
+            latch.count_down();
+            assert!(!latch.wait_with_timeout(Duration::from_millis(20)).await);
+            assert_eq!(latch.get_count(), 1);
+
+            latch.count_down();
+            latch.count_down();
+            assert_eq!(latch.get_count(), 0);
+
+            waiter.await.unwrap();
+            latch.wait().await;
+        });
+    }
+
+    #[test]
+    fn zero_count_is_open_from_the_start() {
+        create_runtime().block_on(async {
+            CountdownLatch::new(0).wait().await;
+        });
+    }
+}
@@ -0,0 +1,319 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+
+use crate::{IsInitialized, StrOrString};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InitDependenciesError {
+    /// The component is registered already.
+    AlreadyRegistered(String),
+    /// Registering the component would close a cycle - the path goes from the component
+    /// back to itself.
+    Cycle(Vec<String>),
+    /// The dependencies of `name` were not initialized in time.
+    Timeout {
+        name: String,
+        pending: Vec<InitPending>,
+    },
+}
+
+/// A component which is not initialized yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InitPending {
+    pub name: String,
+    /// Its dependencies which are not initialized yet either.
+    pub waits_for: Vec<String>,
+    /// `false` if somebody depends on it but it was never registered - most likely a
+    /// typo or a component which is not started at all.
+    pub registered: bool,
+}
+
+struct InitNode {
+    depends_on: Vec<String>,
+    registered: bool,
+    is_initialized: Arc<IsInitialized>,
+}
+
+impl InitNode {
+    fn new() -> Self {
+        Self {
+            depends_on: Vec::new(),
+            registered: false,
+            is_initialized: Arc::new(IsInitialized::new()),
+        }
+    }
+}
+
+/// Lets the startup say "the queue waits for the cache to be initialized" by name.
+///
+/// Every component is [`InitDependencies::register`]ed with the names it depends on, awaits
+/// [`InitDependencies::wait_for_dependencies`] before it starts, and reports
+/// [`InitDependencies::initialized`] once it is up. A dependency can be named before it is
+/// registered. A registration which would close a cycle is rejected - otherwise the
+/// components on it would wait for each other forever - and a wait which times out lists
+/// everything which is still pending.
+pub struct InitDependencies {
+    nodes: Mutex<HashMap<String, InitNode>>,
+}
+
+impl InitDependencies {
+    pub fn new() -> Self {
+        Self {
+            nodes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn register(
+        &self,
+        name: impl Into<StrOrString<'static>>,
+        depends_on: &[&str],
+    ) -> Result<(), InitDependenciesError> {
+        let name = name.into().to_string();
+        let mut nodes = self.nodes.lock();
+
+        if let Some(node) = nodes.get(&name) {
+            if node.registered {
+                return Err(InitDependenciesError::AlreadyRegistered(name));
+            }
+        }
+
+        for dependency in depends_on {
+            if let Some(mut path) = find_path(&nodes, dependency, &name) {
+                path.insert(0, name.clone());
+                return Err(InitDependenciesError::Cycle(path));
+            }
+        }
+
+        for dependency in depends_on {
+            nodes
+                .entry(dependency.to_string())
+                .or_insert_with(InitNode::new);
+        }
+
+        let node = nodes.entry(name).or_insert_with(InitNode::new);
+        node.registered = true;
+        node.depends_on = depends_on.iter().map(|name| name.to_string()).collect();
+
+        Ok(())
+    }
+
+    /// Reports the component as initialized and releases everybody who waits for it.
+    pub async fn initialized(&self, name: &str) {
+        let is_initialized = self.get_is_initialized(name);
+        is_initialized.initialized().await;
+    }
+
+    pub fn is_initialized(&self, name: &str) -> bool {
+        match self.nodes.lock().get(name) {
+            Some(node) => node.is_initialized.is_initialized(),
+            None => false,
+        }
+    }
+
+    /// Waits until every dependency of `name` is initialized. Panics if `name` is not
+    /// registered.
+    pub async fn wait_for_dependencies(
+        &self,
+        name: &str,
+        timeout: Duration,
+    ) -> Result<(), InitDependenciesError> {
+        let dependencies: Vec<Arc<IsInitialized>> = {
+            let nodes = self.nodes.lock();
+
+            let node = match nodes.get(name) {
+                Some(node) if node.registered => node,
+                _ => panic!("Component [{}] is not registered", name),
+            };
+
+            node.depends_on
+                .iter()
+                .map(|dependency| nodes[dependency].is_initialized.clone())
+                .collect()
+        };
+
+        let waited = tokio::time::timeout(timeout, async {
+            for dependency in dependencies {
+                dependency.wait_until_initialized().await;
+            }
+        })
+        .await;
+
+        match waited {
+            Ok(_) => Ok(()),
+            Err(_) => Err(InitDependenciesError::Timeout {
+                name: name.to_string(),
+                pending: self.get_pending(),
+            }),
+        }
+    }
+
+    /// Every component which is not initialized yet, sorted by name.
+    pub fn get_pending(&self) -> Vec<InitPending> {
+        let nodes = self.nodes.lock();
+
+        let mut result: Vec<InitPending> = nodes
+            .iter()
+            .filter(|(_, node)| !node.is_initialized.is_initialized())
+            .map(|(name, node)| InitPending {
+                name: name.clone(),
+                waits_for: node
+                    .depends_on
+                    .iter()
+                    .filter(|dependency| !nodes[*dependency].is_initialized.is_initialized())
+                    .cloned()
+                    .collect(),
+                registered: node.registered,
+            })
+            .collect();
+
+        result.sort_by(|a, b| a.name.cmp(&b.name));
+        result
+    }
+
+    fn get_is_initialized(&self, name: &str) -> Arc<IsInitialized> {
+        let mut nodes = self.nodes.lock();
+        nodes
+            .entry(name.to_string())
+            .or_insert_with(InitNode::new)
+            .is_initialized
+            .clone()
+    }
+}
+
+impl Default for InitDependencies {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The path of dependencies from `from` to `to`, both included - if there is one.
+fn find_path(nodes: &HashMap<String, InitNode>, from: &str, to: &str) -> Option<Vec<String>> {
+    if from == to {
+        return Some(vec![to.to_string()]);
+    }
+
+    let node = nodes.get(from)?;
+
+    for dependency in &node.depends_on {
+        if let Some(mut path) = find_path(nodes, dependency, to) {
+            path.insert(0, from.to_string());
+            return Some(path);
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::*;
+
+    fn create_runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn component_waits_for_its_dependencies() {
+        create_runtime().block_on(async {
+            let dependencies = Arc::new(InitDependencies::new());
+            dependencies.register("queue", &["cache", "db"]).unwrap();
+            dependencies.register("cache", &["db"]).unwrap();
+            dependencies.register("db", &[]).unwrap();
+
+            let queue = tokio::spawn({
+                let dependencies = dependencies.clone();
+                async move {
+                    dependencies
+                        .wait_for_dependencies("queue", Duration::from_secs(30))
+                        .await
+                }
+            });
+
+            dependencies.initialized("db").await;
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            assert!(!queue.is_finished());
+
+            dependencies.initialized("cache").await;
+            queue.await.unwrap().unwrap();
+
+            assert!(dependencies.is_initialized("cache"));
+            assert!(!dependencies.is_initialized("queue"));
+        });
+    }
+
+    #[test]
+    fn cycle_is_rejected() {
+        let dependencies = InitDependencies::new();
+        dependencies.register("a", &["b"]).unwrap();
+        dependencies.register("b", &["c"]).unwrap();
+
+        assert_eq!(
+            dependencies.register("c", &["a"]),
+            Err(InitDependenciesError::Cycle(vec![
+                "c".to_string(),
+                "a".to_string(),
+                "b".to_string(),
+                "c".to_string()
+            ]))
+        );
+        assert_eq!(
+            dependencies.register("d", &["d"]),
+            Err(InitDependenciesError::Cycle(vec![
+                "d".to_string(),
+                "d".to_string()
+            ]))
+        );
+        assert_eq!(
+            dependencies.register("a", &[]),
+            Err(InitDependenciesError::AlreadyRegistered("a".to_string()))
+        );
+    }
+
+    #[test]
+    fn timeout_lists_what_is_pending() {
+        create_runtime().block_on(async {
+            let dependencies = InitDependencies::new();
+            dependencies.register("queue", &["cache"]).unwrap();
+            dependencies.register("cache", &["db"]).unwrap();
+
+            let result = dependencies
+                .wait_for_dependencies("queue", Duration::from_millis(20))
+                .await;
+
+            let Err(InitDependenciesError::Timeout { name, pending }) = result else {
+                panic!("Timeout is expected");
+            };
+
+            assert_eq!(name, "queue");
+            assert_eq!(
+                pending,
+                vec![
+                    InitPending {
+                        name: "cache".to_string(),
+                        waits_for: vec!["db".to_string()],
+                        registered: true,
+                    },
+                    InitPending {
+                        name: "db".to_string(),
+                        waits_for: vec![],
+                        registered: false,
+                    },
+                    InitPending {
+                        name: "queue".to_string(),
+                        waits_for: vec!["cache".to_string()],
+                        registered: true,
+                    },
+                ]
+            );
+        });
+    }
+}
@@ -0,0 +1,11 @@
+mod async_barrier;
+mod async_event;
+mod countdown_latch;
+mod init_dependencies;
+mod value_changed_notifier;
+
+pub use async_barrier::AsyncBarrier;
+pub use async_event::{AsyncEvent, AsyncEventReset};
+pub use countdown_latch::CountdownLatch;
+pub use init_dependencies::{InitDependencies, InitDependenciesError, InitPending};
+pub use value_changed_notifier::ValueChangedNotifier;
@@ -0,0 +1,129 @@
+use std::time::Duration;
+
+use parking_lot::Mutex;
+
+use crate::TaskCompletion;
+
+struct ValueState<T> {
+    generation: u64,
+    value: T,
+    awaiters: Vec<TaskCompletion<(), ()>>,
+}
+
+/// Holds a value and lets the readers wait for it to change.
+///
+/// Every [`ValueChangedNotifier::set`] bumps the generation. A reader remembers the
+/// generation it has seen and asks for anything newer - so it never misses a change
+/// which happened between two waits, it just gets the latest value at once.
+pub struct ValueChangedNotifier<T: Clone> {
+    state: Mutex<ValueState<T>>,
+}
+
+impl<T: Clone> ValueChangedNotifier<T> {
+    /// The initial value has generation `0`.
+    pub fn new(value: T) -> Self {
+        Self {
+            state: Mutex::new(ValueState {
+                generation: 0,
+                value,
+                awaiters: Vec::new(),
+            }),
+        }
+    }
+
+    /// The current generation and value.
+    pub fn get(&self) -> (u64, T) {
+        let state = self.state.lock();
+        (state.generation, state.value.clone())
+    }
+
+    /// Returns the new generation.
+    pub fn set(&self, value: T) -> u64 {
+        let mut state = self.state.lock();
+        state.generation += 1;
+        state.value = value;
+
+        for mut task_completion in state.awaiters.drain(..) {
+            let _ = task_completion.try_set_ok(());
+        }
+
+        state.generation
+    }
+
+    /// Waits for a generation newer than `seen_generation` and returns it with its value.
+    /// Returns right away if there is one already.
+    pub async fn wait_changed(&self, seen_generation: u64) -> (u64, T) {
+        loop {
+            let awaiter = {
+                let mut state = self.state.lock();
+
+                if state.generation > seen_generation {
+                    return (state.generation, state.value.clone());
+                }
+
+                let mut task_completion = TaskCompletion::new();
+                task_completion.set_drop_error(());
+                let awaiter = task_completion.get_awaiter();
+                state.awaiters.push(task_completion);
+                awaiter
+            };
+
+            let _ = awaiter.get_result().await;
+        }
+    }
+
+    /// `None` if nothing changed within `timeout`.
+    pub async fn wait_changed_with_timeout(
+        &self,
+        seen_generation: u64,
+        timeout: Duration,
+    ) -> Option<(u64, T)> {
+        tokio::time::timeout(timeout, self.wait_changed(seen_generation))
+            .await
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn reader_gets_the_latest_value_after_its_generation() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        rt.block_on(async {
+            let notifier = Arc::new(ValueChangedNotifier::new("initial".to_string()));
+            let (generation, value) = notifier.get();
+            assert_eq!((generation, value.as_str()), (0, "initial"));
+
+            let reader = tokio::spawn({
+                let notifier = notifier.clone();
+                async move { notifier.wait_changed(0).await }
+            });
+
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            assert_eq!(notifier.set("first".to_string()), 1);
+
+            let (generation, value) = reader.await.unwrap();
+            assert_eq!((generation, value.as_str()), (1, "first"));
+
+            // Two changes while nobody waited - the reader gets the latest one at once.
+            notifier.set("second".to_string());
+            notifier.set("third".to_string());
+            let (generation, value) = notifier.wait_changed(1).await;
+            assert_eq!((generation, value.as_str()), (3, "third"));
+
+            assert!(notifier
+                .wait_changed_with_timeout(3, Duration::from_millis(20))
+                .await
+                .is_none());
+        });
+    }
+}
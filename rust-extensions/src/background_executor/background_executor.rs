@@ -1,24 +1,95 @@
-use std::sync::{
-    atomic::{AtomicBool, AtomicI64, Ordering},
-    Arc,
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicI64, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
 
 use parking_lot::Mutex;
 
-use crate::{Logger, StrOrString};
+use tokio::sync::Notify;
 
-use super::BackgroundJob;
+use crate::{
+    date_time::DateTimeAsMicroseconds, health::HealthReporter, supervisor::Supervisor, Logger,
+    StrOrString,
+};
+
+use super::{
+    background_executor_reader::BackgroundExecutorSupervisedReader, BackgroundJob, TriggerGate,
+    TriggerGateStep, TriggerPolicy,
+};
 
 pub(super) struct BackgroundExecutorInner {
     pub counter: Arc<AtomicI64>,
     pub job: Arc<dyn BackgroundJob + Send + Sync + 'static>,
     pub logger: Arc<dyn Logger + Send + Sync + 'static>,
     pub name: Arc<String>,
+    /// Set if the reader is owned by the supervisor: it lives all the time and
+    /// is woken up instead of being spawned on every trigger.
+    pub wake_up: Option<Arc<Notify>>,
+    pub health: Option<Arc<HealthReporter>>,
+    pub trigger_policy: TriggerPolicy,
+    pub trigger_gate: Mutex<TriggerGate>,
+    pub execution_timeout: Option<Duration>,
+}
+
+impl BackgroundExecutorInner {
+    /// Passes the trigger to the reader - past the debounce or the throttle.
+    fn fire(self: &Arc<Self>) {
+        let prev = self.counter.fetch_add(1, Ordering::SeqCst);
+
+        if prev != 0 {
+            return;
+        }
+
+        if let Some(wake_up) = self.wake_up.as_ref() {
+            wake_up.notify_one();
+            return;
+        }
+
+        tokio::spawn(super::background_executor_reader::background_executor_reader(
+            self.clone(),
+        ));
+    }
+
+    fn apply_gate_step(self: &Arc<Self>, step: TriggerGateStep) {
+        if step.fire {
+            self.fire();
+        }
+
+        if let Some(wait) = step.wait {
+            tokio::spawn(trigger_gate_timer(self.clone(), wait));
+        }
+    }
+}
+
+async fn trigger_gate_timer(inner: Arc<BackgroundExecutorInner>, mut wait: Duration) {
+    loop {
+        tokio::time::sleep(wait).await;
+
+        let step = inner
+            .trigger_gate
+            .lock()
+            .on_timer(&inner.trigger_policy, DateTimeAsMicroseconds::now());
+
+        if step.fire {
+            inner.fire();
+        }
+
+        match step.wait {
+            Some(next_wait) => wait = next_wait,
+            None => break,
+        }
+    }
 }
 
 pub struct BackgroundExecutor {
     counter: Arc<AtomicI64>,
     pending_job: Mutex<Option<Arc<dyn BackgroundJob + Send + Sync + 'static>>>,
+    health: Mutex<Option<Arc<HealthReporter>>>,
+    trigger_policy: Mutex<TriggerPolicy>,
+    execution_timeout: Mutex<Option<Duration>>,
     inner: Mutex<Option<Arc<BackgroundExecutorInner>>>,
     started: AtomicBool,
     name: Arc<String>,
@@ -31,6 +102,9 @@ impl BackgroundExecutor {
         Self {
             counter: Arc::new(AtomicI64::new(0)),
             pending_job: Mutex::new(None),
+            health: Mutex::new(None),
+            trigger_policy: Mutex::new(TriggerPolicy::Immediate),
+            execution_timeout: Mutex::new(None),
             inner: Mutex::new(None),
             started: AtomicBool::new(false),
             name,
@@ -50,7 +124,51 @@ impl BackgroundExecutor {
         *pending_job = Some(job);
     }
 
+    /// Every executed job is a heartbeat, every panicked one is an error. Has to
+    /// be set before the executor is started.
+    pub fn set_health_reporter(&self, reporter: Arc<HealthReporter>) {
+        *self.health.lock() = Some(reporter);
+    }
+
+    /// Debounces or throttles the triggers before they reach the job. Has to be
+    /// set before the executor is started.
+    pub fn set_trigger_policy(&self, policy: TriggerPolicy) {
+        *self.trigger_policy.lock() = policy;
+    }
+
+    /// The job which does not finish in time is dropped, logged and reported to
+    /// the health reporter - the same way as a panicked one, so the trigger it
+    /// was serving is consumed. No timeout by default. Has to be set before the
+    /// executor is started.
+    pub fn set_execution_timeout(&self, timeout: Duration) {
+        *self.execution_timeout.lock() = Some(timeout);
+    }
+
     pub fn start(&self, logger: Arc<dyn Logger + Send + Sync + 'static>) {
+        self.start_inner(logger, None);
+    }
+
+    /// Hands the reader over to the supervisor: it is started together with the
+    /// supervisor and restarted if it dies. The trigger served by the dead reader
+    /// is not consumed - the restarted reader executes the job for it again.
+    pub fn start_supervised(
+        &self,
+        supervisor: &Supervisor,
+        logger: Arc<dyn Logger + Send + Sync + 'static>,
+    ) {
+        let inner = self.start_inner(logger, Some(Arc::new(Notify::new())));
+
+        supervisor.add_child(
+            format!("BackgroundExecutor {}", self.name),
+            Arc::new(BackgroundExecutorSupervisedReader { inner }),
+        );
+    }
+
+    fn start_inner(
+        &self,
+        logger: Arc<dyn Logger + Send + Sync + 'static>,
+        wake_up: Option<Arc<Notify>>,
+    ) -> Arc<BackgroundExecutorInner> {
         let job = self.pending_job.lock().take();
 
         let Some(job) = job else {
@@ -65,10 +183,16 @@ impl BackgroundExecutor {
             job,
             logger,
             name: self.name.clone(),
+            wake_up,
+            health: self.health.lock().clone(),
+            trigger_policy: *self.trigger_policy.lock(),
+            trigger_gate: Mutex::new(TriggerGate::default()),
+            execution_timeout: *self.execution_timeout.lock(),
         });
 
-        *self.inner.lock() = Some(inner);
+        *self.inner.lock() = Some(inner.clone());
         self.started.store(true, Ordering::SeqCst);
+        inner
     }
 
     pub fn trigger(&self) {
@@ -78,18 +202,23 @@ impl BackgroundExecutor {
             panic!("Background executor {} is not started.", self.name);
         }
 
-        let prev = self.counter.fetch_add(1, Ordering::SeqCst);
-        if prev == 0 {
-            let inner = self.inner.lock();
+        let inner = self.inner.lock().clone();
 
-            let Some(inner) = inner.as_ref() else {
-                panic!("Background executor {} is not started.", self.name);
-            };
+        let Some(inner) = inner else {
+            panic!("Background executor {} is not started.", self.name);
+        };
 
-            tokio::spawn(
-                super::background_executor_reader::background_executor_reader(inner.clone()),
-            );
+        if inner.trigger_policy == TriggerPolicy::Immediate {
+            inner.fire();
+            return;
         }
+
+        let step = inner
+            .trigger_gate
+            .lock()
+            .on_trigger(&inner.trigger_policy, DateTimeAsMicroseconds::now());
+
+        inner.apply_gate_step(step);
     }
 }
 
@@ -101,9 +230,10 @@ mod tests {
     use std::time::Duration;
 
     use crate::background_executor::RepeatIteration;
-    use crate::Logger;
+    use crate::supervisor::{Supervisor, SupervisorStrategy};
+    use crate::{AppStates, Logger};
 
-    use super::{BackgroundExecutor, BackgroundJob};
+    use super::{BackgroundExecutor, BackgroundJob, TriggerPolicy};
 
     fn rt() -> tokio::runtime::Runtime {
         tokio::runtime::Builder::new_current_thread()
@@ -331,4 +461,95 @@ mod tests {
             assert_eq!(runs.load(Ordering::SeqCst), 2);
         });
     }
+
+    #[test]
+    fn supervised_reader_serves_every_trigger() {
+        rt().block_on(async {
+            let runs = Arc::new(AtomicUsize::new(0));
+            let executor = Arc::new(BackgroundExecutor::new("test-supervised"));
+            executor.register(Arc::new(CountingJob {
+                runs: runs.clone(),
+                in_flight: Arc::new(AtomicUsize::new(0)),
+            }));
+
+            let supervisor = Supervisor::new("test-supervised", SupervisorStrategy::OneForOne);
+            executor.start_supervised(&supervisor, Arc::new(TestLogger));
+
+            // Triggers arrived before the supervisor is started wait for the reader.
+            executor.trigger();
+            supervisor.start(
+                Arc::new(AppStates::create_initialized()),
+                Arc::new(TestLogger),
+            );
+
+            wait_for(&runs, 1).await;
+
+            for _ in 0..10 {
+                executor.trigger();
+            }
+
+            wait_for(&runs, 11).await;
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            assert_eq!(runs.load(Ordering::SeqCst), 11);
+            assert!(supervisor.get_health().is_healthy());
+        });
+    }
+
+    #[test]
+    fn debounce_runs_the_job_once_per_burst() {
+        rt().block_on(async {
+            let runs = Arc::new(AtomicUsize::new(0));
+            let executor = Arc::new(BackgroundExecutor::new("test-debounce"));
+            executor.register(Arc::new(CountingJob {
+                runs: runs.clone(),
+                in_flight: Arc::new(AtomicUsize::new(0)),
+            }));
+            executor.set_trigger_policy(TriggerPolicy::Debounce {
+                delay: Duration::from_millis(30),
+                max_wait: None,
+            });
+            executor.start(Arc::new(TestLogger));
+
+            for _ in 0..10 {
+                executor.trigger();
+                tokio::time::sleep(Duration::from_millis(2)).await;
+            }
+
+            assert_eq!(runs.load(Ordering::SeqCst), 0);
+
+            wait_for(&runs, 1).await;
+            tokio::time::sleep(Duration::from_millis(60)).await;
+            assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+            executor.trigger();
+            wait_for(&runs, 2).await;
+        });
+    }
+
+    #[test]
+    fn throttle_runs_leading_and_trailing_jobs() {
+        rt().block_on(async {
+            let runs = Arc::new(AtomicUsize::new(0));
+            let executor = Arc::new(BackgroundExecutor::new("test-throttle"));
+            executor.register(Arc::new(CountingJob {
+                runs: runs.clone(),
+                in_flight: Arc::new(AtomicUsize::new(0)),
+            }));
+            executor.set_trigger_policy(TriggerPolicy::Throttle {
+                interval: Duration::from_millis(50),
+            });
+            executor.start(Arc::new(TestLogger));
+
+            for _ in 0..10 {
+                executor.trigger();
+            }
+
+            wait_for(&runs, 1).await;
+
+            // The trailing run for the 9 coalesced triggers.
+            wait_for(&runs, 2).await;
+            tokio::time::sleep(Duration::from_millis(120)).await;
+            assert_eq!(runs.load(Ordering::SeqCst), 2);
+        });
+    }
 }
@@ -1,25 +1,60 @@
 use std::{
+    any::Any,
     panic::AssertUnwindSafe,
     sync::{atomic::Ordering, Arc},
+    time::Duration,
 };
 
 use futures::FutureExt;
 
+use crate::supervisor::SupervisedTask;
+
 use super::{background_executor::BackgroundExecutorInner, RepeatIteration};
 
+/// The reader owned by the supervisor: it waits for the triggers instead of
+/// exiting when the counter is drained.
+pub(super) struct BackgroundExecutorSupervisedReader {
+    pub inner: Arc<BackgroundExecutorInner>,
+}
+
+#[async_trait::async_trait]
+impl SupervisedTask for BackgroundExecutorSupervisedReader {
+    async fn run(&self) {
+        let wake_up = self.inner.wake_up.as_ref().unwrap();
+
+        loop {
+            // The triggers left by the dead reader are served first. A trigger
+            // arriving after the check leaves the permit to `notified`.
+            if self.inner.counter.load(Ordering::SeqCst) > 0 {
+                background_executor_reader(self.inner.clone()).await;
+            }
+
+            wake_up.notified().await;
+        }
+    }
+}
+
 pub async fn background_executor_reader(inner: Arc<BackgroundExecutorInner>) {
     loop {
-        let result = AssertUnwindSafe(inner.job.execute()).catch_unwind().await;
+        let result = execute_job(&inner).await;
+
+        if let Some(health) = inner.health.as_ref() {
+            match &result {
+                Ok(Ok(_)) => health.heartbeat(),
+                Ok(Err(_)) => health.report_error("Job is panicked"),
+                Err(_) => health.report_error("Job is timed out"),
+            }
+        }
 
         match result {
-            Ok(RepeatIteration::Yes) => {
+            Ok(Ok(RepeatIteration::Yes)) => {
                 // The job left the iteration on purpose and asked for another one.
                 // The trigger it was serving is not consumed - we go for a new
                 // iteration without touching the counter.
                 continue;
             }
-            Ok(RepeatIteration::No) => {}
-            Err(_) => {
+            Ok(Ok(RepeatIteration::No)) => {}
+            Ok(Err(_)) => {
                 inner.logger.write_error(
                     format!("BackgroundExecutor {}", inner.name.as_str()),
                     "Job is panicked".to_string(),
@@ -28,6 +63,14 @@ pub async fn background_executor_reader(inner: Arc<BackgroundExecutorInner>) {
                 // A panicked job told us nothing - we consume the trigger, so a
                 // job which panics every time can not spin the reader forever.
             }
+            Err(timeout) => {
+                inner.logger.write_error(
+                    format!("BackgroundExecutor {}", inner.name.as_str()),
+                    format!("Job is timed out after {:?}", timeout),
+                    None,
+                );
+                // The same as with the panic - the trigger is consumed.
+            }
         }
 
         let prev = inner.counter.fetch_sub(1, Ordering::SeqCst);
@@ -36,3 +79,18 @@ pub async fn background_executor_reader(inner: Arc<BackgroundExecutorInner>) {
         }
     }
 }
+
+/// `Err` with the timeout if the job did not make it in time, `Ok(Err)` with the
+/// payload if it panicked.
+async fn execute_job(
+    inner: &BackgroundExecutorInner,
+) -> Result<Result<RepeatIteration, Box<dyn Any + Send>>, Duration> {
+    let future = AssertUnwindSafe(inner.job.execute()).catch_unwind();
+
+    match inner.execution_timeout {
+        Some(timeout) => tokio::time::timeout(timeout, future)
+            .await
+            .map_err(|_| timeout),
+        None => Ok(future.await),
+    }
+}
@@ -0,0 +1,343 @@
+use std::{panic::AssertUnwindSafe, sync::Arc, time::Duration};
+
+use futures::FutureExt;
+use parking_lot::Mutex;
+use tokio::{
+    sync::{mpsc, oneshot},
+    time::Instant,
+};
+
+use crate::{
+    health::HealthReporter, panic_message::get_panic_message, supervisor::Supervisor, Logger,
+    StrOrString,
+};
+
+use super::{
+    BackgroundExecutor, BackgroundJob, BackgroundJobOutcome, BackgroundJobWithResult,
+    RepeatIteration, TriggerPolicy,
+};
+
+struct JobOutcomes<TResult> {
+    /// Callers of `trigger_and_wait` - they are taken by the next run which
+    /// starts.
+    waiters: Vec<oneshot::Sender<BackgroundJobOutcome<TResult>>>,
+    subscribers: Vec<mpsc::UnboundedSender<BackgroundJobOutcome<TResult>>>,
+    execution_timeout: Option<Duration>,
+}
+
+/// The outcome of the run which is in flight. Dropped without being published
+/// means the run was dropped - by the execution timeout if it outlived it, by an
+/// abort or the runtime shutdown otherwise.
+struct RunOutcome<TResult: Clone> {
+    outcomes: Arc<Mutex<JobOutcomes<TResult>>>,
+    waiters: Vec<oneshot::Sender<BackgroundJobOutcome<TResult>>>,
+    started: Instant,
+    published: bool,
+}
+
+impl<TResult: Clone> RunOutcome<TResult> {
+    fn publish(&mut self, outcome: BackgroundJobOutcome<TResult>) {
+        self.published = true;
+
+        for waiter in self.waiters.drain(..) {
+            let _ = waiter.send(outcome.clone());
+        }
+
+        self.outcomes
+            .lock()
+            .subscribers
+            .retain(|subscriber| subscriber.send(outcome.clone()).is_ok());
+    }
+}
+
+impl<TResult: Clone> Drop for RunOutcome<TResult> {
+    fn drop(&mut self) {
+        if self.published {
+            return;
+        }
+
+        // The timeout drops the run only once its deadline is reached - a run
+        // dropped earlier, or with no timeout at all, was cancelled.
+        let outcome = match self.outcomes.lock().execution_timeout {
+            Some(timeout) if self.started.elapsed() >= timeout => {
+                BackgroundJobOutcome::TimedOut(timeout)
+            }
+            _ => BackgroundJobOutcome::Cancelled,
+        };
+
+        self.publish(outcome);
+    }
+}
+
+struct BackgroundJobWithResultAdapter<TResult: Clone + Send + Sync + 'static> {
+    job: Arc<dyn BackgroundJobWithResult<TResult> + Send + Sync + 'static>,
+    outcomes: Arc<Mutex<JobOutcomes<TResult>>>,
+}
+
+#[async_trait::async_trait]
+impl<TResult: Clone + Send + Sync + 'static> BackgroundJob
+    for BackgroundJobWithResultAdapter<TResult>
+{
+    async fn execute(&self) -> RepeatIteration {
+        let waiters = std::mem::take(&mut self.outcomes.lock().waiters);
+
+        let mut run = RunOutcome {
+            outcomes: self.outcomes.clone(),
+            waiters,
+            started: Instant::now(),
+            published: false,
+        };
+
+        match AssertUnwindSafe(self.job.execute()).catch_unwind().await {
+            Ok(result) => run.publish(BackgroundJobOutcome::Done(result)),
+            Err(payload) => {
+                run.publish(BackgroundJobOutcome::Panicked(get_panic_message(
+                    payload.as_ref(),
+                )));
+
+                // The executor logs the panic and reports it to the health
+                // reporter as with any other job.
+                std::panic::resume_unwind(payload);
+            }
+        }
+
+        RepeatIteration::No
+    }
+}
+
+/// [`BackgroundExecutor`] for a job which produces a value: the outcome of every
+/// run - the value, the panic message, the timeout or the cancellation - is published to the
+/// subscribers, and `trigger_and_wait` lets the caller await the outcome of the
+/// run it caused.
+pub struct BackgroundExecutorWithResult<TResult: Clone + Send + Sync + 'static> {
+    executor: BackgroundExecutor,
+    outcomes: Arc<Mutex<JobOutcomes<TResult>>>,
+}
+
+impl<TResult: Clone + Send + Sync + 'static> BackgroundExecutorWithResult<TResult> {
+    pub fn new(name: impl Into<StrOrString<'static>>) -> Self {
+        Self {
+            executor: BackgroundExecutor::new(name),
+            outcomes: Arc::new(Mutex::new(JobOutcomes {
+                waiters: Vec::new(),
+                subscribers: Vec::new(),
+                execution_timeout: None,
+            })),
+        }
+    }
+
+    pub fn register(&self, job: Arc<dyn BackgroundJobWithResult<TResult> + Send + Sync + 'static>) {
+        self.executor
+            .register(Arc::new(BackgroundJobWithResultAdapter {
+                job,
+                outcomes: self.outcomes.clone(),
+            }));
+    }
+
+    pub fn set_health_reporter(&self, reporter: Arc<HealthReporter>) {
+        self.executor.set_health_reporter(reporter);
+    }
+
+    pub fn set_trigger_policy(&self, policy: TriggerPolicy) {
+        self.executor.set_trigger_policy(policy);
+    }
+
+    /// See [`BackgroundExecutor::set_execution_timeout`]. The timed out run is
+    /// published as [`BackgroundJobOutcome::TimedOut`].
+    pub fn set_execution_timeout(&self, timeout: Duration) {
+        self.outcomes.lock().execution_timeout = Some(timeout);
+        self.executor.set_execution_timeout(timeout);
+    }
+
+    /// The receiver gets the outcome of every run finished after the call.
+    /// Dropping the receiver unsubscribes.
+    pub fn subscribe(&self) -> mpsc::UnboundedReceiver<BackgroundJobOutcome<TResult>> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.outcomes.lock().subscribers.push(sender);
+        receiver
+    }
+
+    pub fn start(&self, logger: Arc<dyn Logger + Send + Sync + 'static>) {
+        self.executor.start(logger);
+    }
+
+    pub fn start_supervised(
+        &self,
+        supervisor: &Supervisor,
+        logger: Arc<dyn Logger + Send + Sync + 'static>,
+    ) {
+        self.executor.start_supervised(supervisor, logger);
+    }
+
+    pub fn trigger(&self) {
+        self.executor.trigger();
+    }
+
+    /// Triggers the job and waits for the outcome of the first run which starts
+    /// after the call. With a debounce or a throttle several callers can get the
+    /// outcome of the same run.
+    pub async fn trigger_and_wait(&self) -> BackgroundJobOutcome<TResult> {
+        let (sender, receiver) = oneshot::channel();
+
+        // Registered before the trigger: the run the trigger causes can not miss
+        // the waiter.
+        self.outcomes.lock().waiters.push(sender);
+        self.executor.trigger();
+
+        match receiver.await {
+            Ok(outcome) => outcome,
+            Err(_) => panic!("Background executor is dropped while the run is awaited"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use super::*;
+
+    fn rt() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap()
+    }
+
+    struct TestLogger;
+
+    impl Logger for TestLogger {
+        fn write_info(&self, _: String, _: String, _: Option<HashMap<String, String>>) {}
+        fn write_warning(&self, _: String, _: String, _: Option<HashMap<String, String>>) {}
+        fn write_error(&self, _: String, _: String, _: Option<HashMap<String, String>>) {}
+        fn write_fatal_error(&self, _: String, _: String, _: Option<HashMap<String, String>>) {}
+        fn write_debug_info(&self, _: String, _: String, _: Option<HashMap<String, String>>) {}
+    }
+
+    /// Returns the number of the run; the second run panics, the third one
+    /// hangs.
+    struct NumberedJob {
+        runs: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl BackgroundJobWithResult<usize> for NumberedJob {
+        async fn execute(&self) -> usize {
+            let run = self.runs.fetch_add(1, Ordering::SeqCst) + 1;
+
+            if run == 2 {
+                panic!("Run {} is panicked", run);
+            }
+
+            if run == 3 {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+            }
+
+            run
+        }
+    }
+
+    fn make_executor() -> BackgroundExecutorWithResult<usize> {
+        let executor = BackgroundExecutorWithResult::new("test-with-result");
+        executor.register(Arc::new(NumberedJob {
+            runs: AtomicUsize::new(0),
+        }));
+        executor.set_execution_timeout(Duration::from_millis(30));
+        executor.start(Arc::new(TestLogger));
+        executor
+    }
+
+    #[test]
+    fn caller_gets_the_outcome_of_the_run_it_caused() {
+        rt().block_on(async {
+            let executor = make_executor();
+
+            assert_eq!(
+                executor.trigger_and_wait().await,
+                BackgroundJobOutcome::Done(1)
+            );
+
+            assert_eq!(
+                executor.trigger_and_wait().await,
+                BackgroundJobOutcome::Panicked("Run 2 is panicked".to_string())
+            );
+
+            assert_eq!(
+                executor.trigger_and_wait().await,
+                BackgroundJobOutcome::TimedOut(Duration::from_millis(30))
+            );
+
+            // Neither the panic nor the timeout broke the executor.
+            let outcome = executor.trigger_and_wait().await;
+            assert!(outcome.is_done());
+            assert_eq!(outcome.into_result(), Some(4));
+        });
+    }
+
+    struct HangingJob;
+
+    #[async_trait::async_trait]
+    impl BackgroundJobWithResult<usize> for HangingJob {
+        async fn execute(&self) -> usize {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            0
+        }
+    }
+
+    #[test]
+    fn aborted_run_is_cancelled_not_timed_out() {
+        let runtime = rt();
+
+        let mut outcomes = runtime.block_on(async {
+            let executor = BackgroundExecutorWithResult::new("test-aborted");
+            executor.register(Arc::new(HangingJob));
+            executor.set_execution_timeout(Duration::from_secs(5));
+            executor.start(Arc::new(TestLogger));
+
+            let outcomes = executor.subscribe();
+            executor.trigger();
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            outcomes
+        });
+
+        // The run is dropped together with the runtime, long before its timeout.
+        drop(runtime);
+
+        assert_eq!(outcomes.try_recv(), Ok(BackgroundJobOutcome::Cancelled));
+    }
+
+    #[test]
+    fn subscribers_get_every_outcome() {
+        rt().block_on(async {
+            let executor = make_executor();
+            let mut first = executor.subscribe();
+            let mut second = executor.subscribe();
+
+            executor.trigger();
+            executor.trigger();
+
+            assert_eq!(first.recv().await, Some(BackgroundJobOutcome::Done(1)));
+            assert_eq!(
+                first.recv().await,
+                Some(BackgroundJobOutcome::Panicked(
+                    "Run 2 is panicked".to_string()
+                ))
+            );
+
+            drop(first);
+
+            assert_eq!(second.recv().await, Some(BackgroundJobOutcome::Done(1)));
+            assert!(second.recv().await.is_some());
+
+            executor.trigger();
+            assert_eq!(
+                second.recv().await,
+                Some(BackgroundJobOutcome::TimedOut(Duration::from_millis(30)))
+            );
+            assert_eq!(executor.outcomes.lock().subscribers.len(), 1);
+        });
+    }
+}
@@ -0,0 +1,38 @@
+use std::time::Duration;
+
+/// The job which produces a value on every run. It is executed by the
+/// [`super::BackgroundExecutorWithResult`] - the outcome of every run is
+/// published to the subscribers and to the callers waiting for it.
+#[async_trait::async_trait]
+pub trait BackgroundJobWithResult<TResult>: Send + Sync + 'static
+where
+    TResult: Clone + Send + Sync + 'static,
+{
+    async fn execute(&self) -> TResult;
+}
+
+/// How a single run of the [`BackgroundJobWithResult`] ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BackgroundJobOutcome<TResult> {
+    Done(TResult),
+    /// The job panicked - with the message of the panic payload.
+    Panicked(String),
+    /// The job was dropped by the execution timeout before it finished.
+    TimedOut(Duration),
+    /// The job was dropped before it finished, but not by the timeout - the reader
+    /// was aborted (by the supervisor, for one) or the runtime is shutting down.
+    Cancelled,
+}
+
+impl<TResult> BackgroundJobOutcome<TResult> {
+    pub fn is_done(&self) -> bool {
+        matches!(self, BackgroundJobOutcome::Done(_))
+    }
+
+    pub fn into_result(self) -> Option<TResult> {
+        match self {
+            BackgroundJobOutcome::Done(result) => Some(result),
+            _ => None,
+        }
+    }
+}
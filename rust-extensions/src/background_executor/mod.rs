@@ -1,6 +1,13 @@
 mod background_executor;
 mod background_executor_reader;
+mod background_executor_with_result;
 mod background_job;
+mod background_job_with_result;
+mod trigger_policy;
 
 pub use background_executor::BackgroundExecutor;
+pub use background_executor_with_result::BackgroundExecutorWithResult;
 pub use background_job::{BackgroundJob, RepeatIteration};
+pub use background_job_with_result::{BackgroundJobOutcome, BackgroundJobWithResult};
+pub use trigger_policy::TriggerPolicy;
+pub(crate) use trigger_policy::{TriggerGate, TriggerGateStep};
@@ -0,0 +1,261 @@
+use std::time::Duration;
+
+use crate::date_time::DateTimeAsMicroseconds;
+
+/// How the triggers are turned into the job executions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TriggerPolicy {
+    /// Every trigger is served - the job runs as soon as the reader is free.
+    #[default]
+    Immediate,
+    /// The job runs once `delay` after the last trigger of a burst. With
+    /// `max_wait` set, a burst which never calms down is still served at least
+    /// once per `max_wait` since its first trigger.
+    Debounce {
+        delay: Duration,
+        max_wait: Option<Duration>,
+    },
+    /// The first trigger runs the job at once, the triggers within the next
+    /// `interval` are coalesced into a single trailing run at the end of it.
+    Throttle { interval: Duration },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct TriggerGateStep {
+    /// Pass the trigger to the reader right now.
+    pub fire: bool,
+    /// Come back to [`TriggerGate::on_timer`] after that long. `None` from
+    /// `on_timer` means the timer is done and the gate is idle.
+    pub wait: Option<Duration>,
+}
+
+/// The state of a debounce or a throttle in front of the trigger counter. It is
+/// driven by the triggers and by the single timer it asks for.
+#[derive(Default)]
+pub(crate) struct TriggerGate {
+    first_trigger: Option<DateTimeAsMicroseconds>,
+    last_trigger: Option<DateTimeAsMicroseconds>,
+    timer_alive: bool,
+}
+
+impl TriggerGate {
+    pub fn is_idle(&self) -> bool {
+        !self.timer_alive
+    }
+
+    pub fn on_trigger(
+        &mut self,
+        policy: &TriggerPolicy,
+        now: DateTimeAsMicroseconds,
+    ) -> TriggerGateStep {
+        match policy {
+            TriggerPolicy::Immediate => TriggerGateStep {
+                fire: true,
+                wait: None,
+            },
+            TriggerPolicy::Debounce { delay, .. } => {
+                self.last_trigger = Some(now);
+
+                if self.first_trigger.is_none() {
+                    self.first_trigger = Some(now);
+                }
+
+                self.start_timer(*delay, false)
+            }
+            TriggerPolicy::Throttle { interval } => {
+                if self.timer_alive {
+                    self.last_trigger = Some(now);
+                }
+
+                self.start_timer(*interval, true)
+            }
+        }
+    }
+
+    pub fn on_timer(
+        &mut self,
+        policy: &TriggerPolicy,
+        now: DateTimeAsMicroseconds,
+    ) -> TriggerGateStep {
+        match policy {
+            TriggerPolicy::Immediate => {
+                self.timer_alive = false;
+                TriggerGateStep {
+                    fire: false,
+                    wait: None,
+                }
+            }
+            TriggerPolicy::Debounce { delay, max_wait } => {
+                let (Some(first_trigger), Some(last_trigger)) =
+                    (self.first_trigger, self.last_trigger)
+                else {
+                    self.timer_alive = false;
+                    return TriggerGateStep {
+                        fire: false,
+                        wait: None,
+                    };
+                };
+
+                let mut deadline = last_trigger.add(*delay);
+
+                if let Some(max_wait) = max_wait {
+                    let max_deadline = first_trigger.add(*max_wait);
+
+                    if max_deadline.unix_microseconds < deadline.unix_microseconds {
+                        deadline = max_deadline;
+                    }
+                }
+
+                if now.unix_microseconds < deadline.unix_microseconds {
+                    return TriggerGateStep {
+                        fire: false,
+                        wait: Some(Duration::from_micros(
+                            (deadline.unix_microseconds - now.unix_microseconds) as u64,
+                        )),
+                    };
+                }
+
+                self.first_trigger = None;
+                self.last_trigger = None;
+                self.timer_alive = false;
+
+                TriggerGateStep {
+                    fire: true,
+                    wait: None,
+                }
+            }
+            TriggerPolicy::Throttle { interval } => {
+                // The trailing run opens the next interval - the triggers coming
+                // within it are coalesced again.
+                if self.last_trigger.take().is_some() {
+                    return TriggerGateStep {
+                        fire: true,
+                        wait: Some(*interval),
+                    };
+                }
+
+                self.timer_alive = false;
+
+                TriggerGateStep {
+                    fire: false,
+                    wait: None,
+                }
+            }
+        }
+    }
+
+    /// Forgets the triggers held back. The timer, if alive, finds nothing to fire
+    /// and lets the gate go idle.
+    pub fn cancel(&mut self) {
+        self.first_trigger = None;
+        self.last_trigger = None;
+    }
+
+    fn start_timer(&mut self, wait: Duration, fire: bool) -> TriggerGateStep {
+        if self.timer_alive {
+            return TriggerGateStep {
+                fire: false,
+                wait: None,
+            };
+        }
+
+        self.timer_alive = true;
+
+        TriggerGateStep {
+            fire,
+            wait: Some(wait),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(millis: i64) -> DateTimeAsMicroseconds {
+        DateTimeAsMicroseconds::new(millis * 1000)
+    }
+
+    fn step(fire: bool, wait_ms: Option<u64>) -> TriggerGateStep {
+        TriggerGateStep {
+            fire,
+            wait: wait_ms.map(Duration::from_millis),
+        }
+    }
+
+    #[test]
+    fn debounce_runs_once_after_the_last_trigger() {
+        let policy = TriggerPolicy::Debounce {
+            delay: Duration::from_millis(100),
+            max_wait: None,
+        };
+
+        let mut gate = TriggerGate::default();
+
+        assert_eq!(gate.on_trigger(&policy, at(0)), step(false, Some(100)));
+        assert_eq!(gate.on_trigger(&policy, at(50)), step(false, None));
+        assert_eq!(gate.on_trigger(&policy, at(80)), step(false, None));
+
+        assert_eq!(gate.on_timer(&policy, at(100)), step(false, Some(80)));
+        assert_eq!(gate.on_timer(&policy, at(180)), step(true, None));
+        assert!(gate.is_idle());
+
+        // The next burst starts from scratch.
+        assert_eq!(gate.on_trigger(&policy, at(500)), step(false, Some(100)));
+        assert_eq!(gate.on_timer(&policy, at(600)), step(true, None));
+    }
+
+    #[test]
+    fn debounce_max_wait_caps_an_endless_burst() {
+        let policy = TriggerPolicy::Debounce {
+            delay: Duration::from_millis(100),
+            max_wait: Some(Duration::from_millis(250)),
+        };
+
+        let mut gate = TriggerGate::default();
+
+        gate.on_trigger(&policy, at(0));
+        gate.on_trigger(&policy, at(90));
+        assert_eq!(gate.on_timer(&policy, at(100)), step(false, Some(90)));
+
+        gate.on_trigger(&policy, at(180));
+        assert_eq!(gate.on_timer(&policy, at(190)), step(false, Some(60)));
+        assert_eq!(gate.on_timer(&policy, at(250)), step(true, None));
+    }
+
+    #[test]
+    fn throttle_runs_at_once_and_then_once_per_interval() {
+        let policy = TriggerPolicy::Throttle {
+            interval: Duration::from_millis(100),
+        };
+
+        let mut gate = TriggerGate::default();
+
+        assert_eq!(gate.on_trigger(&policy, at(0)), step(true, Some(100)));
+        assert_eq!(gate.on_trigger(&policy, at(10)), step(false, None));
+        assert_eq!(gate.on_trigger(&policy, at(20)), step(false, None));
+
+        // The trailing run for the coalesced triggers.
+        assert_eq!(gate.on_timer(&policy, at(100)), step(true, Some(100)));
+
+        // Nothing came within the next interval - the gate goes idle.
+        assert_eq!(gate.on_timer(&policy, at(200)), step(false, None));
+        assert!(gate.is_idle());
+
+        assert_eq!(gate.on_trigger(&policy, at(210)), step(true, Some(100)));
+    }
+
+    #[test]
+    fn immediate_passes_every_trigger() {
+        let mut gate = TriggerGate::default();
+
+        for millis in 0..3 {
+            assert_eq!(
+                gate.on_trigger(&TriggerPolicy::Immediate, at(millis)),
+                step(true, None)
+            );
+        }
+
+        assert!(gate.is_idle());
+    }
+}
@@ -5,52 +5,97 @@ use std::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
+    time::Duration,
 };
 
 use parking_lot::Mutex;
 
-use crate::{Logger, StrOrString};
+use crate::{
+    background_executor::{TriggerGate, TriggerGateStep, TriggerPolicy},
+    date_time::DateTimeAsMicroseconds,
+    health::HealthReporter,
+    Logger, StrOrString,
+};
 
-use super::BackgroundJobWithMultiThreads;
+use super::{threads_state::ThreadsState, BackgroundJobWithMultiThreads, BackgroundThreadStats};
 
 pub(super) struct BackgroundExecutorWithMultiThreadsInner<TThreadId>
 where
     TThreadId: Hash + Eq + Clone + Send + Sync + 'static,
 {
-    /// Amount of not served triggers per thread id. A thread id is present here
-    /// only while its reader is alive - the reader which drains the counter
-    /// removes the thread id and exits.
-    pub threads: Mutex<HashMap<TThreadId, i64>>,
+    pub threads: Arc<Mutex<ThreadsState<TThreadId>>>,
     pub job: Arc<dyn BackgroundJobWithMultiThreads<TThreadId> + Send + Sync + 'static>,
     pub logger: Arc<dyn Logger + Send + Sync + 'static>,
     pub name: Arc<String>,
+    pub health: Option<Arc<HealthReporter>>,
+    pub trigger_policy: TriggerPolicy,
+    /// A gate per thread id - present only while its timer is alive.
+    pub trigger_gates: Mutex<HashMap<TThreadId, TriggerGate>>,
 }
 
 impl<TThreadId> BackgroundExecutorWithMultiThreadsInner<TThreadId>
 where
     TThreadId: Hash + Eq + Clone + Send + Sync + 'static,
 {
-    /// Consumes the trigger which has just been served.
-    ///
-    /// Returns `true` when the thread is drained - the thread id is removed and
-    /// the reader must exit. The next trigger of this thread id spawns a fresh
-    /// reader.
-    pub fn consume_trigger(&self, thread_id: &TThreadId) -> bool {
-        let mut threads = self.threads.lock();
-
-        let Some(counter) = threads.get_mut(thread_id) else {
-            // Can not happen - the thread id is removed by its own reader only.
-            return true;
-        };
+    /// Passes the trigger of the thread id to its reader - past the debounce or
+    /// the throttle.
+    fn fire(self: &Arc<Self>, thread_id: TThreadId) {
+        let spawn_reader = self.threads.lock().add_trigger(thread_id.clone());
+
+        if spawn_reader {
+            self.spawn_reader(thread_id);
+        }
+    }
 
-        *counter -= 1;
+    pub fn spawn_reader(self: &Arc<Self>, thread_id: TThreadId) {
+        tokio::spawn(
+            super::background_executor_with_multi_threads_reader::background_executor_with_multi_threads_reader(
+                self.clone(), thread_id,
+            ),
+        );
+    }
 
-        if *counter > 0 {
-            return false;
+    fn apply_gate_step(self: &Arc<Self>, thread_id: TThreadId, step: TriggerGateStep) {
+        if step.fire {
+            self.fire(thread_id.clone());
         }
 
-        threads.remove(thread_id);
-        true
+        if let Some(wait) = step.wait {
+            tokio::spawn(trigger_gate_timer(self.clone(), thread_id, wait));
+        }
+    }
+}
+
+async fn trigger_gate_timer<TThreadId>(
+    inner: Arc<BackgroundExecutorWithMultiThreadsInner<TThreadId>>,
+    thread_id: TThreadId,
+    mut wait: Duration,
+) where
+    TThreadId: Hash + Eq + Clone + Send + Sync + 'static,
+{
+    loop {
+        tokio::time::sleep(wait).await;
+
+        let step = {
+            let mut gates = inner.trigger_gates.lock();
+            let gate = gates.entry(thread_id.clone()).or_default();
+            let step = gate.on_timer(&inner.trigger_policy, DateTimeAsMicroseconds::now());
+
+            if gate.is_idle() {
+                gates.remove(&thread_id);
+            }
+
+            step
+        };
+
+        if step.fire {
+            inner.fire(thread_id.clone());
+        }
+
+        match step.wait {
+            Some(next_wait) => wait = next_wait,
+            None => break,
+        }
     }
 }
 
@@ -62,11 +107,19 @@ where
 /// in parallel. A reader is spawned by the trigger which created the thread and
 /// it lives only while its thread has not served triggers - as soon as they are
 /// drained the thread id is removed and the reader task is gone.
+///
+/// With `set_max_parallel` the amount of the readers alive at once is capped:
+/// the thread ids beyond the cap wait for a slot, and a reader which still has
+/// triggers to serve gives its slot away after every iteration if anybody is
+/// waiting - so a busy thread id can not starve the rest of them.
 pub struct BackgroundExecutorWithMultiThreads<TThreadId>
 where
     TThreadId: Hash + Eq + Clone + Send + Sync + 'static,
 {
     pending_job: Mutex<Option<Arc<dyn BackgroundJobWithMultiThreads<TThreadId> + Send + Sync>>>,
+    health: Mutex<Option<Arc<HealthReporter>>>,
+    trigger_policy: Mutex<TriggerPolicy>,
+    threads: Arc<Mutex<ThreadsState<TThreadId>>>,
     inner: Mutex<Option<Arc<BackgroundExecutorWithMultiThreadsInner<TThreadId>>>>,
     started: AtomicBool,
     name: Arc<String>,
@@ -81,6 +134,9 @@ where
 
         Self {
             pending_job: Mutex::new(None),
+            health: Mutex::new(None),
+            trigger_policy: Mutex::new(TriggerPolicy::Immediate),
+            threads: Arc::new(Mutex::new(ThreadsState::new())),
             inner: Mutex::new(None),
             started: AtomicBool::new(false),
             name,
@@ -103,6 +159,19 @@ where
         *pending_job = Some(job);
     }
 
+    /// One reporter for all the thread ids: every executed job is a heartbeat,
+    /// every panicked one is an error. Has to be set before the executor is
+    /// started.
+    pub fn set_health_reporter(&self, reporter: Arc<HealthReporter>) {
+        *self.health.lock() = Some(reporter);
+    }
+
+    /// Debounces or throttles the triggers before they reach the job - every
+    /// thread id on its own. Has to be set before the executor is started.
+    pub fn set_trigger_policy(&self, policy: TriggerPolicy) {
+        *self.trigger_policy.lock() = policy;
+    }
+
     pub fn start(&self, logger: Arc<dyn Logger + Send + Sync + 'static>) {
         let job = self.pending_job.lock().take();
 
@@ -114,10 +183,13 @@ where
         };
 
         let inner = Arc::new(BackgroundExecutorWithMultiThreadsInner {
-            threads: Mutex::new(HashMap::new()),
+            threads: self.threads.clone(),
             job,
             logger,
             name: self.name.clone(),
+            health: self.health.lock().clone(),
+            trigger_policy: *self.trigger_policy.lock(),
+            trigger_gates: Mutex::new(HashMap::new()),
         });
 
         *self.inner.lock() = Some(inner);
@@ -127,8 +199,9 @@ where
     /// Signals that there may be work to do within the given thread id.
     ///
     /// The very first trigger of a thread id spawns the reader of that thread
-    /// id; while the reader is alive the trigger only bumps the counter of the
-    /// thread.
+    /// id - or puts the thread id in the queue for a slot if `max_parallel`
+    /// readers are alive already; while the reader is alive the trigger only
+    /// bumps the counter of the thread.
     pub fn trigger(&self, thread_id: TThreadId) {
         // Checked before touching the counter: a leftover increment from a
         // not-started panic would prevent the reader from ever being spawned.
@@ -142,32 +215,96 @@ where
             panic!("Background executor {} is not started.", self.name);
         };
 
-        let mut threads = inner.threads.lock();
+        if inner.trigger_policy == TriggerPolicy::Immediate {
+            inner.fire(thread_id);
+            return;
+        }
 
-        if let Some(counter) = threads.get_mut(&thread_id) {
-            // The reader of this thread id is alive - it will pick the trigger up.
-            *counter += 1;
+        let step = inner
+            .trigger_gates
+            .lock()
+            .entry(thread_id.clone())
+            .or_default()
+            .on_trigger(&inner.trigger_policy, DateTimeAsMicroseconds::now());
+
+        inner.apply_gate_step(thread_id, step);
+    }
+
+    /// Caps the amount of the thread ids served at once. Can be changed at any
+    /// time - the thread ids above a lowered cap finish their current iteration
+    /// first.
+    pub fn set_max_parallel(&self, max_parallel: usize) {
+        if max_parallel == 0 {
+            panic!(
+                "Max parallel of background executor {} must be greater than 0",
+                self.name
+            );
+        }
+
+        let to_spawn = self.threads.lock().set_max_parallel(max_parallel);
+
+        if to_spawn.is_empty() {
             return;
         }
 
-        threads.insert(thread_id.clone(), 1);
-        drop(threads);
+        // Nothing is waiting for a slot before the executor is started.
+        let inner = self.inner.lock().clone();
 
-        tokio::spawn(
-            super::background_executor_with_multi_threads_reader::background_executor_with_multi_threads_reader(
-                inner, thread_id,
-            ),
-        );
+        if let Some(inner) = inner {
+            for thread_id in to_spawn {
+                inner.spawn_reader(thread_id);
+            }
+        }
     }
 
-    /// Amount of thread ids which have a reader alive right now.
-    pub fn get_working_threads_amount(&self) -> usize {
+    /// The waiting thread id with the higher priority gets the free slot first.
+    /// The default priority is 0. Makes sense only together with
+    /// `set_max_parallel` - without the cap nobody waits.
+    pub fn set_thread_priority(&self, thread_id: TThreadId, priority: i32) {
+        self.threads.lock().set_priority(thread_id, priority);
+    }
+
+    /// Drops the triggers of the thread id which are not served yet - including
+    /// the one held by the debounce or the throttle. The iteration which is
+    /// running right now is not interrupted. Returns the amount of the dropped
+    /// counted triggers.
+    pub fn cancel_pending(&self, thread_id: &TThreadId) -> i64 {
         let inner = self.inner.lock().clone();
 
-        match inner {
-            Some(inner) => inner.threads.lock().len(),
-            None => 0,
+        if let Some(inner) = inner {
+            if let Some(gate) = inner.trigger_gates.lock().get_mut(thread_id) {
+                gate.cancel();
+            }
         }
+
+        self.threads.lock().cancel(thread_id)
+    }
+
+    /// Cancels the pending triggers of the thread id and drops its priority and
+    /// statistics - for the thread ids which are gone for good.
+    pub fn forget_thread(&self, thread_id: &TThreadId) -> i64 {
+        let cancelled = self.cancel_pending(thread_id);
+        self.threads.lock().forget(thread_id);
+        cancelled
+    }
+
+    /// `None` if the thread id was never triggered or is forgotten.
+    pub fn get_thread_stats(&self, thread_id: &TThreadId) -> Option<BackgroundThreadStats> {
+        self.threads.lock().get_stats(thread_id)
+    }
+
+    pub fn get_threads_stats(&self) -> HashMap<TThreadId, BackgroundThreadStats> {
+        self.threads.lock().get_all_stats()
+    }
+
+    /// Amount of thread ids which have a reader alive right now.
+    pub fn get_working_threads_amount(&self) -> usize {
+        self.threads.lock().get_running_amount()
+    }
+
+    /// Amount of thread ids which have triggers but wait for a free slot.
+    pub fn get_waiting_threads_amount(&self) -> usize {
+        self.threads.lock().get_waiting_amount()
     }
 }
 
@@ -180,7 +317,7 @@ mod tests {
 
     use parking_lot::Mutex;
 
-    use crate::background_executor::RepeatIteration;
+    use crate::background_executor::{RepeatIteration, TriggerPolicy};
     use crate::Logger;
 
     use super::{BackgroundExecutorWithMultiThreads, BackgroundJobWithMultiThreads};
@@ -537,4 +674,135 @@ mod tests {
             assert_eq!(state.runs_of(1), 2);
         });
     }
+
+    #[test]
+    fn debounce_is_applied_per_thread_id() {
+        rt().block_on(async {
+            let state = Arc::new(TestState::default());
+            let executor = Arc::new(BackgroundExecutorWithMultiThreads::new("test-debounce"));
+            executor.register(Arc::new(CountingJob {
+                state: state.clone(),
+            }));
+            executor.set_trigger_policy(TriggerPolicy::Debounce {
+                delay: Duration::from_millis(30),
+                max_wait: None,
+            });
+            executor.start(Arc::new(TestLogger));
+
+            for _ in 0..5 {
+                executor.trigger(1);
+                executor.trigger(2);
+            }
+
+            executor.trigger(3);
+
+            wait_for(&state, 3).await;
+            wait_until_no_working_threads(&executor).await;
+            tokio::time::sleep(Duration::from_millis(60)).await;
+
+            assert_eq!(state.runs_of(1), 1);
+            assert_eq!(state.runs_of(2), 1);
+            assert_eq!(state.runs_of(3), 1);
+
+            let inner = executor.inner.lock().clone().unwrap();
+            assert!(inner.trigger_gates.lock().is_empty());
+        });
+    }
+
+    #[test]
+    fn max_parallel_caps_the_readers_and_serves_everybody() {
+        rt().block_on(async {
+            let state = Arc::new(TestState::default());
+            let executor = Arc::new(BackgroundExecutorWithMultiThreads::new("test-max-parallel"));
+            executor.register(Arc::new(CountingJob {
+                state: state.clone(),
+            }));
+            executor.set_max_parallel(2);
+            executor.start(Arc::new(TestLogger));
+
+            const THREADS: u64 = 6;
+            const PER_THREAD: usize = 5;
+
+            for _ in 0..PER_THREAD {
+                for thread_id in 0..THREADS {
+                    executor.trigger(thread_id);
+                }
+            }
+
+            assert_eq!(executor.get_working_threads_amount(), 2);
+            assert_eq!(executor.get_waiting_threads_amount(), 4);
+
+            let expected = THREADS as usize * PER_THREAD;
+            wait_for(&state, expected).await;
+            wait_until_no_working_threads(&executor).await;
+
+            assert_eq!(state.max_parallel.load(Ordering::SeqCst), 2);
+            assert!(!state.same_thread_in_parallel.load(Ordering::SeqCst));
+
+            for thread_id in 0..THREADS {
+                assert_eq!(state.runs_of(thread_id), PER_THREAD);
+            }
+        });
+    }
+
+    #[test]
+    fn cancel_pending_drops_the_waiting_triggers() {
+        rt().block_on(async {
+            let state = Arc::new(TestState::default());
+            let executor = Arc::new(BackgroundExecutorWithMultiThreads::new("test-cancel"));
+            executor.register(Arc::new(CountingJob {
+                state: state.clone(),
+            }));
+            executor.set_max_parallel(1);
+            executor.start(Arc::new(TestLogger));
+
+            for _ in 0..3 {
+                executor.trigger(1);
+                executor.trigger(2);
+            }
+
+            assert_eq!(executor.cancel_pending(&2), 3);
+
+            wait_until_no_working_threads(&executor).await;
+            tokio::time::sleep(Duration::from_millis(20)).await;
+
+            assert_eq!(state.runs_of(1), 3);
+            assert_eq!(state.runs_of(2), 0);
+        });
+    }
+
+    #[test]
+    fn stats_are_collected_per_thread_id() {
+        rt().block_on(async {
+            let state = Arc::new(TestState::default());
+            let executor = Arc::new(BackgroundExecutorWithMultiThreads::new("test-stats"));
+            executor.register(Arc::new(PanickingJob {
+                state: state.clone(),
+            }));
+            executor.start(Arc::new(TestLogger));
+
+            executor.trigger(7);
+            executor.trigger(7);
+
+            wait_for(&state, 2).await;
+            wait_until_no_working_threads(&executor).await;
+
+            let stats = executor.get_thread_stats(&7).unwrap();
+            assert_eq!(stats.runs, 2);
+            assert_eq!(stats.panics, 2);
+            assert_eq!(stats.pending_triggers, 0);
+            assert!(!stats.is_running);
+            assert!(stats.last_duration.unwrap() >= Duration::from_millis(1));
+            assert_eq!(
+                stats.last_panic_message.as_deref(),
+                Some("Job of thread 7 is panicked")
+            );
+
+            assert!(executor.get_thread_stats(&8).is_none());
+            assert_eq!(executor.get_threads_stats().len(), 1);
+
+            executor.forget_thread(&7);
+            assert!(executor.get_thread_stats(&7).is_none());
+        });
+    }
 }
@@ -1,14 +1,21 @@
-use std::{hash::Hash, panic::AssertUnwindSafe, sync::Arc};
+use std::{hash::Hash, panic::AssertUnwindSafe, sync::Arc, time::Instant};
 
 use futures::FutureExt;
 
-use crate::background_executor::RepeatIteration;
+use crate::{
+    background_executor::RepeatIteration, date_time::DateTimeAsMicroseconds,
+    panic_message::get_panic_message,
+};
 
-use super::background_executor_with_multi_threads::BackgroundExecutorWithMultiThreadsInner;
+use super::{
+    background_executor_with_multi_threads::BackgroundExecutorWithMultiThreadsInner,
+    threads_state::IterationEnd,
+};
 
 /// The reader of a single thread id. It is spawned by the trigger which created
-/// the thread and it is the only one who removes the thread - so at any moment
-/// there is at most one reader per thread id alive.
+/// the thread (or by the reader which handed its slot over) and it is the only
+/// one who removes the thread - so at any moment there is at most one reader per
+/// thread id alive.
 pub async fn background_executor_with_multi_threads_reader<TThreadId>(
     inner: Arc<BackgroundExecutorWithMultiThreadsInner<TThreadId>>,
     thread_id: TThreadId,
@@ -16,19 +23,31 @@ pub async fn background_executor_with_multi_threads_reader<TThreadId>(
     TThreadId: Hash + Eq + Clone + Send + Sync + 'static,
 {
     loop {
+        let started = DateTimeAsMicroseconds::now();
+        let started_instant = Instant::now();
+
         let result = AssertUnwindSafe(inner.job.execute(&thread_id))
             .catch_unwind()
             .await;
 
-        match result {
+        let duration = started_instant.elapsed();
+
+        if let Some(health) = inner.health.as_ref() {
+            match &result {
+                Ok(_) => health.heartbeat(),
+                Err(_) => health.report_error("Job is panicked"),
+            }
+        }
+
+        let (consumed, panic_message) = match result {
             Ok(RepeatIteration::Yes) => {
                 // The job left the iteration on purpose and asked for another one.
                 // The trigger it was serving is not consumed - we go for a new
                 // iteration without touching the counter of the thread.
-                continue;
+                (false, None)
             }
-            Ok(RepeatIteration::No) => {}
-            Err(_) => {
+            Ok(RepeatIteration::No) => (true, None),
+            Err(payload) => {
                 inner.logger.write_error(
                     format!("BackgroundExecutorWithMultiThreads {}", inner.name.as_str()),
                     "Job is panicked".to_string(),
@@ -36,11 +55,27 @@ pub async fn background_executor_with_multi_threads_reader<TThreadId>(
                 );
                 // A panicked job told us nothing - we consume the trigger, so a
                 // job which panics every time can not spin the reader forever.
+                (true, Some(get_panic_message(payload.as_ref())))
             }
-        }
+        };
+
+        let end = inner.threads.lock().finish_iteration(
+            &thread_id,
+            consumed,
+            started,
+            duration,
+            panic_message,
+        );
 
-        if inner.consume_trigger(&thread_id) {
-            break;
+        match end {
+            IterationEnd::Continue => {}
+            IterationEnd::Exit { hand_over } => {
+                if let Some(next_thread_id) = hand_over {
+                    inner.spawn_reader(next_thread_id);
+                }
+
+                break;
+            }
         }
     }
 }
@@ -0,0 +1,21 @@
+use std::time::Duration;
+
+use crate::date_time::DateTimeAsMicroseconds;
+
+/// What is known about a single thread id of the
+/// [`super::BackgroundExecutorWithMultiThreads`].
+#[derive(Debug, Clone, Default)]
+pub struct BackgroundThreadStats {
+    pub priority: i32,
+    /// Triggers which are not served yet - including the one being served.
+    pub pending_triggers: i64,
+    /// `true` while the reader of the thread id is alive, `false` while the thread
+    /// id has triggers but waits for a free slot.
+    pub is_running: bool,
+    pub runs: u64,
+    pub panics: u64,
+    pub last_run_started: Option<DateTimeAsMicroseconds>,
+    pub last_duration: Option<Duration>,
+    pub last_panic_at: Option<DateTimeAsMicroseconds>,
+    pub last_panic_message: Option<String>,
+}
@@ -1,8 +1,11 @@
 mod background_executor_with_multi_threads;
 mod background_executor_with_multi_threads_reader;
 mod background_job_with_multi_threads;
+mod background_thread_stats;
+mod threads_state;
 
 pub use background_executor_with_multi_threads::BackgroundExecutorWithMultiThreads;
 pub use background_job_with_multi_threads::BackgroundJobWithMultiThreads;
+pub use background_thread_stats::BackgroundThreadStats;
 
-pub use crate::background_executor::RepeatIteration;
+pub use crate::background_executor::{RepeatIteration, TriggerPolicy};
@@ -0,0 +1,401 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::Hash,
+    time::Duration,
+};
+
+use crate::date_time::DateTimeAsMicroseconds;
+
+use super::BackgroundThreadStats;
+
+pub(super) enum IterationEnd<TThreadId> {
+    /// The reader goes for the next iteration of its thread id.
+    Continue,
+    /// The reader exits. If the slot it had is handed over, the reader of the
+    /// given thread id has to be spawned.
+    Exit { hand_over: Option<TThreadId> },
+}
+
+/// Which thread ids have triggers to be served, which of them have the reader
+/// alive and which of them wait for a free slot.
+pub(super) struct ThreadsState<TThreadId>
+where
+    TThreadId: Hash + Eq + Clone,
+{
+    /// Amount of not served triggers per thread id. A thread id is present here
+    /// only while it has a reader alive or waits for a slot - the reader which
+    /// drains the counter removes the thread id and exits.
+    counters: HashMap<TThreadId, i64>,
+    waiting: VecDeque<TThreadId>,
+    running: usize,
+    max_parallel: Option<usize>,
+    /// Priority and run statistics - they outlive the readers and are kept until
+    /// the thread id is forgotten.
+    records: HashMap<TThreadId, BackgroundThreadStats>,
+}
+
+impl<TThreadId> ThreadsState<TThreadId>
+where
+    TThreadId: Hash + Eq + Clone,
+{
+    pub fn new() -> Self {
+        Self {
+            counters: HashMap::new(),
+            waiting: VecDeque::new(),
+            running: 0,
+            max_parallel: None,
+            records: HashMap::new(),
+        }
+    }
+
+    pub fn get_running_amount(&self) -> usize {
+        self.running
+    }
+
+    pub fn get_waiting_amount(&self) -> usize {
+        self.waiting.len()
+    }
+
+    /// Returns the waiting thread ids which got a slot thanks to the new limit -
+    /// their readers have to be spawned.
+    pub fn set_max_parallel(&mut self, max_parallel: usize) -> Vec<TThreadId> {
+        self.max_parallel = Some(max_parallel);
+
+        let mut result = Vec::new();
+
+        while self.has_free_slot() {
+            let Some(thread_id) = self.take_next_waiting() else {
+                break;
+            };
+
+            self.running += 1;
+            result.push(thread_id);
+        }
+
+        result
+    }
+
+    pub fn set_priority(&mut self, thread_id: TThreadId, priority: i32) {
+        self.records.entry(thread_id).or_default().priority = priority;
+    }
+
+    /// Returns `true` if the reader of the thread id has to be spawned.
+    pub fn add_trigger(&mut self, thread_id: TThreadId) -> bool {
+        if let Some(counter) = self.counters.get_mut(&thread_id) {
+            // The thread id is served or waits for a slot - the trigger is picked
+            // up anyway.
+            *counter += 1;
+            return false;
+        }
+
+        self.counters.insert(thread_id.clone(), 1);
+
+        if self.has_free_slot() {
+            self.running += 1;
+            return true;
+        }
+
+        self.waiting.push_back(thread_id);
+        false
+    }
+
+    /// Called by the reader after every iteration. `consumed` is `false` if the
+    /// job asked for the repeat - the counter stays as it is.
+    pub fn finish_iteration(
+        &mut self,
+        thread_id: &TThreadId,
+        consumed: bool,
+        started: DateTimeAsMicroseconds,
+        duration: Duration,
+        panic_message: Option<String>,
+    ) -> IterationEnd<TThreadId> {
+        self.record_run(thread_id, started, duration, panic_message);
+
+        if consumed {
+            let drained = match self.counters.get_mut(thread_id) {
+                Some(counter) => {
+                    *counter -= 1;
+                    *counter <= 0
+                }
+                // Can not happen - the thread id is removed by its own reader only.
+                None => true,
+            };
+
+            if drained {
+                self.counters.remove(thread_id);
+                self.running -= 1;
+                return self.hand_over_slot(thread_id);
+            }
+        }
+
+        // There is more to do, but the other thread ids are waiting - the slot
+        // goes to the next of them, this thread id gets back to the queue.
+        if !self.waiting.is_empty() || !self.is_within_limit() {
+            self.waiting.push_back(thread_id.clone());
+            self.running -= 1;
+            return self.hand_over_slot(thread_id);
+        }
+
+        IterationEnd::Continue
+    }
+
+    /// Drops the not served triggers of the thread id. The trigger which is being
+    /// served right now stays. Returns the amount of the dropped triggers.
+    pub fn cancel(&mut self, thread_id: &TThreadId) -> i64 {
+        let Some(counter) = self.counters.get_mut(thread_id) else {
+            return 0;
+        };
+
+        if let Some(index) = self.waiting.iter().position(|itm| itm == thread_id) {
+            self.waiting.remove(index);
+            return self.counters.remove(thread_id).unwrap_or(0);
+        }
+
+        let cancelled = *counter - 1;
+        *counter = 1;
+        cancelled
+    }
+
+    pub fn forget(&mut self, thread_id: &TThreadId) -> i64 {
+        self.records.remove(thread_id);
+        self.cancel(thread_id)
+    }
+
+    pub fn get_stats(&self, thread_id: &TThreadId) -> Option<BackgroundThreadStats> {
+        let counter = self.counters.get(thread_id);
+        let record = self.records.get(thread_id);
+
+        if counter.is_none() && record.is_none() {
+            return None;
+        }
+
+        let mut result = record.cloned().unwrap_or_default();
+        result.pending_triggers = counter.copied().unwrap_or(0);
+        result.is_running = counter.is_some() && !self.waiting.contains(thread_id);
+        Some(result)
+    }
+
+    pub fn get_all_stats(&self) -> HashMap<TThreadId, BackgroundThreadStats> {
+        self.counters
+            .keys()
+            .chain(self.records.keys())
+            .filter_map(|thread_id| {
+                let stats = self.get_stats(thread_id)?;
+                Some((thread_id.clone(), stats))
+            })
+            .collect()
+    }
+
+    fn record_run(
+        &mut self,
+        thread_id: &TThreadId,
+        started: DateTimeAsMicroseconds,
+        duration: Duration,
+        panic_message: Option<String>,
+    ) {
+        let record = self.records.entry(thread_id.clone()).or_default();
+
+        record.runs += 1;
+        record.last_run_started = Some(started);
+        record.last_duration = Some(duration);
+
+        if let Some(panic_message) = panic_message {
+            record.panics += 1;
+            record.last_panic_at = Some(started.add(duration));
+            record.last_panic_message = Some(panic_message);
+        }
+    }
+
+    /// The slot just released goes to the waiting thread id with the highest
+    /// priority - the one which waits the longest among the equal ones.
+    fn hand_over_slot(&mut self, thread_id: &TThreadId) -> IterationEnd<TThreadId> {
+        if !self.has_free_slot() {
+            return IterationEnd::Exit { hand_over: None };
+        }
+
+        let Some(next) = self.take_next_waiting() else {
+            return IterationEnd::Exit { hand_over: None };
+        };
+
+        self.running += 1;
+
+        if &next == thread_id {
+            return IterationEnd::Continue;
+        }
+
+        IterationEnd::Exit {
+            hand_over: Some(next),
+        }
+    }
+
+    fn take_next_waiting(&mut self) -> Option<TThreadId> {
+        let mut best: Option<(usize, i32)> = None;
+
+        for (index, thread_id) in self.waiting.iter().enumerate() {
+            let priority = self.get_priority(thread_id);
+
+            match best {
+                Some((_, best_priority)) if best_priority >= priority => {}
+                _ => best = Some((index, priority)),
+            }
+        }
+
+        let (index, _) = best?;
+        self.waiting.remove(index)
+    }
+
+    fn get_priority(&self, thread_id: &TThreadId) -> i32 {
+        self.records
+            .get(thread_id)
+            .map(|record| record.priority)
+            .unwrap_or(0)
+    }
+
+    fn has_free_slot(&self) -> bool {
+        match self.max_parallel {
+            Some(max_parallel) => self.running < max_parallel,
+            None => true,
+        }
+    }
+
+    fn is_within_limit(&self) -> bool {
+        match self.max_parallel {
+            Some(max_parallel) => self.running <= max_parallel,
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finish(state: &mut ThreadsState<u64>, thread_id: u64) -> IterationEnd<u64> {
+        state.finish_iteration(
+            &thread_id,
+            true,
+            DateTimeAsMicroseconds::new(0),
+            Duration::from_millis(1),
+            None,
+        )
+    }
+
+    fn assert_exit(end: IterationEnd<u64>, expected: Option<u64>) {
+        match end {
+            IterationEnd::Continue => panic!("Expected exit, got continue"),
+            IterationEnd::Exit { hand_over } => assert_eq!(hand_over, expected),
+        }
+    }
+
+    fn assert_continue(end: IterationEnd<u64>) {
+        if let IterationEnd::Exit { hand_over } = end {
+            panic!("Expected continue, got exit with hand over {:?}", hand_over);
+        }
+    }
+
+    #[test]
+    fn the_slot_goes_to_the_highest_priority_then_the_oldest() {
+        let mut state = ThreadsState::new();
+        state.set_max_parallel(1);
+        state.set_priority(3, 10);
+
+        assert!(state.add_trigger(1));
+        assert!(!state.add_trigger(2));
+        assert!(!state.add_trigger(3));
+        assert!(!state.add_trigger(4));
+        assert_eq!(state.get_waiting_amount(), 3);
+
+        assert_exit(finish(&mut state, 1), Some(3));
+        assert_exit(finish(&mut state, 3), Some(2));
+        assert_exit(finish(&mut state, 2), Some(4));
+        assert_exit(finish(&mut state, 4), None);
+        assert_eq!(state.get_running_amount(), 0);
+    }
+
+    #[test]
+    fn busy_thread_id_gives_the_slot_away_between_triggers() {
+        let mut state = ThreadsState::new();
+        state.set_max_parallel(1);
+
+        state.add_trigger(1);
+        state.add_trigger(1);
+        state.add_trigger(1);
+
+        // Nobody is waiting - the reader keeps its slot.
+        assert_continue(finish(&mut state, 1));
+
+        state.add_trigger(2);
+        assert_exit(finish(&mut state, 1), Some(2));
+        assert_exit(finish(&mut state, 2), Some(1));
+        assert_exit(finish(&mut state, 1), None);
+    }
+
+    #[test]
+    fn raising_the_limit_starts_the_waiting_thread_ids() {
+        let mut state = ThreadsState::new();
+        state.set_max_parallel(1);
+
+        state.add_trigger(1);
+        state.add_trigger(2);
+        state.add_trigger(3);
+
+        assert_eq!(state.set_max_parallel(2), vec![2]);
+        assert_eq!(state.get_running_amount(), 2);
+        assert_eq!(state.get_waiting_amount(), 1);
+    }
+
+    #[test]
+    fn cancel_drops_the_not_served_triggers() {
+        let mut state = ThreadsState::new();
+        state.set_max_parallel(1);
+
+        for _ in 0..3 {
+            state.add_trigger(1);
+            state.add_trigger(2);
+        }
+
+        // The serving trigger of the running thread id stays.
+        assert_eq!(state.cancel(&1), 2);
+        // The waiting thread id is gone altogether.
+        assert_eq!(state.cancel(&2), 3);
+        assert_eq!(state.cancel(&5), 0);
+
+        assert_exit(finish(&mut state, 1), None);
+        assert_eq!(state.get_running_amount(), 0);
+        assert_eq!(state.get_waiting_amount(), 0);
+    }
+
+    #[test]
+    fn stats_are_kept_after_the_thread_id_is_drained() {
+        let mut state = ThreadsState::new();
+
+        state.add_trigger(1);
+        state.add_trigger(1);
+
+        let stats = state.get_stats(&1).unwrap();
+        assert_eq!(stats.pending_triggers, 2);
+        assert!(stats.is_running);
+        assert_eq!(stats.runs, 0);
+
+        finish(&mut state, 1);
+        state.finish_iteration(
+            &1,
+            true,
+            DateTimeAsMicroseconds::new(1_000),
+            Duration::from_millis(5),
+            Some("boom".to_string()),
+        );
+
+        let stats = state.get_stats(&1).unwrap();
+        assert_eq!(stats.pending_triggers, 0);
+        assert!(!stats.is_running);
+        assert_eq!(stats.runs, 2);
+        assert_eq!(stats.panics, 1);
+        assert_eq!(stats.last_duration, Some(Duration::from_millis(5)));
+        assert_eq!(stats.last_panic_message.as_deref(), Some("boom"));
+        assert_eq!(state.get_all_stats().len(), 1);
+
+        state.forget(&1);
+        assert!(state.get_stats(&1).is_none());
+    }
+}
@@ -0,0 +1,564 @@
+use std::{future::Future, panic::AssertUnwindSafe, sync::Arc};
+
+use futures::FutureExt;
+use parking_lot::Mutex;
+
+use crate::{date_time::DateTimeAsMicroseconds, Logger, StrOrString};
+
+use super::{rolling_window::RollingWindow, CircuitBreakerSettings};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitBreakerState {
+    /// The calls go through, their outcomes are counted.
+    Closed,
+    /// The calls fail fast until the cool-down is over.
+    Open,
+    /// Only the trial calls go through: enough of them succeed - the circuit is
+    /// closed, any of them fails - it is open again.
+    HalfOpen,
+}
+
+#[derive(Debug)]
+pub enum CircuitBreakerError<TError> {
+    /// The call was rejected without being started.
+    Open,
+    /// The call was made and failed.
+    Inner(TError),
+}
+
+struct CircuitBreakerInner {
+    state: CircuitBreakerState,
+    opened_at: DateTimeAsMicroseconds,
+    window: RollingWindow,
+    trials_started: u32,
+    trials_succeeded: u32,
+    /// Changes with every state change - the outcome of a call started in the
+    /// previous state says nothing about the current one and is dropped.
+    generation: u64,
+}
+
+enum StateChange {
+    Opened { calls: u32, failures: u32 },
+    TrialFailed,
+    HalfOpened,
+    Closed,
+}
+
+/// Protects a flaky partner: as soon as the share of the failed calls within the
+/// rolling window reaches `failure_ratio`, the calls fail fast with
+/// [`CircuitBreakerError::Open`] for the `cool_down`, then `trial_calls` calls are
+/// let through to find out whether the partner is back.
+///
+/// The state changes are written to the [`Logger`].
+pub struct CircuitBreaker {
+    name: StrOrString<'static>,
+    settings: CircuitBreakerSettings,
+    inner: Mutex<CircuitBreakerInner>,
+    logger: Arc<dyn Logger + Send + Sync + 'static>,
+}
+
+impl CircuitBreaker {
+    pub fn new(
+        name: impl Into<StrOrString<'static>>,
+        settings: CircuitBreakerSettings,
+        logger: Arc<dyn Logger + Send + Sync + 'static>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            inner: Mutex::new(CircuitBreakerInner {
+                state: CircuitBreakerState::Closed,
+                opened_at: DateTimeAsMicroseconds::new(0),
+                window: RollingWindow::new(settings.window),
+                trials_started: 0,
+                trials_succeeded: 0,
+                generation: 0,
+            }),
+            settings,
+            logger,
+        }
+    }
+
+    pub fn get_name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn get_settings(&self) -> &CircuitBreakerSettings {
+        &self.settings
+    }
+
+    pub fn get_state(&self) -> CircuitBreakerState {
+        self.get_state_at(DateTimeAsMicroseconds::now())
+    }
+
+    pub fn get_state_at(&self, now: DateTimeAsMicroseconds) -> CircuitBreakerState {
+        let (state, change) = {
+            let mut inner = self.inner.lock();
+            let change = self.refresh(&mut inner, now);
+            (inner.state, change)
+        };
+
+        self.log_state_change(change);
+        state
+    }
+
+    /// Makes the call unless the circuit is open. The future is not polled at all
+    /// if the call is rejected.
+    ///
+    /// `Err` and panic count as failures. The future dropped before it is
+    /// finished does not count at all.
+    pub async fn execute<TResult, TError>(
+        &self,
+        future: impl Future<Output = Result<TResult, TError>>,
+    ) -> Result<TResult, CircuitBreakerError<TError>> {
+        let generation = match self.try_acquire_at(DateTimeAsMicroseconds::now()) {
+            Some(generation) => generation,
+            None => return Err(CircuitBreakerError::Open),
+        };
+
+        let mut call = CallGuard {
+            breaker: self,
+            generation,
+            finished: false,
+        };
+
+        let result = AssertUnwindSafe(future).catch_unwind().await;
+        call.finished = true;
+
+        let now = DateTimeAsMicroseconds::now();
+
+        match result {
+            Ok(Ok(value)) => {
+                self.record_at(generation, true, now);
+                Ok(value)
+            }
+            Ok(Err(err)) => {
+                self.record_at(generation, false, now);
+                Err(CircuitBreakerError::Inner(err))
+            }
+            Err(panic) => {
+                self.record_at(generation, false, now);
+                std::panic::resume_unwind(panic)
+            }
+        }
+    }
+
+    fn try_acquire_at(&self, now: DateTimeAsMicroseconds) -> Option<u64> {
+        let (result, change) = {
+            let mut inner = self.inner.lock();
+            let change = self.refresh(&mut inner, now);
+
+            let result = match inner.state {
+                CircuitBreakerState::Closed => Some(inner.generation),
+                CircuitBreakerState::Open => None,
+                CircuitBreakerState::HalfOpen => {
+                    if inner.trials_started < self.get_trial_calls() {
+                        inner.trials_started += 1;
+                        Some(inner.generation)
+                    } else {
+                        None
+                    }
+                }
+            };
+
+            (result, change)
+        };
+
+        self.log_state_change(change);
+        result
+    }
+
+    fn record_at(&self, generation: u64, success: bool, now: DateTimeAsMicroseconds) {
+        let change = {
+            let mut inner = self.inner.lock();
+
+            if inner.generation != generation {
+                return;
+            }
+
+            match inner.state {
+                CircuitBreakerState::Closed => {
+                    inner.window.add(success, now);
+                    let (calls, failures) = inner.window.get_totals(now);
+
+                    if calls >= self.settings.min_calls
+                        && failures as f64 >= self.settings.failure_ratio * calls as f64
+                    {
+                        Self::open(&mut inner, now);
+                        Some(StateChange::Opened { calls, failures })
+                    } else {
+                        None
+                    }
+                }
+                CircuitBreakerState::Open => None,
+                CircuitBreakerState::HalfOpen => {
+                    if !success {
+                        Self::open(&mut inner, now);
+                        Some(StateChange::TrialFailed)
+                    } else {
+                        inner.trials_succeeded += 1;
+
+                        if inner.trials_succeeded >= self.get_trial_calls() {
+                            inner.state = CircuitBreakerState::Closed;
+                            inner.window.clear();
+                            inner.generation += 1;
+                            Some(StateChange::Closed)
+                        } else {
+                            None
+                        }
+                    }
+                }
+            }
+        };
+
+        self.log_state_change(change);
+    }
+
+    /// The trial call which was given up on gives its slot to the next caller.
+    fn release(&self, generation: u64) {
+        let mut inner = self.inner.lock();
+
+        if inner.generation == generation && inner.state == CircuitBreakerState::HalfOpen {
+            inner.trials_started -= 1;
+        }
+    }
+
+    fn refresh(
+        &self,
+        inner: &mut CircuitBreakerInner,
+        now: DateTimeAsMicroseconds,
+    ) -> Option<StateChange> {
+        if inner.state != CircuitBreakerState::Open {
+            return None;
+        }
+
+        if now.unix_microseconds
+            < inner
+                .opened_at
+                .add(self.settings.cool_down)
+                .unix_microseconds
+        {
+            return None;
+        }
+
+        inner.state = CircuitBreakerState::HalfOpen;
+        inner.trials_started = 0;
+        inner.trials_succeeded = 0;
+        inner.generation += 1;
+        Some(StateChange::HalfOpened)
+    }
+
+    fn open(inner: &mut CircuitBreakerInner, now: DateTimeAsMicroseconds) {
+        inner.state = CircuitBreakerState::Open;
+        inner.opened_at = now;
+        inner.generation += 1;
+    }
+
+    fn get_trial_calls(&self) -> u32 {
+        self.settings.trial_calls.max(1)
+    }
+
+    fn log_state_change(&self, change: Option<StateChange>) {
+        let change = match change {
+            Some(change) => change,
+            None => return,
+        };
+
+        match change {
+            StateChange::Opened { calls, failures } => self.logger.write_warning(
+                "CircuitBreaker".to_string(),
+                format!(
+                    "Circuit breaker {} is open: {} of {} calls failed within {:?}",
+                    self.name.as_str(),
+                    failures,
+                    calls,
+                    self.settings.window
+                ),
+                None,
+            ),
+            StateChange::TrialFailed => self.logger.write_warning(
+                "CircuitBreaker".to_string(),
+                format!(
+                    "Circuit breaker {} is open again: trial call failed",
+                    self.name.as_str()
+                ),
+                None,
+            ),
+            StateChange::HalfOpened => self.logger.write_info(
+                "CircuitBreaker".to_string(),
+                format!(
+                    "Circuit breaker {} is half-open: letting {} trial calls through",
+                    self.name.as_str(),
+                    self.get_trial_calls()
+                ),
+                None,
+            ),
+            StateChange::Closed => self.logger.write_info(
+                "CircuitBreaker".to_string(),
+                format!("Circuit breaker {} is closed", self.name.as_str()),
+                None,
+            ),
+        }
+    }
+}
+
+struct CallGuard<'s> {
+    breaker: &'s CircuitBreaker,
+    generation: u64,
+    finished: bool,
+}
+
+impl<'s> Drop for CallGuard<'s> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.breaker.release(self.generation);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, time::Duration};
+
+    use super::*;
+
+    struct TestLogger {
+        messages: Mutex<Vec<String>>,
+    }
+
+    impl Logger for TestLogger {
+        fn write_info(&self, _: String, message: String, _: Option<HashMap<String, String>>) {
+            self.messages.lock().push(message);
+        }
+
+        fn write_warning(&self, _: String, message: String, _: Option<HashMap<String, String>>) {
+            self.messages.lock().push(message);
+        }
+
+        fn write_error(&self, _: String, message: String, _: Option<HashMap<String, String>>) {
+            self.messages.lock().push(message);
+        }
+
+        fn write_fatal_error(
+            &self,
+            _: String,
+            message: String,
+            _: Option<HashMap<String, String>>,
+        ) {
+            self.messages.lock().push(message);
+        }
+
+        fn write_debug_info(&self, _: String, message: String, _: Option<HashMap<String, String>>) {
+            self.messages.lock().push(message);
+        }
+    }
+
+    fn at(millis: i64) -> DateTimeAsMicroseconds {
+        DateTimeAsMicroseconds::new(millis * 1000)
+    }
+
+    fn create_breaker() -> (CircuitBreaker, Arc<TestLogger>) {
+        let logger = Arc::new(TestLogger {
+            messages: Mutex::new(Vec::new()),
+        });
+
+        let settings = CircuitBreakerSettings {
+            failure_ratio: 0.5,
+            min_calls: 4,
+            window: Duration::from_secs(10),
+            cool_down: Duration::from_secs(5),
+            trial_calls: 2,
+        };
+
+        (
+            CircuitBreaker::new("test", settings, logger.clone()),
+            logger,
+        )
+    }
+
+    fn call(breaker: &CircuitBreaker, success: bool, now: DateTimeAsMicroseconds) -> bool {
+        match breaker.try_acquire_at(now) {
+            Some(generation) => {
+                breaker.record_at(generation, success, now);
+                true
+            }
+            None => false,
+        }
+    }
+
+    #[test]
+    fn opens_when_the_failure_ratio_is_reached_after_min_calls() {
+        let (breaker, logger) = create_breaker();
+
+        assert!(call(&breaker, false, at(0)));
+        assert!(call(&breaker, false, at(100)));
+        assert!(call(&breaker, false, at(200)));
+        assert_eq!(breaker.get_state_at(at(200)), CircuitBreakerState::Closed);
+
+        assert!(call(&breaker, true, at(300)));
+        assert_eq!(breaker.get_state_at(at(300)), CircuitBreakerState::Open);
+        assert!(!call(&breaker, true, at(400)));
+
+        assert_eq!(
+            logger.messages.lock().as_slice(),
+            ["Circuit breaker test is open: 3 of 4 calls failed within 10s"]
+        );
+    }
+
+    #[test]
+    fn failures_leave_the_rolling_window() {
+        let (breaker, _) = create_breaker();
+
+        call(&breaker, false, at(0));
+        call(&breaker, false, at(1_000));
+
+        call(&breaker, true, at(11_000));
+        call(&breaker, true, at(11_000));
+        call(&breaker, false, at(11_000));
+        call(&breaker, true, at(11_000));
+
+        assert_eq!(
+            breaker.get_state_at(at(11_000)),
+            CircuitBreakerState::Closed
+        );
+    }
+
+    #[test]
+    fn half_open_closes_after_successful_trials() {
+        let (breaker, logger) = create_breaker();
+
+        for _ in 0..4 {
+            call(&breaker, false, at(0));
+        }
+
+        assert!(!call(&breaker, true, at(4_999)));
+        assert_eq!(
+            breaker.get_state_at(at(5_000)),
+            CircuitBreakerState::HalfOpen
+        );
+
+        let first = breaker.try_acquire_at(at(5_000)).unwrap();
+        let second = breaker.try_acquire_at(at(5_000)).unwrap();
+        assert!(breaker.try_acquire_at(at(5_000)).is_none());
+
+        breaker.record_at(first, true, at(5_100));
+        assert_eq!(
+            breaker.get_state_at(at(5_100)),
+            CircuitBreakerState::HalfOpen
+        );
+        breaker.record_at(second, true, at(5_200));
+        assert_eq!(breaker.get_state_at(at(5_200)), CircuitBreakerState::Closed);
+
+        // The failures from before the circuit was open are forgotten.
+        assert!(call(&breaker, false, at(5_300)));
+        assert_eq!(breaker.get_state_at(at(5_300)), CircuitBreakerState::Closed);
+
+        assert_eq!(
+            logger.messages.lock().as_slice(),
+            [
+                "Circuit breaker test is open: 4 of 4 calls failed within 10s",
+                "Circuit breaker test is half-open: letting 2 trial calls through",
+                "Circuit breaker test is closed",
+            ]
+        );
+    }
+
+    #[test]
+    fn failed_trial_opens_again_and_late_outcomes_are_ignored() {
+        let (breaker, _) = create_breaker();
+
+        let late = breaker.try_acquire_at(at(0)).unwrap();
+
+        for _ in 0..4 {
+            call(&breaker, false, at(0));
+        }
+
+        let trial = breaker.try_acquire_at(at(5_000)).unwrap();
+
+        // Started while the circuit was closed - not a trial.
+        breaker.record_at(late, true, at(5_000));
+        assert_eq!(
+            breaker.get_state_at(at(5_000)),
+            CircuitBreakerState::HalfOpen
+        );
+
+        breaker.record_at(trial, false, at(5_100));
+        assert_eq!(breaker.get_state_at(at(5_100)), CircuitBreakerState::Open);
+        assert_eq!(breaker.get_state_at(at(10_099)), CircuitBreakerState::Open);
+        assert_eq!(
+            breaker.get_state_at(at(10_100)),
+            CircuitBreakerState::HalfOpen
+        );
+    }
+
+    #[test]
+    fn released_trial_gives_its_slot_away() {
+        let (breaker, _) = create_breaker();
+
+        for _ in 0..4 {
+            call(&breaker, false, at(0));
+        }
+
+        let first = breaker.try_acquire_at(at(5_000)).unwrap();
+        breaker.try_acquire_at(at(5_000)).unwrap();
+        assert!(breaker.try_acquire_at(at(5_000)).is_none());
+
+        breaker.release(first);
+        assert!(breaker.try_acquire_at(at(5_000)).is_some());
+    }
+
+    #[test]
+    fn execute_fails_fast_when_open() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        rt.block_on(async {
+            let (breaker, _) = create_breaker();
+
+            for _ in 0..4 {
+                let result = breaker.execute(async { Err::<(), _>("down") }).await;
+                assert!(matches!(result, Err(CircuitBreakerError::Inner("down"))));
+            }
+
+            let polled = Arc::new(Mutex::new(false));
+            let polled_in_call = polled.clone();
+
+            let result = breaker
+                .execute(async move {
+                    *polled_in_call.lock() = true;
+                    Ok::<_, ()>(1)
+                })
+                .await;
+
+            assert!(matches!(result, Err(CircuitBreakerError::Open)));
+            assert!(!*polled.lock());
+        });
+    }
+
+    #[test]
+    fn execute_counts_panic_as_failure() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        rt.block_on(async {
+            let (breaker, _) = create_breaker();
+
+            for _ in 0..4 {
+                let result = AssertUnwindSafe(breaker.execute(async {
+                    if true {
+                        panic!("partner exploded");
+                    }
+
+                    Ok::<(), ()>(())
+                }))
+                .catch_unwind()
+                .await;
+
+                assert!(result.is_err());
+            }
+
+            assert_eq!(breaker.get_state(), CircuitBreakerState::Open);
+        });
+    }
+}
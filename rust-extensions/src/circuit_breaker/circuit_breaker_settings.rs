@@ -0,0 +1,29 @@
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy)]
+pub struct CircuitBreakerSettings {
+    /// The share of the failed calls within the `window` (`0.0..=1.0`) which opens
+    /// the circuit.
+    pub failure_ratio: f64,
+    /// The ratio is not trusted until the window has at least that many calls - two
+    /// failures out of three calls say nothing yet.
+    pub min_calls: u32,
+    pub window: Duration,
+    /// How long the open circuit fails the calls fast before it lets the trial
+    /// calls through.
+    pub cool_down: Duration,
+    /// Amount of the successful trial calls which close the half-open circuit.
+    pub trial_calls: u32,
+}
+
+impl Default for CircuitBreakerSettings {
+    fn default() -> Self {
+        Self {
+            failure_ratio: 0.5,
+            min_calls: 10,
+            window: Duration::from_secs(30),
+            cool_down: Duration::from_secs(10),
+            trial_calls: 3,
+        }
+    }
+}
@@ -0,0 +1,6 @@
+mod circuit_breaker;
+mod circuit_breaker_settings;
+mod rolling_window;
+
+pub use circuit_breaker::{CircuitBreaker, CircuitBreakerError, CircuitBreakerState};
+pub use circuit_breaker_settings::CircuitBreakerSettings;
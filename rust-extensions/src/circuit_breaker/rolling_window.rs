@@ -0,0 +1,78 @@
+use std::time::Duration;
+
+use crate::date_time::DateTimeAsMicroseconds;
+
+const BUCKETS_AMOUNT: usize = 10;
+
+#[derive(Clone, Copy, Default)]
+struct Bucket {
+    started: i64,
+    successes: u32,
+    failures: u32,
+}
+
+/// Outcomes of the calls within the last `window`, kept in the fixed amount of
+/// buckets - the memory does not depend on the rate of the calls. The outcomes
+/// leave the window one bucket at a time.
+pub(super) struct RollingWindow {
+    bucket_micros: i64,
+    buckets: [Bucket; BUCKETS_AMOUNT],
+}
+
+impl RollingWindow {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            bucket_micros: (window.as_micros() as i64 / BUCKETS_AMOUNT as i64).max(1),
+            buckets: [Bucket::default(); BUCKETS_AMOUNT],
+        }
+    }
+
+    fn get_bucket(&mut self, now: DateTimeAsMicroseconds) -> &mut Bucket {
+        let bucket_no = now.unix_microseconds.div_euclid(self.bucket_micros);
+        let started = bucket_no * self.bucket_micros;
+        let bucket = &mut self.buckets[bucket_no.rem_euclid(BUCKETS_AMOUNT as i64) as usize];
+
+        if bucket.started != started {
+            *bucket = Bucket {
+                started,
+                successes: 0,
+                failures: 0,
+            };
+        }
+
+        bucket
+    }
+
+    pub fn add(&mut self, success: bool, now: DateTimeAsMicroseconds) {
+        let bucket = self.get_bucket(now);
+
+        if success {
+            bucket.successes += 1;
+        } else {
+            bucket.failures += 1;
+        }
+    }
+
+    /// Returns `(calls, failures)` within the window ending at `now`.
+    pub fn get_totals(&self, now: DateTimeAsMicroseconds) -> (u32, u32) {
+        let oldest_started = now.unix_microseconds.div_euclid(self.bucket_micros)
+            * self.bucket_micros
+            - (BUCKETS_AMOUNT as i64 - 1) * self.bucket_micros;
+
+        self.buckets
+            .iter()
+            .filter(|bucket| {
+                bucket.started >= oldest_started && bucket.started <= now.unix_microseconds
+            })
+            .fold((0, 0), |(calls, failures), bucket| {
+                (
+                    calls + bucket.successes + bucket.failures,
+                    failures + bucket.failures,
+                )
+            })
+    }
+
+    pub fn clear(&mut self) {
+        self.buckets = [Bucket::default(); BUCKETS_AMOUNT];
+    }
+}
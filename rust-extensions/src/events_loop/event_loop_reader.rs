@@ -1,52 +1,91 @@
 use std::{ panic::AssertUnwindSafe, sync::Arc, time::Duration};
 
-use crate::{ApplicationStates, Logger};
+use crate::{date_time::DateTimeAsMicroseconds, ApplicationStates, Logger, SizeBudget};
 
-use super::{events_loop::EventsLoopInner};
+use super::{
+    events_loop::{EventsLoopInner, EventsLoopTickKind},
+    events_loop_queue::EventsLoopQueue,
+    EventsLoopMessage, EventsLoopPriority,
+};
 
 use futures::FutureExt;
 
+/// Returns `true` if the loop is stopped on request - not because the application
+/// is shutting down.
 pub async fn events_loop_reader<TModel : Send + 'static>(
     name: Arc<String>,
-    inner: EventsLoopInner<TModel>,
+    inner: Arc<EventsLoopInner<TModel>>,
     app_states: Arc<dyn ApplicationStates + Send +  Sync+ 'static>,
     logger: Arc<dyn Logger + Send + Sync+ 'static>,
     iteration_timeout: Duration,
 
-) {
-    let EventsLoopInner {
-        event_loop_tick,
-        mut receiver,
-    } = inner;
+) -> bool {
+    let event_loop_tick = &inner.event_loop_tick;
+    let metrics = &inner.metrics;
+
+    // Held while the reader lives - the restarted reader gets the receiver once
+    // the dead one is gone.
+    let mut receiver = inner.receiver.lock().await;
 
     while !app_states.is_initialized() {
         tokio::time::sleep(Duration::from_secs(1)).await;
     }
 
-    let _ = AssertUnwindSafe(event_loop_tick.started())
+    let started = match event_loop_tick {
+        EventsLoopTickKind::Single(tick) => tick.started(),
+        EventsLoopTickKind::Batch(tick, _) => tick.started(),
+    };
+
+    let _ = AssertUnwindSafe(started)
     .catch_unwind()
     .await;
 
+    let mut queue = EventsLoopQueue::new();
+    let mut shutdown_requested = false;
+
     while !app_states.is_shutting_down() {
-        if let Some(message) = tokio::sync::mpsc::UnboundedReceiver::recv(&mut receiver).await {
+        // Everything which has already arrived is taken before the next message is
+        // picked - so a message of higher priority overtakes the ones waiting.
+        while !shutdown_requested {
+            match receiver.try_recv() {
+                Ok(message) => {
+                    shutdown_requested = push_message(&mut queue, message);
+                }
+                Err(_) => break,
+            }
+        }
 
-            let message = match message{
-                super::EventsLoopMessage::NewMessage(message) => message,
-                super::EventsLoopMessage::Shutdown => {
-                    break;
-                },
-            };
+        queue.promote_due(DateTimeAsMicroseconds::now());
+
+        let models = take_models(&mut queue, event_loop_tick);
 
-            let timeout_tick = event_loop_tick.tick(message);
+        if !models.is_empty() {
+            let amount = models.len();
+
+            let timeout_tick = match event_loop_tick {
+                EventsLoopTickKind::Single(tick) => tick.tick(models.into_iter().next().unwrap()),
+                EventsLoopTickKind::Batch(tick, _) => tick.tick(models),
+            };
 
             let timer_tick_future = AssertUnwindSafe(timeout_tick)
                     .catch_unwind();
 
                 match tokio::time::timeout(iteration_timeout, timer_tick_future).await {
                 Ok(Ok(_)) => {
-              
+                    if let Some(metrics) = metrics.as_ref() {
+                        metrics.tick_finished(amount);
+                    }
+                    if let Some(health) = inner.health.as_ref() {
+                        health.heartbeat();
+                    }
                 }
                 Ok(Err(_panic)) => {
+                    if let Some(metrics) = metrics.as_ref() {
+                        metrics.tick_panicked(amount);
+                    }
+                    if let Some(health) = inner.health.as_ref() {
+                        health.report_error(format!("EventLoop {} iteration is panicked", name));
+                    }
                       logger.write_error(
                             format!("EventLoop {} iteration", name.as_str()),
                             format!("Iteration is panicked"),
@@ -54,6 +93,12 @@ pub async fn events_loop_reader<TModel : Send + 'static>(
                         );
                 }
                 Err(_elapsed) => {
+                    if let Some(metrics) = metrics.as_ref() {
+                        metrics.tick_timed_out(amount);
+                    }
+                    if let Some(health) = inner.health.as_ref() {
+                        health.report_error(format!("EventLoop {} iteration is time outed", name));
+                    }
                     logger.write_error(
                         format!("EventLoop {} iteration", name.as_str()),
                         format!("Iteration is time outed"),
@@ -62,11 +107,102 @@ pub async fn events_loop_reader<TModel : Send + 'static>(
                 }
             }
 
+            continue;
+        }
+
+        // The messages published before the shutdown are delivered - the scheduled
+        // ones which are not due yet are dropped.
+        if shutdown_requested {
+            break;
+        }
+
+        let message = match queue.get_next_deliver_at() {
+            Some(deliver_at) => {
+                let delay = deliver_at
+                    .duration_since(DateTimeAsMicroseconds::now())
+                    .as_positive_or_zero();
+
+                match tokio::time::timeout(delay, receiver.recv()).await {
+                    Ok(message) => message,
+                    // The scheduled message is due - it is promoted on the next round.
+                    Err(_) => continue,
+                }
+            }
+            None => receiver.recv().await,
+        };
+
+        match message {
+            Some(message) => {
+                shutdown_requested = push_message(&mut queue, message);
+            }
+            // Every publisher is gone - nothing is going to come anymore.
+            None => {
+                shutdown_requested = true;
+            }
         }
     }
 
-  
-    let _ = AssertUnwindSafe(event_loop_tick.finished())
+
+    let finished = match event_loop_tick {
+        EventsLoopTickKind::Single(tick) => tick.finished(),
+        EventsLoopTickKind::Batch(tick, _) => tick.finished(),
+    };
+
+    let _ = AssertUnwindSafe(finished)
     .catch_unwind()
     .await;
+
+    shutdown_requested && !app_states.is_shutting_down()
+}
+
+/// Takes the models for the next iteration: a single one for a single tick, as
+/// many as the limits allow for a batch tick.
+fn take_models<TModel: Send + 'static>(
+    queue: &mut EventsLoopQueue<TModel>,
+    event_loop_tick: &EventsLoopTickKind<TModel>,
+) -> Vec<TModel> {
+    let (tick, limits) = match event_loop_tick {
+        EventsLoopTickKind::Single(_) => return queue.pop_ready().into_iter().collect(),
+        EventsLoopTickKind::Batch(tick, limits) => (tick, limits),
+    };
+
+    let mut budget = SizeBudget::new(limits.max_size);
+    let mut result = Vec::new();
+
+    while result.len() < limits.max_count {
+        let Some(model) = queue.peek_ready() else {
+            break;
+        };
+
+        let size = tick.get_model_size(model);
+
+        if budget.needs_flush(size) {
+            break;
+        }
+
+        budget.add(size);
+        result.push(queue.pop_ready().unwrap());
+    }
+
+    result
+}
+
+/// Returns `true` if the message is the shutdown request.
+fn push_message<TModel>(queue: &mut EventsLoopQueue<TModel>, message: EventsLoopMessage<TModel>) -> bool {
+    match message {
+        EventsLoopMessage::NewMessage(model) => {
+            queue.push_ready(model, EventsLoopPriority::Normal);
+        }
+        EventsLoopMessage::NewMessageWithPriority(model, priority) => {
+            queue.push_ready(model, priority);
+        }
+        EventsLoopMessage::Scheduled(message) => {
+            queue.push_scheduled(message);
+        }
+        EventsLoopMessage::Shutdown => {
+            return true;
+        }
+    }
+
+    false
 }
@@ -1,13 +1,25 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+    sync::{atomic::AtomicBool, Arc},
+    time::Duration,
+};
 
 use parking_lot::Mutex;
 
-use crate::{ApplicationStates, Logger, StrOrString};
+use crate::{
+    date_time::DateTimeAsMicroseconds, health::HealthReporter, supervisor::Supervisor,
+    ApplicationStates, Logger, StrOrString,
+};
 
-use super::{EventsLoopPublisher, EventsLoopTick};
+use super::{
+    events_loop_metrics::EventsLoopMetrics, events_loop_supervised_reader::EventsLoopSupervisedReader, EventsLoopBatchLimits, EventsLoopBatchTick,
+    EventsLoopPriority, EventsLoopPublisher, EventsLoopScheduledHandle,
+    EventsLoopScheduledMessage, EventsLoopTick,
+};
 
 pub enum EventsLoopMessage<TModel> {
     NewMessage(TModel),
+    NewMessageWithPriority(TModel, EventsLoopPriority),
+    Scheduled(EventsLoopScheduledMessage<TModel>),
     Shutdown,
 }
 
@@ -22,20 +34,33 @@ impl<TModel: 'static> EventsLoopMessage<TModel> {
     pub fn unwrap_message(self) -> TModel {
         match self {
             EventsLoopMessage::NewMessage(message) => message,
+            EventsLoopMessage::NewMessageWithPriority(message, _) => message,
             _ => panic!("EventsLoopMessage::unwrap_message() called on a non-NewMessage message"),
         }
     }
 }
 
+/// What the reader delivers the messages to.
+pub(super) enum EventsLoopTickKind<TModel: Send + 'static> {
+    Single(Arc<dyn EventsLoopTick<TModel> + Send + Sync + 'static>),
+    Batch(
+        Arc<dyn EventsLoopBatchTick<TModel> + Send + Sync + 'static>,
+        EventsLoopBatchLimits,
+    ),
+}
+
 pub(super) struct EventsLoopInner<TModel: Send+'static> {
-    pub event_loop_tick: Arc<dyn EventsLoopTick<TModel> + Send + Sync + 'static>,
-    pub receiver: tokio::sync::mpsc::UnboundedReceiver<EventsLoopMessage<TModel>>,
+    pub event_loop_tick: EventsLoopTickKind<TModel>,
+    pub receiver: tokio::sync::Mutex<tokio::sync::mpsc::UnboundedReceiver<EventsLoopMessage<TModel>>>,
+    pub metrics: Option<Arc<EventsLoopMetrics>>,
+    pub health: Option<Arc<HealthReporter>>,
 }
 
 pub struct EventsLoop<TModel: Send + 'static> {
     pending_receiver:
         Mutex<Option<tokio::sync::mpsc::UnboundedReceiver<EventsLoopMessage<TModel>>>>,
     inner: Mutex<Option<EventsLoopInner<TModel>>>,
+    health: Mutex<Option<Arc<HealthReporter>>>,
     publisher: EventsLoopPublisher<TModel>,
     name: Arc<String>,
     iteration_timeout: Duration,
@@ -53,6 +78,7 @@ impl<TModel: Send + 'static> EventsLoop<TModel> {
             iteration_timeout: Duration::from_secs(30),
             pending_receiver: Mutex::new(Some(receiver)),
             inner: Mutex::new(None),
+            health: Mutex::new(None),
         }
     }
 
@@ -65,6 +91,21 @@ impl<TModel: Send + 'static> EventsLoop<TModel> {
         &self,
         event_loop: Arc<dyn EventsLoopTick<TModel> + Send + Sync+  'static>,
     ) {
+        self.register(EventsLoopTickKind::Single(event_loop));
+    }
+
+    /// Registers the tick which gets the messages in batches instead of one by one:
+    /// every iteration delivers all the messages available at the moment, but not
+    /// more than `limits` allow.
+    pub fn register_batch_event_loop(
+        &self,
+        event_loop: Arc<dyn EventsLoopBatchTick<TModel> + Send + Sync + 'static>,
+        limits: EventsLoopBatchLimits,
+    ) {
+        self.register(EventsLoopTickKind::Batch(event_loop, limits));
+    }
+
+    fn register(&self, event_loop_tick: EventsLoopTickKind<TModel>) {
         let receiver = self.pending_receiver.lock().take();
 
         if receiver.is_none() {
@@ -76,35 +117,69 @@ impl<TModel: Send + 'static> EventsLoop<TModel> {
 
         let mut inner_lock = self.inner.lock();
         *inner_lock = Some(EventsLoopInner {
-            event_loop_tick: event_loop,
-            receiver: receiver.unwrap(),
+            event_loop_tick,
+            receiver: tokio::sync::Mutex::new(receiver.unwrap()),
+            metrics: None,
+            health: None,
         });
     }
 
-    pub fn start(
-        &self,
-        app_states: Arc<dyn ApplicationStates + Send + Sync + 'static>,
-        logger: Arc<dyn Logger + Send + Sync + 'static>,
-    ) {
+    /// Every delivered message is a heartbeat, every panicked or timed out tick is
+    /// an error. Has to be set before the loop is started.
+    pub fn set_health_reporter(&self, reporter: Arc<HealthReporter>) {
+        *self.health.lock() = Some(reporter);
+    }
+
+    fn take_inner(&self) -> Arc<EventsLoopInner<TModel>> {
         let inner = self.inner.lock().take();
 
-        let Some(inner) = inner else{
+        let Some(mut inner) = inner else{
              panic!(
                 "Event Loop {} is not registered or already started.",
                 self.name
             );
         };
 
+        inner.health = self.health.lock().clone();
+        Arc::new(inner)
+    }
 
+    pub fn start(
+        &self,
+        app_states: Arc<dyn ApplicationStates + Send + Sync + 'static>,
+        logger: Arc<dyn Logger + Send + Sync + 'static>,
+    ) {
         tokio::spawn(super::event_loop_reader::events_loop_reader(
             self.name.clone(),
-            inner,
+            self.take_inner(),
             app_states,
             logger,
             self.iteration_timeout,
         ));
     }
 
+    /// Hands the reader over to the supervisor instead of spawning it: the reader
+    /// is started together with the supervisor and restarted if it dies. The loop
+    /// stopped with [`Self::stop`] is not restarted.
+    pub fn start_supervised(
+        &self,
+        supervisor: &Supervisor,
+        app_states: Arc<dyn ApplicationStates + Send + Sync + 'static>,
+        logger: Arc<dyn Logger + Send + Sync + 'static>,
+    ) {
+        supervisor.add_child(
+            format!("EventsLoop {}", self.name),
+            Arc::new(EventsLoopSupervisedReader {
+                name: self.name.clone(),
+                inner: self.take_inner(),
+                app_states,
+                logger,
+                iteration_timeout: self.iteration_timeout,
+                stopped: AtomicBool::new(false),
+            }),
+        );
+    }
+
     pub fn get_publisher(&self) -> EventsLoopPublisher<TModel> {
         self.publisher.clone()
     }
@@ -113,7 +188,258 @@ impl<TModel: Send + 'static> EventsLoop<TModel> {
         self.publisher.send(model);
     }
 
+    pub fn send_with_priority(&self, model: TModel, priority: EventsLoopPriority) {
+        self.publisher.send_with_priority(model, priority);
+    }
+
+    /// Delivers the message not earlier than `deliver_at`. The returned handle
+    /// cancels the delivery.
+    pub fn schedule(
+        &self,
+        model: TModel,
+        deliver_at: DateTimeAsMicroseconds,
+    ) -> EventsLoopScheduledHandle {
+        self.publisher.schedule(model, deliver_at)
+    }
+
+    pub fn schedule_with_priority(
+        &self,
+        model: TModel,
+        deliver_at: DateTimeAsMicroseconds,
+        priority: EventsLoopPriority,
+    ) -> EventsLoopScheduledHandle {
+        self.publisher
+            .schedule_with_priority(model, deliver_at, priority)
+    }
+
     pub fn stop(&self) {
         self.publisher.stop();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc, time::Duration};
+
+    use parking_lot::Mutex;
+
+    use crate::{
+        date_time::DateTimeAsMicroseconds,
+        supervisor::{Supervisor, SupervisorStrategy},
+        AppStates, Logger,
+    };
+
+    use super::{
+        EventsLoop, EventsLoopBatchLimits, EventsLoopBatchTick, EventsLoopPriority, EventsLoopTick,
+    };
+
+    struct TestLogger;
+
+    impl Logger for TestLogger {
+        fn write_info(&self, _: String, _: String, _: Option<HashMap<String, String>>) {}
+        fn write_warning(&self, _: String, _: String, _: Option<HashMap<String, String>>) {}
+        fn write_error(&self, _: String, _: String, _: Option<HashMap<String, String>>) {}
+        fn write_fatal_error(&self, _: String, _: String, _: Option<HashMap<String, String>>) {}
+        fn write_debug_info(&self, _: String, _: String, _: Option<HashMap<String, String>>) {}
+    }
+
+    #[derive(Default)]
+    struct CapturingTick {
+        captured: Mutex<Vec<&'static str>>,
+    }
+
+    #[async_trait::async_trait]
+    impl EventsLoopTick<&'static str> for CapturingTick {
+        async fn started(&self) {}
+
+        async fn tick(&self, model: &'static str) {
+            self.captured.lock().push(model);
+        }
+
+        async fn finished(&self) {}
+    }
+
+    fn rt() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    async fn wait_for(tick: &CapturingTick, expected: usize) {
+        for _ in 0..400 {
+            if tick.captured.lock().len() >= expected {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        panic!("Expected {} messages, got {:?}", expected, tick.captured.lock());
+    }
+
+    fn start(name: &'static str) -> (EventsLoop<&'static str>, Arc<CapturingTick>) {
+        let events_loop = EventsLoop::new(name);
+        let tick = Arc::new(CapturingTick::default());
+        events_loop.register_event_loop(tick.clone());
+        (events_loop, tick)
+    }
+
+    #[test]
+    fn waiting_messages_are_delivered_by_priority() {
+        rt().block_on(async {
+            let (events_loop, tick) = start("test-priority");
+
+            events_loop.send("normal");
+            events_loop.send_with_priority("low", EventsLoopPriority::Low);
+            events_loop.send_with_priority("high", EventsLoopPriority::High);
+
+            events_loop.start(
+                Arc::new(AppStates::create_initialized()),
+                Arc::new(TestLogger),
+            );
+
+            wait_for(&tick, 3).await;
+            assert_eq!(*tick.captured.lock(), ["high", "normal", "low"]);
+        });
+    }
+
+    #[test]
+    fn scheduled_message_is_delivered_when_due_unless_cancelled() {
+        rt().block_on(async {
+            let (events_loop, tick) = start("test-scheduled");
+
+            events_loop.start(
+                Arc::new(AppStates::create_initialized()),
+                Arc::new(TestLogger),
+            );
+
+            let now = DateTimeAsMicroseconds::now();
+            events_loop.schedule("later", now.add(Duration::from_millis(100)));
+            let cancelled =
+                events_loop.schedule("cancelled", now.add(Duration::from_millis(50)));
+            events_loop.send("now");
+
+            assert!(cancelled.cancel());
+
+            wait_for(&tick, 1).await;
+            assert_eq!(*tick.captured.lock(), ["now"]);
+
+            wait_for(&tick, 2).await;
+            let elapsed = DateTimeAsMicroseconds::now()
+                .duration_since(now)
+                .as_positive_or_zero();
+            assert!(elapsed >= Duration::from_millis(100));
+
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            assert_eq!(*tick.captured.lock(), ["now", "later"]);
+            assert!(cancelled.is_cancelled());
+        });
+    }
+
+    #[derive(Default)]
+    struct CapturingBatchTick {
+        batches: Mutex<Vec<Vec<&'static str>>>,
+        started: std::sync::atomic::AtomicBool,
+    }
+
+    #[async_trait::async_trait]
+    impl EventsLoopBatchTick<&'static str> for CapturingBatchTick {
+        async fn started(&self) {
+            self.started.store(true, std::sync::atomic::Ordering::SeqCst);
+        }
+
+        async fn tick(&self, models: Vec<&'static str>) {
+            self.batches.lock().push(models);
+        }
+
+        async fn finished(&self) {}
+
+        fn get_model_size(&self, model: &&'static str) -> usize {
+            model.len()
+        }
+    }
+
+    #[test]
+    fn batch_tick_gets_batches_bounded_by_count_and_size() {
+        rt().block_on(async {
+            let events_loop = EventsLoop::new("test-batch");
+            let tick = Arc::new(CapturingBatchTick::default());
+            events_loop.register_batch_event_loop(tick.clone(), EventsLoopBatchLimits::new(3, 10));
+
+            for model in ["aaaa", "bbbb", "cc", "dddddddddddd", "e", "f", "g", "h"] {
+                events_loop.send(model);
+            }
+
+            events_loop.start(
+                Arc::new(AppStates::create_initialized()),
+                Arc::new(TestLogger),
+            );
+
+            for _ in 0..400 {
+                if tick.batches.lock().len() >= 4 {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+
+            assert!(tick.started.load(std::sync::atomic::Ordering::SeqCst));
+            assert_eq!(
+                *tick.batches.lock(),
+                vec![
+                    vec!["aaaa", "bbbb", "cc"],
+                    // Larger than the limit alone - it is delivered alone.
+                    vec!["dddddddddddd"],
+                    vec!["e", "f", "g"],
+                    vec!["h"],
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn supervised_reader_is_restarted_and_delivers_the_rest() {
+        rt().block_on(async {
+            let (events_loop, tick) = start("test-supervised");
+
+            let supervisor = Supervisor::new("test-supervised", SupervisorStrategy::OneForAll);
+            let app_states = Arc::new(AppStates::create_initialized());
+
+            events_loop.start_supervised(&supervisor, app_states.clone(), Arc::new(TestLogger));
+
+            // The neighbour which dies once - the reader is restarted together with it.
+            let runs = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+            supervisor.add_child_fn("dies-once", {
+                let runs = runs.clone();
+                move || {
+                    let runs = runs.clone();
+                    async move {
+                        if runs.fetch_add(1, std::sync::atomic::Ordering::SeqCst) == 0 {
+                            tokio::time::sleep(Duration::from_millis(20)).await;
+                            panic!("Dies once");
+                        }
+                        loop {
+                            tokio::time::sleep(Duration::from_secs(60)).await;
+                        }
+                    }
+                }
+            });
+
+            supervisor.start(app_states, Arc::new(TestLogger));
+
+            events_loop.send("before");
+            wait_for(&tick, 1).await;
+
+            for _ in 0..400 {
+                if supervisor.get_health().children[0].restarts == 1 {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+
+            events_loop.send("after");
+            wait_for(&tick, 2).await;
+
+            assert_eq!(*tick.captured.lock(), ["before", "after"]);
+            assert!(supervisor.get_health().is_healthy());
+        });
+    }
+}
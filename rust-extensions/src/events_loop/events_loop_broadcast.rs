@@ -0,0 +1,413 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use parking_lot::Mutex;
+
+use crate::{
+    health::HealthReporter, supervisor::Supervisor, ApplicationStates, Logger, StrOrString,
+};
+
+use super::{
+    events_loop::{EventsLoopInner, EventsLoopTickKind},
+    events_loop_metrics::EventsLoopMetrics,
+    events_loop_supervised_reader::EventsLoopSupervisedReader,
+    EventsLoopMessage, EventsLoopPriority, EventsLoopSubscriberStats, EventsLoopTick,
+};
+
+type EventsLoopFilter<TModel> = Box<dyn Fn(&TModel) -> bool + Send + Sync + 'static>;
+
+struct EventsLoopSubscriber<TModel: Send + 'static> {
+    name: Arc<String>,
+    sender: tokio::sync::mpsc::UnboundedSender<EventsLoopMessage<TModel>>,
+    filter: Option<EventsLoopFilter<TModel>>,
+    metrics: Arc<EventsLoopMetrics>,
+    pending_inner: Mutex<Option<EventsLoopInner<TModel>>>,
+}
+
+impl<TModel: Clone + Send + 'static> EventsLoopSubscriber<TModel> {
+    fn send(&self, model: &TModel, priority: EventsLoopPriority) {
+        if let Some(filter) = self.filter.as_ref() {
+            if !filter(model) {
+                self.metrics.filtered_out.fetch_add(1, Ordering::SeqCst);
+                return;
+            }
+        }
+
+        self.metrics.published.fetch_add(1, Ordering::SeqCst);
+
+        // The subscriber is gone only if its reader is finished - there is nobody
+        // to deliver to, and the other subscribers must not suffer from it.
+        let _ = self.sender.send(EventsLoopMessage::NewMessageWithPriority(
+            model.clone(),
+            priority,
+        ));
+    }
+}
+
+/// The events loop with many consumers: every message is delivered to every
+/// subscribed [`EventsLoopTick`] whose filter lets it in.
+///
+/// Every subscriber has its own queue and its own reader task - the same one the
+/// [`super::EventsLoop`] uses - so a slow, panicking or timing out subscriber
+/// delays nobody but itself. [`Self::get_subscribers_stats`] shows how far behind
+/// every subscriber is.
+pub struct EventsLoopBroadcast<TModel: Clone + Send + 'static> {
+    subscribers: Mutex<Vec<Arc<EventsLoopSubscriber<TModel>>>>,
+    name: Arc<String>,
+    iteration_timeout: Duration,
+    health: Mutex<Option<Arc<HealthReporter>>>,
+    started: Mutex<bool>,
+}
+
+impl<TModel: Clone + Send + 'static> EventsLoopBroadcast<TModel> {
+    pub fn new(name: impl Into<StrOrString<'static>>) -> Self {
+        Self {
+            subscribers: Mutex::new(Vec::new()),
+            name: Arc::new(name.into().to_string()),
+            iteration_timeout: Duration::from_secs(30),
+            health: Mutex::new(None),
+            started: Mutex::new(false),
+        }
+    }
+
+    /// The timeout of a single tick of every subscriber.
+    pub fn set_iteration_timeout(mut self, timeout: Duration) -> Self {
+        self.iteration_timeout = timeout;
+        self
+    }
+
+    /// One reporter for all the subscribers - the error names the subscriber. Has
+    /// to be set before the loop is started.
+    pub fn set_health_reporter(&self, reporter: Arc<HealthReporter>) {
+        *self.health.lock() = Some(reporter);
+    }
+
+    pub fn subscribe(
+        &self,
+        name: impl Into<StrOrString<'static>>,
+        event_loop: Arc<dyn EventsLoopTick<TModel> + Send + Sync + 'static>,
+    ) {
+        self.add_subscriber(name.into(), event_loop, None);
+    }
+
+    /// Subscribes the tick to the messages the `filter` returns `true` for. The
+    /// filter is called by the publisher - the filtered out messages never get
+    /// into the queue of the subscriber.
+    pub fn subscribe_with_filter(
+        &self,
+        name: impl Into<StrOrString<'static>>,
+        event_loop: Arc<dyn EventsLoopTick<TModel> + Send + Sync + 'static>,
+        filter: impl Fn(&TModel) -> bool + Send + Sync + 'static,
+    ) {
+        self.add_subscriber(name.into(), event_loop, Some(Box::new(filter)));
+    }
+
+    fn add_subscriber(
+        &self,
+        name: StrOrString<'static>,
+        event_loop: Arc<dyn EventsLoopTick<TModel> + Send + Sync + 'static>,
+        filter: Option<EventsLoopFilter<TModel>>,
+    ) {
+        if *self.started.lock() {
+            panic!(
+                "Can not subscribe {} to events loop {} - it is already started",
+                name, self.name
+            );
+        }
+
+        let mut subscribers = self.subscribers.lock();
+
+        if subscribers
+            .iter()
+            .any(|itm| itm.name.as_str() == name.as_str())
+        {
+            panic!(
+                "Subscriber {} is already registered for events loop {}",
+                name, self.name
+            );
+        }
+
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        let metrics = Arc::new(EventsLoopMetrics::new());
+
+        subscribers.push(Arc::new(EventsLoopSubscriber {
+            name: Arc::new(name.to_string()),
+            sender,
+            filter,
+            metrics: metrics.clone(),
+            pending_inner: Mutex::new(Some(EventsLoopInner {
+                event_loop_tick: EventsLoopTickKind::Single(event_loop),
+                receiver: tokio::sync::Mutex::new(receiver),
+                metrics: Some(metrics),
+                health: None,
+            })),
+        }));
+    }
+
+    /// Takes the readers of the subscribers together with their names.
+    fn take_readers(&self) -> Vec<(Arc<String>, Arc<EventsLoopInner<TModel>>)> {
+        let mut started = self.started.lock();
+
+        if *started {
+            panic!("Events loop {} is already started.", self.name);
+        }
+
+        let subscribers = self.subscribers.lock();
+
+        if subscribers.is_empty() {
+            panic!("Events loop {} has no subscribers.", self.name);
+        }
+
+        *started = true;
+
+        let health = self.health.lock().clone();
+
+        subscribers
+            .iter()
+            .map(|subscriber| {
+                let mut inner = subscriber.pending_inner.lock().take().unwrap();
+                inner.health = health.clone();
+                (
+                    Arc::new(format!("{}.{}", self.name, subscriber.name)),
+                    Arc::new(inner),
+                )
+            })
+            .collect()
+    }
+
+    pub fn start(
+        &self,
+        app_states: Arc<dyn ApplicationStates + Send + Sync + 'static>,
+        logger: Arc<dyn Logger + Send + Sync + 'static>,
+    ) {
+        for (name, inner) in self.take_readers() {
+            tokio::spawn(super::event_loop_reader::events_loop_reader(
+                name,
+                inner,
+                app_states.clone(),
+                logger.clone(),
+                self.iteration_timeout,
+            ));
+        }
+    }
+
+    /// Hands the reader of every subscriber over to the supervisor - each of them
+    /// is a separate child.
+    pub fn start_supervised(
+        &self,
+        supervisor: &Supervisor,
+        app_states: Arc<dyn ApplicationStates + Send + Sync + 'static>,
+        logger: Arc<dyn Logger + Send + Sync + 'static>,
+    ) {
+        for (name, inner) in self.take_readers() {
+            supervisor.add_child(
+                format!("EventsLoop {}", name),
+                Arc::new(EventsLoopSupervisedReader {
+                    name,
+                    inner,
+                    app_states: app_states.clone(),
+                    logger: logger.clone(),
+                    iteration_timeout: self.iteration_timeout,
+                    stopped: AtomicBool::new(false),
+                }),
+            );
+        }
+    }
+
+    pub fn send(&self, model: TModel) {
+        self.send_with_priority(model, EventsLoopPriority::Normal);
+    }
+
+    pub fn send_with_priority(&self, model: TModel, priority: EventsLoopPriority) {
+        for subscriber in self.subscribers.lock().iter() {
+            subscriber.send(&model, priority);
+        }
+    }
+
+    /// Asks every subscriber to stop once the messages published before are
+    /// delivered.
+    pub fn stop(&self) {
+        for subscriber in self.subscribers.lock().iter() {
+            let _ = subscriber.sender.send(EventsLoopMessage::Shutdown);
+        }
+    }
+
+    pub fn get_subscribers_stats(&self) -> Vec<EventsLoopSubscriberStats> {
+        self.subscribers
+            .lock()
+            .iter()
+            .map(|itm| itm.metrics.get_stats(itm.name.as_str()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc, time::Duration};
+
+    use parking_lot::Mutex;
+
+    use crate::{AppStates, Logger};
+
+    use super::{EventsLoopBroadcast, EventsLoopTick};
+
+    struct TestLogger;
+
+    impl Logger for TestLogger {
+        fn write_info(&self, _: String, _: String, _: Option<HashMap<String, String>>) {}
+        fn write_warning(&self, _: String, _: String, _: Option<HashMap<String, String>>) {}
+        fn write_error(&self, _: String, _: String, _: Option<HashMap<String, String>>) {}
+        fn write_fatal_error(&self, _: String, _: String, _: Option<HashMap<String, String>>) {}
+        fn write_debug_info(&self, _: String, _: String, _: Option<HashMap<String, String>>) {}
+    }
+
+    #[derive(Default)]
+    struct CapturingTick {
+        captured: Mutex<Vec<u32>>,
+        panic_on: Option<u32>,
+        sleep_on: Option<u32>,
+    }
+
+    #[async_trait::async_trait]
+    impl EventsLoopTick<u32> for CapturingTick {
+        async fn started(&self) {}
+
+        async fn tick(&self, model: u32) {
+            if self.sleep_on == Some(model) {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+
+            if self.panic_on == Some(model) {
+                panic!("Tick is panicked on {}", model);
+            }
+
+            self.captured.lock().push(model);
+        }
+
+        async fn finished(&self) {}
+    }
+
+    fn rt() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    async fn wait_for(tick: &CapturingTick, expected: usize) {
+        for _ in 0..400 {
+            if tick.captured.lock().len() >= expected {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        panic!(
+            "Expected {} messages, got {:?}",
+            expected,
+            tick.captured.lock()
+        );
+    }
+
+    #[test]
+    fn every_subscriber_gets_every_message_it_is_subscribed_to() {
+        rt().block_on(async {
+            let events_loop = EventsLoopBroadcast::new("test-broadcast");
+
+            let all = Arc::new(CapturingTick::default());
+            let even = Arc::new(CapturingTick::default());
+
+            events_loop.subscribe("all", all.clone());
+            events_loop.subscribe_with_filter("even", even.clone(), |model| model % 2 == 0);
+
+            for model in 0..10 {
+                events_loop.send(model);
+            }
+
+            events_loop.start(
+                Arc::new(AppStates::create_initialized()),
+                Arc::new(TestLogger),
+            );
+
+            wait_for(&all, 10).await;
+            wait_for(&even, 5).await;
+
+            assert_eq!(*all.captured.lock(), (0..10).collect::<Vec<_>>());
+            assert_eq!(*even.captured.lock(), [0, 2, 4, 6, 8]);
+
+            let stats = events_loop.get_subscribers_stats();
+            let even_stats = stats.iter().find(|itm| itm.name == "even").unwrap();
+            assert_eq!(even_stats.published, 5);
+            assert_eq!(even_stats.filtered_out, 5);
+            assert_eq!(even_stats.delivered, 5);
+            assert_eq!(even_stats.lag, 0);
+            assert!(even_stats.last_delivered_at.is_some());
+        });
+    }
+
+    #[test]
+    fn panicking_and_stuck_subscribers_do_not_affect_the_others() {
+        rt().block_on(async {
+            let events_loop = EventsLoopBroadcast::new("test-isolation")
+                .set_iteration_timeout(Duration::from_millis(300));
+
+            let healthy = Arc::new(CapturingTick::default());
+            let panicking = Arc::new(CapturingTick {
+                panic_on: Some(1),
+                ..Default::default()
+            });
+            let stuck = Arc::new(CapturingTick {
+                sleep_on: Some(0),
+                ..Default::default()
+            });
+
+            events_loop.subscribe("healthy", healthy.clone());
+            events_loop.subscribe("panicking", panicking.clone());
+            events_loop.subscribe("stuck", stuck.clone());
+
+            events_loop.start(
+                Arc::new(AppStates::create_initialized()),
+                Arc::new(TestLogger),
+            );
+
+            for model in 0..3 {
+                events_loop.send(model);
+            }
+
+            wait_for(&healthy, 3).await;
+            assert_eq!(*healthy.captured.lock(), [0, 1, 2]);
+
+            let stats = events_loop.get_subscribers_stats();
+            let stuck_stats = stats.iter().find(|itm| itm.name == "stuck").unwrap();
+            assert_eq!(stuck_stats.published, 3);
+            assert!(stuck_stats.lag > 0);
+
+            wait_for(&panicking, 2).await;
+            assert_eq!(*panicking.captured.lock(), [0, 2]);
+
+            wait_for(&stuck, 2).await;
+            assert_eq!(*stuck.captured.lock(), [1, 2]);
+
+            let stats = events_loop.get_subscribers_stats();
+            let panicking_stats = stats.iter().find(|itm| itm.name == "panicking").unwrap();
+            assert_eq!(panicking_stats.panicked, 1);
+            assert_eq!(panicking_stats.delivered, 3);
+
+            let stuck_stats = stats.iter().find(|itm| itm.name == "stuck").unwrap();
+            assert_eq!(stuck_stats.timed_out, 1);
+            assert_eq!(stuck_stats.lag, 0);
+        });
+    }
+
+    #[test]
+    #[should_panic]
+    fn subscriber_names_are_unique() {
+        let events_loop = EventsLoopBroadcast::<u32>::new("test-names");
+        events_loop.subscribe("one", Arc::new(CapturingTick::default()));
+        events_loop.subscribe("one", Arc::new(CapturingTick::default()));
+    }
+}
@@ -0,0 +1,82 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::date_time::{AtomicDateTimeAsMicroseconds, DateTimeAsMicroseconds};
+
+/// Counters of a single consumer of the events loop. The publishing side counts
+/// what is put into the queue of the consumer, the reader counts what it is done
+/// with.
+pub(super) struct EventsLoopMetrics {
+    pub published: AtomicU64,
+    pub filtered_out: AtomicU64,
+    pub delivered: AtomicU64,
+    pub panicked: AtomicU64,
+    pub timed_out: AtomicU64,
+    pub last_delivered_at: AtomicDateTimeAsMicroseconds,
+}
+
+impl EventsLoopMetrics {
+    pub fn new() -> Self {
+        Self {
+            published: AtomicU64::new(0),
+            filtered_out: AtomicU64::new(0),
+            delivered: AtomicU64::new(0),
+            panicked: AtomicU64::new(0),
+            timed_out: AtomicU64::new(0),
+            last_delivered_at: AtomicDateTimeAsMicroseconds::new(0),
+        }
+    }
+
+    pub fn tick_finished(&self, amount: usize) {
+        self.delivered.fetch_add(amount as u64, Ordering::SeqCst);
+        self.last_delivered_at.update(DateTimeAsMicroseconds::now());
+    }
+
+    pub fn tick_panicked(&self, amount: usize) {
+        self.panicked.fetch_add(amount as u64, Ordering::SeqCst);
+        self.tick_finished(amount);
+    }
+
+    pub fn tick_timed_out(&self, amount: usize) {
+        self.timed_out.fetch_add(amount as u64, Ordering::SeqCst);
+        self.tick_finished(amount);
+    }
+
+    pub fn get_stats(&self, name: &str) -> EventsLoopSubscriberStats {
+        let delivered = self.delivered.load(Ordering::SeqCst);
+        let published = self.published.load(Ordering::SeqCst);
+        let last_delivered_at = self.last_delivered_at.as_date_time();
+
+        EventsLoopSubscriberStats {
+            name: name.to_string(),
+            published,
+            filtered_out: self.filtered_out.load(Ordering::SeqCst),
+            delivered,
+            panicked: self.panicked.load(Ordering::SeqCst),
+            timed_out: self.timed_out.load(Ordering::SeqCst),
+            lag: published.saturating_sub(delivered),
+            last_delivered_at: if last_delivered_at.unix_microseconds == 0 {
+                None
+            } else {
+                Some(last_delivered_at)
+            },
+        }
+    }
+}
+
+/// The snapshot of the counters of a subscriber of the [`super::EventsLoopBroadcast`].
+#[derive(Debug, Clone)]
+pub struct EventsLoopSubscriberStats {
+    pub name: String,
+    /// Messages put into the queue of the subscriber.
+    pub published: u64,
+    /// Messages the filter of the subscriber did not let in.
+    pub filtered_out: u64,
+    /// Messages the tick of the subscriber is done with - including the panicked
+    /// and the timed out ones.
+    pub delivered: u64,
+    pub panicked: u64,
+    pub timed_out: u64,
+    /// Messages waiting in the queue of the subscriber (or being handled right now).
+    pub lag: u64,
+    pub last_delivered_at: Option<DateTimeAsMicroseconds>,
+}
@@ -0,0 +1,12 @@
+/// Priority of a message of the [`super::EventsLoop`].
+///
+/// Of the messages which are ready to be delivered the one with the highest priority
+/// goes first; messages of the same priority are delivered in the order they were
+/// published (or became due, for scheduled ones).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum EventsLoopPriority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
@@ -1,6 +1,10 @@
 use std::sync::Arc;
 
-use super::EventsLoopMessage;
+use crate::date_time::DateTimeAsMicroseconds;
+
+use super::{
+    EventsLoopMessage, EventsLoopPriority, EventsLoopScheduledHandle, EventsLoopScheduledMessage,
+};
 
 
 pub struct EventsLoopPublisher<TModel: 'static> {
@@ -21,7 +25,40 @@ impl<TModel: 'static> EventsLoopPublisher<TModel> {
     }
 
     pub fn send(&self, model: TModel) {
-        if let Err(err) = self.sender.send(EventsLoopMessage::NewMessage(model)) {
+        self.send_message(EventsLoopMessage::NewMessage(model));
+    }
+
+    pub fn send_with_priority(&self, model: TModel, priority: EventsLoopPriority) {
+        self.send_message(EventsLoopMessage::NewMessageWithPriority(model, priority));
+    }
+
+    /// Delivers the message not earlier than `deliver_at` with the normal priority.
+    pub fn schedule(
+        &self,
+        model: TModel,
+        deliver_at: DateTimeAsMicroseconds,
+    ) -> EventsLoopScheduledHandle {
+        self.schedule_with_priority(model, deliver_at, EventsLoopPriority::Normal)
+    }
+
+    /// Delivers the message not earlier than `deliver_at`. Once due, the message
+    /// competes with the other ready ones by its priority.
+    ///
+    /// Scheduled messages which are not due at the moment the loop stops are
+    /// never delivered.
+    pub fn schedule_with_priority(
+        &self,
+        model: TModel,
+        deliver_at: DateTimeAsMicroseconds,
+        priority: EventsLoopPriority,
+    ) -> EventsLoopScheduledHandle {
+        let (message, handle) = EventsLoopScheduledMessage::new(model, priority, deliver_at);
+        self.send_message(EventsLoopMessage::Scheduled(message));
+        handle
+    }
+
+    fn send_message(&self, message: EventsLoopMessage<TModel>) {
+        if let Err(err) = self.sender.send(message) {
             panic!(
                 "Error while sending message to event loop {}. Err: {}",
                 self.name, err
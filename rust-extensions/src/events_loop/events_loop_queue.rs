@@ -0,0 +1,253 @@
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+use crate::date_time::DateTimeAsMicroseconds;
+
+use super::{EventsLoopPriority, EventsLoopScheduledMessage};
+
+struct ReadyItem<TModel> {
+    priority: EventsLoopPriority,
+    seq: u64,
+    model: TModel,
+}
+
+/// The max-heap pops the highest priority first, and the earliest published of
+/// the same priority.
+impl<TModel> Ord for ReadyItem<TModel> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl<TModel> PartialOrd for ReadyItem<TModel> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<TModel> PartialEq for ReadyItem<TModel> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<TModel> Eq for ReadyItem<TModel> {}
+
+struct ScheduledItem<TModel> {
+    seq: u64,
+    message: EventsLoopScheduledMessage<TModel>,
+}
+
+/// The max-heap pops the earliest `deliver_at` first, and the earliest scheduled
+/// of the same `deliver_at`.
+impl<TModel> Ord for ScheduledItem<TModel> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .message
+            .deliver_at
+            .cmp(&self.message.deliver_at)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl<TModel> PartialOrd for ScheduledItem<TModel> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<TModel> PartialEq for ScheduledItem<TModel> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<TModel> Eq for ScheduledItem<TModel> {}
+
+/// Messages the reader of the [`super::EventsLoop`] has received but not delivered
+/// yet: the ones ready to be delivered ordered by priority, and the scheduled ones
+/// ordered by the moment they become due.
+///
+/// A cancelled scheduled message stays in the heap until it is due - it is dropped
+/// then instead of being delivered.
+pub(super) struct EventsLoopQueue<TModel> {
+    ready: BinaryHeap<ReadyItem<TModel>>,
+    scheduled: BinaryHeap<ScheduledItem<TModel>>,
+    next_seq: u64,
+}
+
+impl<TModel> EventsLoopQueue<TModel> {
+    pub fn new() -> Self {
+        Self {
+            ready: BinaryHeap::new(),
+            scheduled: BinaryHeap::new(),
+            next_seq: 0,
+        }
+    }
+
+    fn get_next_seq(&mut self) -> u64 {
+        let result = self.next_seq;
+        self.next_seq += 1;
+        result
+    }
+
+    pub fn push_ready(&mut self, model: TModel, priority: EventsLoopPriority) {
+        let seq = self.get_next_seq();
+        self.ready.push(ReadyItem {
+            priority,
+            seq,
+            model,
+        });
+    }
+
+    pub fn push_scheduled(&mut self, message: EventsLoopScheduledMessage<TModel>) {
+        let seq = self.get_next_seq();
+        self.scheduled.push(ScheduledItem { seq, message });
+    }
+
+    /// Moves every scheduled message which is due at `now` to the ready ones.
+    pub fn promote_due(&mut self, now: DateTimeAsMicroseconds) {
+        while let Some(item) = self.scheduled.peek() {
+            if item.message.deliver_at.is_later_than(now) {
+                break;
+            }
+
+            let item = self.scheduled.pop().unwrap();
+
+            if item.message.set_delivered() {
+                let priority = item.message.priority;
+                self.push_ready(item.message.model, priority);
+            }
+        }
+    }
+
+    pub fn peek_ready(&self) -> Option<&TModel> {
+        self.ready.peek().map(|item| &item.model)
+    }
+
+    pub fn pop_ready(&mut self) -> Option<TModel> {
+        self.ready.pop().map(|item| item.model)
+    }
+
+    /// The moment the earliest not cancelled scheduled message becomes due.
+    pub fn get_next_deliver_at(&mut self) -> Option<DateTimeAsMicroseconds> {
+        while let Some(item) = self.scheduled.peek() {
+            if !item.message.is_cancelled() {
+                return Some(item.message.deliver_at);
+            }
+
+            self.scheduled.pop();
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule(
+        queue: &mut EventsLoopQueue<&'static str>,
+        model: &'static str,
+        priority: EventsLoopPriority,
+        deliver_at: i64,
+    ) -> super::super::EventsLoopScheduledHandle {
+        let (message, handle) = EventsLoopScheduledMessage::new(
+            model,
+            priority,
+            DateTimeAsMicroseconds::new(deliver_at),
+        );
+        queue.push_scheduled(message);
+        handle
+    }
+
+    fn drain(queue: &mut EventsLoopQueue<&'static str>) -> Vec<&'static str> {
+        let mut result = Vec::new();
+        while let Some(model) = queue.pop_ready() {
+            result.push(model);
+        }
+        result
+    }
+
+    #[test]
+    fn higher_priority_goes_first_and_fifo_within_priority() {
+        let mut queue = EventsLoopQueue::new();
+
+        queue.push_ready("low-1", EventsLoopPriority::Low);
+        queue.push_ready("normal-1", EventsLoopPriority::Normal);
+        queue.push_ready("high-1", EventsLoopPriority::High);
+        queue.push_ready("normal-2", EventsLoopPriority::Normal);
+        queue.push_ready("high-2", EventsLoopPriority::High);
+        queue.push_ready("low-2", EventsLoopPriority::Low);
+
+        assert_eq!(
+            drain(&mut queue),
+            ["high-1", "high-2", "normal-1", "normal-2", "low-1", "low-2"]
+        );
+    }
+
+    #[test]
+    fn scheduled_message_is_ready_only_when_due() {
+        let mut queue = EventsLoopQueue::new();
+
+        schedule(&mut queue, "later", EventsLoopPriority::Normal, 200);
+        schedule(&mut queue, "sooner", EventsLoopPriority::Normal, 100);
+
+        assert_eq!(queue.get_next_deliver_at().unwrap().unix_microseconds, 100);
+
+        queue.promote_due(DateTimeAsMicroseconds::new(99));
+        assert!(queue.pop_ready().is_none());
+
+        queue.promote_due(DateTimeAsMicroseconds::new(100));
+        assert_eq!(drain(&mut queue), ["sooner"]);
+        assert_eq!(queue.get_next_deliver_at().unwrap().unix_microseconds, 200);
+
+        queue.promote_due(DateTimeAsMicroseconds::new(1000));
+        assert_eq!(drain(&mut queue), ["later"]);
+        assert!(queue.get_next_deliver_at().is_none());
+    }
+
+    #[test]
+    fn due_message_keeps_its_priority() {
+        let mut queue = EventsLoopQueue::new();
+
+        queue.push_ready("normal", EventsLoopPriority::Normal);
+        let handle = schedule(&mut queue, "scheduled-high", EventsLoopPriority::High, 100);
+
+        queue.promote_due(DateTimeAsMicroseconds::new(100));
+        assert!(handle.is_delivered());
+        assert_eq!(drain(&mut queue), ["scheduled-high", "normal"]);
+    }
+
+    #[test]
+    fn cancelled_message_is_not_delivered() {
+        let mut queue = EventsLoopQueue::new();
+
+        let cancelled = schedule(&mut queue, "cancelled", EventsLoopPriority::Normal, 100);
+        schedule(&mut queue, "kept", EventsLoopPriority::Normal, 200);
+
+        assert!(cancelled.cancel());
+        assert!(cancelled.is_cancelled());
+        // The second cancel has nothing to cancel.
+        assert!(!cancelled.cancel());
+
+        assert_eq!(queue.get_next_deliver_at().unwrap().unix_microseconds, 200);
+
+        queue.promote_due(DateTimeAsMicroseconds::new(1000));
+        assert_eq!(drain(&mut queue), ["kept"]);
+        assert!(queue.get_next_deliver_at().is_none());
+    }
+
+    #[test]
+    fn delivered_message_can_not_be_cancelled() {
+        let mut queue = EventsLoopQueue::new();
+
+        let handle = schedule(&mut queue, "delivered", EventsLoopPriority::Normal, 100);
+        queue.promote_due(DateTimeAsMicroseconds::new(100));
+
+        assert!(!handle.cancel());
+        assert_eq!(drain(&mut queue), ["delivered"]);
+    }
+}
@@ -0,0 +1,96 @@
+use std::sync::{
+    atomic::{AtomicU8, Ordering},
+    Arc,
+};
+
+use crate::date_time::DateTimeAsMicroseconds;
+
+use super::EventsLoopPriority;
+
+const STATE_PENDING: u8 = 0;
+const STATE_CANCELLED: u8 = 1;
+const STATE_DELIVERED: u8 = 2;
+
+/// The message which is delivered to the tick not earlier than `deliver_at`.
+pub struct EventsLoopScheduledMessage<TModel> {
+    pub model: TModel,
+    pub priority: EventsLoopPriority,
+    pub deliver_at: DateTimeAsMicroseconds,
+    state: Arc<AtomicU8>,
+}
+
+impl<TModel> EventsLoopScheduledMessage<TModel> {
+    pub(super) fn new(
+        model: TModel,
+        priority: EventsLoopPriority,
+        deliver_at: DateTimeAsMicroseconds,
+    ) -> (Self, EventsLoopScheduledHandle) {
+        let state = Arc::new(AtomicU8::new(STATE_PENDING));
+
+        let handle = EventsLoopScheduledHandle {
+            state: state.clone(),
+            deliver_at,
+        };
+
+        let message = Self {
+            model,
+            priority,
+            deliver_at,
+            state,
+        };
+
+        (message, handle)
+    }
+
+    pub(super) fn is_cancelled(&self) -> bool {
+        self.state.load(Ordering::SeqCst) == STATE_CANCELLED
+    }
+
+    /// Marks the message as delivered. Returns `false` if it was cancelled - then
+    /// it must not be delivered.
+    pub(super) fn set_delivered(&self) -> bool {
+        self.state
+            .compare_exchange(
+                STATE_PENDING,
+                STATE_DELIVERED,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            )
+            .is_ok()
+    }
+}
+
+/// Given back by `schedule` - lets the publisher cancel the scheduled message
+/// until it is due.
+#[derive(Clone)]
+pub struct EventsLoopScheduledHandle {
+    state: Arc<AtomicU8>,
+    deliver_at: DateTimeAsMicroseconds,
+}
+
+impl EventsLoopScheduledHandle {
+    /// Cancels the message. Returns `false` if it is too late - the message is
+    /// already handed over to the tick (or was cancelled before).
+    pub fn cancel(&self) -> bool {
+        self.state
+            .compare_exchange(
+                STATE_PENDING,
+                STATE_CANCELLED,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            )
+            .is_ok()
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state.load(Ordering::SeqCst) == STATE_CANCELLED
+    }
+
+    pub fn is_delivered(&self) -> bool {
+        self.state.load(Ordering::SeqCst) == STATE_DELIVERED
+    }
+
+    pub fn get_deliver_at(&self) -> DateTimeAsMicroseconds {
+        self.deliver_at
+    }
+}
@@ -0,0 +1,47 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use crate::{supervisor::SupervisedTask, ApplicationStates, Logger};
+
+use super::events_loop::EventsLoopInner;
+
+/// The reader of the events loop owned by the [`crate::supervisor::Supervisor`].
+///
+/// The messages the dead reader has already taken from the channel but not
+/// delivered are lost - the ones still in the channel are delivered by the
+/// restarted reader.
+pub(super) struct EventsLoopSupervisedReader<TModel: Send + 'static> {
+    pub name: Arc<String>,
+    pub inner: Arc<EventsLoopInner<TModel>>,
+    pub app_states: Arc<dyn ApplicationStates + Send + Sync + 'static>,
+    pub logger: Arc<dyn Logger + Send + Sync + 'static>,
+    pub iteration_timeout: Duration,
+    pub stopped: AtomicBool,
+}
+
+#[async_trait::async_trait]
+impl<TModel: Send + 'static> SupervisedTask for EventsLoopSupervisedReader<TModel> {
+    async fn run(&self) {
+        let stopped = super::event_loop_reader::events_loop_reader(
+            self.name.clone(),
+            self.inner.clone(),
+            self.app_states.clone(),
+            self.logger.clone(),
+            self.iteration_timeout,
+        )
+        .await;
+
+        if stopped {
+            self.stopped.store(true, Ordering::SeqCst);
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
+}
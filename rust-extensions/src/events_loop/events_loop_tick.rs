@@ -5,3 +5,42 @@ pub trait EventsLoopTick<TModel: 'static>: Send + 'static {
     async fn tick(&self, model: TModel);
     async fn finished(&self);
 }
+
+/// The alternative to [`EventsLoopTick`] which gets all the messages available at
+/// the moment at once - bounded by the count and the size given to
+/// [`super::EventsLoop::register_batch_event_loop`].
+///
+/// The iteration timeout of the loop applies to a whole batch.
+#[async_trait::async_trait]
+pub trait EventsLoopBatchTick<TModel: 'static>: Send + 'static {
+    async fn started(&self);
+    async fn tick(&self, models: Vec<TModel>);
+    async fn finished(&self);
+
+    /// The size of the model in bytes, counted against the size limit of a batch.
+    /// The default `0` leaves batches bounded by the count only.
+    fn get_model_size(&self, _model: &TModel) -> usize {
+        0
+    }
+}
+
+/// The limits of a batch given to [`EventsLoopBatchTick::tick`]. A model larger
+/// than `max_size` alone is delivered in a batch of its own.
+#[derive(Debug, Clone, Copy)]
+pub struct EventsLoopBatchLimits {
+    pub max_count: usize,
+    pub max_size: usize,
+}
+
+impl EventsLoopBatchLimits {
+    pub fn new(max_count: usize, max_size: usize) -> Self {
+        if max_count == 0 {
+            panic!("EventsLoopBatchLimits.max_count must be greater than 0");
+        }
+
+        Self {
+            max_count,
+            max_size,
+        }
+    }
+}
@@ -2,7 +2,17 @@ mod events_loop;
 mod events_loop_tick;
 mod event_loop_reader;
 mod events_loop_publisher;
+mod events_loop_broadcast;
+mod events_loop_metrics;
+mod events_loop_priority;
+mod events_loop_queue;
+mod events_loop_scheduled;
+mod events_loop_supervised_reader;
 
 pub use events_loop::{EventsLoop, EventsLoopMessage};
-pub use events_loop_tick::EventsLoopTick;
+pub use events_loop_tick::{EventsLoopBatchLimits, EventsLoopBatchTick, EventsLoopTick};
 pub use events_loop_publisher::EventsLoopPublisher;
+pub use events_loop_priority::EventsLoopPriority;
+pub use events_loop_scheduled::{EventsLoopScheduledHandle, EventsLoopScheduledMessage};
+pub use events_loop_broadcast::EventsLoopBroadcast;
+pub use events_loop_metrics::EventsLoopSubscriberStats;
@@ -1,7 +1,12 @@
 use std::{sync::Arc, time::Duration};
 
 use crate::{
-    my_timer::timers_iteration::{execute_timer, execute_timers_iteration, RegisteredTimer},
+    health::HealthReporter,
+    lease::LeaseKeeper,
+    my_timer::timers_iteration::{
+        execute_timer_by_hand, execute_timers_iteration, RegisteredTimer,
+    },
+    my_timer::{TimerTickRecord, TimersHistory, TimersHistorySnapshot},
     ApplicationStates, Logger, MyTimerTick, RepeatTimerIteration,
 };
 
@@ -22,6 +27,9 @@ pub struct MyExactTimer {
     interval: ExactTimerInterval,
     timers: Vec<RegisteredTimer>,
     iteration_timeout: Duration,
+    health: Option<Arc<HealthReporter>>,
+    history: Arc<TimersHistory>,
+    lease: Option<Arc<LeaseKeeper>>,
 }
 
 impl MyExactTimer {
@@ -30,6 +38,9 @@ impl MyExactTimer {
             interval,
             timers: Vec::new(),
             iteration_timeout: Duration::from_secs(60),
+            health: None,
+            history: Arc::new(TimersHistory::new()),
+            lease: None,
         }
     }
 
@@ -41,6 +52,9 @@ impl MyExactTimer {
             interval,
             timers: Vec::new(),
             iteration_timeout,
+            health: None,
+            history: Arc::new(TimersHistory::new()),
+            lease: None,
         }
     }
 
@@ -48,6 +62,37 @@ impl MyExactTimer {
         self.iteration_timeout = iteration_timeout;
     }
 
+    /// Reported the same way as by [`MyTimer`](crate::MyTimer): a heartbeat per
+    /// fully finished pass, an error per panicked or timed out tick.
+    pub fn set_health_reporter(&mut self, reporter: Arc<HealthReporter>) {
+        self.health = Some(reporter);
+    }
+
+    /// Makes the timer tick only on the replica holding the lease, the same way
+    /// as [`MyTimer::set_lease`](crate::MyTimer::set_lease) does.
+    pub fn set_lease(&mut self, lease: Arc<LeaseKeeper>) {
+        self.lease = Some(lease);
+    }
+
+    /// Amount of the last ticks kept per timer - 10 by default, 0 turns the
+    /// history off.
+    pub fn set_history_capacity(&mut self, capacity: usize) {
+        self.history.set_capacity(capacity);
+    }
+
+    /// The last ticks of the timer, the oldest first. Panics if there is no timer
+    /// with the name.
+    pub fn get_history(&self, timer_name: &str) -> Vec<TimerTickRecord> {
+        match self.history.get(timer_name) {
+            Some(history) => history,
+            None => panic!("Timer with the name [{}] is not found", timer_name),
+        }
+    }
+
+    pub fn get_history_snapshot(&self) -> TimersHistorySnapshot {
+        self.history.get_snapshot()
+    }
+
     pub fn register_timer(
         &mut self,
         name: &str,
@@ -59,6 +104,7 @@ impl MyExactTimer {
             }
         }
 
+        self.history.register(name);
         self.timers.push((name.to_string(), my_timer_tick));
     }
 
@@ -67,25 +113,32 @@ impl MyExactTimer {
         app_states: Arc<dyn ApplicationStates + Send + Sync + 'static>,
         logger: Arc<dyn Logger + Send + Sync + 'static>,
     ) {
-        let timers = self.timers.clone();
+        if let Some(lease) = self.lease.as_ref() {
+            lease.start(app_states.clone(), logger.clone());
+        }
+
         tokio::spawn(exact_timer_loop(
-            timers,
-            self.interval,
+            ExactTimerLoop {
+                timers: self.timers.clone(),
+                interval: self.interval,
+                iteration_timeout: self.iteration_timeout,
+                health: self.health.clone(),
+                history: self.history.clone(),
+                lease: self.lease.clone(),
+            },
             app_states,
             logger,
-            self.iteration_timeout,
         ));
     }
 
-    /// Executes the named tick once, out of schedule. There is no mark to wait
-    /// for here, so a `RepeatTimerIteration::Immediately` is handed back to the
-    /// caller rather than acted upon.
+    /// Executes the named tick once, out of schedule, and writes it to the
+    /// history. There is no mark to wait for here, so a
+    /// `RepeatTimerIteration::Immediately` is handed back to the caller rather
+    /// than acted upon.
     pub async fn execute_timer(&self, timer_name: &str) -> RepeatTimerIteration {
-        for (timer_id, timer_tick) in &self.timers {
-            if timer_id == timer_name {
-                return tokio::spawn(execute_timer(timer_tick.clone()))
-                    .await
-                    .unwrap();
+        for timer in &self.timers {
+            if timer.0 == timer_name {
+                return execute_timer_by_hand(timer, &self.history).await;
             }
         }
 
@@ -93,13 +146,29 @@ impl MyExactTimer {
     }
 }
 
-async fn exact_timer_loop(
+struct ExactTimerLoop {
     timers: Vec<RegisteredTimer>,
     interval: ExactTimerInterval,
+    iteration_timeout: Duration,
+    health: Option<Arc<HealthReporter>>,
+    history: Arc<TimersHistory>,
+    lease: Option<Arc<LeaseKeeper>>,
+}
+
+async fn exact_timer_loop(
+    timer_loop: ExactTimerLoop,
     app_states: Arc<dyn ApplicationStates + Send + Sync + 'static>,
     logger: Arc<dyn Logger + Send + Sync + 'static>,
-    iteration_timeout: Duration,
 ) {
+    let ExactTimerLoop {
+        timers,
+        interval,
+        iteration_timeout,
+        health,
+        history,
+        lease,
+    } = timer_loop;
+
     let interval_micros = interval.get_duration_micros();
 
     while !app_states.is_initialized() {
@@ -121,10 +190,26 @@ async fn exact_timer_loop(
             break;
         }
 
+        // A replica which is not the leader skips the mark.
+        let _lease_guard = match lease.as_ref() {
+            Some(lease) => match lease.try_enter() {
+                Some(guard) => Some(guard),
+                None => continue,
+            },
+            None => None,
+        };
+
         let mut to_execute: Vec<&RegisteredTimer> = timers.iter().collect();
 
         loop {
-            to_execute = execute_timers_iteration(&to_execute, &logger, iteration_timeout).await;
+            to_execute = execute_timers_iteration(
+                &to_execute,
+                &logger,
+                iteration_timeout,
+                health.as_ref(),
+                &history,
+            )
+            .await;
 
             // Ticks which left their iteration on purpose are restarted right
             // away - each with a fresh timeout window. The extra passes do not
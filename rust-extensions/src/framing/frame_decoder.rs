@@ -0,0 +1,131 @@
+use super::*;
+
+/// Cuts frames out of the bytes as they come. A read may end anywhere - inside the length
+/// prefix or inside the payload; the bytes are kept until the frame is complete.
+pub struct FrameDecoder {
+    prefix: FrameLengthPrefix,
+    max_frame_size: usize,
+    buffer: Vec<u8>,
+    read_pos: usize,
+}
+
+impl FrameDecoder {
+    /// `max_frame_size` is capped by the max length the `prefix` can carry.
+    pub fn new(prefix: FrameLengthPrefix, max_frame_size: usize) -> Self {
+        Self {
+            prefix,
+            max_frame_size: max_frame_size.min(prefix.get_max_length()),
+            buffer: Vec::new(),
+            read_pos: 0,
+        }
+    }
+
+    pub fn get_max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+
+    pub fn push(&mut self, data: &[u8]) {
+        // Compact before growing, so a long stream does not pile up consumed frames.
+        if self.read_pos > 0 && self.read_pos >= self.buffer.len() / 2 {
+            self.buffer.drain(..self.read_pos);
+            self.read_pos = 0;
+        }
+
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// Amount of bytes of the frames which are not complete yet.
+    pub fn get_buffered_len(&self) -> usize {
+        self.buffer.len() - self.read_pos
+    }
+
+    /// `Ok(None)` - not enough bytes for the next frame yet. After an error the decoder is
+    /// out of sync with the stream and must not be used anymore.
+    pub fn try_get_frame(&mut self) -> Result<Option<Vec<u8>>, FramingError> {
+        let available = &self.buffer[self.read_pos..];
+
+        let (length, prefix_size) = match self.prefix.parse_length(available) {
+            ParseFrameLengthResult::Ok {
+                length,
+                prefix_size,
+            } => (length, prefix_size),
+            ParseFrameLengthResult::NotEnoughDataInBuffer => return Ok(None),
+        };
+
+        if length > self.max_frame_size {
+            return Err(FramingError::FrameTooLarge {
+                size: length,
+                max_frame_size: self.max_frame_size,
+            });
+        }
+
+        if available.len() < prefix_size + length {
+            return Ok(None);
+        }
+
+        let frame = available[prefix_size..prefix_size + length].to_vec();
+        self.read_pos += prefix_size + length;
+
+        if self.read_pos == self.buffer.len() {
+            self.buffer.clear();
+            self.read_pos = 0;
+        }
+
+        Ok(Some(frame))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_are_cut_out_of_reads_split_anywhere() {
+        for prefix in [FrameLengthPrefix::VarInt, FrameLengthPrefix::U32Le] {
+            let encoder = FrameEncoder::new(prefix, 1024);
+            let payloads: Vec<Vec<u8>> =
+                vec![vec![], vec![1], vec![7; 63], vec![9; 64], vec![5; 300]];
+
+            let mut stream = Vec::new();
+            for payload in &payloads {
+                encoder.encode(payload, &mut stream).unwrap();
+            }
+
+            for chunk_size in [1, 2, 3, 5, 64, stream.len()] {
+                let mut decoder = FrameDecoder::new(prefix, 1024);
+                let mut result = Vec::new();
+
+                for chunk in stream.chunks(chunk_size) {
+                    decoder.push(chunk);
+
+                    while let Some(frame) = decoder.try_get_frame().unwrap() {
+                        result.push(frame);
+                    }
+                }
+
+                assert_eq!(result, payloads, "{:?} by {} bytes", prefix, chunk_size);
+                assert_eq!(decoder.get_buffered_len(), 0);
+            }
+        }
+    }
+
+    #[test]
+    fn too_large_frame_is_rejected_by_its_prefix() {
+        let mut decoder = FrameDecoder::new(FrameLengthPrefix::U32Le, 16);
+        decoder.push(&1000u32.to_le_bytes());
+
+        let result = decoder.try_get_frame();
+        assert!(matches!(
+            result,
+            Err(FramingError::FrameTooLarge {
+                size: 1000,
+                max_frame_size: 16
+            })
+        ));
+
+        let encoder = FrameEncoder::new(FrameLengthPrefix::VarInt, 16);
+        let mut out = Vec::new();
+        assert!(encoder.encode(&[0; 17], &mut out).is_err());
+        assert!(out.is_empty());
+    }
+}
@@ -0,0 +1,46 @@
+use super::*;
+
+/// Writes `length prefix + payload` frames.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameEncoder {
+    prefix: FrameLengthPrefix,
+    max_frame_size: usize,
+}
+
+impl FrameEncoder {
+    /// `max_frame_size` is capped by the max length the `prefix` can carry.
+    pub fn new(prefix: FrameLengthPrefix, max_frame_size: usize) -> Self {
+        Self {
+            prefix,
+            max_frame_size: max_frame_size.min(prefix.get_max_length()),
+        }
+    }
+
+    pub fn get_prefix(&self) -> FrameLengthPrefix {
+        self.prefix
+    }
+
+    pub fn get_max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+
+    /// Appends the frame to `out`. `out` is left untouched if the payload is too large.
+    pub fn encode(&self, payload: &[u8], out: &mut Vec<u8>) -> Result<(), FramingError> {
+        if payload.len() > self.max_frame_size {
+            return Err(FramingError::FrameTooLarge {
+                size: payload.len(),
+                max_frame_size: self.max_frame_size,
+            });
+        }
+
+        self.prefix.write_length(payload.len(), out);
+        out.extend_from_slice(payload);
+        Ok(())
+    }
+
+    pub fn encode_to_vec(&self, payload: &[u8]) -> Result<Vec<u8>, FramingError> {
+        let mut result = Vec::with_capacity(payload.len() + 4);
+        self.encode(payload, &mut result)?;
+        Ok(result)
+    }
+}
@@ -0,0 +1,76 @@
+use crate::{ParseUInt32VariableSizeResult, UInt32VariableSize};
+
+/// How the length of a frame is written in front of its payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameLengthPrefix {
+    /// [`UInt32VariableSize`] - 1 to 4 bytes, lengths up to [`Self::VAR_INT_MAX_LENGTH`].
+    VarInt,
+    /// 4 bytes, little endian.
+    U32Le,
+}
+
+pub enum ParseFrameLengthResult {
+    Ok { length: usize, prefix_size: usize },
+    NotEnoughDataInBuffer,
+}
+
+impl FrameLengthPrefix {
+    pub const VAR_INT_MAX_LENGTH: usize = 1073741822;
+
+    pub fn get_max_length(&self) -> usize {
+        match self {
+            FrameLengthPrefix::VarInt => Self::VAR_INT_MAX_LENGTH,
+            FrameLengthPrefix::U32Le => u32::MAX as usize,
+        }
+    }
+
+    /// Panics if `length` is above [`Self::get_max_length`].
+    pub fn write_length(&self, length: usize, out: &mut Vec<u8>) {
+        if length > self.get_max_length() {
+            panic!(
+                "Frame length {} is above the max length {} of the {:?} prefix",
+                length,
+                self.get_max_length(),
+                self
+            );
+        }
+
+        match self {
+            FrameLengthPrefix::VarInt => UInt32VariableSize::new(length as u32).serialize(out),
+            FrameLengthPrefix::U32Le => out.extend_from_slice(&(length as u32).to_le_bytes()),
+        }
+    }
+
+    pub fn parse_length(&self, slice: &[u8]) -> ParseFrameLengthResult {
+        if slice.is_empty() {
+            return ParseFrameLengthResult::NotEnoughDataInBuffer;
+        }
+
+        match self {
+            FrameLengthPrefix::VarInt => match UInt32VariableSize::from_slice(slice) {
+                ParseUInt32VariableSizeResult::Ok { value, size } => ParseFrameLengthResult::Ok {
+                    length: value.get_value() as usize,
+                    prefix_size: size,
+                },
+                ParseUInt32VariableSizeResult::NotEnoughDataInBuffer(_) => {
+                    ParseFrameLengthResult::NotEnoughDataInBuffer
+                }
+            },
+            FrameLengthPrefix::U32Le => {
+                const SIZE: usize = 4;
+
+                if slice.len() < SIZE {
+                    return ParseFrameLengthResult::NotEnoughDataInBuffer;
+                }
+
+                let mut bytes = [0u8; SIZE];
+                bytes.copy_from_slice(&slice[..SIZE]);
+
+                ParseFrameLengthResult::Ok {
+                    length: u32::from_le_bytes(bytes) as usize,
+                    prefix_size: SIZE,
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use super::*;
+
+const READ_CHUNK_SIZE: usize = 8 * 1024;
+
+/// Reads whole frames from any [`AsyncRead`] - a socket, a file, a
+/// [`TokioQueue`](crate::tokio_queue::TokioQueue).
+pub struct FrameReader<TRead: AsyncRead + Unpin> {
+    reader: TRead,
+    decoder: FrameDecoder,
+    read_chunk: Vec<u8>,
+}
+
+impl<TRead: AsyncRead + Unpin> FrameReader<TRead> {
+    pub fn new(reader: TRead, prefix: FrameLengthPrefix, max_frame_size: usize) -> Self {
+        Self {
+            reader,
+            decoder: FrameDecoder::new(prefix, max_frame_size),
+            read_chunk: vec![0; READ_CHUNK_SIZE],
+        }
+    }
+
+    /// `Ok(None)` - the stream ended right after a complete frame.
+    /// [`FramingError::UnexpectedEof`] - it ended in the middle of one.
+    pub async fn read_frame(&mut self) -> Result<Option<Vec<u8>>, FramingError> {
+        loop {
+            if let Some(frame) = self.decoder.try_get_frame()? {
+                return Ok(Some(frame));
+            }
+
+            let read = self.reader.read(&mut self.read_chunk).await?;
+
+            if read == 0 {
+                let buffered = self.decoder.get_buffered_len();
+
+                if buffered == 0 {
+                    return Ok(None);
+                }
+
+                return Err(FramingError::UnexpectedEof { buffered });
+            }
+
+            self.decoder.push(&self.read_chunk[..read]);
+        }
+    }
+
+    pub fn into_inner(self) -> TRead {
+        self.reader
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncWriteExt;
+
+    use super::*;
+    use crate::tokio_queue::TokioQueue;
+
+    fn create_runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn frames_are_read_from_tokio_queue() {
+        create_runtime().block_on(async {
+            let queue = TokioQueue::new_bounded(16);
+            let publisher = queue.get_publisher();
+            let mut writer = FrameWriter::new(
+                queue.get_writer(),
+                FrameEncoder::new(FrameLengthPrefix::VarInt, 1024),
+            );
+
+            let writing = tokio::spawn(async move {
+                for i in 0..50u8 {
+                    writer.write_frame(&vec![i; i as usize * 3]).await.unwrap();
+                }
+
+                publisher.close();
+            });
+
+            let mut reader = FrameReader::new(queue, FrameLengthPrefix::VarInt, 1024);
+
+            for i in 0..50u8 {
+                let frame = reader.read_frame().await.unwrap().unwrap();
+                assert_eq!(frame, vec![i; i as usize * 3]);
+            }
+
+            assert!(reader.read_frame().await.unwrap().is_none());
+            writing.await.unwrap();
+        });
+    }
+
+    #[test]
+    fn eof_inside_a_frame_is_an_error() {
+        create_runtime().block_on(async {
+            let queue = TokioQueue::new();
+            let mut writer = queue.get_writer();
+
+            let frame = FrameEncoder::new(FrameLengthPrefix::U32Le, 1024)
+                .encode_to_vec(b"hello")
+                .unwrap();
+            writer.write_all(&frame[..6]).await.unwrap();
+            queue.get_publisher().close();
+
+            let mut reader = FrameReader::new(queue, FrameLengthPrefix::U32Le, 1024);
+            let result = reader.read_frame().await;
+
+            assert!(matches!(
+                result,
+                Err(FramingError::UnexpectedEof { buffered: 6 })
+            ));
+        });
+    }
+}
@@ -0,0 +1,37 @@
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use super::*;
+
+/// Writes frames into any [`AsyncWrite`] - a socket, a file, a
+/// [`TokioQueueWriter`](crate::tokio_queue::TokioQueueWriter).
+pub struct FrameWriter<TWrite: AsyncWrite + Unpin> {
+    writer: TWrite,
+    encoder: FrameEncoder,
+    buffer: Vec<u8>,
+}
+
+impl<TWrite: AsyncWrite + Unpin> FrameWriter<TWrite> {
+    pub fn new(writer: TWrite, encoder: FrameEncoder) -> Self {
+        Self {
+            writer,
+            encoder,
+            buffer: Vec::new(),
+        }
+    }
+
+    pub async fn write_frame(&mut self, payload: &[u8]) -> Result<(), FramingError> {
+        self.buffer.clear();
+        self.encoder.encode(payload, &mut self.buffer)?;
+        self.writer.write_all(&self.buffer).await?;
+        Ok(())
+    }
+
+    pub async fn flush(&mut self) -> Result<(), FramingError> {
+        self.writer.flush().await?;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> TWrite {
+        self.writer
+    }
+}
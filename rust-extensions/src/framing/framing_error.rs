@@ -0,0 +1,22 @@
+/// Why a frame could not be encoded or read.
+#[derive(Debug)]
+pub enum FramingError {
+    /// The frame is bigger than the max frame size. On reading it is detected by the
+    /// length prefix, before the payload is buffered - the stream can not be continued.
+    FrameTooLarge {
+        size: usize,
+        max_frame_size: usize,
+    },
+    /// The stream ended in the middle of a frame - with the amount of bytes of the
+    /// unfinished frame.
+    UnexpectedEof {
+        buffered: usize,
+    },
+    Io(std::io::Error),
+}
+
+impl From<std::io::Error> for FramingError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
@@ -0,0 +1,12 @@
+mod frame_length_prefix;
+pub use frame_length_prefix::*;
+mod framing_error;
+pub use framing_error::*;
+mod frame_encoder;
+pub use frame_encoder::*;
+mod frame_decoder;
+pub use frame_decoder::*;
+mod frame_reader;
+pub use frame_reader::*;
+mod frame_writer;
+pub use frame_writer::*;
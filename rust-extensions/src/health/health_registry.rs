@@ -0,0 +1,128 @@
+use std::{sync::Arc, time::Duration};
+
+use parking_lot::Mutex;
+
+use crate::{date_time::DateTimeAsMicroseconds, ApplicationStates, StrOrString};
+
+use super::{HealthReporter, HealthSnapshot, HealthStatus};
+
+/// Collects the [`HealthReporter`]s of the components of the application and
+/// aggregates them - together with the [`ApplicationStates`] - into the
+/// [`HealthSnapshot`] the readiness and liveness probes answer with.
+///
+/// The reporter is given to the component with its `set_health_reporter` - the
+/// timers, the executors, the events loops and the queues-to-save report
+/// themselves then.
+pub struct HealthRegistry {
+    app_states: Arc<dyn ApplicationStates + Send + Sync + 'static>,
+    reporters: Mutex<Vec<Arc<HealthReporter>>>,
+}
+
+impl HealthRegistry {
+    pub fn new(app_states: Arc<dyn ApplicationStates + Send + Sync + 'static>) -> Self {
+        Self {
+            app_states,
+            reporters: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Registers the component which is never unhealthy by being silent.
+    pub fn register(&self, name: impl Into<StrOrString<'static>>) -> Arc<HealthReporter> {
+        self.add_reporter(name.into(), None)
+    }
+
+    /// Registers the component which is unhealthy if it has not sent a heartbeat
+    /// for longer than `max_silence` - a timer is expected to tick, a busy queue
+    /// to be persisted.
+    pub fn register_with_max_silence(
+        &self,
+        name: impl Into<StrOrString<'static>>,
+        max_silence: Duration,
+    ) -> Arc<HealthReporter> {
+        self.add_reporter(name.into(), Some(max_silence))
+    }
+
+    fn add_reporter(
+        &self,
+        name: StrOrString<'static>,
+        max_silence: Option<Duration>,
+    ) -> Arc<HealthReporter> {
+        let mut reporters = self.reporters.lock();
+
+        if reporters.iter().any(|itm| itm.get_name() == name.as_str()) {
+            panic!("Health of the component {} is already registered", name);
+        }
+
+        let result = Arc::new(HealthReporter::new(name.to_string(), max_silence));
+        reporters.push(result.clone());
+        result
+    }
+
+    pub fn get_snapshot(&self) -> HealthSnapshot {
+        let now = DateTimeAsMicroseconds::now();
+
+        let components: Vec<_> = self
+            .reporters
+            .lock()
+            .iter()
+            .map(|itm| itm.get_health(now))
+            .collect();
+
+        let status = components
+            .iter()
+            .map(|itm| itm.status)
+            .max()
+            .unwrap_or(HealthStatus::Healthy);
+
+        HealthSnapshot {
+            status,
+            initialized: self.app_states.is_initialized(),
+            shutting_down: self.app_states.is_shutting_down(),
+            components,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use crate::AppStates;
+
+    use super::*;
+
+    #[test]
+    fn snapshot_is_as_bad_as_the_worst_component() {
+        let app_states = Arc::new(AppStates::create_un_initialized());
+        let registry = HealthRegistry::new(app_states.clone());
+
+        let timer = registry.register_with_max_silence("timer", Duration::from_secs(60));
+        let queue = registry.register("queue");
+
+        let snapshot = registry.get_snapshot();
+        assert_eq!(snapshot.status, HealthStatus::Healthy);
+        assert!(snapshot.is_alive());
+        assert!(!snapshot.is_ready());
+
+        app_states.set_initialized();
+        timer.heartbeat();
+        queue.report_error("Timeout");
+
+        let snapshot = registry.get_snapshot();
+        assert_eq!(snapshot.status, HealthStatus::Degraded);
+        assert!(snapshot.is_ready());
+        assert_eq!(snapshot.components[1].status, HealthStatus::Degraded);
+
+        let json = serde_json::to_value(&snapshot).unwrap();
+        assert_eq!(json["status"], "Degraded");
+        assert_eq!(json["components"][1]["last_error"], "Timeout");
+    }
+
+    #[test]
+    #[should_panic]
+    fn component_names_are_unique() {
+        let registry = HealthRegistry::new(Arc::new(AppStates::create_initialized()));
+        registry.register("queue");
+        registry.register("queue");
+    }
+}
@@ -0,0 +1,147 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use parking_lot::Mutex;
+
+use crate::date_time::{AtomicDateTimeAsMicroseconds, DateTimeAsMicroseconds};
+
+use super::{ComponentHealth, HealthStatus};
+
+/// What a single component tells the [`super::HealthRegistry`] about itself.
+///
+/// The component calls [`Self::heartbeat`] every time it has done its work
+/// successfully and [`Self::report_error`] every time it has failed. The component
+/// is degraded while the latest of the two is an error, and unhealthy if it has
+/// not sent a heartbeat for longer than `max_silence` - if the component is
+/// registered with one. A component which is idle by design (an events loop
+/// nobody sends to) is registered without it.
+pub struct HealthReporter {
+    name: String,
+    max_silence: Option<Duration>,
+    registered_at: DateTimeAsMicroseconds,
+    last_heartbeat: AtomicDateTimeAsMicroseconds,
+    errors: AtomicU64,
+    last_error: Mutex<Option<(DateTimeAsMicroseconds, String)>>,
+}
+
+impl HealthReporter {
+    pub(super) fn new(name: String, max_silence: Option<Duration>) -> Self {
+        Self {
+            name,
+            max_silence,
+            registered_at: DateTimeAsMicroseconds::now(),
+            last_heartbeat: AtomicDateTimeAsMicroseconds::new(0),
+            errors: AtomicU64::new(0),
+            last_error: Mutex::new(None),
+        }
+    }
+
+    pub fn get_name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn heartbeat(&self) {
+        self.last_heartbeat.update(DateTimeAsMicroseconds::now());
+    }
+
+    pub fn report_error(&self, error: impl Into<String>) {
+        self.errors.fetch_add(1, Ordering::SeqCst);
+        *self.last_error.lock() = Some((DateTimeAsMicroseconds::now(), error.into()));
+    }
+
+    fn get_last_heartbeat(&self) -> Option<DateTimeAsMicroseconds> {
+        let result = self.last_heartbeat.as_date_time();
+
+        if result.unix_microseconds == 0 {
+            None
+        } else {
+            Some(result)
+        }
+    }
+
+    pub fn get_health(&self, now: DateTimeAsMicroseconds) -> ComponentHealth {
+        let last_heartbeat = self.get_last_heartbeat();
+        let (last_error_at, last_error) = match self.last_error.lock().clone() {
+            Some((at, error)) => (Some(at), Some(error)),
+            None => (None, None),
+        };
+
+        ComponentHealth {
+            name: self.name.clone(),
+            status: self.get_status(now, last_heartbeat, last_error_at),
+            last_heartbeat,
+            last_error,
+            last_error_at,
+            errors: self.errors.load(Ordering::SeqCst),
+        }
+    }
+
+    fn get_status(
+        &self,
+        now: DateTimeAsMicroseconds,
+        last_heartbeat: Option<DateTimeAsMicroseconds>,
+        last_error_at: Option<DateTimeAsMicroseconds>,
+    ) -> HealthStatus {
+        if let Some(max_silence) = self.max_silence {
+            // A component which has never sent a heartbeat is given the same time
+            // from the moment it is registered.
+            let silent_since = last_heartbeat.unwrap_or(self.registered_at);
+
+            if now.duration_since(silent_since).as_positive_or_zero() > max_silence {
+                return HealthStatus::Unhealthy;
+            }
+        }
+
+        match (last_error_at, last_heartbeat) {
+            (Some(error_at), Some(heartbeat)) if error_at.is_later_than(heartbeat) => {
+                HealthStatus::Degraded
+            }
+            (Some(_), None) => HealthStatus::Degraded,
+            _ => HealthStatus::Healthy,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_latest_report_decides_between_healthy_and_degraded() {
+        let reporter = HealthReporter::new("test".to_string(), None);
+        let now = DateTimeAsMicroseconds::now();
+
+        assert_eq!(reporter.get_health(now).status, HealthStatus::Healthy);
+
+        reporter.report_error("Connection refused");
+        let health = reporter.get_health(now);
+        assert_eq!(health.status, HealthStatus::Degraded);
+        assert_eq!(health.last_error.as_deref(), Some("Connection refused"));
+        assert_eq!(health.errors, 1);
+
+        std::thread::sleep(Duration::from_millis(1));
+        reporter.heartbeat();
+        let health = reporter.get_health(DateTimeAsMicroseconds::now());
+        assert_eq!(health.status, HealthStatus::Healthy);
+        // The error is kept for the record.
+        assert_eq!(health.errors, 1);
+        assert!(health.last_error.is_some());
+    }
+
+    #[test]
+    fn silence_longer_than_allowed_is_unhealthy() {
+        let reporter = HealthReporter::new("test".to_string(), Some(Duration::from_secs(10)));
+        let now = DateTimeAsMicroseconds::now();
+
+        assert_eq!(reporter.get_health(now).status, HealthStatus::Healthy);
+
+        let later = now.add(Duration::from_secs(11));
+        assert_eq!(reporter.get_health(later).status, HealthStatus::Unhealthy);
+
+        reporter.heartbeat();
+        let later = DateTimeAsMicroseconds::now().add(Duration::from_secs(5));
+        assert_eq!(reporter.get_health(later).status, HealthStatus::Healthy);
+    }
+}
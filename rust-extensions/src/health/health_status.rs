@@ -0,0 +1,47 @@
+use serde::Serialize;
+
+use crate::date_time::DateTimeAsMicroseconds;
+
+/// Ordered from the best to the worst - the status of a group is the worst status
+/// of its members.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum HealthStatus {
+    Healthy,
+    /// The component is alive, but the last thing it reported is an error.
+    Degraded,
+    /// The component has not reported a heartbeat for longer than it is allowed to.
+    Unhealthy,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentHealth {
+    pub name: String,
+    pub status: HealthStatus,
+    pub last_heartbeat: Option<DateTimeAsMicroseconds>,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<DateTimeAsMicroseconds>,
+    pub errors: u64,
+}
+
+/// The snapshot given by [`super::HealthRegistry::get_snapshot`] - ready to be
+/// serialized as the answer of the readiness and liveness probes.
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthSnapshot {
+    pub status: HealthStatus,
+    pub initialized: bool,
+    pub shutting_down: bool,
+    pub components: Vec<ComponentHealth>,
+}
+
+impl HealthSnapshot {
+    /// Liveness: no component is stuck. A degraded component is alive - restarting
+    /// the process is not going to fix an unavailable database.
+    pub fn is_alive(&self) -> bool {
+        self.status != HealthStatus::Unhealthy
+    }
+
+    /// Readiness: the application is initialized, not shutting down and alive.
+    pub fn is_ready(&self) -> bool {
+        self.initialized && !self.shutting_down && self.is_alive()
+    }
+}
@@ -0,0 +1,7 @@
+mod health_registry;
+mod health_reporter;
+mod health_status;
+
+pub use health_registry::HealthRegistry;
+pub use health_reporter::HealthReporter;
+pub use health_status::{ComponentHealth, HealthSnapshot, HealthStatus};
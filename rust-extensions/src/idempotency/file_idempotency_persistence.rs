@@ -0,0 +1,340 @@
+use std::sync::Arc;
+
+use sha2::{Digest, Sha256};
+
+use crate::{date_time::DateTimeAsMicroseconds, StrOrString};
+
+use super::{IdempotencyPersistedResult, IdempotencyPersistence};
+
+const KIND_OK: u8 = 0;
+const KIND_ERR: u8 = 1;
+
+const HEADER_SIZE: usize = 18;
+const KEY_LEN_SIZE: usize = 4;
+
+const RECORD_EXTENSION: &str = "result";
+const TMP_EXTENSION: &str = "tmp";
+
+/// Turns the results into bytes and back for [`FileIdempotencyPersistence`].
+///
+/// `None` from a `deserialize_*` means the payload can not be read any more (the type
+/// has changed since it was saved) - such a result is dropped, the key is executed again
+/// on its next retry.
+pub trait IdempotencyResultSerializer<TOk, TErr>: Send + Sync + 'static {
+    fn serialize_ok(&self, ok: &TOk) -> Vec<u8>;
+    fn deserialize_ok(&self, payload: &[u8]) -> Option<TOk>;
+
+    fn serialize_err(&self, err: &TErr) -> Vec<u8>;
+    fn deserialize_err(&self, payload: &[u8]) -> Option<TErr>;
+}
+
+/// Keeps the memorized results in a directory - one file per key.
+///
+/// The file is named after the SHA-256 of the key - 64 hex chars, so a key of any length
+/// makes a valid file name, and the keys coming from the clients can not be crafted to
+/// share a file. It holds one byte of the result kind (`0` - ok,
+/// `1` - error), the completion time as little-endian unix microseconds, one byte telling
+/// whether there is a fingerprint and the fingerprint itself (little-endian, zero if there
+/// is none), the length of the key as a little-endian u32 and the key itself, then the
+/// serialized payload. A file whose name is not the hash of the key it holds is dropped on
+/// load. A result is written to a `.tmp` file and renamed over the `.result` one, so a
+/// crash never leaves it half written. The directory is created on the first save.
+pub struct FileIdempotencyPersistence<TOk: Send + Sync + 'static, TErr: Send + Sync + 'static> {
+    dir: String,
+    serializer: Arc<dyn IdempotencyResultSerializer<TOk, TErr>>,
+}
+
+impl<TOk: Send + Sync + 'static, TErr: Send + Sync + 'static>
+    FileIdempotencyPersistence<TOk, TErr>
+{
+    pub fn new(
+        dir: impl Into<StrOrString<'static>>,
+        serializer: Arc<dyn IdempotencyResultSerializer<TOk, TErr>>,
+    ) -> Self {
+        Self {
+            dir: dir.into().to_string(),
+            serializer,
+        }
+    }
+
+    pub fn get_dir(&self) -> &str {
+        self.dir.as_str()
+    }
+
+    fn get_file_name(&self, key: &str, extension: &str) -> std::path::PathBuf {
+        std::path::Path::new(&self.dir).join(format!("{}.{}", get_key_hash(key), extension))
+    }
+
+    fn serialize(&self, record: &IdempotencyPersistedResult<TOk, TErr>) -> Vec<u8> {
+        let (kind, payload) = match record.result.as_ref() {
+            Ok(ok) => (KIND_OK, self.serializer.serialize_ok(ok)),
+            Err(err) => (KIND_ERR, self.serializer.serialize_err(err)),
+        };
+
+        let key = record.key.as_bytes();
+
+        let mut result = Vec::with_capacity(HEADER_SIZE + KEY_LEN_SIZE + key.len() + payload.len());
+        result.push(kind);
+        result.extend_from_slice(&record.completed_at.unix_microseconds.to_le_bytes());
+        result.push(record.fingerprint.is_some() as u8);
+        result.extend_from_slice(&record.fingerprint.unwrap_or(0).to_le_bytes());
+        result.extend_from_slice(&(key.len() as u32).to_le_bytes());
+        result.extend_from_slice(key);
+        result.extend_from_slice(&payload);
+        result
+    }
+
+    fn deserialize(&self, content: &[u8]) -> Option<IdempotencyPersistedResult<TOk, TErr>> {
+        if content.len() < HEADER_SIZE + KEY_LEN_SIZE {
+            return None;
+        }
+
+        let completed_at = i64::from_le_bytes(content[1..9].try_into().ok()?);
+        let fingerprint = match content[9] {
+            0 => None,
+            1 => Some(u64::from_le_bytes(
+                content[10..HEADER_SIZE].try_into().ok()?,
+            )),
+            _ => return None,
+        };
+
+        let key_len = u32::from_le_bytes(
+            content[HEADER_SIZE..HEADER_SIZE + KEY_LEN_SIZE]
+                .try_into()
+                .ok()?,
+        ) as usize;
+        let key_start = HEADER_SIZE + KEY_LEN_SIZE;
+        let key = content.get(key_start..key_start.checked_add(key_len)?)?;
+        let key = String::from_utf8(key.to_vec()).ok()?;
+
+        let payload = &content[key_start + key_len..];
+
+        let result = match content[0] {
+            KIND_OK => Ok(Arc::new(self.serializer.deserialize_ok(payload)?)),
+            KIND_ERR => Err(Arc::new(self.serializer.deserialize_err(payload)?)),
+            _ => return None,
+        };
+
+        Some(IdempotencyPersistedResult {
+            key,
+            completed_at: DateTimeAsMicroseconds::new(completed_at),
+            fingerprint,
+            result,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl<TOk: Send + Sync + 'static, TErr: Send + Sync + 'static> IdempotencyPersistence<TOk, TErr>
+    for FileIdempotencyPersistence<TOk, TErr>
+{
+    async fn load(&self) -> std::io::Result<Vec<IdempotencyPersistedResult<TOk, TErr>>> {
+        let mut dir = match tokio::fs::read_dir(&self.dir).await {
+            Ok(dir) => dir,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+
+        let mut result = Vec::new();
+
+        while let Some(entry) = dir.next_entry().await? {
+            let path = entry.path();
+
+            if path.extension().and_then(|ext| ext.to_str()) != Some(RECORD_EXTENSION) {
+                continue;
+            }
+
+            let Some(key_hash) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+
+            let content = tokio::fs::read(&path).await?;
+
+            match self.deserialize(&content) {
+                Some(record) if get_key_hash(&record.key) == key_hash => result.push(record),
+                // Unreadable, or not the file of the key it holds - it would never be
+                // de-duplicated anyway.
+                _ => {
+                    let _ = tokio::fs::remove_file(&path).await;
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    async fn save(&self, record: &IdempotencyPersistedResult<TOk, TErr>) -> std::io::Result<()> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+
+        let tmp_file_name = self.get_file_name(&record.key, TMP_EXTENSION);
+        tokio::fs::write(&tmp_file_name, self.serialize(record)).await?;
+        tokio::fs::rename(
+            &tmp_file_name,
+            self.get_file_name(&record.key, RECORD_EXTENSION),
+        )
+        .await
+    }
+
+    async fn remove(&self, keys: &[String]) -> std::io::Result<()> {
+        for key in keys {
+            match tokio::fs::remove_file(self.get_file_name(key, RECORD_EXTENSION)).await {
+                Ok(_) => {}
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// SHA-256, hex-encoded. Not `DefaultHasher`: its output may change with the Rust
+/// version, and the file names have to survive an upgrade.
+fn get_key_hash(key: &str) -> String {
+    let mut result = String::with_capacity(64);
+
+    for b in Sha256::digest(key.as_bytes()) {
+        result.push_str(&format!("{:02x}", b));
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::date_time::DateTimeAsMicroseconds;
+
+    use super::*;
+
+    struct StringSerializer;
+
+    impl IdempotencyResultSerializer<String, String> for StringSerializer {
+        fn serialize_ok(&self, ok: &String) -> Vec<u8> {
+            ok.as_bytes().to_vec()
+        }
+
+        fn deserialize_ok(&self, payload: &[u8]) -> Option<String> {
+            String::from_utf8(payload.to_vec()).ok()
+        }
+
+        fn serialize_err(&self, err: &String) -> Vec<u8> {
+            err.as_bytes().to_vec()
+        }
+
+        fn deserialize_err(&self, payload: &[u8]) -> Option<String> {
+            String::from_utf8(payload.to_vec()).ok()
+        }
+    }
+
+    fn rt() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn key_hash_is_stable_and_fixed_length() {
+        assert_eq!(
+            get_key_hash(""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            get_key_hash("a"),
+            "ca978112ca1bbdcafac231b39a23dc4da786eff8147c4e72b9807785afee48bb"
+        );
+
+        for key in ["request-1", "a/b\\c:d", "ключ", &"k".repeat(300)] {
+            assert_eq!(get_key_hash(key).len(), 64);
+        }
+    }
+
+    #[test]
+    fn saved_results_are_loaded_back() {
+        rt().block_on(async {
+            let dir = std::env::temp_dir()
+                .join(format!("idempotency-test-{}", crate::uuid::generate_v4()))
+                .to_string_lossy()
+                .to_string();
+
+            let persistence =
+                FileIdempotencyPersistence::new(dir.clone(), Arc::new(StringSerializer));
+
+            assert!(persistence.load().await.unwrap().is_empty());
+
+            let completed_at = DateTimeAsMicroseconds::new(1_700_000_000_000_000);
+
+            for (key, fingerprint, result) in [
+                ("ok/key", Some(42), Ok(Arc::new("done".to_string()))),
+                ("err/key", None, Err(Arc::new("failed".to_string()))),
+                ("removed", None, Ok(Arc::new("gone".to_string()))),
+            ] {
+                let record = IdempotencyPersistedResult {
+                    key: key.to_string(),
+                    completed_at,
+                    fingerprint,
+                    result,
+                };
+                persistence.save(&record).await.unwrap();
+            }
+
+            persistence
+                .remove(&["removed".to_string(), "unknown".to_string()])
+                .await
+                .unwrap();
+
+            let mut loaded = persistence.load().await.unwrap();
+            loaded.sort_by(|a, b| a.key.cmp(&b.key));
+
+            assert_eq!(loaded.len(), 2);
+            assert_eq!(loaded[0].key, "err/key");
+            assert_eq!(loaded[0].result.as_ref().unwrap_err().as_str(), "failed");
+            assert_eq!(loaded[0].fingerprint, None);
+            assert_eq!(loaded[1].key, "ok/key");
+            assert_eq!(loaded[1].result.as_ref().unwrap().as_str(), "done");
+            assert_eq!(loaded[1].fingerprint, Some(42));
+            assert_eq!(
+                loaded[1].completed_at.unix_microseconds,
+                completed_at.unix_microseconds
+            );
+
+            tokio::fs::remove_dir_all(&dir).await.unwrap();
+        });
+    }
+
+    #[test]
+    fn long_key_is_saved() {
+        rt().block_on(async {
+            let dir = std::env::temp_dir()
+                .join(format!("idempotency-test-{}", crate::uuid::generate_v4()))
+                .to_string_lossy()
+                .to_string();
+
+            let persistence =
+                FileIdempotencyPersistence::new(dir.clone(), Arc::new(StringSerializer));
+
+            // Hex-encoded it would be a 600 chars long file name.
+            let key = "k".repeat(300);
+
+            let record = IdempotencyPersistedResult {
+                key: key.clone(),
+                completed_at: DateTimeAsMicroseconds::new(1_700_000_000_000_000),
+                fingerprint: None,
+                result: Ok(Arc::new("done".to_string())),
+            };
+            persistence.save(&record).await.unwrap();
+
+            let loaded = persistence.load().await.unwrap();
+            assert_eq!(loaded.len(), 1);
+            assert_eq!(loaded[0].key, key);
+            assert_eq!(loaded[0].result.as_ref().unwrap().as_str(), "done");
+
+            persistence.remove(&[key]).await.unwrap();
+            assert!(persistence.load().await.unwrap().is_empty());
+
+            tokio::fs::remove_dir_all(&dir).await.unwrap();
+        });
+    }
+}
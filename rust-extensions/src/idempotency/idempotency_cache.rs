@@ -1,12 +1,22 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, OnceLock};
 use std::time::Duration;
 
+use futures::FutureExt;
 use parking_lot::Mutex;
 
-use crate::{StrOrString, TaskCompletion};
+use crate::{
+    date_time::DateTimeAsMicroseconds, panic_message::get_panic_message, StrOrString,
+    TaskCompletion, TaskCompletionAwaiter,
+};
 
-use super::{IdempotencyCacheItem, IdempotencyEntry, IdempotencyExecution, IdempotencyResult};
+use super::{
+    is_fingerprint_mismatch, IdempotencyEntry, IdempotencyError, IdempotencyExecution,
+    IdempotencyFingerprint, IdempotencyInFlightMismatch, IdempotencyOwnerFailure,
+    IdempotencyPersistedResult, IdempotencyPersistence, IdempotencyResult, ParkedRetry,
+};
 
 /// How many completed results are kept by default.
 pub const DEFAULT_MAX_AMOUNT: usize = 1000;
@@ -16,37 +26,107 @@ pub const DEFAULT_EXECUTION_TIMEOUT: Duration = Duration::from_secs(5);
 
 type RegisteredExecution<TParams, TOk, TErr> = Arc<dyn IdempotencyExecution<TParams, TOk, TErr>>;
 
-/// A single flat queue: new keys are pushed to the back, the oldest results are dropped
-/// from the front. There is deliberately no index next to it - one container means there
-/// is no second structure that could drift out of sync with this one.
+/// The entries are looked up by key in the map. The completed keys are also queued in
+/// the order of their completion, so the oldest one is always at the front - that is
+/// what the eviction by `max_amount` and by `ttl` pops.
+///
+/// The queue is not cleaned up when a key leaves the map some other way, so it may hold
+/// stale records. A record is live only while the map holds the `Completed` entry of the
+/// same key with the same `completed_at` - the stale ones are skipped when popped.
 struct IdempotencyCacheInner<TOk, TErr> {
-    items: VecDeque<IdempotencyCacheItem<TOk, TErr>>,
+    items: HashMap<String, IdempotencyEntry<TOk, TErr>>,
+    completed: VecDeque<(String, DateTimeAsMicroseconds)>,
+    completed_amount: usize,
     max_amount: usize,
+    ttl: Option<Duration>,
 }
 
 impl<TOk, TErr> IdempotencyCacheInner<TOk, TErr> {
-    fn find_index(&self, key: &str) -> Option<usize> {
-        self.items.iter().position(|item| item.key == key)
+    fn is_expired(
+        &self,
+        completed_at: DateTimeAsMicroseconds,
+        now: DateTimeAsMicroseconds,
+    ) -> bool {
+        match self.ttl {
+            Some(ttl) => completed_at.add(ttl).unix_microseconds <= now.unix_microseconds,
+            None => false,
+        }
+    }
+
+    fn is_live(&self, key: &str, completed_at: DateTimeAsMicroseconds) -> bool {
+        match self.items.get(key) {
+            Some(IdempotencyEntry::Completed {
+                completed_at: entry_completed_at,
+                ..
+            }) => *entry_completed_at == completed_at,
+            _ => false,
+        }
+    }
+
+    fn get_completed(
+        &self,
+        key: &str,
+        now: DateTimeAsMicroseconds,
+    ) -> Option<&IdempotencyResult<TOk, TErr>> {
+        match self.items.get(key)? {
+            IdempotencyEntry::Completed {
+                result,
+                completed_at,
+                ..
+            } if !self.is_expired(*completed_at, now) => Some(result),
+            _ => None,
+        }
     }
 
-    fn get_completed_amount(&self) -> usize {
-        self.items.iter().filter(|item| item.entry.is_completed()).count()
+    fn add_completed(
+        &mut self,
+        key: String,
+        result: IdempotencyResult<TOk, TErr>,
+        completed_at: DateTimeAsMicroseconds,
+        fingerprint: Option<u64>,
+    ) -> Option<IdempotencyEntry<TOk, TErr>> {
+        self.completed.push_back((key.clone(), completed_at));
+        self.completed_amount += 1;
+
+        self.items.insert(
+            key,
+            IdempotencyEntry::Completed {
+                result,
+                completed_at,
+                fingerprint,
+            },
+        )
     }
 
-    /// Drops the oldest completed results until we are back within `max_amount`.
+    /// Drops the oldest completed results until we are back within `max_amount`, and the
+    /// expired ones. Returns the keys of the dropped results.
     ///
     /// Only `Completed` entries are eviction candidates: an `Executing` one is being
     /// awaited by somebody, and dropping it would panic every one of them. So `max_amount`
     /// caps the memorized answers, and in-flight executions sit on top of that.
-    fn gc(&mut self) {
-        while self.get_completed_amount() > self.max_amount {
-            let Some(index) = self.items.iter().position(|item| item.entry.is_completed())
-            else {
+    fn gc(&mut self, now: DateTimeAsMicroseconds) -> Vec<String> {
+        let mut evicted = Vec::new();
+
+        while let Some((key, completed_at)) = self.completed.front() {
+            if !self.is_live(key, *completed_at) {
+                self.completed.pop_front();
+                continue;
+            }
+
+            if self.completed_amount <= self.max_amount && !self.is_expired(*completed_at, now) {
+                break;
+            }
+
+            let Some((key, _)) = self.completed.pop_front() else {
                 break;
             };
 
-            self.items.remove(index);
+            self.items.remove(&key);
+            self.completed_amount -= 1;
+            evicted.push(key);
         }
+
+        evicted
     }
 }
 
@@ -68,15 +148,32 @@ impl<TOk, TErr> IdempotencyCacheInner<TOk, TErr> {
 /// The last `max_amount` results are kept, evicted **FIFO by completion time** (a cache
 /// hit does not refresh the entry). `max_amount == 0` is legal and means "de-duplicate
 /// concurrent retries, but do not remember anything afterwards". In-flight executions are
-/// never eviction candidates, so they do not count against `max_amount`.
+/// never eviction candidates, so they do not count against `max_amount`. With a `ttl` set
+/// a result is also forgotten once it is older than that - the key is executed again.
+///
+/// Lookups go through a hash map, so `max_amount` in the hundreds of thousands is fine.
 ///
-/// Lookups are a linear scan over a single queue, which is the right shape at these sizes
-/// (a `String` comparison rejects on length first) and keeps the whole state in one
-/// container. It is not the right shape for a `max_amount` in the hundreds of thousands.
+/// # Fingerprints
+///
+/// By default the key alone identifies the request, so a retry with the same key but
+/// different `params` gets the old result. With an [`IdempotencyFingerprint`] set, the
+/// fingerprint of the `params` is kept with the entry, and a retry through
+/// [`IdempotencyCache::execute_checked`] which carries a different one gets
+/// [`IdempotencyError::KeyReusedWithDifferentRequest`] instead. What such a retry does
+/// while the key is still being executed is up to [`IdempotencyInFlightMismatch`].
 ///
 /// It is designed to live inside an `AppCtx` as a plain field - every method takes
 /// `&self`, no outer `Mutex` needed.
 ///
+/// # Persistence
+///
+/// With an [`IdempotencyPersistence`] set, every memorized result is saved and every
+/// forgotten one is removed. [`IdempotencyCache::restore`] called on start brings the
+/// saved results back - so a retry which comes after a redeploy is still de-duplicated.
+/// A result is saved before it is memorized, so the retries parked on it are released
+/// once the save is over. It is served from memory whether saving it succeeded or not;
+/// the failures are counted by [`IdempotencyCache::get_persistence_errors_amount`].
+///
 /// # Cancellation, timeouts and panics
 ///
 /// The first caller owns the execution, so it also owns its fate. If that caller's future
@@ -86,6 +183,12 @@ impl<TOk, TErr> IdempotencyCacheInner<TOk, TErr> {
 /// behaviour: their `get_result()` panics with `"Task is dropped"`. Nothing is memorized in
 /// any of those cases, because we do not know whether the side effect happened.
 ///
+/// A panic is hard to turn into a clean response, so with
+/// [`IdempotencyCache::set_owner_failure`] the parked retries can get a typed
+/// [`IdempotencyError`] from [`IdempotencyCache::execute_checked`] instead
+/// ([`IdempotencyOwnerFailure::Report`]), or take over the
+/// execution with their own `params` ([`IdempotencyOwnerFailure::TakeOver`]).
+///
 /// The timeout is just the third way to not produce a result, so it is handled as a panic
 /// like the other two: the execution future is dropped and the owner panics too. It also
 /// bounds how long an `Executing` entry can hold its key - without it a hung execution
@@ -141,6 +244,11 @@ pub struct IdempotencyCache<
     /// the hot path never touches the `Arc` refcount at all.
     execution: OnceLock<RegisteredExecution<TParams, TOk, TErr>>,
     execution_timeout: Duration,
+    fingerprint: Option<Arc<dyn IdempotencyFingerprint<TParams>>>,
+    in_flight_mismatch: IdempotencyInFlightMismatch,
+    owner_failure: IdempotencyOwnerFailure,
+    persistence: Option<Arc<dyn IdempotencyPersistence<TOk, TErr>>>,
+    persistence_errors: AtomicU64,
     name: Arc<String>,
 }
 
@@ -156,11 +264,19 @@ impl<TParams: Send + Sync + 'static, TOk: Send + Sync + 'static, TErr: Send + Sy
     pub fn new_with_max_amount(name: impl Into<StrOrString<'static>>, max_amount: usize) -> Self {
         Self {
             inner: Mutex::new(IdempotencyCacheInner {
-                items: VecDeque::new(),
+                items: HashMap::new(),
+                completed: VecDeque::new(),
+                completed_amount: 0,
                 max_amount,
+                ttl: None,
             }),
             execution: OnceLock::new(),
             execution_timeout: DEFAULT_EXECUTION_TIMEOUT,
+            fingerprint: None,
+            in_flight_mismatch: IdempotencyInFlightMismatch::default(),
+            owner_failure: IdempotencyOwnerFailure::default(),
+            persistence: None,
+            persistence_errors: AtomicU64::new(0),
             name: Arc::new(name.into().to_string()),
         }
     }
@@ -174,6 +290,51 @@ impl<TParams: Send + Sync + 'static, TOk: Send + Sync + 'static, TErr: Send + Sy
         self
     }
 
+    /// Forgets a result once it is older than `ttl` - on top of `max_amount`. Builder
+    /// style, as [`Self::set_execution_timeout`].
+    pub fn set_ttl(self, ttl: Duration) -> Self {
+        self.inner.lock().ttl = Some(ttl);
+        self
+    }
+
+    /// Verifies that a retry carries the same request - see the type documentation.
+    /// Builder style, as [`Self::set_execution_timeout`].
+    pub fn set_fingerprint(
+        mut self,
+        fingerprint: Arc<dyn IdempotencyFingerprint<TParams>>,
+    ) -> Self {
+        self.fingerprint = Some(fingerprint);
+        self
+    }
+
+    /// What a retry with a different fingerprint does while the key is still being
+    /// executed. Default [`IdempotencyInFlightMismatch::Reject`]. Builder style, as
+    /// [`Self::set_execution_timeout`].
+    pub fn set_in_flight_mismatch(
+        mut self,
+        in_flight_mismatch: IdempotencyInFlightMismatch,
+    ) -> Self {
+        self.in_flight_mismatch = in_flight_mismatch;
+        self
+    }
+
+    /// What the parked retries get if the execution ends without a result. Default
+    /// [`IdempotencyOwnerFailure::Panic`]. Builder style, as [`Self::set_execution_timeout`].
+    pub fn set_owner_failure(mut self, owner_failure: IdempotencyOwnerFailure) -> Self {
+        self.owner_failure = owner_failure;
+        self
+    }
+
+    /// Saves the memorized results - see the type documentation. Builder style, as
+    /// [`Self::set_execution_timeout`].
+    pub fn set_persistence(
+        mut self,
+        persistence: Arc<dyn IdempotencyPersistence<TOk, TErr>>,
+    ) -> Self {
+        self.persistence = Some(persistence);
+        self
+    }
+
     /// Registers the execution. One-shot: a second call panics.
     ///
     /// It is a separate step (not a constructor argument) so the execution is free to
@@ -199,55 +360,148 @@ impl<TParams: Send + Sync + 'static, TOk: Send + Sync + 'static, TErr: Send + Sy
         }
     }
 
+    /// Loads the results saved by the persistence - to be called on start, before the
+    /// first `execute`. The expired results are removed from the persistence, the keys
+    /// which are known already are left as they are. Returns the amount of the restored
+    /// results.
+    pub async fn restore(&self) -> std::io::Result<usize> {
+        let Some(persistence) = self.persistence.as_ref() else {
+            return Ok(0);
+        };
+
+        let mut loaded = persistence.load().await?;
+        loaded.sort_by_key(|record| record.completed_at.unix_microseconds);
+
+        let now = DateTimeAsMicroseconds::now();
+
+        let (restored, expired) = {
+            let mut inner = self.inner.lock();
+            let mut restored = 0;
+            let mut expired = Vec::new();
+
+            for record in loaded {
+                if inner.is_expired(record.completed_at, now) {
+                    expired.push(record.key);
+                    continue;
+                }
+
+                if inner.items.contains_key(&record.key) {
+                    continue;
+                }
+
+                inner.add_completed(
+                    record.key,
+                    record.result,
+                    record.completed_at,
+                    record.fingerprint,
+                );
+                restored += 1;
+            }
+
+            expired.extend(inner.gc(now));
+            (restored, expired)
+        };
+
+        self.forget_persisted(expired).await;
+
+        Ok(restored)
+    }
+
     /// Returns the result of `key`, executing it only if it has to be executed.
     ///
     /// See the type documentation for what happens on a retry, on cancellation and on a
     /// panic. `params` is consumed only by the caller which actually executes; the ones
     /// which get a memorized result simply drop it.
+    ///
+    /// Fingerprints are not verified here - the key alone identifies the request, and a
+    /// parked retry which gets an [`IdempotencyError`] with
+    /// [`IdempotencyOwnerFailure::Report`] panics with it. [`Self::execute_checked`]
+    /// returns both as errors instead.
     pub async fn execute(&self, key: String, params: TParams) -> IdempotencyResult<TOk, TErr> {
+        match self.execute_with(key, params, false).await {
+            Ok(result) => result,
+            Err(err) => panic!(
+                "Idempotency execution in the cache '{}' ended without a result: {:?}",
+                self.name, err
+            ),
+        }
+    }
+
+    /// Same as [`Self::execute`], but with a fingerprint set, a retry which carries a
+    /// different request gets [`IdempotencyError::KeyReusedWithDifferentRequest`], and the
+    /// typed outcomes of [`IdempotencyOwnerFailure::Report`] are returned rather than
+    /// panicked with.
+    pub async fn execute_checked(
+        &self,
+        key: String,
+        params: TParams,
+    ) -> Result<IdempotencyResult<TOk, TErr>, IdempotencyError> {
+        self.execute_with(key, params, true).await
+    }
+
+    /// `verify_fingerprint == false` still keeps the fingerprint with the entry, so a
+    /// later [`Self::execute_checked`] of the key verifies against it.
+    async fn execute_with(
+        &self,
+        key: String,
+        params: TParams,
+        verify_fingerprint: bool,
+    ) -> Result<IdempotencyResult<TOk, TErr>, IdempotencyError> {
         // Resolved before we claim the key: panicking here after inserting the `Executing`
-        // entry would leave that entry stuck in the queue forever.
+        // entry would leave that entry stuck in the map forever.
         let execution = self.get_execution();
 
-        let awaiter = {
-            let mut inner = self.inner.lock();
+        let fingerprint = self
+            .fingerprint
+            .as_ref()
+            .map(|fingerprint| fingerprint.get_fingerprint(&params));
 
-            match inner.find_index(key.as_str()) {
-                Some(index) => match &mut inner.items[index].entry {
-                    IdempotencyEntry::Completed(result) => return result.clone(),
-                    IdempotencyEntry::Executing(awaiters) => {
-                        let mut task_completion = TaskCompletion::new();
-                        let awaiter = task_completion.get_awaiter();
-                        awaiters.push(task_completion);
-                        Some(awaiter)
-                    }
+        loop {
+            let (action, evicted) =
+                self.get_execute_action(key.as_str(), fingerprint, verify_fingerprint);
+
+            self.forget_persisted(evicted).await;
+
+            match action {
+                ExecuteAction::Completed(result) => return Ok(result),
+                ExecuteAction::Rejected => {
+                    return Err(IdempotencyError::KeyReusedWithDifferentRequest)
+                }
+                ExecuteAction::Park(awaiter) => match awaiter.get_result().await {
+                    Ok(result) => return Ok(result),
+                    // The key is free by now - the next round claims it or parks on
+                    // whoever was faster.
+                    Err(_) if self.owner_failure == IdempotencyOwnerFailure::TakeOver => {}
+                    Err(err) => return Err(err),
                 },
-                None => {
-                    inner.items.push_back(IdempotencyCacheItem {
-                        key: key.clone(),
-                        entry: IdempotencyEntry::Executing(Vec::new()),
-                    });
-                    None
+                ExecuteAction::WaitForKey(receiver) => {
+                    // Woken up once the entry is gone or completed - either way the next
+                    // round decides.
+                    let _ = receiver.await;
                 }
+                ExecuteAction::Execute => break,
             }
-        };
-
-        if let Some(awaiter) = awaiter {
-            return awaiter.get_result().await;
         }
 
         // From here on we own the execution of this key. The guard makes sure the
         // `Executing` entry never outlives us: if this future is cancelled or the
         // execution panics, the entry is removed and the parked `TaskCompletion`s are
-        // dropped, which makes their awaiters panic with "Task is dropped".
-        let mut guard = ExecutionOwnerGuard::new(&self.inner, key);
+        // dropped, which makes their awaiters panic with "Task is dropped" - or get the
+        // failure, depending on `owner_failure`.
+        let mut guard = ExecutionOwnerGuard::new(&self.inner, key, fingerprint);
 
         // An overrun is the third way to not produce a result, so it is handled like the
         // other two: `timeout` drops the execution future, and the panic unwinds through
-        // the guard, which frees the key and releases the awaiters.
-        let executed = tokio::time::timeout(self.execution_timeout, execution.execute(params)).await;
+        // the guard, which frees the key and releases the awaiters. The panic is caught
+        // only to tell the awaiters its message - it is resumed right away.
+        let executed = tokio::time::timeout(
+            self.execution_timeout,
+            AssertUnwindSafe(execution.execute(params)).catch_unwind(),
+        )
+        .await;
 
         let Ok(executed) = executed else {
+            guard.set_failure(IdempotencyError::TimedOut);
             panic!(
                 "Idempotency execution of the key '{}' in the cache '{}' timed out after {:?}",
                 guard.get_key(),
@@ -256,48 +510,198 @@ impl<TParams: Send + Sync + 'static, TOk: Send + Sync + 'static, TErr: Send + Sy
             );
         };
 
+        let executed = match executed {
+            Ok(executed) => executed,
+            Err(payload) => {
+                guard.set_failure(IdempotencyError::Panicked(get_panic_message(
+                    payload.as_ref(),
+                )));
+                std::panic::resume_unwind(payload);
+            }
+        };
+
         let result = match executed {
             Ok(ok) => Ok(Arc::new(ok)),
             Err(err) => Err(Arc::new(err)),
         };
 
-        let awaiters = guard.commit(result.clone());
+        let key = guard.get_key().to_string();
+        let completed_at = DateTimeAsMicroseconds::now();
+
+        // Saved before the commit, while the entry is still `Executing` and so can not be
+        // evicted: an eviction removing the file before the save lands would leave an
+        // orphan which comes back on the next `restore`. With `max_amount == 0` the result
+        // is forgotten right away - nothing to save.
+        if self.inner.lock().max_amount > 0 {
+            self.persist(key, completed_at, fingerprint, &result).await;
+        }
+
+        let committed = guard.commit(result.clone(), completed_at);
 
         // Outside the lock. `try_*` and not the panicking versions: an awaiter could have
         // been cancelled while we were executing, and then its receiver is already gone.
-        for mut awaiter in awaiters {
-            let _ = match result.as_ref() {
-                Ok(ok) => awaiter.try_set_ok(ok.clone()),
-                Err(err) => awaiter.try_set_error(err.clone()),
-            };
+        for mut awaiter in committed.awaiters {
+            let _ = awaiter.try_set_ok(result.clone());
         }
 
-        result
+        // They wake up to a completed entry with a different fingerprint, so they are
+        // rejected.
+        drop(committed.mismatched);
+
+        self.forget_persisted(committed.evicted).await;
+
+        Ok(result)
+    }
+
+    /// Decides under the lock what `execute` does with `key`: returns the memorized
+    /// result, rejects, parks, or claims the key - in which case the caller executes.
+    fn get_execute_action(
+        &self,
+        key: &str,
+        fingerprint: Option<u64>,
+        verify_fingerprint: bool,
+    ) -> (ExecuteAction<TOk, TErr>, Vec<String>) {
+        let mut inner = self.inner.lock();
+
+        // The expired results are at the front of the queue - dropping them first makes
+        // an expired key look unknown.
+        let evicted = inner.gc(DateTimeAsMicroseconds::now());
+
+        let action = match inner.items.get_mut(key) {
+            Some(IdempotencyEntry::Completed {
+                result,
+                fingerprint: stored,
+                ..
+            }) => {
+                if verify_fingerprint && is_fingerprint_mismatch(*stored, fingerprint) {
+                    ExecuteAction::Rejected
+                } else {
+                    ExecuteAction::Completed(result.clone())
+                }
+            }
+            Some(IdempotencyEntry::Executing {
+                awaiters,
+                fingerprint: stored,
+                mismatched,
+            }) => {
+                if !verify_fingerprint || !is_fingerprint_mismatch(*stored, fingerprint) {
+                    let mut task_completion = TaskCompletion::new();
+
+                    // Unless the owner says otherwise, dropping the entry means its future
+                    // was dropped.
+                    if self.owner_failure != IdempotencyOwnerFailure::Panic {
+                        task_completion.set_drop_error(IdempotencyError::OwnerCancelled);
+                    }
+
+                    let awaiter = task_completion.get_awaiter();
+                    awaiters.push(task_completion);
+                    ExecuteAction::Park(awaiter)
+                } else {
+                    match self.in_flight_mismatch {
+                        IdempotencyInFlightMismatch::Reject => ExecuteAction::Rejected,
+                        IdempotencyInFlightMismatch::Wait => {
+                            let (sender, receiver) = tokio::sync::oneshot::channel();
+                            mismatched.push(sender);
+                            ExecuteAction::WaitForKey(receiver)
+                        }
+                    }
+                }
+            }
+            None => {
+                inner.items.insert(
+                    key.to_string(),
+                    IdempotencyEntry::Executing {
+                        awaiters: Vec::new(),
+                        fingerprint,
+                        mismatched: Vec::new(),
+                    },
+                );
+                ExecuteAction::Execute
+            }
+        };
+
+        (action, evicted)
     }
 
     /// Peeks the memorized result without executing anything. `None` means the key is
-    /// unknown or is being executed right now.
+    /// unknown, expired or is being executed right now.
     pub fn get_if_completed(&self, key: &str) -> Option<IdempotencyResult<TOk, TErr>> {
         let inner = self.inner.lock();
-
-        let index = inner.find_index(key)?;
-
-        match &inner.items[index].entry {
-            IdempotencyEntry::Completed(result) => Some(result.clone()),
-            IdempotencyEntry::Executing(_) => None,
-        }
+        inner
+            .get_completed(key, DateTimeAsMicroseconds::now())
+            .cloned()
     }
 
     /// Amount of memorized results - never above `max_amount`.
     pub fn get_completed_amount(&self) -> usize {
-        self.inner.lock().get_completed_amount()
+        self.inner.lock().completed_amount
     }
 
     /// Amount of executions which are in flight right now.
     pub fn get_executing_amount(&self) -> usize {
         let inner = self.inner.lock();
-        inner.items.len() - inner.get_completed_amount()
+        inner.items.len() - inner.completed_amount
     }
+
+    /// Amount of the failed saves and removals - the results are served from memory
+    /// regardless.
+    pub fn get_persistence_errors_amount(&self) -> u64 {
+        self.persistence_errors.load(Ordering::Relaxed)
+    }
+
+    async fn persist(
+        &self,
+        key: String,
+        completed_at: DateTimeAsMicroseconds,
+        fingerprint: Option<u64>,
+        result: &IdempotencyResult<TOk, TErr>,
+    ) {
+        let Some(persistence) = self.persistence.as_ref() else {
+            return;
+        };
+
+        let record = IdempotencyPersistedResult {
+            key,
+            completed_at,
+            fingerprint,
+            result: result.clone(),
+        };
+
+        if persistence.save(&record).await.is_err() {
+            self.persistence_errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    async fn forget_persisted(&self, keys: Vec<String>) {
+        if keys.is_empty() {
+            return;
+        }
+
+        let Some(persistence) = self.persistence.as_ref() else {
+            return;
+        };
+
+        if persistence.remove(&keys).await.is_err() {
+            self.persistence_errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+enum ExecuteAction<TOk, TErr> {
+    Completed(IdempotencyResult<TOk, TErr>),
+    Rejected,
+    Park(TaskCompletionAwaiter<IdempotencyResult<TOk, TErr>, IdempotencyError>),
+    /// A different request is in flight with this key - wait for it and try again.
+    WaitForKey(tokio::sync::oneshot::Receiver<()>),
+    Execute,
+}
+
+struct CommittedExecution<TOk, TErr> {
+    awaiters: Vec<ParkedRetry<TOk, TErr>>,
+    /// Dropped by the caller, outside the lock - that is what wakes them up.
+    mismatched: Vec<tokio::sync::oneshot::Sender<()>>,
+    /// Keys of the results the commit pushed out of the cache.
+    evicted: Vec<String>,
 }
 
 /// Owns the `Executing` entry for the duration of the execution.
@@ -309,16 +713,30 @@ struct ExecutionOwnerGuard<'s, TOk, TErr> {
     inner: &'s Mutex<IdempotencyCacheInner<TOk, TErr>>,
     /// `None` once committed - that is what disarms `Drop`.
     key: Option<String>,
+    fingerprint: Option<u64>,
+    /// Why the execution ended without a result, if we know - the awaiters which asked
+    /// for a typed outcome get it instead of `OwnerCancelled`.
+    failure: Option<IdempotencyError>,
 }
 
 impl<'s, TOk, TErr> ExecutionOwnerGuard<'s, TOk, TErr> {
-    fn new(inner: &'s Mutex<IdempotencyCacheInner<TOk, TErr>>, key: String) -> Self {
+    fn new(
+        inner: &'s Mutex<IdempotencyCacheInner<TOk, TErr>>,
+        key: String,
+        fingerprint: Option<u64>,
+    ) -> Self {
         Self {
             inner,
             key: Some(key),
+            fingerprint,
+            failure: None,
         }
     }
 
+    fn set_failure(&mut self, failure: IdempotencyError) {
+        self.failure = Some(failure);
+    }
+
     /// The key we are holding. Only valid before `commit` - which is the only place it is
     /// used from (diagnostics while the execution is still ours).
     fn get_key(&self) -> &str {
@@ -332,7 +750,8 @@ impl<'s, TOk, TErr> ExecutionOwnerGuard<'s, TOk, TErr> {
     fn commit(
         &mut self,
         result: IdempotencyResult<TOk, TErr>,
-    ) -> Vec<TaskCompletion<Arc<TOk>, Arc<TErr>>> {
+        completed_at: DateTimeAsMicroseconds,
+    ) -> CommittedExecution<TOk, TErr> {
         let key = self
             .key
             .take()
@@ -340,25 +759,25 @@ impl<'s, TOk, TErr> ExecutionOwnerGuard<'s, TOk, TErr> {
 
         let mut inner = self.inner.lock();
 
-        // Unreachable while we hold the key: nobody else can remove our entry.
-        let Some(index) = inner.find_index(key.as_str()) else {
-            return Vec::new();
-        };
-
-        let Some(mut item) = inner.items.remove(index) else {
-            return Vec::new();
-        };
-
-        let previous = std::mem::replace(&mut item.entry, IdempotencyEntry::Completed(result));
-
         // Back of the queue, so the eviction order stays "oldest completion first" even
         // when a slow execution finishes after ones which started later.
-        inner.items.push_back(item);
-        inner.gc();
+        let previous = inner.add_completed(key, result, completed_at, self.fingerprint);
+        let evicted = inner.gc(completed_at);
+
+        let (awaiters, mismatched) = match previous {
+            Some(IdempotencyEntry::Executing {
+                awaiters,
+                mismatched,
+                ..
+            }) => (awaiters, mismatched),
+            // Unreachable while we hold the key: nobody else can replace our entry.
+            _ => (Vec::new(), Vec::new()),
+        };
 
-        match previous {
-            IdempotencyEntry::Executing(awaiters) => awaiters,
-            IdempotencyEntry::Completed(_) => Vec::new(),
+        CommittedExecution {
+            awaiters,
+            mismatched,
+            evicted,
         }
     }
 }
@@ -369,15 +788,21 @@ impl<'s, TOk, TErr> Drop for ExecutionOwnerGuard<'s, TOk, TErr> {
             return; // committed - nothing to clean up
         };
 
-        let removed = {
-            let mut inner = self.inner.lock();
-            match inner.find_index(key.as_str()) {
-                Some(index) => inner.items.remove(index),
-                None => None,
+        let mut removed = self.inner.lock().items.remove(key.as_str());
+
+        if let (Some(failure), Some(IdempotencyEntry::Executing { awaiters, .. })) =
+            (self.failure.take(), removed.as_mut())
+        {
+            for awaiter in awaiters.iter_mut() {
+                // Only the ones parked for a typed outcome have a drop error.
+                if awaiter.drop_error.is_some() {
+                    awaiter.set_drop_error(failure.clone());
+                }
             }
-        };
+        }
 
-        // Outside the lock: dropping the parked `TaskCompletion`s notifies their awaiters.
+        // Outside the lock: dropping the parked `TaskCompletion`s notifies their awaiters,
+        // and the mismatched retries wake up to a free key.
         drop(removed);
     }
 }
@@ -646,8 +1071,8 @@ mod tests {
         });
     }
 
-    /// The queue holds `Executing` and `Completed` entries side by side, so eviction has
-    /// to step over the in-flight ones instead of blindly dropping the front.
+    /// `Executing` and `Completed` entries live side by side, so eviction has to step
+    /// over the in-flight ones instead of dropping whatever is the oldest.
     #[test]
     fn in_flight_execution_is_not_evicted_by_newer_results() {
         create_runtime().block_on(async {
@@ -663,8 +1088,8 @@ mod tests {
             yield_to_others().await;
             assert_eq!(cache.get_executing_amount(), 1);
 
-            // Two results complete while "a" is still in flight and sitting at the very
-            // front of the queue. `max_amount` is 1, so gc runs on both of them.
+            // Two results complete while "a", the oldest key, is still in flight.
+            // `max_amount` is 1, so gc runs on both of them.
             cache.execute("b".to_string(), 2).await.unwrap();
             cache.execute("c".to_string(), 3).await.unwrap();
 
@@ -814,10 +1239,9 @@ mod tests {
         });
     }
 
-    /// The `Err` twin of the test above - it is the one that pins `try_set_error` rather
-    /// than `try_set_ok`. Without it, swapping `try_set_error` for the panicking
-    /// `set_error` goes unnoticed, and in production that panic would hit the *owner*:
-    /// the caller which did the real work and whose result is already memorized.
+    /// The `Err` twin of the test above: a cancelled retry must not break the *owner*
+    /// whichever result it hands out - the owner is the caller which did the real work
+    /// and whose result is already memorized.
     #[test]
     fn cancelled_retry_does_not_break_the_owner_when_the_execution_fails() {
         create_runtime().block_on(async {
@@ -926,6 +1350,514 @@ mod tests {
         });
     }
 
+    #[test]
+    fn expired_result_is_executed_again() {
+        create_runtime().block_on(async {
+            let execution = TestExecution::new(TestOutcome::Ok);
+            let executions = execution.executions();
+            let cache: TestCache = IdempotencyCache::new_with_max_amount("test", 10)
+                .set_ttl(Duration::from_millis(50));
+            cache.register_execution(Arc::new(execution));
+
+            cache.execute("key".to_string(), 1).await.unwrap();
+            cache.execute("key".to_string(), 1).await.unwrap();
+            assert_eq!(executions.load(Ordering::SeqCst), 1);
+
+            tokio::time::sleep(Duration::from_millis(80)).await;
+
+            assert!(cache.get_if_completed("key").is_none());
+            cache.execute("key".to_string(), 1).await.unwrap();
+            assert_eq!(executions.load(Ordering::SeqCst), 2);
+            assert_eq!(cache.get_completed_amount(), 1);
+        });
+    }
+
+    type TestPersistedItem = (String, i64, Option<u64>, IdempotencyResult<String, String>);
+
+    #[derive(Default)]
+    struct TestPersistence {
+        items: parking_lot::Mutex<Vec<TestPersistedItem>>,
+        /// If set, saving this key can only finish once the test grants it a permit.
+        save_gate: Option<(String, Arc<Semaphore>)>,
+    }
+
+    impl TestPersistence {
+        fn get_keys(&self) -> Vec<String> {
+            let mut result: Vec<String> = self
+                .items
+                .lock()
+                .iter()
+                .map(|item| item.0.clone())
+                .collect();
+            result.sort();
+            result
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl IdempotencyPersistence<String, String> for TestPersistence {
+        async fn load(&self) -> std::io::Result<Vec<IdempotencyPersistedResult<String, String>>> {
+            let result = self
+                .items
+                .lock()
+                .iter()
+                .map(
+                    |(key, completed_at, fingerprint, result)| IdempotencyPersistedResult {
+                        key: key.clone(),
+                        completed_at: DateTimeAsMicroseconds::new(*completed_at),
+                        fingerprint: *fingerprint,
+                        result: result.clone(),
+                    },
+                )
+                .collect();
+
+            Ok(result)
+        }
+
+        async fn save(
+            &self,
+            record: &IdempotencyPersistedResult<String, String>,
+        ) -> std::io::Result<()> {
+            if let Some((key, gate)) = self.save_gate.as_ref() {
+                if key == &record.key {
+                    gate.acquire().await.unwrap().forget();
+                }
+            }
+
+            let mut items = self.items.lock();
+            items.retain(|item| item.0 != record.key);
+            items.push((
+                record.key.clone(),
+                record.completed_at.unix_microseconds,
+                record.fingerprint,
+                record.result.clone(),
+            ));
+            Ok(())
+        }
+
+        async fn remove(&self, keys: &[String]) -> std::io::Result<()> {
+            self.items.lock().retain(|item| !keys.contains(&item.0));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn persisted_results_are_de_duplicated_after_a_restart() {
+        create_runtime().block_on(async {
+            let persistence = Arc::new(TestPersistence::default());
+
+            let before_restart: TestCache = IdempotencyCache::new_with_max_amount("test", 2)
+                .set_persistence(persistence.clone());
+            before_restart.register_execution(Arc::new(TestExecution::new(TestOutcome::Ok)));
+
+            before_restart.execute("a".to_string(), 1).await.unwrap();
+            before_restart.execute("b".to_string(), 2).await.unwrap();
+            before_restart.execute("c".to_string(), 3).await.unwrap();
+
+            // "a" was evicted, so it is removed from the persistence too.
+            assert_eq!(persistence.get_keys(), vec!["b", "c"]);
+            drop(before_restart);
+
+            let execution = TestExecution::new(TestOutcome::Ok);
+            let executions = execution.executions();
+            let after_restart: TestCache = IdempotencyCache::new_with_max_amount("test", 2)
+                .set_persistence(persistence.clone());
+            after_restart.register_execution(Arc::new(execution));
+
+            assert_eq!(after_restart.restore().await.unwrap(), 2);
+
+            let result = after_restart.execute("b".to_string(), 2).await;
+            assert_eq!(result.unwrap().as_str(), "ok:2");
+            assert_eq!(executions.load(Ordering::SeqCst), 0);
+
+            after_restart.execute("a".to_string(), 1).await.unwrap();
+            assert_eq!(executions.load(Ordering::SeqCst), 1);
+            assert_eq!(after_restart.get_persistence_errors_amount(), 0);
+        });
+    }
+
+    #[test]
+    fn result_evicted_while_it_is_saved_is_not_left_persisted() {
+        create_runtime().block_on(async {
+            let gate = Arc::new(Semaphore::new(0));
+            let persistence = Arc::new(TestPersistence {
+                save_gate: Some(("a".to_string(), gate.clone())),
+                ..Default::default()
+            });
+
+            let cache: TestCache = IdempotencyCache::new_with_max_amount("test", 1)
+                .set_persistence(persistence.clone());
+            cache.register_execution(Arc::new(TestExecution::new(TestOutcome::Ok)));
+            let cache = Arc::new(cache);
+
+            let saving = tokio::spawn({
+                let cache = cache.clone();
+                async move { cache.execute("a".to_string(), 1).await }
+            });
+            yield_to_others().await;
+
+            // "b" would push "a" out - unless "a" is not memorized until it is saved.
+            cache.execute("b".to_string(), 2).await.unwrap();
+            assert_eq!(cache.get_executing_amount(), 1);
+
+            gate.add_permits(1);
+            saving.await.unwrap().unwrap();
+
+            assert!(cache.get_if_completed("a").is_some());
+            assert!(cache.get_if_completed("b").is_none());
+            assert_eq!(persistence.get_keys(), vec!["a"]);
+        });
+    }
+
+    #[test]
+    fn expired_persisted_results_are_not_restored() {
+        create_runtime().block_on(async {
+            let persistence = Arc::new(TestPersistence::default());
+            let now = DateTimeAsMicroseconds::now();
+            let old = now.sub(Duration::from_secs(120));
+
+            persistence.items.lock().push((
+                "old".to_string(),
+                old.unix_microseconds,
+                None,
+                Ok(Arc::new("old".to_string())),
+            ));
+            persistence.items.lock().push((
+                "new".to_string(),
+                now.unix_microseconds,
+                None,
+                Ok(Arc::new("new".to_string())),
+            ));
+
+            let cache: TestCache = IdempotencyCache::new("test")
+                .set_ttl(Duration::from_secs(60))
+                .set_persistence(persistence.clone());
+            cache.register_execution(Arc::new(TestExecution::new(TestOutcome::Ok)));
+
+            assert_eq!(cache.restore().await.unwrap(), 1);
+            assert!(cache.get_if_completed("old").is_none());
+            assert!(cache.get_if_completed("new").is_some());
+            assert_eq!(persistence.get_keys(), vec!["new"]);
+        });
+    }
+
+    struct TestFingerprint;
+
+    impl IdempotencyFingerprint<u64> for TestFingerprint {
+        fn get_fingerprint(&self, params: &u64) -> u64 {
+            *params
+        }
+    }
+
+    fn create_fingerprinted_cache(
+        execution: TestExecution,
+        in_flight_mismatch: IdempotencyInFlightMismatch,
+    ) -> (Arc<TestCache>, Arc<AtomicUsize>) {
+        let executions = execution.executions();
+        let cache: TestCache = IdempotencyCache::new("test")
+            .set_fingerprint(Arc::new(TestFingerprint))
+            .set_in_flight_mismatch(in_flight_mismatch);
+
+        let cache = Arc::new(cache);
+        cache.register_execution(Arc::new(execution));
+        (cache, executions)
+    }
+
+    #[test]
+    fn retry_with_a_different_request_is_rejected() {
+        create_runtime().block_on(async {
+            let (cache, executions) = create_fingerprinted_cache(
+                TestExecution::new(TestOutcome::Ok),
+                IdempotencyInFlightMismatch::Reject,
+            );
+
+            cache
+                .execute_checked("key".to_string(), 1)
+                .await
+                .unwrap()
+                .unwrap();
+
+            let reused = cache.execute_checked("key".to_string(), 2).await;
+            assert_eq!(
+                reused.unwrap_err(),
+                IdempotencyError::KeyReusedWithDifferentRequest
+            );
+
+            let retry = cache.execute_checked("key".to_string(), 1).await.unwrap();
+            assert_eq!(retry.unwrap().as_str(), "ok:1");
+            assert_eq!(executions.load(Ordering::SeqCst), 1);
+        });
+    }
+
+    #[test]
+    fn different_request_during_the_execution_is_rejected_right_away() {
+        create_runtime().block_on(async {
+            let (execution, gate) = TestExecution::gated(TestOutcome::Ok);
+            let (cache, executions) =
+                create_fingerprinted_cache(execution, IdempotencyInFlightMismatch::Reject);
+
+            let owner = tokio::spawn({
+                let cache = cache.clone();
+                async move { cache.execute_checked("key".to_string(), 1).await.unwrap() }
+            });
+
+            yield_to_others().await;
+
+            let reused = cache.execute_checked("key".to_string(), 2).await;
+            assert_eq!(
+                reused.unwrap_err(),
+                IdempotencyError::KeyReusedWithDifferentRequest
+            );
+
+            gate.add_permits(1);
+            assert_eq!(owner.await.unwrap().unwrap().as_str(), "ok:1");
+            assert_eq!(executions.load(Ordering::SeqCst), 1);
+        });
+    }
+
+    #[test]
+    fn waiting_different_request_is_rejected_once_the_execution_completes() {
+        create_runtime().block_on(async {
+            let (execution, gate) = TestExecution::gated(TestOutcome::Ok);
+            let (cache, executions) =
+                create_fingerprinted_cache(execution, IdempotencyInFlightMismatch::Wait);
+
+            let owner = tokio::spawn({
+                let cache = cache.clone();
+                async move { cache.execute_checked("key".to_string(), 1).await.unwrap() }
+            });
+
+            yield_to_others().await;
+
+            let reused = tokio::spawn({
+                let cache = cache.clone();
+                async move { cache.execute_checked("key".to_string(), 2).await }
+            });
+
+            yield_to_others().await;
+            assert!(!reused.is_finished());
+
+            gate.add_permits(1);
+            assert_eq!(owner.await.unwrap().unwrap().as_str(), "ok:1");
+            assert_eq!(
+                reused.await.unwrap().unwrap_err(),
+                IdempotencyError::KeyReusedWithDifferentRequest
+            );
+            assert_eq!(executions.load(Ordering::SeqCst), 1);
+        });
+    }
+
+    #[test]
+    fn waiting_different_request_executes_once_the_key_is_freed() {
+        create_runtime().block_on(async {
+            let (execution, gate) = TestExecution::gated_for_param(TestOutcome::Ok, 1);
+            let (cache, executions) =
+                create_fingerprinted_cache(execution, IdempotencyInFlightMismatch::Wait);
+
+            let owner = tokio::spawn({
+                let cache = cache.clone();
+                async move { cache.execute_checked("key".to_string(), 1).await }
+            });
+
+            yield_to_others().await;
+
+            let reused = tokio::spawn({
+                let cache = cache.clone();
+                async move { cache.execute_checked("key".to_string(), 2).await }
+            });
+
+            yield_to_others().await;
+
+            // The owner gives up without a result, so the key is free for the other request.
+            owner.abort();
+            assert!(owner.await.unwrap_err().is_cancelled());
+
+            let reused = reused.await.unwrap().unwrap();
+            assert_eq!(reused.unwrap().as_str(), "ok:2");
+            assert_eq!(executions.load(Ordering::SeqCst), 2);
+            drop(gate);
+        });
+    }
+
+    #[test]
+    fn fingerprint_is_verified_after_a_restart() {
+        create_runtime().block_on(async {
+            let persistence = Arc::new(TestPersistence::default());
+
+            let before_restart: TestCache = IdempotencyCache::new("test")
+                .set_fingerprint(Arc::new(TestFingerprint))
+                .set_persistence(persistence.clone());
+            before_restart.register_execution(Arc::new(TestExecution::new(TestOutcome::Ok)));
+            before_restart
+                .execute_checked("key".to_string(), 1)
+                .await
+                .unwrap()
+                .unwrap();
+
+            let after_restart: TestCache = IdempotencyCache::new("test")
+                .set_fingerprint(Arc::new(TestFingerprint))
+                .set_persistence(persistence.clone());
+            after_restart.register_execution(Arc::new(TestExecution::new(TestOutcome::Ok)));
+            after_restart.restore().await.unwrap();
+
+            assert_eq!(
+                after_restart
+                    .execute_checked("key".to_string(), 2)
+                    .await
+                    .unwrap_err(),
+                IdempotencyError::KeyReusedWithDifferentRequest
+            );
+        });
+    }
+
+    #[test]
+    fn unchecked_execute_does_not_verify_the_fingerprint() {
+        create_runtime().block_on(async {
+            let (cache, executions) = create_fingerprinted_cache(
+                TestExecution::new(TestOutcome::Ok),
+                IdempotencyInFlightMismatch::Reject,
+            );
+
+            cache.execute("key".to_string(), 1).await.unwrap();
+
+            let reused = cache.execute("key".to_string(), 2).await;
+            assert_eq!(reused.unwrap().as_str(), "ok:1");
+
+            // The fingerprint is kept all the same.
+            let checked = cache.execute_checked("key".to_string(), 2).await;
+            assert_eq!(
+                checked.unwrap_err(),
+                IdempotencyError::KeyReusedWithDifferentRequest
+            );
+            assert_eq!(executions.load(Ordering::SeqCst), 1);
+        });
+    }
+
+    fn create_reporting_cache(
+        execution: TestExecution,
+        owner_failure: IdempotencyOwnerFailure,
+        execution_timeout: Duration,
+    ) -> (Arc<TestCache>, Arc<AtomicUsize>) {
+        let executions = execution.executions();
+        let cache: TestCache = IdempotencyCache::new("test")
+            .set_execution_timeout(execution_timeout)
+            .set_owner_failure(owner_failure);
+
+        let cache = Arc::new(cache);
+        cache.register_execution(Arc::new(execution));
+        (cache, executions)
+    }
+
+    /// Parks a retry of "key" on an execution claimed by an owner task and returns both.
+    async fn park_retry(
+        cache: &Arc<TestCache>,
+        retry_params: u64,
+    ) -> (
+        tokio::task::JoinHandle<Result<IdempotencyResult<String, String>, IdempotencyError>>,
+        tokio::task::JoinHandle<Result<IdempotencyResult<String, String>, IdempotencyError>>,
+    ) {
+        let owner = tokio::spawn({
+            let cache = cache.clone();
+            async move { cache.execute_checked("key".to_string(), 1).await }
+        });
+
+        yield_to_others().await;
+
+        let retry = tokio::spawn({
+            let cache = cache.clone();
+            async move { cache.execute_checked("key".to_string(), retry_params).await }
+        });
+
+        yield_to_others().await;
+        assert_eq!(cache.get_executing_amount(), 1);
+
+        (owner, retry)
+    }
+
+    #[test]
+    fn reported_owner_cancellation() {
+        create_runtime().block_on(async {
+            let (execution, _gate) = TestExecution::gated(TestOutcome::Ok);
+            let (cache, _) = create_reporting_cache(
+                execution,
+                IdempotencyOwnerFailure::Report,
+                DEFAULT_EXECUTION_TIMEOUT,
+            );
+
+            let (owner, retry) = park_retry(&cache, 1).await;
+            owner.abort();
+
+            assert_eq!(
+                retry.await.unwrap().unwrap_err(),
+                IdempotencyError::OwnerCancelled
+            );
+            assert_eq!(cache.get_executing_amount(), 0);
+        });
+    }
+
+    #[test]
+    fn reported_owner_timeout() {
+        create_runtime().block_on(async {
+            let (execution, _gate) = TestExecution::gated(TestOutcome::Ok);
+            let (cache, _) = create_reporting_cache(
+                execution,
+                IdempotencyOwnerFailure::Report,
+                Duration::from_millis(100),
+            );
+
+            let (owner, retry) = park_retry(&cache, 1).await;
+
+            // The owner still panics - only the parked retry gets the typed outcome.
+            assert!(owner.await.unwrap_err().is_panic());
+            assert_eq!(
+                retry.await.unwrap().unwrap_err(),
+                IdempotencyError::TimedOut
+            );
+        });
+    }
+
+    #[test]
+    fn reported_owner_panic() {
+        create_runtime().block_on(async {
+            let (execution, gate) = TestExecution::gated(TestOutcome::Panic);
+            let (cache, _) = create_reporting_cache(
+                execution,
+                IdempotencyOwnerFailure::Report,
+                DEFAULT_EXECUTION_TIMEOUT,
+            );
+
+            let (owner, retry) = park_retry(&cache, 1).await;
+            gate.add_permits(1);
+
+            assert!(owner.await.unwrap_err().is_panic());
+            assert_eq!(
+                retry.await.unwrap().unwrap_err(),
+                IdempotencyError::Panicked("execution panicked".to_string())
+            );
+        });
+    }
+
+    #[test]
+    fn parked_retry_takes_over_after_the_owner_is_cancelled() {
+        create_runtime().block_on(async {
+            let (execution, _gate) = TestExecution::gated_for_param(TestOutcome::Ok, 1);
+            let (cache, executions) = create_reporting_cache(
+                execution,
+                IdempotencyOwnerFailure::TakeOver,
+                DEFAULT_EXECUTION_TIMEOUT,
+            );
+
+            let (owner, retry) = park_retry(&cache, 2).await;
+            owner.abort();
+
+            // It executes with its own params.
+            let result = retry.await.unwrap().unwrap();
+            assert_eq!(result.unwrap().as_str(), "ok:2");
+            assert_eq!(executions.load(Ordering::SeqCst), 2);
+            assert_eq!(cache.get_completed_amount(), 1);
+        });
+    }
+
     #[test]
     #[should_panic(expected = "Execution is not registered")]
     fn execute_without_registered_execution_panics() {
@@ -1,6 +1,8 @@
 use std::sync::Arc;
 
-use crate::TaskCompletion;
+use crate::{date_time::DateTimeAsMicroseconds, TaskCompletion};
+
+use super::IdempotencyError;
 
 /// What a retry of an already known key gets back.
 ///
@@ -8,22 +10,27 @@ use crate::TaskCompletion;
 /// sharing it costs one atomic increment and neither `TOk` nor `TErr` has to be `Clone`.
 pub type IdempotencyResult<TOk, TErr> = Result<Arc<TOk>, Arc<TErr>>;
 
+/// A retry parked on an execution. The error side is only used if the execution ends
+/// without a result - see [`super::IdempotencyOwnerFailure`].
+pub(crate) type ParkedRetry<TOk, TErr> =
+    TaskCompletion<IdempotencyResult<TOk, TErr>, IdempotencyError>;
+
 pub(crate) enum IdempotencyEntry<TOk, TErr> {
     /// Somebody is executing this key right now. Everybody else who came in meanwhile
     /// parked here - each of them owns the awaiter of one of these `TaskCompletion`s.
-    Executing(Vec<TaskCompletion<Arc<TOk>, Arc<TErr>>>),
+    Executing {
+        awaiters: Vec<ParkedRetry<TOk, TErr>>,
+        fingerprint: Option<u64>,
+        /// Retries with a different fingerprint, parked by
+        /// [`super::IdempotencyInFlightMismatch::Wait`]. Nothing is ever sent - dropping the
+        /// entry drops the senders, and that is what wakes them up.
+        mismatched: Vec<tokio::sync::oneshot::Sender<()>>,
+    },
     /// The execution is over and its result is memorized. Both `Ok` and `Err` land here:
-    /// a retry of this key never re-executes anything.
-    Completed(IdempotencyResult<TOk, TErr>),
-}
-
-impl<TOk, TErr> IdempotencyEntry<TOk, TErr> {
-    pub fn is_completed(&self) -> bool {
-        matches!(self, Self::Completed(_))
-    }
-}
-
-pub(crate) struct IdempotencyCacheItem<TOk, TErr> {
-    pub key: String,
-    pub entry: IdempotencyEntry<TOk, TErr>,
+    /// a retry of this key never re-executes anything - until the result expires.
+    Completed {
+        result: IdempotencyResult<TOk, TErr>,
+        completed_at: DateTimeAsMicroseconds,
+        fingerprint: Option<u64>,
+    },
 }
@@ -0,0 +1,17 @@
+/// Why [`IdempotencyCache::execute_checked`](super::IdempotencyCache::execute_checked) returned
+/// no result.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdempotencyError {
+    /// The key is already known with a different fingerprint - see
+    /// [`IdempotencyFingerprint`](super::IdempotencyFingerprint).
+    KeyReusedWithDifferentRequest,
+    /// The retry parked on an execution whose owner was dropped before it produced a
+    /// result. Only with [`IdempotencyOwnerFailure::Report`](super::IdempotencyOwnerFailure::Report).
+    OwnerCancelled,
+    /// The retry parked on an execution which overran the execution timeout. Only with
+    /// [`IdempotencyOwnerFailure::Report`](super::IdempotencyOwnerFailure::Report).
+    TimedOut,
+    /// The retry parked on an execution which panicked - with the panic message. Only
+    /// with [`IdempotencyOwnerFailure::Report`](super::IdempotencyOwnerFailure::Report).
+    Panicked(String),
+}
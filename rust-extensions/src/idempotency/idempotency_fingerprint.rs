@@ -0,0 +1,30 @@
+/// Tells whether a retry carries the same request as the one which claimed the key.
+///
+/// Typically a hash of the fields of `TParams` which define the request. The cache keeps
+/// the fingerprint with the entry, and a retry of the key with a different fingerprint is
+/// rejected by [`IdempotencyCache::execute_checked`](super::IdempotencyCache::execute_checked) with [`IdempotencyError::KeyReusedWithDifferentRequest`](super::IdempotencyError::KeyReusedWithDifferentRequest)
+/// instead of getting somebody else's result.
+pub trait IdempotencyFingerprint<TParams>: Send + Sync + 'static {
+    fn get_fingerprint(&self, params: &TParams) -> u64;
+}
+
+/// What a retry with a different fingerprint does while the key is still being executed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IdempotencyInFlightMismatch {
+    /// Rejected right away.
+    #[default]
+    Reject,
+    /// Waits for the execution to finish and then tries again: it is rejected if the
+    /// execution produced a result, and executes its own request if the key was freed
+    /// without one (the owner was cancelled, panicked or timed out).
+    Wait,
+}
+
+pub(crate) fn is_fingerprint_mismatch(stored: Option<u64>, requested: Option<u64>) -> bool {
+    match (stored, requested) {
+        (Some(stored), Some(requested)) => stored != requested,
+        // Either side is not fingerprinted - e.g. a result restored from the times the
+        // fingerprint was not set up. Trusting the key is all we can do.
+        _ => false,
+    }
+}
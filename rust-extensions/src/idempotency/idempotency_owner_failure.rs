@@ -0,0 +1,21 @@
+/// What the retries parked on an execution get if that execution ends without a result -
+/// its owner was cancelled, it timed out or it panicked.
+///
+/// The owner itself is not affected: a timed out or panicked execution panics its owner
+/// in every mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IdempotencyOwnerFailure {
+    /// They panic with `"Task is dropped"` - the standard [`crate::TaskCompletion`] drop
+    /// behaviour.
+    #[default]
+    Panic,
+    /// They get [`IdempotencyError::OwnerCancelled`](super::IdempotencyError::OwnerCancelled),
+    /// [`IdempotencyError::TimedOut`](super::IdempotencyError::TimedOut) or
+    /// [`IdempotencyError::Panicked`](super::IdempotencyError::Panicked). The key is free
+    /// by then, so calling `execute_checked` again executes it from scratch. `execute` panics
+    /// with them.
+    Report,
+    /// They try again right away: the first of them takes over the execution with its own
+    /// `params`, the rest park on it.
+    TakeOver,
+}
@@ -0,0 +1,29 @@
+use crate::date_time::DateTimeAsMicroseconds;
+
+use super::IdempotencyResult;
+
+/// A memorized result the way it is handed to an [`IdempotencyPersistence`].
+pub struct IdempotencyPersistedResult<TOk, TErr> {
+    pub key: String,
+    pub completed_at: DateTimeAsMicroseconds,
+    /// `None` if the cache has no [`IdempotencyFingerprint`](super::IdempotencyFingerprint).
+    pub fingerprint: Option<u64>,
+    pub result: IdempotencyResult<TOk, TErr>,
+}
+
+/// Where [`IdempotencyCache`](super::IdempotencyCache) keeps its memorized results so
+/// they survive a restart.
+///
+/// The cache saves every result it memorizes and removes every result it forgets, and
+/// reads them all back once - on [`IdempotencyCache::restore`](super::IdempotencyCache::restore).
+/// Saving the same key twice overwrites it; removing an unknown key is not an error.
+#[async_trait::async_trait]
+pub trait IdempotencyPersistence<TOk: Send + Sync + 'static, TErr: Send + Sync + 'static>:
+    Send + Sync + 'static
+{
+    async fn load(&self) -> std::io::Result<Vec<IdempotencyPersistedResult<TOk, TErr>>>;
+
+    async fn save(&self, record: &IdempotencyPersistedResult<TOk, TErr>) -> std::io::Result<()>;
+
+    async fn remove(&self, keys: &[String]) -> std::io::Result<()>;
+}
@@ -1,10 +1,24 @@
+// `tokio::fs` is not there on wasm
+#[cfg(not(target_arch = "wasm32"))]
+mod file_idempotency_persistence;
 mod idempotency_cache;
 mod idempotency_entry;
+mod idempotency_error;
 mod idempotency_execution;
+mod idempotency_fingerprint;
+mod idempotency_owner_failure;
+mod idempotency_persistence;
 
+#[cfg(not(target_arch = "wasm32"))]
+pub use file_idempotency_persistence::{FileIdempotencyPersistence, IdempotencyResultSerializer};
 pub use idempotency_cache::{
     IdempotencyCache, DEFAULT_EXECUTION_TIMEOUT, DEFAULT_MAX_AMOUNT,
 };
-pub(crate) use idempotency_entry::{IdempotencyCacheItem, IdempotencyEntry};
+pub(crate) use idempotency_entry::{IdempotencyEntry, ParkedRetry};
 pub use idempotency_entry::IdempotencyResult;
+pub use idempotency_error::IdempotencyError;
 pub use idempotency_execution::IdempotencyExecution;
+pub(crate) use idempotency_fingerprint::is_fingerprint_mismatch;
+pub use idempotency_fingerprint::{IdempotencyFingerprint, IdempotencyInFlightMismatch};
+pub use idempotency_owner_failure::IdempotencyOwnerFailure;
+pub use idempotency_persistence::{IdempotencyPersistedResult, IdempotencyPersistence};
@@ -91,8 +91,8 @@ impl IsInitialized {
         self.initialized.store(true, Ordering::Release);
 
         for mut task_completion in awaiters.drain(..) {
-            // `try_set_ok` (not `set_ok`) because an awaiter may have been cancelled
-            // (its receiver dropped), which would make `set_ok` panic.
+            // An awaiter may have been cancelled (its receiver dropped) - nothing to
+            // tell it then.
             let _ = task_completion.try_set_ok(());
         }
     }
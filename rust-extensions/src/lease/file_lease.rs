@@ -0,0 +1,250 @@
+use std::time::Duration;
+
+use crate::{date_time::DateTimeAsMicroseconds, StrOrString};
+
+use super::{lease_record::LeaseRecord, Lease};
+
+const LOCK_ATTEMPTS: usize = 200;
+const LOCK_RETRY_DELAY: Duration = Duration::from_millis(10);
+
+/// A lock file older than that is left by a process which died while holding
+/// it - nobody keeps the lock longer than one read and one write.
+const STALE_LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The lease kept in a file - for the replicas running on the same host or
+/// sharing a file system.
+///
+/// Every operation takes the lock file `{file_name}.lock` - created exclusively,
+/// so only one replica at a time reads and rewrites the lease file - and the new
+/// lease is written to `{file_name}.tmp` and renamed over the lease file, so a
+/// crash never leaves it half written.
+pub struct FileLease {
+    file_name: String,
+    lock_file_name: String,
+    tmp_file_name: String,
+}
+
+impl FileLease {
+    pub fn new(file_name: impl Into<StrOrString<'static>>) -> Self {
+        let file_name = file_name.into().to_string();
+
+        Self {
+            lock_file_name: format!("{}.lock", file_name),
+            tmp_file_name: format!("{}.tmp", file_name),
+            file_name,
+        }
+    }
+
+    pub fn get_file_name(&self) -> &str {
+        self.file_name.as_str()
+    }
+
+    async fn update<TResult>(
+        &self,
+        update: impl FnOnce(&mut Option<LeaseRecord>) -> TResult,
+    ) -> std::io::Result<TResult> {
+        let _lock = self.lock().await?;
+        self.update_locked(update).await
+    }
+
+    async fn update_locked<TResult>(
+        &self,
+        update: impl FnOnce(&mut Option<LeaseRecord>) -> TResult,
+    ) -> std::io::Result<TResult> {
+        let before = match tokio::fs::read_to_string(&self.file_name).await {
+            Ok(content) => Some(LeaseRecord::parse(&content).ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("Lease file {} is corrupted", self.file_name),
+                )
+            })?),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err),
+        };
+
+        let mut record = before.clone();
+        let result = update(&mut record);
+
+        if record == before {
+            return Ok(result);
+        }
+
+        match record {
+            Some(record) => {
+                tokio::fs::write(&self.tmp_file_name, record.serialize()).await?;
+                tokio::fs::rename(&self.tmp_file_name, &self.file_name).await?;
+            }
+            None => match tokio::fs::remove_file(&self.file_name).await {
+                Ok(_) => {}
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            },
+        }
+
+        Ok(result)
+    }
+
+    async fn lock(&self) -> std::io::Result<LockFileGuard<'_>> {
+        for _ in 0..LOCK_ATTEMPTS {
+            // Sync, so the lock file and its guard come to life in the same poll. With
+            // `tokio::fs` the file is created on a blocking thread, and a future dropped
+            // meanwhile would leave it behind with nobody to remove it.
+            let created = std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&self.lock_file_name);
+
+            match created {
+                Ok(_) => {
+                    return Ok(LockFileGuard {
+                        lock_file_name: self.lock_file_name.as_str(),
+                    })
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
+                    if self.lock_is_stale().await {
+                        let _ = tokio::fs::remove_file(&self.lock_file_name).await;
+                        continue;
+                    }
+
+                    tokio::time::sleep(LOCK_RETRY_DELAY).await;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        Err(std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            format!("Can not lock the lease file {}", self.file_name),
+        ))
+    }
+
+    async fn lock_is_stale(&self) -> bool {
+        let Ok(metadata) = tokio::fs::metadata(&self.lock_file_name).await else {
+            return false;
+        };
+
+        let Ok(modified) = metadata.modified() else {
+            return false;
+        };
+
+        match modified.elapsed() {
+            Ok(elapsed) => elapsed > STALE_LOCK_TIMEOUT,
+            Err(_) => false,
+        }
+    }
+}
+
+/// Removes the lock file once the update is over - also if the update future is
+/// dropped half way (a timeout around `renew`), so the other replicas do not wait
+/// for [`STALE_LOCK_TIMEOUT`].
+struct LockFileGuard<'s> {
+    lock_file_name: &'s str,
+}
+
+impl Drop for LockFileGuard<'_> {
+    fn drop(&mut self) {
+        // Sync on purpose - `Drop` can not await, and it is a single unlink.
+        let _ = std::fs::remove_file(self.lock_file_name);
+    }
+}
+
+#[async_trait::async_trait]
+impl Lease for FileLease {
+    async fn try_acquire(&self, holder: &str, ttl: Duration) -> std::io::Result<bool> {
+        let now = DateTimeAsMicroseconds::now();
+        self.update(|record| super::lease_record::try_acquire(record, holder, ttl, now))
+            .await
+    }
+
+    async fn renew(&self, holder: &str, ttl: Duration) -> std::io::Result<bool> {
+        let now = DateTimeAsMicroseconds::now();
+        self.update(|record| super::lease_record::renew(record, holder, ttl, now))
+            .await
+    }
+
+    async fn release(&self, holder: &str) -> std::io::Result<()> {
+        self.update(|record| super::lease_record::release(record, holder))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{FileLease, Lease};
+
+    fn rt() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn replicas_share_the_lease_through_the_file() {
+        rt().block_on(async {
+            let file_name = std::env::temp_dir()
+                .join(format!("lease-test-{}", crate::uuid::generate_v4()))
+                .to_string_lossy()
+                .to_string();
+
+            let first = FileLease::new(file_name.clone());
+            let second = FileLease::new(file_name.clone());
+            let ttl = Duration::from_secs(30);
+
+            assert!(first.try_acquire("first", ttl).await.unwrap());
+            assert!(!second.try_acquire("second", ttl).await.unwrap());
+            assert!(first.renew("first", ttl).await.unwrap());
+            assert!(!second.renew("second", ttl).await.unwrap());
+
+            first.release("first").await.unwrap();
+            assert!(second.try_acquire("second", ttl).await.unwrap());
+            assert!(!first.renew("first", ttl).await.unwrap());
+
+            second.release("second").await.unwrap();
+            assert!(tokio::fs::metadata(&file_name).await.is_err());
+            assert!(tokio::fs::metadata(format!("{}.lock", file_name))
+                .await
+                .is_err());
+        });
+    }
+
+    #[test]
+    fn dropped_update_removes_the_lock_file() {
+        rt().block_on(async {
+            let file_name = std::env::temp_dir()
+                .join(format!("lease-test-{}", crate::uuid::generate_v4()))
+                .to_string_lossy()
+                .to_string();
+            let lock_file_name = format!("{}.lock", file_name);
+
+            let lease = FileLease::new(file_name.clone());
+            let ttl = Duration::from_secs(30);
+
+            // Polled by hand, so it is dropped right after it took the lock.
+            let mut acquiring = Box::pin(lease.try_acquire("first", ttl));
+
+            loop {
+                assert!(futures::poll!(acquiring.as_mut()).is_pending());
+
+                if tokio::fs::metadata(&lock_file_name).await.is_ok() {
+                    break;
+                }
+
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+
+            drop(acquiring);
+            assert!(tokio::fs::metadata(&lock_file_name).await.is_err());
+
+            let other = FileLease::new(file_name.clone());
+            let acquired =
+                tokio::time::timeout(Duration::from_secs(1), other.try_acquire("second", ttl))
+                    .await;
+            assert!(acquired.unwrap().unwrap());
+
+            other.release("second").await.unwrap();
+        });
+    }
+}
@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+use parking_lot::Mutex;
+
+use crate::date_time::DateTimeAsMicroseconds;
+
+use super::{lease_record::LeaseRecord, Lease};
+
+/// The lease living in the memory of the process - for the tests, where the
+/// replicas are several timers sharing one `Arc<InMemoryLease>`.
+#[derive(Default)]
+pub struct InMemoryLease {
+    record: Mutex<Option<LeaseRecord>>,
+}
+
+impl InMemoryLease {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get_holder(&self) -> Option<String> {
+        let record = self.record.lock();
+        let record = record.as_ref()?;
+
+        if record.expires.unix_microseconds <= DateTimeAsMicroseconds::now().unix_microseconds {
+            return None;
+        }
+
+        Some(record.holder.clone())
+    }
+}
+
+#[async_trait::async_trait]
+impl Lease for InMemoryLease {
+    async fn try_acquire(&self, holder: &str, ttl: Duration) -> std::io::Result<bool> {
+        let mut record = self.record.lock();
+        Ok(super::lease_record::try_acquire(
+            &mut record,
+            holder,
+            ttl,
+            DateTimeAsMicroseconds::now(),
+        ))
+    }
+
+    async fn renew(&self, holder: &str, ttl: Duration) -> std::io::Result<bool> {
+        let mut record = self.record.lock();
+        Ok(super::lease_record::renew(
+            &mut record,
+            holder,
+            ttl,
+            DateTimeAsMicroseconds::now(),
+        ))
+    }
+
+    async fn release(&self, holder: &str) -> std::io::Result<()> {
+        super::lease_record::release(&mut self.record.lock(), holder);
+        Ok(())
+    }
+}
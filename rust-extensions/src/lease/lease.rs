@@ -0,0 +1,23 @@
+use std::time::Duration;
+
+/// A named lock with a time to live, shared by the replicas of a service - the
+/// replica holding it is the leader. A holder which stops renewing the lease
+/// loses it once `ttl` passes, so a crashed leader is replaced without anybody
+/// releasing anything.
+///
+/// `holder` identifies the replica - the host name, the pod name or any id
+/// unique within the service.
+#[async_trait::async_trait]
+pub trait Lease: Send + Sync + 'static {
+    /// Takes the lease if it is free, expired or already held by `holder`.
+    /// Returns `true` if `holder` holds the lease for the next `ttl`.
+    async fn try_acquire(&self, holder: &str, ttl: Duration) -> std::io::Result<bool>;
+
+    /// Extends the lease held by `holder` for the next `ttl`. Returns `false` if
+    /// the lease is lost - it is held by somebody else or nobody holds it.
+    async fn renew(&self, holder: &str, ttl: Duration) -> std::io::Result<bool>;
+
+    /// Frees the lease if it is held by `holder`, so the other replicas do not
+    /// have to wait for it to expire.
+    async fn release(&self, holder: &str) -> std::io::Result<()>;
+}
@@ -0,0 +1,311 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicI64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use crate::{date_time::DateTimeAsMicroseconds, ApplicationStates, Logger, StrOrString};
+
+use super::Lease;
+
+const SHUTDOWN_CHECK_DELAY: Duration = Duration::from_millis(100);
+
+/// Keeps acquiring and renewing the [`Lease`] on behalf of the replica and
+/// tells whether the replica is the leader right now.
+///
+/// The lease is renewed every `ttl / 3`. The replica counts itself the leader
+/// till `ttl` since the moment the last successful renewal was asked for - so a
+/// replica which can not reach the lease stops leading before anybody else can
+/// take it over.
+///
+/// On shutdown the keeper stops the new work from starting, waits for the work
+/// already started (at most `ttl`) and releases the lease, so the next leader
+/// takes over right away instead of waiting for the lease to expire.
+///
+/// One keeper can be shared by several timers - it is started once, by the
+/// first of them.
+pub struct LeaseKeeper {
+    lease: Arc<dyn Lease>,
+    holder: String,
+    ttl: Duration,
+    leader_until: AtomicI64,
+    busy: Arc<AtomicUsize>,
+    started: AtomicBool,
+}
+
+/// Work started while the replica was the leader. The keeper does not release
+/// the lease on shutdown until every guard is dropped.
+pub struct LeaseGuard {
+    busy: Arc<AtomicUsize>,
+}
+
+impl Drop for LeaseGuard {
+    fn drop(&mut self) {
+        self.busy.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl LeaseKeeper {
+    pub fn new(
+        lease: Arc<dyn Lease>,
+        holder: impl Into<StrOrString<'static>>,
+        ttl: Duration,
+    ) -> Self {
+        if ttl.is_zero() {
+            panic!("Lease ttl can not be zero");
+        }
+
+        Self {
+            lease,
+            holder: holder.into().to_string(),
+            ttl,
+            leader_until: AtomicI64::new(0),
+            busy: Arc::new(AtomicUsize::new(0)),
+            started: AtomicBool::new(false),
+        }
+    }
+
+    pub fn get_holder(&self) -> &str {
+        self.holder.as_str()
+    }
+
+    pub fn is_leader(&self) -> bool {
+        DateTimeAsMicroseconds::now().unix_microseconds < self.leader_until.load(Ordering::SeqCst)
+    }
+
+    /// Returns `None` if the replica is not the leader - the work has to be
+    /// skipped then.
+    pub fn try_enter(&self) -> Option<LeaseGuard> {
+        // Counted before the check - so the keeper which stops the leadership on
+        // shutdown either sees the guard or makes the check fail.
+        self.busy.fetch_add(1, Ordering::SeqCst);
+
+        let guard = LeaseGuard {
+            busy: self.busy.clone(),
+        };
+
+        if !self.is_leader() {
+            return None;
+        }
+
+        Some(guard)
+    }
+
+    /// Spawns the loop acquiring and renewing the lease. Does nothing if the
+    /// keeper is already started.
+    pub fn start(
+        self: &Arc<Self>,
+        app_states: Arc<dyn ApplicationStates + Send + Sync + 'static>,
+        logger: Arc<dyn Logger + Send + Sync + 'static>,
+    ) {
+        if self.started.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        tokio::spawn(lease_keeper_loop(self.clone(), app_states, logger));
+    }
+
+    fn get_renew_delay(&self) -> Duration {
+        self.ttl / 3
+    }
+
+    fn set_leader_until(&self, value: i64) {
+        self.leader_until.store(value, Ordering::SeqCst);
+    }
+}
+
+async fn lease_keeper_loop(
+    keeper: Arc<LeaseKeeper>,
+    app_states: Arc<dyn ApplicationStates + Send + Sync + 'static>,
+    logger: Arc<dyn Logger + Send + Sync + 'static>,
+) {
+    let process = format!("LeaseKeeper {}", keeper.holder);
+    let mut holding = false;
+
+    while !app_states.is_shutting_down() {
+        let asked_at = DateTimeAsMicroseconds::now();
+
+        let result = if holding {
+            keeper.lease.renew(&keeper.holder, keeper.ttl).await
+        } else {
+            keeper.lease.try_acquire(&keeper.holder, keeper.ttl).await
+        };
+
+        match result {
+            Ok(true) => {
+                keeper.set_leader_until(asked_at.add(keeper.ttl).unix_microseconds);
+
+                if !holding {
+                    holding = true;
+                    logger.write_info(
+                        process.clone(),
+                        format!("Lease is acquired by {}", keeper.holder),
+                        None,
+                    );
+                }
+            }
+            Ok(false) => {
+                keeper.set_leader_until(0);
+
+                if holding {
+                    holding = false;
+                    logger.write_warning(
+                        process.clone(),
+                        format!("Lease is lost by {}", keeper.holder),
+                        None,
+                    );
+                }
+            }
+            Err(err) => {
+                // The leadership is not extended - it ends by itself unless one of
+                // the next attempts succeeds.
+                logger.write_error(
+                    process.clone(),
+                    format!("Can not renew the lease by {}. Err: {}", keeper.holder, err),
+                    None,
+                );
+            }
+        }
+
+        sleep_unless_shutting_down(keeper.get_renew_delay(), app_states.as_ref()).await;
+    }
+
+    keeper.set_leader_until(0);
+
+    if !holding {
+        return;
+    }
+
+    let started = tokio::time::Instant::now();
+
+    while keeper.busy.load(Ordering::SeqCst) > 0 && started.elapsed() < keeper.ttl {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    match keeper.lease.release(&keeper.holder).await {
+        Ok(_) => logger.write_info(
+            process,
+            format!("Lease is released by {}", keeper.holder),
+            None,
+        ),
+        Err(err) => logger.write_error(
+            process,
+            format!(
+                "Can not release the lease by {}. Err: {}",
+                keeper.holder, err
+            ),
+            None,
+        ),
+    }
+}
+
+async fn sleep_unless_shutting_down(
+    delay: Duration,
+    app_states: &(dyn ApplicationStates + Send + Sync + 'static),
+) {
+    let deadline = tokio::time::Instant::now() + delay;
+
+    loop {
+        let now = tokio::time::Instant::now();
+
+        if now >= deadline || app_states.is_shutting_down() {
+            return;
+        }
+
+        tokio::time::sleep((deadline - now).min(SHUTDOWN_CHECK_DELAY)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{atomic::AtomicBool, Arc},
+        time::Duration,
+    };
+
+    use crate::{lease::InMemoryLease, ApplicationStates, Logger};
+
+    use super::LeaseKeeper;
+
+    fn rt() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    struct TestLogger;
+
+    impl Logger for TestLogger {
+        fn write_info(&self, _: String, _: String, _: Option<HashMap<String, String>>) {}
+        fn write_warning(&self, _: String, _: String, _: Option<HashMap<String, String>>) {}
+        fn write_error(&self, _: String, _: String, _: Option<HashMap<String, String>>) {}
+        fn write_fatal_error(&self, _: String, _: String, _: Option<HashMap<String, String>>) {}
+        fn write_debug_info(&self, _: String, _: String, _: Option<HashMap<String, String>>) {}
+    }
+
+    #[derive(Default)]
+    struct TestAppStates {
+        shutting_down: AtomicBool,
+    }
+
+    impl ApplicationStates for TestAppStates {
+        fn is_initialized(&self) -> bool {
+            true
+        }
+
+        fn is_shutting_down(&self) -> bool {
+            self.shutting_down.load(std::sync::atomic::Ordering::SeqCst)
+        }
+    }
+
+    #[test]
+    fn lease_is_handed_over_on_shutdown() {
+        rt().block_on(async {
+            let lease = Arc::new(InMemoryLease::new());
+            let ttl = Duration::from_millis(300);
+
+            let first = Arc::new(LeaseKeeper::new(lease.clone(), "first", ttl));
+            let second = Arc::new(LeaseKeeper::new(lease.clone(), "second", ttl));
+
+            let first_states = Arc::new(TestAppStates::default());
+
+            first.start(first_states.clone(), Arc::new(TestLogger));
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            second.start(Arc::new(TestAppStates::default()), Arc::new(TestLogger));
+            tokio::time::sleep(Duration::from_millis(20)).await;
+
+            assert!(first.is_leader());
+            assert!(!second.is_leader());
+            assert!(second.try_enter().is_none());
+
+            let guard = first.try_enter().unwrap();
+            first_states
+                .shutting_down
+                .store(true, std::sync::atomic::Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(150)).await;
+
+            // The first one does not lead any more, but keeps the lease till its
+            // work is done.
+            assert!(!first.is_leader());
+            assert_eq!(lease.get_holder().as_deref(), Some("first"));
+
+            drop(guard);
+
+            // The second one takes over on its next attempt - long before the
+            // lease would expire.
+            for _ in 0..40 {
+                if second.is_leader() {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+
+            assert!(second.is_leader());
+            assert_eq!(lease.get_holder().as_deref(), Some("second"));
+        });
+    }
+}
@@ -0,0 +1,139 @@
+use std::time::Duration;
+
+use crate::date_time::DateTimeAsMicroseconds;
+
+/// Who holds the lease and till when. The lease implementations keep an
+/// `Option<LeaseRecord>` - `None` is a lease nobody holds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct LeaseRecord {
+    pub holder: String,
+    pub expires: DateTimeAsMicroseconds,
+}
+
+impl LeaseRecord {
+    /// The format of the lease file: the holder on the first line, the expiration
+    /// in unix microseconds on the second one.
+    pub fn parse(src: &str) -> Option<Self> {
+        let mut lines = src.lines();
+        let holder = lines.next()?;
+        let expires = lines.next()?.trim().parse::<i64>().ok()?;
+
+        if holder.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            holder: holder.to_string(),
+            expires: DateTimeAsMicroseconds::new(expires),
+        })
+    }
+
+    pub fn serialize(&self) -> String {
+        format!("{}\n{}\n", self.holder, self.expires.unix_microseconds)
+    }
+
+    fn is_expired(&self, now: DateTimeAsMicroseconds) -> bool {
+        self.expires.unix_microseconds <= now.unix_microseconds
+    }
+}
+
+pub(crate) fn try_acquire(
+    record: &mut Option<LeaseRecord>,
+    holder: &str,
+    ttl: Duration,
+    now: DateTimeAsMicroseconds,
+) -> bool {
+    if let Some(current) = record.as_ref() {
+        if current.holder != holder && !current.is_expired(now) {
+            return false;
+        }
+    }
+
+    *record = Some(LeaseRecord {
+        holder: holder.to_string(),
+        expires: now.add(ttl),
+    });
+
+    true
+}
+
+pub(crate) fn renew(
+    record: &mut Option<LeaseRecord>,
+    holder: &str,
+    ttl: Duration,
+    now: DateTimeAsMicroseconds,
+) -> bool {
+    let Some(current) = record.as_mut() else {
+        return false;
+    };
+
+    // An expired lease nobody took over is still ours - there was no leader in
+    // between.
+    if current.holder != holder {
+        return false;
+    }
+
+    current.expires = now.add(ttl);
+    true
+}
+
+pub(crate) fn release(record: &mut Option<LeaseRecord>, holder: &str) {
+    if let Some(current) = record.as_ref() {
+        if current.holder == holder {
+            *record = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTL: Duration = Duration::from_secs(10);
+
+    fn at(seconds: i64) -> DateTimeAsMicroseconds {
+        DateTimeAsMicroseconds::new(seconds * 1_000_000)
+    }
+
+    #[test]
+    fn lease_is_taken_over_only_when_expired() {
+        let mut record = None;
+
+        assert!(try_acquire(&mut record, "first", TTL, at(0)));
+        assert!(!try_acquire(&mut record, "second", TTL, at(5)));
+        assert!(!renew(&mut record, "second", TTL, at(5)));
+
+        assert!(renew(&mut record, "first", TTL, at(5)));
+        assert!(!try_acquire(&mut record, "second", TTL, at(14)));
+
+        // The first one stopped renewing.
+        assert!(try_acquire(&mut record, "second", TTL, at(15)));
+        assert!(!renew(&mut record, "first", TTL, at(16)));
+        assert_eq!(record.as_ref().unwrap().holder, "second");
+    }
+
+    #[test]
+    fn only_the_holder_releases_the_lease() {
+        let mut record = None;
+        try_acquire(&mut record, "first", TTL, at(0));
+
+        release(&mut record, "second");
+        assert!(record.is_some());
+
+        release(&mut record, "first");
+        assert!(record.is_none());
+        assert!(try_acquire(&mut record, "second", TTL, at(1)));
+    }
+
+    #[test]
+    fn record_survives_the_file_round_trip() {
+        let record = LeaseRecord {
+            holder: "replica-1".to_string(),
+            expires: at(100),
+        };
+
+        assert_eq!(LeaseRecord::parse(&record.serialize()), Some(record));
+        assert_eq!(LeaseRecord::parse(""), None);
+        assert_eq!(LeaseRecord::parse("replica-1\nnot-a-number"), None);
+    }
+}
@@ -0,0 +1,10 @@
+mod file_lease;
+mod in_memory_lease;
+mod lease;
+mod lease_keeper;
+mod lease_record;
+
+pub use file_lease::FileLease;
+pub use in_memory_lease::InMemoryLease;
+pub use lease::Lease;
+pub use lease_keeper::{LeaseGuard, LeaseKeeper};
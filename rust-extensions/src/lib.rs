@@ -6,12 +6,24 @@ pub mod duration_utils;
 #[cfg(all(feature = "with-tokio", not(target_arch = "wasm32")))]
 pub mod events_loop;
 #[cfg(all(feature = "with-tokio", not(target_arch = "wasm32")))]
+pub mod supervisor;
+#[cfg(all(feature = "with-tokio", not(target_arch = "wasm32")))]
+pub mod health;
+#[cfg(all(feature = "with-tokio", not(target_arch = "wasm32")))]
+pub mod rate_limiter;
+#[cfg(all(feature = "with-tokio", not(target_arch = "wasm32")))]
+pub mod circuit_breaker;
+#[cfg(all(feature = "with-tokio", not(target_arch = "wasm32")))]
+pub mod lease;
+#[cfg(all(feature = "with-tokio", not(target_arch = "wasm32")))]
 pub mod background_executor;
 #[cfg(all(feature = "with-tokio", not(target_arch = "wasm32")))]
 pub mod background_executor_with_multi_threads;
 pub mod lazy;
 pub mod linq;
 mod logger;
+#[cfg(feature = "with-tokio")]
+mod panic_message;
 #[cfg(all(feature = "with-tokio", not(target_arch = "wasm32")))]
 mod my_timer;
 #[cfg(all(feature = "with-tokio", not(target_arch = "wasm32")))]
@@ -32,26 +44,42 @@ mod is_initialized;
 #[cfg(feature = "with-tokio")]
 pub use is_initialized::*;
 #[cfg(feature = "with-tokio")]
+mod async_primitives;
+#[cfg(feature = "with-tokio")]
+pub use async_primitives::*;
+#[cfg(feature = "with-tokio")]
 mod idempotency;
 #[cfg(feature = "with-tokio")]
 pub use idempotency::*;
 #[cfg(feature = "with-tokio")]
+mod async_cache;
+#[cfg(feature = "with-tokio")]
+pub use async_cache::*;
+#[cfg(feature = "with-tokio")]
 mod task_completion;
 #[cfg(feature = "with-tokio")]
 pub mod tokio_queue;
+#[cfg(feature = "with-tokio")]
+pub mod framing;
 
 #[cfg(feature = "with-tokio")]
 pub use application_states::*;
 pub use stop_watch::StopWatch;
 pub use string_builder::StringBuilder;
 #[cfg(feature = "with-tokio")]
-pub use task_completion::{TaskCompletion, TaskCompletionAwaiter, TaskCompletionError};
+pub use task_completion::{
+    SharedTaskCompletion, TaskCompletion, TaskCompletionAwaiter, TaskCompletionError,
+};
 pub mod grouped_data;
 
 pub use binary_payload_builder::*;
 pub use logger::*;
 #[cfg(all(feature = "with-tokio", not(target_arch = "wasm32")))]
-pub use my_timer::{MyTimer, MyTimerTick, RepeatTimerIteration};
+pub use my_timer::{
+    MyTimer, MyTimerHandle, MyTimerOverlapPolicy, MyTimerTick, RepeatTimerIteration,
+    TimerHistorySnapshot, TimerTickOutcome, TimerTickRecord, TimersHistorySnapshot,
+    DEFAULT_MAX_CONCURRENT_TICKS,
+};
 #[cfg(all(feature = "with-tokio", not(target_arch = "wasm32")))]
 pub use exact_timer::{ExactTimerInterval, MyExactTimer};
 pub use slice_or_vec::*;
@@ -1,6 +1,16 @@
 mod my_timer;
+mod my_timer_handle;
+mod my_timer_overlap_policy;
 mod my_timer_tick;
+mod timer_history;
+mod timer_schedule;
 pub(crate) mod timers_iteration;
 
-pub use my_timer::MyTimer;
+pub use my_timer::{MyTimer, DEFAULT_MAX_CONCURRENT_TICKS};
+pub use my_timer_handle::MyTimerHandle;
+pub use my_timer_overlap_policy::MyTimerOverlapPolicy;
 pub use my_timer_tick::{MyTimerTick, RepeatTimerIteration};
+pub(crate) use timer_history::TimersHistory;
+pub use timer_history::{
+    TimerHistorySnapshot, TimerTickOutcome, TimerTickRecord, TimersHistorySnapshot,
+};
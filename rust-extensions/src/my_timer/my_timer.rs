@@ -1,17 +1,37 @@
 use std::{sync::Arc, time::Duration};
 
-use crate::{ApplicationStates, Logger};
+use tokio::{sync::Notify, time::Instant};
+
+use crate::{health::HealthReporter, lease::LeaseKeeper, ApplicationStates, Logger};
 
 use super::{
-    timers_iteration::{execute_timer, execute_timers_iteration, RegisteredTimer},
-    MyTimerTick, RepeatTimerIteration,
+    my_timer_handle::MyTimerControl,
+    timer_history::{TimerTickRecord, TimersHistory, TimersHistorySnapshot},
+    timer_schedule::{
+        execute_with_repeats, get_jitter, timer_with_interval_loop, wait_for_deadline,
+        TimerLoopSettings, TimerWithInterval,
+    },
+    timers_iteration::{execute_timer_by_hand, RegisteredTimer},
+    MyTimerHandle, MyTimerOverlapPolicy, MyTimerTick, RepeatTimerIteration,
 };
 
+/// How many ticks of a timer with [`MyTimerOverlapPolicy::Concurrent`] may run at once
+/// by default.
+pub const DEFAULT_MAX_CONCURRENT_TICKS: usize = 4;
+
 pub struct MyTimer {
     interval: Duration,
     timers: Vec<RegisteredTimer>,
+    timers_with_interval: Vec<(RegisteredTimer, Duration, MyTimerOverlapPolicy)>,
     iteration_timeout: Duration,
+    health: Option<Arc<HealthReporter>>,
     delay_before_first_tick: bool,
+    max_jitter: Duration,
+    max_concurrent_ticks: usize,
+    handle: MyTimerHandle,
+    wake_up: Arc<Notify>,
+    history: Arc<TimersHistory>,
+    lease: Option<Arc<LeaseKeeper>>,
 }
 
 impl MyTimer {
@@ -19,8 +39,16 @@ impl MyTimer {
         Self {
             interval,
             timers: Vec::new(),
+            timers_with_interval: Vec::new(),
             iteration_timeout: Duration::from_secs(60),
+            health: None,
             delay_before_first_tick: true,
+            max_jitter: Duration::ZERO,
+            max_concurrent_ticks: DEFAULT_MAX_CONCURRENT_TICKS,
+            handle: MyTimerHandle::default(),
+            wake_up: Arc::new(Notify::new()),
+            history: Arc::new(TimersHistory::new()),
+            lease: None,
         }
     }
 
@@ -29,30 +57,118 @@ impl MyTimer {
     }
 
     pub fn new_with_execute_timeout(interval: Duration, iteration_timeout: Duration) -> Self {
-        Self {
-            interval,
-            timers: Vec::new(),
-            iteration_timeout,
-            delay_before_first_tick: true,
-        }
+        let mut result = Self::new(interval);
+        result.iteration_timeout = iteration_timeout;
+        result
     }
 
     pub fn set_first_tick_before_delay(&mut self) {
         self.delay_before_first_tick = false;
     }
 
+    /// Every wait for the next tick - the first one included - is extended by a
+    /// random delay within `0..=max_jitter`, so the instances of a service
+    /// started at once do not hit their partners at once.
+    pub fn set_jitter(&mut self, max_jitter: Duration) {
+        self.max_jitter = max_jitter;
+    }
+
+    /// Caps the ticks of a timer with [`MyTimerOverlapPolicy::Concurrent`] which run at
+    /// once - a tick due while that many are still running is skipped. Panics on zero.
+    pub fn set_max_concurrent_ticks(&mut self, max_concurrent_ticks: usize) {
+        if max_concurrent_ticks == 0 {
+            panic!("MyTimer max concurrent ticks must be above zero");
+        }
+
+        self.max_concurrent_ticks = max_concurrent_ticks;
+    }
+
+    /// The timer sends a heartbeat after every pass in which every tick finished
+    /// and reports the ticks which panicked or timed out.
+    pub fn set_health_reporter(&mut self, reporter: Arc<HealthReporter>) {
+        self.health = Some(reporter);
+    }
+
+    /// Amount of the last ticks kept per timer - 10 by default, 0 turns the
+    /// history off.
+    pub fn set_history_capacity(&mut self, capacity: usize) {
+        self.history.set_capacity(capacity);
+    }
+
+    /// The last ticks of the timer, the oldest first. Panics if there is no timer
+    /// with the name.
+    pub fn get_history(&self, timer_name: &str) -> Vec<TimerTickRecord> {
+        match self.history.get(timer_name) {
+            Some(history) => history,
+            None => panic!("Timer with the name [{}] is not found", timer_name),
+        }
+    }
+
+    pub fn get_history_snapshot(&self) -> TimersHistorySnapshot {
+        self.history.get_snapshot()
+    }
+
+    /// Makes the timer tick only on the replica holding the lease - for the
+    /// singleton jobs of a service running in several replicas. The keeper is
+    /// started together with the timer.
+    pub fn set_lease(&mut self, lease: Arc<LeaseKeeper>) {
+        self.lease = Some(lease);
+    }
+
+    /// Pauses, resumes and triggers the registered timers by name while the timer
+    /// is running.
+    pub fn get_handle(&self) -> MyTimerHandle {
+        self.handle.clone()
+    }
+
     pub fn register_timer(
         &mut self,
         name: &str,
         my_timer_tick: Arc<dyn MyTimerTick + Send + Sync + 'static>,
     ) {
-        for (timer_name, _) in &self.timers {
+        self.check_name_is_free(name);
+        self.history.register(name);
+        self.handle.add(Arc::new(MyTimerControl::new(
+            name.to_string(),
+            self.wake_up.clone(),
+        )));
+        self.timers.push((name.to_string(), my_timer_tick));
+    }
+
+    /// The timer runs in a loop of its own instead of the shared pass: it ticks
+    /// every `interval` since the start, and `overlap_policy` decides what to do
+    /// if a tick is still running when the next one is due.
+    pub fn register_timer_with_interval(
+        &mut self,
+        name: &str,
+        interval: Duration,
+        overlap_policy: MyTimerOverlapPolicy,
+        my_timer_tick: Arc<dyn MyTimerTick + Send + Sync + 'static>,
+    ) {
+        self.check_name_is_free(name);
+        self.history.register(name);
+        self.handle.add(Arc::new(MyTimerControl::new(
+            name.to_string(),
+            Arc::new(Notify::new()),
+        )));
+        self.timers_with_interval.push((
+            (name.to_string(), my_timer_tick),
+            interval,
+            overlap_policy,
+        ));
+    }
+
+    fn check_name_is_free(&self, name: &str) {
+        let registered = self
+            .timers
+            .iter()
+            .chain(self.timers_with_interval.iter().map(|(timer, _, _)| timer));
+
+        for (timer_name, _) in registered {
             if timer_name == name {
                 panic!("Timer with the name [{}] is already registered", name);
             }
         }
-
-        self.timers.push((name.to_string(), my_timer_tick));
     }
 
     pub fn start(
@@ -60,26 +176,64 @@ impl MyTimer {
         app_states: Arc<dyn ApplicationStates + Send + Sync + 'static>,
         logger: Arc<dyn Logger + Send + Sync + 'static>,
     ) {
-        let timers = self.timers.clone();
+        if let Some(lease) = self.lease.as_ref() {
+            lease.start(app_states.clone(), logger.clone());
+        }
+
+        let settings = Arc::new(TimerLoopSettings {
+            max_jitter: self.max_jitter,
+            max_concurrent_ticks: self.max_concurrent_ticks,
+            delay_before_first_tick: self.delay_before_first_tick,
+            iteration_timeout: self.iteration_timeout,
+            app_states,
+            logger,
+            health: self.health.clone(),
+            history: self.history.clone(),
+            lease: self.lease.clone(),
+        });
+
+        for (timer, interval, overlap_policy) in &self.timers_with_interval {
+            let timer = TimerWithInterval {
+                control: self.handle.get(&timer.0),
+                timer: timer.clone(),
+                interval: *interval,
+                overlap_policy: *overlap_policy,
+            };
+
+            tokio::spawn(timer_with_interval_loop(timer, settings.clone()));
+        }
+
+        if self.timers.is_empty() && !self.timers_with_interval.is_empty() {
+            return;
+        }
+
+        let timers = self
+            .timers
+            .iter()
+            .map(|timer| (timer.clone(), self.handle.get(&timer.0)))
+            .collect();
+
         tokio::spawn(timer_loop(
             timers,
             self.interval,
-            app_states,
-            logger,
-            self.iteration_timeout,
-            self.delay_before_first_tick,
+            settings,
+            self.wake_up.clone(),
         ));
     }
 
-    /// Executes the named tick once, out of schedule. There is no interval to
-    /// wait for here, so a `RepeatTimerIteration::Immediately` is handed back to
-    /// the caller rather than acted upon.
+    /// Executes the named tick once, out of schedule, and writes it to the
+    /// history. There is no interval to wait for here, so a
+    /// `RepeatTimerIteration::Immediately` is handed back to the caller rather
+    /// than acted upon.
     pub async fn execute_timer(&self, timer_name: &str) -> RepeatTimerIteration {
-        for (timer_id, timer_tick) in &self.timers {
-            if timer_id == timer_name {
-                return tokio::spawn(execute_timer(timer_tick.clone()))
-                    .await
-                    .unwrap();
+        let registered = self
+            .timers
+            .iter()
+            .chain(self.timers_with_interval.iter().map(|(timer, _, _)| timer));
+
+        for timer in registered {
+            if timer.0 == timer_name {
+                return execute_timer_by_hand(timer, &self.history).await;
             }
         }
 
@@ -88,18 +242,19 @@ impl MyTimer {
 }
 
 async fn timer_loop(
-    timers: Vec<RegisteredTimer>,
+    timers: Vec<(RegisteredTimer, Arc<MyTimerControl>)>,
     interval: Duration,
-    app_states: Arc<dyn ApplicationStates + Send + Sync + 'static>,
-    logger: Arc<dyn Logger + Send + Sync + 'static>,
-    iteration_timeout: Duration,
-    delay_before_first_tick: bool,
+    settings: Arc<TimerLoopSettings>,
+    wake_up: Arc<Notify>,
 ) {
+    let app_states = &settings.app_states;
+    let logger = &settings.logger;
+
     while !app_states.is_initialized() {
         tokio::time::sleep(Duration::from_secs(1)).await;
     }
 
-    for (timer_id, _) in &timers {
+    for ((timer_id, _), _) in &timers {
         let message = format!(
             "Timer {} is started with delay {} sec",
             timer_id,
@@ -109,38 +264,53 @@ async fn timer_loop(
         logger.write_info(timer_id.to_string().into(), message.into(), None.into());
     }
 
-    if delay_before_first_tick {
-        tokio::time::sleep(interval).await;
+    let mut deadline = Instant::now() + get_jitter(settings.max_jitter);
+
+    if settings.delay_before_first_tick {
+        deadline += interval;
     }
 
     while !app_states.is_shutting_down() {
-        let mut to_execute: Vec<&RegisteredTimer> = timers.iter().collect();
+        if !wait_for_deadline(deadline, &wake_up).await {
+            // Triggered by hand - the schedule stays as it is.
+            let triggered = timers
+                .iter()
+                .filter(|(_, control)| control.take_triggered())
+                .map(|(timer, _)| timer)
+                .collect();
 
-        loop {
-            to_execute = execute_timers_iteration(&to_execute, &logger, iteration_timeout).await;
+            execute_with_repeats(triggered, &settings).await;
 
-            // Ticks which left their iteration on purpose are restarted right
-            // away - each with a fresh timeout window - and the interval is not
-            // slept until every one of them is done.
-            if to_execute.is_empty() || app_states.is_shutting_down() {
-                break;
-            }
+            continue;
         }
 
-        tokio::time::sleep(interval).await;
+        let to_execute = timers
+            .iter()
+            .filter(|(_, control)| control.should_run_when_due())
+            .map(|(timer, _)| timer)
+            .collect();
+
+        // Ticks which left their iteration on purpose are restarted right
+        // away - each with a fresh timeout window - and the interval is not
+        // slept until every one of them is done.
+        execute_with_repeats(to_execute, &settings).await;
+
+        deadline = Instant::now() + interval + get_jitter(settings.max_jitter);
     }
 }
 
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
-    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
     use std::sync::Arc;
     use std::time::Duration;
 
     use crate::{ApplicationStates, Logger};
 
-    use super::{MyTimer, MyTimerTick, RepeatTimerIteration};
+    use super::{MyTimer, MyTimerOverlapPolicy, MyTimerTick, RepeatTimerIteration};
+    use crate::lease::{InMemoryLease, LeaseKeeper};
+    use crate::TimerTickOutcome;
 
     /// Far longer than any test waits for - so anything that happens within a
     /// test window happened because a tick asked for it, not because the
@@ -231,7 +401,11 @@ mod tests {
             }
             tokio::time::sleep(Duration::from_millis(5)).await;
         }
-        panic!("Expected {} runs, got {}", expected, runs.load(Ordering::SeqCst));
+        panic!(
+            "Expected {} runs, got {}",
+            expected,
+            runs.load(Ordering::SeqCst)
+        );
     }
 
     #[test]
@@ -282,17 +456,323 @@ mod tests {
 
             let mut timer = MyTimer::new(INTERVAL);
             timer.set_first_tick_before_delay();
+            timer.register_timer("panicking", Arc::new(PanickingTick { runs: runs.clone() }));
+            timer.start(Arc::new(TestAppStates), Arc::new(TestLogger));
+
+            wait_for(&runs, 1).await;
+
+            // A panic answered nothing - it must not spin the loop.
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            assert_eq!(runs.load(Ordering::SeqCst), 1);
+        });
+    }
+
+    #[test]
+    fn ticks_are_written_to_the_history() {
+        rt().block_on(async {
+            let repeating_runs = Arc::new(AtomicUsize::new(0));
+            let panicking_runs = Arc::new(AtomicUsize::new(0));
+
+            let mut timer = MyTimer::new(INTERVAL);
+            timer.set_first_tick_before_delay();
+            timer.register_timer("repeating", repeating_tick(&repeating_runs, 1));
             timer.register_timer(
                 "panicking",
-                Arc::new(PanickingTick { runs: runs.clone() }),
+                Arc::new(PanickingTick {
+                    runs: panicking_runs.clone(),
+                }),
             );
             timer.start(Arc::new(TestAppStates), Arc::new(TestLogger));
 
+            wait_for(&repeating_runs, 2).await;
+            wait_for(&panicking_runs, 1).await;
+            tokio::time::sleep(Duration::from_millis(50)).await;
+
+            let repeating = timer.get_history("repeating");
+            assert_eq!(repeating.len(), 2);
+            assert_eq!(repeating[0].repeat, Some(RepeatTimerIteration::Immediately));
+            assert_eq!(
+                repeating[1].repeat,
+                Some(RepeatTimerIteration::WithInterval)
+            );
+            assert!(repeating
+                .iter()
+                .all(|tick| tick.outcome == TimerTickOutcome::Ok));
+
+            let panicking = timer.get_history("panicking");
+            assert_eq!(panicking.len(), 1);
+            assert_eq!(panicking[0].outcome, TimerTickOutcome::Panic);
+            assert_eq!(panicking[0].repeat, None);
+
+            // The ticks executed by hand are there too.
+            timer.execute_timer("repeating").await;
+            assert_eq!(timer.get_history("repeating").len(), 3);
+            assert_eq!(timer.get_history_snapshot().timers.len(), 2);
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Timer with the name [unknown] is not found")]
+    fn history_panics_on_unknown_timer() {
+        let timer = MyTimer::new(INTERVAL);
+        timer.get_history("unknown");
+    }
+
+    #[test]
+    fn passes_are_reported_to_the_health_registry() {
+        use crate::health::{HealthRegistry, HealthStatus};
+
+        rt().block_on(async {
+            let registry = HealthRegistry::new(Arc::new(TestAppStates));
+            let runs = Arc::new(AtomicUsize::new(0));
+            let panicking_runs = Arc::new(AtomicUsize::new(0));
+
+            let mut healthy_timer = MyTimer::new(INTERVAL);
+            healthy_timer.set_first_tick_before_delay();
+            healthy_timer.set_health_reporter(registry.register("healthy"));
+            healthy_timer.register_timer("test", repeating_tick(&runs, 0));
+            healthy_timer.start(Arc::new(TestAppStates), Arc::new(TestLogger));
+
+            let mut panicking_timer = MyTimer::new(INTERVAL);
+            panicking_timer.set_first_tick_before_delay();
+            panicking_timer.set_health_reporter(registry.register("panicking"));
+            panicking_timer.register_timer(
+                "panicking",
+                Arc::new(PanickingTick {
+                    runs: panicking_runs.clone(),
+                }),
+            );
+            panicking_timer.start(Arc::new(TestAppStates), Arc::new(TestLogger));
+
             wait_for(&runs, 1).await;
+            wait_for(&panicking_runs, 1).await;
+            tokio::time::sleep(Duration::from_millis(20)).await;
+
+            let snapshot = registry.get_snapshot();
+            assert!(snapshot.components[0].last_heartbeat.is_some());
+            assert_eq!(snapshot.components[0].status, HealthStatus::Healthy);
+            assert!(snapshot.components[1].last_heartbeat.is_none());
+            assert_eq!(snapshot.components[1].status, HealthStatus::Degraded);
+            assert_eq!(snapshot.status, HealthStatus::Degraded);
+        });
+    }
+
+    /// Sleeps for `duration` and keeps the max amount of the ticks which were
+    /// running at once.
+    struct SlowTick {
+        runs: Arc<AtomicUsize>,
+        in_flight: AtomicUsize,
+        max_in_flight: Arc<AtomicUsize>,
+        duration: Duration,
+    }
+
+    #[async_trait::async_trait]
+    impl MyTimerTick for SlowTick {
+        async fn tick(&self) -> RepeatTimerIteration {
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            tokio::time::sleep(self.duration).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            self.runs.fetch_add(1, Ordering::SeqCst);
+            RepeatTimerIteration::WithInterval
+        }
+    }
+
+    fn run_slow_timer(overlap_policy: MyTimerOverlapPolicy) -> (usize, usize) {
+        rt().block_on(async {
+            let runs = Arc::new(AtomicUsize::new(0));
+            let max_in_flight = Arc::new(AtomicUsize::new(0));
+
+            let mut timer = MyTimer::new(INTERVAL);
+            timer.set_first_tick_before_delay();
+            timer.register_timer_with_interval(
+                "slow",
+                Duration::from_millis(20),
+                overlap_policy,
+                Arc::new(SlowTick {
+                    runs: runs.clone(),
+                    in_flight: AtomicUsize::new(0),
+                    max_in_flight: max_in_flight.clone(),
+                    duration: Duration::from_millis(30),
+                }),
+            );
+            timer.start(Arc::new(TestAppStates), Arc::new(TestLogger));
+
+            tokio::time::sleep(Duration::from_millis(300)).await;
+
+            (
+                runs.load(Ordering::SeqCst),
+                max_in_flight.load(Ordering::SeqCst),
+            )
+        })
+    }
+
+    #[test]
+    fn overlap_policies_of_the_timer_with_its_own_interval() {
+        let (skip_runs, skip_in_flight) = run_slow_timer(MyTimerOverlapPolicy::Skip);
+        let (queue_runs, queue_in_flight) = run_slow_timer(MyTimerOverlapPolicy::Queue);
+        let (concurrent_runs, concurrent_in_flight) =
+            run_slow_timer(MyTimerOverlapPolicy::Concurrent);
+
+        assert_eq!(skip_in_flight, 1);
+        assert_eq!(queue_in_flight, 1);
+        assert!(concurrent_in_flight > 1);
+
+        // A tick takes 30ms with the interval of 20ms: skip starts one every 40ms,
+        // queue - one every 30ms, concurrent - one every 20ms.
+        assert!(skip_runs < queue_runs, "{} < {}", skip_runs, queue_runs);
+        assert!(
+            queue_runs <= concurrent_runs,
+            "{} <= {}",
+            queue_runs,
+            concurrent_runs
+        );
+    }
+
+    #[test]
+    fn concurrent_ticks_are_capped() {
+        rt().block_on(async {
+            let runs = Arc::new(AtomicUsize::new(0));
+            let max_in_flight = Arc::new(AtomicUsize::new(0));
+
+            let mut timer = MyTimer::new(INTERVAL);
+            timer.set_first_tick_before_delay();
+            timer.set_max_concurrent_ticks(2);
+            timer.register_timer_with_interval(
+                "hanging",
+                Duration::from_millis(10),
+                MyTimerOverlapPolicy::Concurrent,
+                Arc::new(SlowTick {
+                    runs: runs.clone(),
+                    in_flight: AtomicUsize::new(0),
+                    max_in_flight: max_in_flight.clone(),
+                    duration: Duration::from_secs(10),
+                }),
+            );
+            timer.start(Arc::new(TestAppStates), Arc::new(TestLogger));
 
-            // A panic answered nothing - it must not spin the loop.
             tokio::time::sleep(Duration::from_millis(200)).await;
-            assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+            assert_eq!(max_in_flight.load(Ordering::SeqCst), 2);
+            assert_eq!(runs.load(Ordering::SeqCst), 0);
+        });
+    }
+
+    #[test]
+    fn paused_timer_skips_ticks_but_can_be_triggered() {
+        rt().block_on(async {
+            let fast_runs = Arc::new(AtomicUsize::new(0));
+            let shared_runs = Arc::new(AtomicUsize::new(0));
+
+            let mut timer = MyTimer::new(INTERVAL);
+            timer.set_first_tick_before_delay();
+            timer.set_jitter(Duration::from_millis(5));
+            timer.register_timer("shared", repeating_tick(&shared_runs, 0));
+            timer.register_timer_with_interval(
+                "fast",
+                Duration::from_millis(10),
+                MyTimerOverlapPolicy::Skip,
+                repeating_tick(&fast_runs, 0),
+            );
+
+            let handle = timer.get_handle();
+            timer.start(Arc::new(TestAppStates), Arc::new(TestLogger));
+
+            wait_for(&fast_runs, 3).await;
+            wait_for(&shared_runs, 1).await;
+
+            handle.pause("fast");
+            assert!(handle.is_paused("fast"));
+            tokio::time::sleep(Duration::from_millis(30)).await;
+            let paused_at = fast_runs.load(Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            assert_eq!(fast_runs.load(Ordering::SeqCst), paused_at);
+
+            // Triggered by hand - paused or not.
+            handle.trigger_now("fast");
+            wait_for(&fast_runs, paused_at + 1).await;
+
+            handle.resume("fast");
+            wait_for(&fast_runs, paused_at + 4).await;
+
+            // The shared loop waits for INTERVAL, but the trigger does not.
+            handle.trigger_now("shared");
+            wait_for(&shared_runs, 2).await;
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            assert_eq!(shared_runs.load(Ordering::SeqCst), 2);
+        });
+    }
+
+    struct StoppableAppStates {
+        shutting_down: AtomicBool,
+    }
+
+    impl ApplicationStates for StoppableAppStates {
+        fn is_initialized(&self) -> bool {
+            true
+        }
+        fn is_shutting_down(&self) -> bool {
+            self.shutting_down.load(Ordering::SeqCst)
+        }
+    }
+
+    #[test]
+    fn only_the_lease_holder_ticks() {
+        rt().block_on(async {
+            let lease = Arc::new(InMemoryLease::new());
+            let ttl = Duration::from_millis(300);
+
+            let mut replicas = Vec::new();
+
+            for holder in ["first", "second"] {
+                let runs = Arc::new(AtomicUsize::new(0));
+                let app_states = Arc::new(StoppableAppStates {
+                    shutting_down: AtomicBool::new(false),
+                });
+
+                let mut timer = MyTimer::new(INTERVAL);
+                timer.set_lease(Arc::new(LeaseKeeper::new(lease.clone(), holder, ttl)));
+                timer.register_timer_with_interval(
+                    "singleton",
+                    Duration::from_millis(10),
+                    MyTimerOverlapPolicy::Skip,
+                    repeating_tick(&runs, 0),
+                );
+                timer.start(app_states.clone(), Arc::new(TestLogger));
+
+                replicas.push((runs, app_states));
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+
+            let (first_runs, first_states) = &replicas[0];
+            let (second_runs, _) = &replicas[1];
+
+            wait_for(first_runs, 5).await;
+            assert_eq!(second_runs.load(Ordering::SeqCst), 0);
+
+            // The leader goes away - the other replica takes over without waiting
+            // for the lease to expire.
+            first_states.shutting_down.store(true, Ordering::SeqCst);
+            wait_for(second_runs, 3).await;
+            assert_eq!(lease.get_holder().as_deref(), Some("second"));
         });
     }
+
+    #[test]
+    #[should_panic(expected = "Timer with the name [unknown] is not found")]
+    fn handle_panics_on_unknown_timer() {
+        let timer = MyTimer::new(INTERVAL);
+        timer.get_handle().pause("unknown");
+    }
+
+    #[test]
+    fn jitter_stays_within_the_limit() {
+        let max_jitter = Duration::from_millis(3);
+
+        for _ in 0..100 {
+            assert!(super::get_jitter(max_jitter) <= max_jitter);
+        }
+
+        assert_eq!(super::get_jitter(Duration::ZERO), Duration::ZERO);
+    }
 }
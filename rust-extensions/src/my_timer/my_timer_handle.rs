@@ -0,0 +1,89 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use parking_lot::Mutex;
+use tokio::sync::Notify;
+
+pub(super) struct MyTimerControl {
+    pub name: String,
+    paused: AtomicBool,
+    triggered: AtomicBool,
+    /// Shared by the timers which run in the same loop.
+    pub wake_up: Arc<Notify>,
+}
+
+impl MyTimerControl {
+    pub fn new(name: String, wake_up: Arc<Notify>) -> Self {
+        Self {
+            name,
+            paused: AtomicBool::new(false),
+            triggered: AtomicBool::new(false),
+            wake_up,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    pub fn take_triggered(&self) -> bool {
+        self.triggered.swap(false, Ordering::SeqCst)
+    }
+
+    /// The triggered one runs now, the rest of them which are due - as
+    /// scheduled; the paused one is not run unless it is triggered.
+    pub fn should_run_when_due(&self) -> bool {
+        let triggered = self.take_triggered();
+        triggered || !self.is_paused()
+    }
+}
+
+/// Controls the timers of a [`super::MyTimer`] by name at runtime. Cloned handles
+/// control the same timers. The methods panic if there is no timer with the name.
+#[derive(Clone, Default)]
+pub struct MyTimerHandle {
+    controls: Arc<Mutex<Vec<Arc<MyTimerControl>>>>,
+}
+
+impl MyTimerHandle {
+    pub(super) fn add(&self, control: Arc<MyTimerControl>) {
+        self.controls.lock().push(control);
+    }
+
+    pub(super) fn get(&self, timer_name: &str) -> Arc<MyTimerControl> {
+        let controls = self.controls.lock();
+
+        for control in controls.iter() {
+            if control.name == timer_name {
+                return control.clone();
+            }
+        }
+
+        panic!("Timer with the name [{}] is not found", timer_name);
+    }
+
+    /// The scheduled ticks of the timer are skipped until it is resumed. The tick
+    /// which is running right now is not interrupted.
+    pub fn pause(&self, timer_name: &str) {
+        self.get(timer_name).paused.store(true, Ordering::SeqCst);
+    }
+
+    pub fn resume(&self, timer_name: &str) {
+        self.get(timer_name).paused.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_paused(&self, timer_name: &str) -> bool {
+        self.get(timer_name).is_paused()
+    }
+
+    /// Runs the tick as soon as its loop is free - even if the timer is paused.
+    /// The schedule of the timer is not shifted. Triggers which come before the
+    /// tick is started are coalesced.
+    pub fn trigger_now(&self, timer_name: &str) {
+        let control = self.get(timer_name);
+        control.triggered.store(true, Ordering::SeqCst);
+        control.wake_up.notify_one();
+    }
+}
@@ -0,0 +1,16 @@
+/// What a timer with its own interval does when a tick runs past the moment the
+/// next one is due.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MyTimerOverlapPolicy {
+    /// The ticks missed while the long one was running are dropped - the next
+    /// tick is the next one due by the schedule.
+    #[default]
+    Skip,
+    /// The ticks missed while the long one was running collapse into one extra
+    /// tick which starts as soon as the long one is done.
+    Queue,
+    /// The next tick starts on schedule even if the previous one is still
+    /// running - unless `MyTimer::set_max_concurrent_ticks` of them are, then
+    /// it is skipped.
+    Concurrent,
+}
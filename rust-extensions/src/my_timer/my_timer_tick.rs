@@ -1,3 +1,5 @@
+use serde::Serialize;
+
 /// What the timer loop must do once `tick()` returned.
 ///
 /// A tick which understood in the middle of its work that there is more to do -
@@ -5,7 +7,7 @@
 /// `Immediately`: it leaves the iteration and is started again straight away,
 /// **with a fresh `iteration_timeout` window**. That is the way to do a long job
 /// in portions without ever tripping the timer's per-iteration timeout.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 pub enum RepeatTimerIteration {
     /// The iteration is complete - wait for the next scheduled tick as usual.
     WithInterval,
@@ -0,0 +1,182 @@
+use std::{collections::VecDeque, time::Duration};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+
+use crate::date_time::DateTimeAsMicroseconds;
+
+use super::RepeatTimerIteration;
+
+pub const DEFAULT_HISTORY_CAPACITY: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum TimerTickOutcome {
+    Ok,
+    Timeout,
+    Panic,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TimerTickRecord {
+    pub started: DateTimeAsMicroseconds,
+    pub duration: Duration,
+    pub outcome: TimerTickOutcome,
+    /// What the tick answered - `None` if it panicked or timed out.
+    pub repeat: Option<RepeatTimerIteration>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TimerHistorySnapshot {
+    pub name: String,
+    /// The oldest first.
+    pub ticks: Vec<TimerTickRecord>,
+}
+
+/// The recent ticks of every timer registered on a [`super::MyTimer`] or a
+/// [`crate::MyExactTimer`] - for an admin page.
+#[derive(Debug, Clone, Serialize)]
+pub struct TimersHistorySnapshot {
+    pub timers: Vec<TimerHistorySnapshot>,
+}
+
+struct TimersHistoryInner {
+    capacity: usize,
+    timers: Vec<(String, VecDeque<TimerTickRecord>)>,
+}
+
+/// Keeps the last `capacity` ticks of every registered timer - the scheduled
+/// ones, the repeated ones and the ones executed by hand.
+pub(crate) struct TimersHistory {
+    inner: Mutex<TimersHistoryInner>,
+}
+
+impl TimersHistory {
+    pub fn new() -> Self {
+        Self {
+            inner: Mutex::new(TimersHistoryInner {
+                capacity: DEFAULT_HISTORY_CAPACITY,
+                timers: Vec::new(),
+            }),
+        }
+    }
+
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut inner = self.inner.lock();
+        inner.capacity = capacity;
+
+        for (_, ticks) in inner.timers.iter_mut() {
+            while ticks.len() > capacity {
+                ticks.pop_front();
+            }
+        }
+    }
+
+    pub fn register(&self, timer_name: &str) {
+        self.inner
+            .lock()
+            .timers
+            .push((timer_name.to_string(), VecDeque::new()));
+    }
+
+    pub fn add(&self, timer_name: &str, record: TimerTickRecord) {
+        let mut inner = self.inner.lock();
+        let capacity = inner.capacity;
+
+        if capacity == 0 {
+            return;
+        }
+
+        let Some((_, ticks)) = inner.timers.iter_mut().find(|(name, _)| name == timer_name) else {
+            return;
+        };
+
+        if ticks.len() >= capacity {
+            ticks.pop_front();
+        }
+
+        ticks.push_back(record);
+    }
+
+    pub fn get(&self, timer_name: &str) -> Option<Vec<TimerTickRecord>> {
+        let inner = self.inner.lock();
+
+        inner
+            .timers
+            .iter()
+            .find(|(name, _)| name == timer_name)
+            .map(|(_, ticks)| ticks.iter().cloned().collect())
+    }
+
+    pub fn get_snapshot(&self) -> TimersHistorySnapshot {
+        let inner = self.inner.lock();
+
+        TimersHistorySnapshot {
+            timers: inner
+                .timers
+                .iter()
+                .map(|(name, ticks)| TimerHistorySnapshot {
+                    name: name.clone(),
+                    ticks: ticks.iter().cloned().collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(started: i64) -> TimerTickRecord {
+        TimerTickRecord {
+            started: DateTimeAsMicroseconds::new(started),
+            duration: Duration::from_millis(1),
+            outcome: TimerTickOutcome::Ok,
+            repeat: Some(RepeatTimerIteration::WithInterval),
+        }
+    }
+
+    #[test]
+    fn keeps_the_last_ticks_of_every_timer() {
+        let history = TimersHistory::new();
+        history.set_capacity(2);
+        history.register("first");
+        history.register("second");
+
+        for started in 1..=3 {
+            history.add("first", record(started));
+        }
+
+        history.add("unknown", record(4));
+
+        let first: Vec<_> = history
+            .get("first")
+            .unwrap()
+            .iter()
+            .map(|tick| tick.started.unix_microseconds)
+            .collect();
+
+        assert_eq!(first, vec![2, 3]);
+        assert!(history.get("second").unwrap().is_empty());
+        assert!(history.get("unknown").is_none());
+
+        history.set_capacity(1);
+        assert_eq!(history.get("first").unwrap().len(), 1);
+
+        history.set_capacity(0);
+        history.add("second", record(5));
+        assert!(history.get("second").unwrap().is_empty());
+    }
+
+    #[test]
+    fn snapshot_is_serializable() {
+        let history = TimersHistory::new();
+        history.register("test");
+        history.add("test", record(1));
+
+        let json = serde_json::to_string(&history.get_snapshot()).unwrap();
+
+        assert!(json.starts_with(r#"{"timers":[{"name":"test","ticks":[{"started":"#));
+        assert!(json.contains(r#""outcome":"Ok","repeat":"WithInterval""#));
+    }
+}
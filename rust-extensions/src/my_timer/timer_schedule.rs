@@ -0,0 +1,195 @@
+use std::{sync::Arc, time::Duration};
+
+use futures::future::Either;
+use tokio::{sync::Notify, task::JoinSet, time::Instant};
+
+use crate::{health::HealthReporter, lease::LeaseKeeper, ApplicationStates, Logger};
+
+use super::{
+    my_timer_handle::MyTimerControl, timer_history::TimersHistory,
+    timers_iteration::execute_timers_iteration, timers_iteration::RegisteredTimer,
+    MyTimerOverlapPolicy,
+};
+
+/// A random delay within `0..=max_jitter` - so the timers of the instances
+/// started at once do not tick at once.
+pub fn get_jitter(max_jitter: Duration) -> Duration {
+    if max_jitter.is_zero() {
+        return Duration::ZERO;
+    }
+
+    Duration::from_micros(fastrand::u64(..=max_jitter.as_micros() as u64))
+}
+
+/// Returns `true` if the deadline is reached, `false` if the loop is woken up by
+/// a trigger.
+pub async fn wait_for_deadline(deadline: Instant, wake_up: &Notify) -> bool {
+    let sleep = Box::pin(tokio::time::sleep_until(deadline));
+    let notified = Box::pin(wake_up.notified());
+
+    match futures::future::select(sleep, notified).await {
+        Either::Left(_) => true,
+        Either::Right(_) => false,
+    }
+}
+
+/// Executes the ticks and then the ones which asked to be repeated - each time
+/// with a fresh timeout window - until every one of them is done. Nothing is
+/// executed if the timer has a lease and this replica is not the leader.
+pub async fn execute_with_repeats(timers: Vec<&RegisteredTimer>, settings: &TimerLoopSettings) {
+    if timers.is_empty() {
+        return;
+    }
+
+    let _lease_guard = match settings.lease.as_ref() {
+        Some(lease) => match lease.try_enter() {
+            Some(guard) => Some(guard),
+            None => return,
+        },
+        None => None,
+    };
+
+    let mut to_execute = timers;
+
+    while !to_execute.is_empty() {
+        to_execute = execute_timers_iteration(
+            &to_execute,
+            &settings.logger,
+            settings.iteration_timeout,
+            settings.health.as_ref(),
+            &settings.history,
+        )
+        .await;
+
+        if settings.app_states.is_shutting_down() {
+            break;
+        }
+    }
+}
+
+pub struct TimerWithInterval {
+    pub timer: RegisteredTimer,
+    pub control: Arc<MyTimerControl>,
+    pub interval: Duration,
+    pub overlap_policy: MyTimerOverlapPolicy,
+}
+
+pub struct TimerLoopSettings {
+    pub max_jitter: Duration,
+    pub max_concurrent_ticks: usize,
+    pub delay_before_first_tick: bool,
+    pub iteration_timeout: Duration,
+    pub app_states: Arc<dyn ApplicationStates + Send + Sync + 'static>,
+    pub logger: Arc<dyn Logger + Send + Sync + 'static>,
+    pub health: Option<Arc<HealthReporter>>,
+    pub history: Arc<TimersHistory>,
+    pub lease: Option<Arc<LeaseKeeper>>,
+}
+
+/// The loop of a timer registered with its own interval. Unlike the shared loop
+/// the ticks are due at the fixed rate - every `interval` since the start, plus
+/// the jitter - so a tick which runs longer than `interval` overlaps the next
+/// one and the overlap policy decides what happens.
+///
+/// The ticks of the concurrent policy are kept in a [`JoinSet`]: once the loop is over
+/// it waits for the ones still running.
+pub async fn timer_with_interval_loop(timer: TimerWithInterval, settings: Arc<TimerLoopSettings>) {
+    while !settings.app_states.is_initialized() {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+
+    settings.logger.write_info(
+        timer.timer.0.to_string(),
+        format!(
+            "Timer {} is started with interval {:?}",
+            timer.timer.0, timer.interval
+        ),
+        None,
+    );
+
+    let timer = Arc::new(timer);
+
+    let mut scheduled = Instant::now();
+
+    if settings.delay_before_first_tick {
+        scheduled += timer.interval;
+    }
+
+    let mut deadline = scheduled + get_jitter(settings.max_jitter);
+    let mut catching_up = false;
+    let mut in_flight = JoinSet::new();
+
+    while !settings.app_states.is_shutting_down() {
+        if !wait_for_deadline(deadline, &timer.control.wake_up).await {
+            if timer.control.take_triggered() {
+                execute_tick(&timer, &settings, &mut in_flight).await;
+            }
+
+            continue;
+        }
+
+        if timer.control.should_run_when_due() {
+            execute_tick(&timer, &settings, &mut in_flight).await;
+        }
+
+        // The extra tick of the queue policy is not a scheduled one.
+        if !catching_up {
+            scheduled += timer.interval;
+        }
+
+        let now = Instant::now();
+        catching_up = false;
+
+        if scheduled <= now {
+            // The tick ran past the moment the next one was due.
+            while scheduled <= now {
+                scheduled += timer.interval;
+            }
+
+            catching_up = timer.overlap_policy == MyTimerOverlapPolicy::Queue;
+        }
+
+        deadline = if catching_up {
+            now
+        } else {
+            scheduled + get_jitter(settings.max_jitter)
+        };
+    }
+
+    while in_flight.join_next().await.is_some() {}
+}
+
+async fn execute_tick(
+    timer: &Arc<TimerWithInterval>,
+    settings: &Arc<TimerLoopSettings>,
+    in_flight: &mut JoinSet<()>,
+) {
+    if timer.overlap_policy != MyTimerOverlapPolicy::Concurrent {
+        execute_with_repeats(vec![&timer.timer], settings).await;
+        return;
+    }
+
+    while in_flight.try_join_next().is_some() {}
+
+    // A tick which hangs or is always slower than the interval would pile up the
+    // running ones without end - the tick is skipped instead.
+    if in_flight.len() >= settings.max_concurrent_ticks {
+        settings.logger.write_warning(
+            timer.timer.0.to_string(),
+            format!(
+                "Timer {} skips a tick: {} of its ticks are still running",
+                timer.timer.0,
+                in_flight.len()
+            ),
+            None,
+        );
+        return;
+    }
+
+    let timer = timer.clone();
+    let settings = settings.clone();
+
+    in_flight.spawn(async move {
+        execute_with_repeats(vec![&timer.timer], &settings).await;
+    });
+}
@@ -1,10 +1,17 @@
-use std::{panic::AssertUnwindSafe, sync::Arc, time::Duration};
+use std::{
+    panic::AssertUnwindSafe,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use futures::FutureExt;
 
-use crate::Logger;
+use crate::{date_time::DateTimeAsMicroseconds, health::HealthReporter, Logger};
 
-use super::{MyTimerTick, RepeatTimerIteration};
+use super::{
+    timer_history::{TimerTickOutcome, TimerTickRecord, TimersHistory},
+    MyTimerTick, RepeatTimerIteration,
+};
 
 pub type RegisteredTimer = (String, Arc<dyn MyTimerTick + Send + Sync + 'static>);
 
@@ -23,12 +30,21 @@ pub type RegisteredTimer = (String, Arc<dyn MyTimerTick + Send + Sync + 'static>
 /// `WithInterval` keeps its schedule and is not dragged into a neighbour's extra
 /// pass. A tick which panicked or timed out answered nothing and is not
 /// repeated either.
+///
+/// The pass in which every tick finished is the heartbeat of the timer; every
+/// panicked or timed out tick is reported as an error. Every tick is written
+/// to the `history`.
 pub async fn execute_timers_iteration<'s>(
     timers: &[&'s RegisteredTimer],
     logger: &Arc<dyn Logger + Send + Sync + 'static>,
     iteration_timeout: Duration,
+    health: Option<&Arc<HealthReporter>>,
+    history: &TimersHistory,
 ) -> Vec<&'s RegisteredTimer> {
     let mut repeat_immediately = Vec::new();
+    let mut failed = false;
+    let started = DateTimeAsMicroseconds::now();
+    let started_instant = Instant::now();
 
     if timers.len() == 1 {
         let timer = timers[0];
@@ -37,26 +53,48 @@ pub async fn execute_timers_iteration<'s>(
 
         match tokio::time::timeout(iteration_timeout, tick_future).await {
             Ok(Ok(repeat)) => {
+                add_to_history(
+                    history,
+                    timer_id,
+                    started,
+                    started_instant.elapsed(),
+                    Some(repeat),
+                );
+
                 if repeat.is_immediately() {
                     repeat_immediately.push(timer);
                 }
             }
             Ok(Err(_panic)) => {
+                add_to_history(history, timer_id, started, started_instant.elapsed(), None);
                 let message = format!("Timer {} is panicked", timer_id);
                 println!("{}", message);
+                report_error(health, &message);
                 logger.write_error(timer_id.to_string().into(), message.into(), None.into());
+                failed = true;
             }
             Err(err) => {
+                add_timeout_to_history(history, timer_id, started, started_instant.elapsed());
                 println!("Timer {} is time outed with err: {:?}", timer_id, err);
+                report_error(health, &format!("Timer {} is time outed", timer_id));
+                failed = true;
             }
         }
 
+        report_heartbeat(health, failed);
         return repeat_immediately;
     }
 
     let mut timer_handles = Vec::with_capacity(timers.len());
     for timer in timers {
-        let handle = tokio::spawn(execute_timer(timer.1.clone()));
+        let timer_tick = timer.1.clone();
+
+        let handle = tokio::spawn(async move {
+            let tick_started = Instant::now();
+            let repeat = execute_timer(timer_tick).await;
+            (repeat, tick_started.elapsed())
+        });
+
         timer_handles.push((*timer, handle));
     }
 
@@ -64,13 +102,18 @@ pub async fn execute_timers_iteration<'s>(
         let timer_id = &timer.0;
 
         match tokio::time::timeout(iteration_timeout, timer_handler).await {
-            Ok(Ok(repeat)) => {
+            Ok(Ok((repeat, duration))) => {
+                add_to_history(history, timer_id, started, duration, Some(repeat));
+
                 if repeat.is_immediately() {
                     repeat_immediately.push(timer);
                 }
             }
             Ok(Err(err)) => {
+                add_to_history(history, timer_id, started, started_instant.elapsed(), None);
                 let message = format!("Timer {} is panicked. Err: {:?}", timer_id, err);
+                report_error(health, &message);
+                failed = true;
                 let timer_id = timer_id.to_string();
                 let logger = logger.clone();
 
@@ -80,14 +123,96 @@ pub async fn execute_timers_iteration<'s>(
                 });
             }
             Err(err) => {
+                add_timeout_to_history(history, timer_id, started, started_instant.elapsed());
                 println!("Timer {} is time outed with err: {:?}", timer_id, err);
+                report_error(health, &format!("Timer {} is time outed", timer_id));
+                failed = true;
             }
         }
     }
 
+    report_heartbeat(health, failed);
     repeat_immediately
 }
 
+/// `repeat` is `None` for the panicked tick.
+fn add_to_history(
+    history: &TimersHistory,
+    timer_id: &str,
+    started: DateTimeAsMicroseconds,
+    duration: Duration,
+    repeat: Option<RepeatTimerIteration>,
+) {
+    history.add(
+        timer_id,
+        TimerTickRecord {
+            started,
+            duration,
+            outcome: if repeat.is_some() {
+                TimerTickOutcome::Ok
+            } else {
+                TimerTickOutcome::Panic
+            },
+            repeat,
+        },
+    );
+}
+
+fn add_timeout_to_history(
+    history: &TimersHistory,
+    timer_id: &str,
+    started: DateTimeAsMicroseconds,
+    duration: Duration,
+) {
+    history.add(
+        timer_id,
+        TimerTickRecord {
+            started,
+            duration,
+            outcome: TimerTickOutcome::Timeout,
+            repeat: None,
+        },
+    );
+}
+
+/// Executes the tick by hand, out of any schedule, and writes it to the
+/// `history`. A panic of the tick is passed on to the caller.
+pub async fn execute_timer_by_hand(
+    timer: &RegisteredTimer,
+    history: &TimersHistory,
+) -> RepeatTimerIteration {
+    let started = DateTimeAsMicroseconds::now();
+    let started_instant = Instant::now();
+
+    let result = tokio::spawn(execute_timer(timer.1.clone())).await;
+    let repeat = result.as_ref().ok().copied();
+    add_to_history(
+        history,
+        &timer.0,
+        started,
+        started_instant.elapsed(),
+        repeat,
+    );
+
+    result.unwrap()
+}
+
+fn report_error(health: Option<&Arc<HealthReporter>>, message: &str) {
+    if let Some(health) = health {
+        health.report_error(message);
+    }
+}
+
+fn report_heartbeat(health: Option<&Arc<HealthReporter>>, failed: bool) {
+    if failed {
+        return;
+    }
+
+    if let Some(health) = health {
+        health.heartbeat();
+    }
+}
+
 pub async fn execute_timer(
     timer: Arc<dyn MyTimerTick + Send + Sync + 'static>,
 ) -> RepeatTimerIteration {
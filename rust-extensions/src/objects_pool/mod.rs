@@ -1,6 +1,9 @@
 mod object_pool;
+mod object_pool_error;
 mod object_pool_inner;
+mod object_pool_stats;
 mod rented_object;
 pub use object_pool::{ObjectsPool, ObjectsPoolFactory};
-pub use object_pool_inner::ObjectPoolInner;
+pub use object_pool_error::ObjectsPoolError;
+pub use object_pool_stats::ObjectsPoolStats;
 pub use rented_object::RentedObject;
@@ -1,50 +1,527 @@
-use std::sync::Arc;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use parking_lot::Mutex;
+use tokio::sync::oneshot;
 
-use super::{ObjectPoolInner, RentedObject};
+use crate::Logger;
 
-use super::object_pool_inner::RentResult;
+use super::{
+    object_pool_inner::{
+        Handoff, ObjectPoolInner, ObjectsPoolLimits, ObjectsPoolShared, PooledObject, RentResult,
+    },
+    ObjectsPoolError, ObjectsPoolStats, RentedObject,
+};
 
 #[async_trait::async_trait]
-pub trait ObjectsPoolFactory<T: Sync + Send + 'static> {
-    async fn create_new(&self) -> T;
+pub trait ObjectsPoolFactory<T: Sync + Send + 'static>: Send + Sync + 'static {
+    type Error: std::fmt::Debug + Send + 'static;
+
+    async fn create_new(&self) -> Result<T, Self::Error>;
+
+    /// Checked before an idle object is handed out - a ping of a connection. The
+    /// object which fails is dropped and the next one is tried.
+    async fn validate(&self, _item: &T) -> bool {
+        true
+    }
+
+    /// Checked when the object is returned - a flag of a closed connection. The
+    /// broken object is dropped instead of going back to the pool.
+    fn is_broken(&self, _item: &T) -> bool {
+        false
+    }
 }
 
+/// Up to `max_pool_size` objects created by the factory on demand. When all of
+/// them are rented, the callers wait in a queue and get the objects in the
+/// order they came - an object returned to the pool goes straight to the first
+/// of them.
 pub struct ObjectsPool<T: Sync + Send + 'static, TFactory: ObjectsPoolFactory<T>> {
-    inner: Arc<Mutex<ObjectPoolInner<T>>>,
-
-    max_pool_size: usize,
+    shared: Arc<ObjectsPoolShared<T>>,
     factory: Arc<TFactory>,
+    acquire_timeout: Option<Duration>,
 }
 
 impl<T: Sync + Send + 'static, TFactory: ObjectsPoolFactory<T>> ObjectsPool<T, TFactory> {
     pub fn new(max_pool_size: usize, factory: Arc<TFactory>) -> Self {
+        let limits = ObjectsPoolLimits {
+            max_size: max_pool_size,
+            min_idle: 0,
+            idle_timeout: None,
+            max_lifetime: None,
+        };
+
+        let is_broken = {
+            let factory = factory.clone();
+            Box::new(move |item: &T| factory.is_broken(item))
+        };
+
         Self {
-            inner: Arc::new(Mutex::new(ObjectPoolInner::new())),
-            max_pool_size,
+            shared: Arc::new(ObjectsPoolShared {
+                inner: Mutex::new(ObjectPoolInner::new(limits)),
+                is_broken,
+            }),
             factory,
+            acquire_timeout: None,
+        }
+    }
+
+    /// The object idle for that long is dropped instead of being handed out.
+    pub fn set_idle_timeout(self, idle_timeout: Duration) -> Self {
+        self.shared.inner.lock().limits.idle_timeout = Some(idle_timeout);
+        self
+    }
+
+    /// The object created that long ago is dropped instead of being handed out
+    /// or returned to the pool.
+    pub fn set_max_lifetime(self, max_lifetime: Duration) -> Self {
+        self.shared.inner.lock().limits.max_lifetime = Some(max_lifetime);
+        self
+    }
+
+    /// [`Self::warm_up`] and the replenishment keep at least that many idle
+    /// objects - as long as `max_pool_size` allows.
+    pub fn set_min_idle(self, min_idle: usize) -> Self {
+        self.shared.inner.lock().limits.min_idle = min_idle;
+        self
+    }
+
+    /// [`Self::get_element`] gives up with [`ObjectsPoolError::AcquireTimeout`]
+    /// if no object gets free within the timeout. Waits forever by default.
+    pub fn set_acquire_timeout(mut self, acquire_timeout: Duration) -> Self {
+        self.acquire_timeout = Some(acquire_timeout);
+        self
+    }
+
+    pub async fn get_element(&self) -> Result<RentedObject<T>, ObjectsPoolError<TFactory::Error>> {
+        let started = Instant::now();
+        let result = self.acquire().await;
+
+        let mut inner = self.shared.inner.lock();
+
+        match &result {
+            Ok(_) => inner.add_acquired(started.elapsed()),
+            Err(ObjectsPoolError::AcquireTimeout) => inner.add_acquire_timeout(),
+            // Counted by the creation itself.
+            Err(ObjectsPoolError::Factory(_)) => {}
         }
+
+        drop(inner);
+        result
     }
 
-    pub async fn get_element(&self) -> RentedObject<T> {
+    /// Creates the objects till there are `min_idle` of them idle. Returns the
+    /// amount of the created objects. An object created while somebody waits
+    /// goes to the waiter.
+    pub async fn warm_up(&self) -> Result<usize, ObjectsPoolError<TFactory::Error>> {
+        let mut created = 0;
+
         loop {
-            let rent_result = {
-                let mut write_access = self.inner.lock();
-                write_access.take(self.max_pool_size)
-            };
+            let reserved = self.shared.inner.lock().reserve_for_idle(Instant::now());
 
-            match rent_result {
-                RentResult::Rented(result) => {
-                    return RentedObject::new(self.inner.clone(), result);
-                }
-                RentResult::CreateNew => {
-                    return RentedObject::new(self.inner.clone(), self.factory.create_new().await);
+            if !reserved {
+                return Ok(created);
+            }
+
+            let object = self.create_object().await?;
+            self.shared.inner.lock().return_object(object);
+            created += 1;
+        }
+    }
+
+    /// Spawns the task which evicts the expired objects and brings the idle ones
+    /// back to `min_idle` every `interval`. The task stops once the pool is
+    /// dropped.
+    pub fn start_replenishing(
+        self: &Arc<Self>,
+        interval: Duration,
+        logger: Arc<dyn Logger + Send + Sync + 'static>,
+    ) {
+        let pool = Arc::downgrade(self);
+
+        tokio::spawn(async move {
+            loop {
+                let Some(pool) = pool.upgrade() else {
+                    return;
+                };
+
+                pool.evict_expired();
+
+                if let Err(err) = pool.warm_up().await {
+                    logger.write_warning(
+                        "ObjectsPool".to_string(),
+                        format!("Can not replenish the pool. Err: {:?}", err),
+                        None,
+                    );
                 }
-                RentResult::Wait => {
-                    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+                drop(pool);
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+
+    pub fn get_stats(&self) -> ObjectsPoolStats {
+        self.shared.inner.lock().get_stats()
+    }
+
+    async fn acquire(&self) -> Result<RentedObject<T>, ObjectsPoolError<TFactory::Error>> {
+        let deadline = self
+            .acquire_timeout
+            .map(|timeout| tokio::time::Instant::now() + timeout);
+
+        loop {
+            let rent_result = self.shared.inner.lock().take(Instant::now());
+
+            let handoff = match rent_result {
+                RentResult::Rented(object) => Handoff::Object(object),
+                RentResult::CreateNew => Handoff::CreateNew,
+                RentResult::Wait(receiver) => match self.wait(receiver, deadline).await? {
+                    Some(handoff) => handoff,
+                    None => continue,
+                },
+            };
+
+            match handoff {
+                Handoff::Object(object) => {
+                    let rented = RentedObject::new(self.shared.clone(), object);
+
+                    if self.factory.validate(rented.get_value()).await {
+                        return Ok(rented);
+                    }
+
+                    rented.discard();
                 }
+                Handoff::CreateNew => return self.create_new().await,
             }
         }
     }
+
+    /// The objects alive - idle, rented or being created.
+    pub fn get_created_amount(&self) -> usize {
+        self.shared.inner.lock().get_created_amount()
+    }
+
+    pub fn get_idle_amount(&self) -> usize {
+        self.shared.inner.lock().get_idle_amount()
+    }
+
+    /// Drops the idle objects which reached the idle timeout or the max lifetime
+    /// right away - otherwise it happens when the objects are asked for. Returns
+    /// the amount of the dropped objects.
+    pub fn evict_expired(&self) -> usize {
+        self.shared.inner.lock().evict_expired(Instant::now())
+    }
+
+    async fn create_new(&self) -> Result<RentedObject<T>, ObjectsPoolError<TFactory::Error>> {
+        let object = self.create_object().await?;
+        Ok(RentedObject::new(self.shared.clone(), object))
+    }
+
+    /// Creates the object for the slot already taken.
+    async fn create_object(&self) -> Result<PooledObject<T>, ObjectsPoolError<TFactory::Error>> {
+        // Frees the slot if the factory fails or the caller gives up.
+        let mut slot = SlotGuard {
+            shared: &self.shared,
+            taken: false,
+        };
+
+        let value = match self.factory.create_new().await {
+            Ok(value) => value,
+            Err(err) => {
+                self.shared.inner.lock().add_creation_failure();
+                return Err(ObjectsPoolError::Factory(err));
+            }
+        };
+
+        slot.taken = true;
+
+        let now = Instant::now();
+
+        Ok(PooledObject {
+            value,
+            created: now,
+            returned: now,
+        })
+    }
+
+    /// `None` if the pool dropped the waiter without an answer - the caller
+    /// goes back to the queue.
+    async fn wait(
+        &self,
+        receiver: oneshot::Receiver<Handoff<T>>,
+        deadline: Option<tokio::time::Instant>,
+    ) -> Result<Option<Handoff<T>>, ObjectsPoolError<TFactory::Error>> {
+        let mut waiter = Waiter {
+            receiver,
+            shared: &self.shared,
+        };
+
+        let received = match deadline {
+            Some(deadline) => tokio::time::timeout_at(deadline, &mut waiter.receiver)
+                .await
+                .map_err(|_| ObjectsPoolError::AcquireTimeout)?,
+            None => (&mut waiter.receiver).await,
+        };
+
+        Ok(received.ok())
+    }
+}
+
+struct SlotGuard<'s, T> {
+    shared: &'s ObjectsPoolShared<T>,
+    taken: bool,
+}
+
+impl<T> Drop for SlotGuard<'_, T> {
+    fn drop(&mut self) {
+        if !self.taken {
+            self.shared.inner.lock().release_slot();
+        }
+    }
+}
+
+/// The place in the queue. A waiter which gives up - times out or is cancelled -
+/// may already have been handed an object or a slot; it is given back then.
+struct Waiter<'s, T> {
+    receiver: oneshot::Receiver<Handoff<T>>,
+    shared: &'s ObjectsPoolShared<T>,
+}
+
+impl<T> Drop for Waiter<'_, T> {
+    fn drop(&mut self) {
+        self.receiver.close();
+
+        if let Ok(handoff) = self.receiver.try_recv() {
+            self.shared.inner.lock().give_back(handoff);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use super::{ObjectsPool, ObjectsPoolFactory};
+    use crate::{objects_pool::ObjectsPoolError, Logger};
+
+    fn rt() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap()
+    }
+
+    struct TestLogger;
+
+    impl Logger for TestLogger {
+        fn write_info(&self, _: String, _: String, _: Option<HashMap<String, String>>) {}
+        fn write_warning(&self, _: String, _: String, _: Option<HashMap<String, String>>) {}
+        fn write_error(&self, _: String, _: String, _: Option<HashMap<String, String>>) {}
+        fn write_fatal_error(&self, _: String, _: String, _: Option<HashMap<String, String>>) {}
+        fn write_debug_info(&self, _: String, _: String, _: Option<HashMap<String, String>>) {}
+    }
+
+    struct TestObject {
+        id: usize,
+        broken: AtomicBool,
+    }
+
+    #[derive(Default)]
+    struct TestFactory {
+        created: AtomicUsize,
+        fail: AtomicBool,
+    }
+
+    #[async_trait::async_trait]
+    impl ObjectsPoolFactory<TestObject> for TestFactory {
+        type Error = String;
+
+        async fn create_new(&self) -> Result<TestObject, String> {
+            if self.fail.load(Ordering::SeqCst) {
+                return Err("can not connect".to_string());
+            }
+
+            Ok(TestObject {
+                id: self.created.fetch_add(1, Ordering::SeqCst),
+                broken: AtomicBool::new(false),
+            })
+        }
+
+        fn is_broken(&self, item: &TestObject) -> bool {
+            item.broken.load(Ordering::SeqCst)
+        }
+    }
+
+    #[test]
+    fn waiters_get_the_returned_object_in_order() {
+        rt().block_on(async {
+            let pool = Arc::new(ObjectsPool::new(1, Arc::new(TestFactory::default())));
+
+            let rented = pool.get_element().await.unwrap();
+            let order = Arc::new(parking_lot::Mutex::new(Vec::new()));
+
+            let mut waiters = Vec::new();
+
+            for waiter in 0..3 {
+                let pool = pool.clone();
+                let order = order.clone();
+
+                waiters.push(tokio::spawn(async move {
+                    let rented = pool.get_element().await.unwrap();
+                    order.lock().push((waiter, rented.get_value().id));
+                }));
+
+                tokio::task::yield_now().await;
+            }
+
+            drop(rented);
+
+            for waiter in waiters {
+                waiter.await.unwrap();
+            }
+
+            assert_eq!(*order.lock(), vec![(0, 0), (1, 0), (2, 0)]);
+        });
+    }
+
+    #[test]
+    fn broken_and_discarded_objects_free_their_slots() {
+        rt().block_on(async {
+            let factory = Arc::new(TestFactory::default());
+            let pool = ObjectsPool::new(1, factory.clone());
+
+            let rented = pool.get_element().await.unwrap();
+            rented.get_value().broken.store(true, Ordering::SeqCst);
+            drop(rented);
+
+            let rented = pool.get_element().await.unwrap();
+            assert_eq!(rented.get_value().id, 1);
+            rented.discard();
+
+            let mut rented = pool.get_element().await.unwrap();
+            assert_eq!(rented.get_value_mut().id, 2);
+            drop(rented);
+
+            // Healthy one is reused.
+            assert_eq!(pool.get_element().await.unwrap().get_value().id, 2);
+        });
+    }
+
+    #[test]
+    fn acquire_times_out_and_failed_factory_frees_the_slot() {
+        rt().block_on(async {
+            let factory = Arc::new(TestFactory::default());
+            let pool =
+                ObjectsPool::new(1, factory.clone()).set_acquire_timeout(Duration::from_millis(20));
+
+            let rented = pool.get_element().await.unwrap();
+
+            assert!(matches!(
+                pool.get_element().await,
+                Err(ObjectsPoolError::AcquireTimeout)
+            ));
+
+            rented.discard();
+            factory.fail.store(true, Ordering::SeqCst);
+
+            assert!(matches!(
+                pool.get_element().await,
+                Err(ObjectsPoolError::Factory(_))
+            ));
+
+            factory.fail.store(false, Ordering::SeqCst);
+            assert!(pool.get_element().await.is_ok());
+        });
+    }
+
+    #[test]
+    fn warm_up_and_stats() {
+        rt().block_on(async {
+            let factory = Arc::new(TestFactory::default());
+            let pool = ObjectsPool::new(3, factory.clone())
+                .set_min_idle(2)
+                .set_acquire_timeout(Duration::from_millis(10));
+
+            assert_eq!(pool.warm_up().await.unwrap(), 2);
+            assert_eq!(pool.warm_up().await.unwrap(), 0);
+
+            let first = pool.get_element().await.unwrap();
+            let second = pool.get_element().await.unwrap();
+            let third = pool.get_element().await.unwrap();
+            assert!(pool.get_element().await.is_err());
+
+            let stats = pool.get_stats();
+            assert_eq!(stats.total, 3);
+            assert_eq!(stats.idle, 0);
+            assert_eq!(stats.in_use, 3);
+            assert_eq!(stats.acquired, 3);
+            assert_eq!(stats.acquire_timeouts, 1);
+
+            drop((first, second));
+            third.discard();
+            factory.fail.store(true, Ordering::SeqCst);
+
+            // Two of them are idle already - nothing to create.
+            assert_eq!(pool.warm_up().await.unwrap(), 0);
+
+            pool.get_element().await.unwrap().discard();
+            assert!(pool.warm_up().await.is_err());
+
+            let stats = pool.get_stats();
+            assert_eq!(stats.total, 1);
+            assert_eq!(stats.idle, 1);
+            assert_eq!(stats.creation_failures, 1);
+            assert_eq!(stats.waiters, 0);
+        });
+    }
+
+    #[test]
+    fn replenishing_brings_back_the_idle_objects() {
+        rt().block_on(async {
+            let pool = Arc::new(
+                ObjectsPool::new(5, Arc::new(TestFactory::default()))
+                    .set_min_idle(2)
+                    .set_max_lifetime(Duration::from_millis(30)),
+            );
+
+            pool.start_replenishing(Duration::from_millis(10), Arc::new(TestLogger));
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            assert_eq!(pool.get_stats().idle, 2);
+
+            // The first ones outlive the max lifetime and are replaced.
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            let stats = pool.get_stats();
+            assert_eq!(stats.idle, 2);
+            assert_eq!(stats.total, 2);
+
+            let rented = pool.get_element().await.unwrap();
+            assert!(rented.get_value().id >= 2);
+        });
+    }
+
+    #[test]
+    fn idle_objects_expire() {
+        rt().block_on(async {
+            let pool = ObjectsPool::new(2, Arc::new(TestFactory::default()))
+                .set_idle_timeout(Duration::from_millis(20));
+
+            drop(pool.get_element().await.unwrap());
+            tokio::time::sleep(Duration::from_millis(30)).await;
+
+            assert_eq!(pool.evict_expired(), 1);
+            assert_eq!(pool.get_idle_amount(), 0);
+            assert_eq!(pool.get_created_amount(), 0);
+            assert_eq!(pool.get_element().await.unwrap().get_value().id, 1);
+        });
+    }
 }
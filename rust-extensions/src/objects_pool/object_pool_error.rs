@@ -0,0 +1,7 @@
+#[derive(Debug)]
+pub enum ObjectsPoolError<TError> {
+    /// No object got free within the acquire timeout.
+    AcquireTimeout,
+    /// The factory failed to create a new object.
+    Factory(TError),
+}
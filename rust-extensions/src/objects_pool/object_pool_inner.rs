@@ -1,38 +1,391 @@
-pub enum RentResult<T: Sync + Send + 'static> {
-    Rented(T),
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+use tokio::sync::oneshot;
+
+use super::ObjectsPoolStats;
+
+pub(super) struct PooledObject<T> {
+    pub value: T,
+    pub created: Instant,
+    pub returned: Instant,
+}
+
+/// What a waiter gets once it reaches the head of the queue: an object returned
+/// to the pool or the slot of an object which was discarded.
+pub(super) enum Handoff<T> {
+    Object(PooledObject<T>),
     CreateNew,
-    Wait,
 }
 
-pub struct ObjectPoolInner<T: Sync + Send + 'static> {
-    pool: Vec<T>,
+pub(super) enum RentResult<T> {
+    Rented(PooledObject<T>),
+    CreateNew,
+    Wait(oneshot::Receiver<Handoff<T>>),
+}
+
+#[derive(Clone, Copy)]
+pub(super) struct ObjectsPoolLimits {
+    pub max_size: usize,
+    pub min_idle: usize,
+    pub idle_timeout: Option<Duration>,
+    pub max_lifetime: Option<Duration>,
+}
+
+impl ObjectsPoolLimits {
+    pub fn is_expired(&self, object: &PooledObject<impl Sized>, now: Instant) -> bool {
+        if let Some(max_lifetime) = self.max_lifetime {
+            if now.duration_since(object.created) >= max_lifetime {
+                return true;
+            }
+        }
+
+        if let Some(idle_timeout) = self.idle_timeout {
+            if now.duration_since(object.returned) >= idle_timeout {
+                return true;
+            }
+        }
+
+        false
+    }
+}
+
+/// The part of the pool the rented objects go back to.
+pub(super) struct ObjectsPoolShared<T> {
+    pub inner: Mutex<ObjectPoolInner<T>>,
+    pub is_broken: Box<dyn Fn(&T) -> bool + Send + Sync>,
+}
+
+impl<T> ObjectsPoolShared<T> {
+    pub fn return_object(&self, mut object: PooledObject<T>) {
+        let now = Instant::now();
+        let limits = self.inner.lock().limits;
+
+        let outlived = match limits.max_lifetime {
+            Some(max_lifetime) => now.duration_since(object.created) >= max_lifetime,
+            None => false,
+        };
+
+        if outlived || (self.is_broken)(&object.value) {
+            self.discard(object);
+            return;
+        }
+
+        object.returned = now;
+        self.inner.lock().return_object(object);
+    }
+
+    pub fn discard(&self, object: PooledObject<T>) {
+        // Closing a connection may take a while - not under the lock.
+        drop(object);
+        self.inner.lock().release_slot();
+    }
+}
+
+/// `created_amount` counts every object which is alive - idle, rented or being
+/// created. A slot is freed only when an object is dropped for good, and it goes
+/// to the first waiter if there is one.
+pub(super) struct ObjectPoolInner<T> {
+    pub limits: ObjectsPoolLimits,
+    idle: VecDeque<PooledObject<T>>,
     created_amount: usize,
+    waiters: VecDeque<oneshot::Sender<Handoff<T>>>,
+    creation_failures: u64,
+    acquire_timeouts: u64,
+    acquired: u64,
+    total_wait: Duration,
 }
 
-impl<T: Sync + Send + 'static> ObjectPoolInner<T> {
-    pub fn new() -> Self {
+impl<T> ObjectPoolInner<T> {
+    pub fn new(limits: ObjectsPoolLimits) -> Self {
         Self {
-            pool: Vec::new(),
+            limits,
+            idle: VecDeque::new(),
             created_amount: 0,
+            waiters: VecDeque::new(),
+            creation_failures: 0,
+            acquire_timeouts: 0,
+            acquired: 0,
+            total_wait: Duration::ZERO,
         }
     }
 
-    pub fn take(&mut self, max_amount: usize) -> RentResult<T> {
-        if self.pool.len() > 0 {
-            let result = self.pool.pop().unwrap();
-            return RentResult::Rented(result);
+    pub fn get_stats(&self) -> ObjectsPoolStats {
+        let average_wait = if self.acquired == 0 {
+            Duration::ZERO
+        } else {
+            // In nanos, not `Duration / u32` - `acquired` is a u64 and does not fit.
+            Duration::from_nanos((self.total_wait.as_nanos() / self.acquired as u128) as u64)
+        };
+
+        ObjectsPoolStats {
+            total: self.created_amount,
+            idle: self.idle.len(),
+            in_use: self.created_amount - self.idle.len(),
+            waiters: self
+                .waiters
+                .iter()
+                .filter(|waiter| !waiter.is_closed())
+                .count(),
+            creation_failures: self.creation_failures,
+            acquire_timeouts: self.acquire_timeouts,
+            acquired: self.acquired,
+            average_wait,
+        }
+    }
+
+    pub fn add_acquired(&mut self, wait: Duration) {
+        self.acquired += 1;
+        self.total_wait += wait;
+    }
+
+    pub fn add_acquire_timeout(&mut self) {
+        self.acquire_timeouts += 1;
+    }
+
+    pub fn add_creation_failure(&mut self) {
+        self.creation_failures += 1;
+    }
+
+    /// Takes a slot for an object to be created into the idle ones - if there are
+    /// fewer of them than `min_idle` and nobody is waiting for a slot.
+    pub fn reserve_for_idle(&mut self, now: Instant) -> bool {
+        self.evict_expired(now);
+
+        if self.idle.len() >= self.limits.min_idle {
+            return false;
         }
 
-        if self.created_amount >= max_amount {
-            return RentResult::Wait;
+        // A free slot is theirs - `get()` creates the object for them right away.
+        if self.waiters.iter().any(|waiter| !waiter.is_closed()) {
+            return false;
+        }
+
+        if self.created_amount >= self.limits.max_size {
+            return false;
         }
 
         self.created_amount += 1;
+        true
+    }
 
-        return RentResult::CreateNew;
+    pub fn get_created_amount(&self) -> usize {
+        self.created_amount
     }
 
-    pub fn return_element(&mut self, item: T) {
-        self.pool.push(item);
+    pub fn get_idle_amount(&self) -> usize {
+        self.idle.len()
+    }
+
+    pub fn take(&mut self, now: Instant) -> RentResult<T> {
+        self.evict_expired(now);
+        self.waiters.retain(|waiter| !waiter.is_closed());
+
+        // Whoever came earlier is served first - a newcomer does not jump the
+        // queue even if an object is returned right now.
+        if self.waiters.is_empty() {
+            // The most recently returned object - the rest of them get the
+            // chance to reach the idle timeout.
+            if let Some(object) = self.idle.pop_back() {
+                return RentResult::Rented(object);
+            }
+
+            if self.created_amount < self.limits.max_size {
+                self.created_amount += 1;
+                return RentResult::CreateNew;
+            }
+        }
+
+        let (sender, receiver) = oneshot::channel();
+        self.waiters.push_back(sender);
+        RentResult::Wait(receiver)
+    }
+
+    pub fn return_object(&mut self, mut object: PooledObject<T>) {
+        while let Some(waiter) = self.waiters.pop_front() {
+            match waiter.send(Handoff::Object(object)) {
+                Ok(_) => return,
+                Err(handoff) => {
+                    // The waiter is gone - timed out or cancelled.
+                    let Handoff::Object(returned) = handoff else {
+                        unreachable!("The object was sent");
+                    };
+                    object = returned;
+                }
+            }
+        }
+
+        self.idle.push_back(object);
+    }
+
+    pub fn release_slot(&mut self) {
+        self.created_amount -= 1;
+
+        while let Some(waiter) = self.waiters.pop_front() {
+            self.created_amount += 1;
+
+            if waiter.send(Handoff::CreateNew).is_ok() {
+                return;
+            }
+
+            self.created_amount -= 1;
+        }
+    }
+
+    /// Puts back what a waiter got but can not use any more.
+    pub fn give_back(&mut self, handoff: Handoff<T>) {
+        match handoff {
+            Handoff::Object(object) => self.return_object(object),
+            Handoff::CreateNew => self.release_slot(),
+        }
+    }
+
+    /// Drops the idle objects which reached the idle timeout or the max lifetime.
+    /// Returns the amount of them.
+    pub fn evict_expired(&mut self, now: Instant) -> usize {
+        let before = self.idle.len();
+        let limits = self.limits;
+        self.idle.retain(|object| !limits.is_expired(object, now));
+
+        let evicted = before - self.idle.len();
+
+        for _ in 0..evicted {
+            self.release_slot();
+        }
+
+        evicted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(max_size: usize) -> ObjectsPoolLimits {
+        ObjectsPoolLimits {
+            max_size,
+            min_idle: 0,
+            idle_timeout: Some(Duration::from_secs(10)),
+            max_lifetime: Some(Duration::from_secs(60)),
+        }
+    }
+
+    fn object(value: u32, created: Instant, returned: Instant) -> PooledObject<u32> {
+        PooledObject {
+            value,
+            created,
+            returned,
+        }
+    }
+
+    fn rent(inner: &mut ObjectPoolInner<u32>, now: Instant) -> RentResult<u32> {
+        inner.take(now)
+    }
+
+    #[test]
+    fn waiters_are_served_in_order() {
+        let now = Instant::now();
+        let mut inner = ObjectPoolInner::new(limits(1));
+
+        assert!(matches!(rent(&mut inner, now), RentResult::CreateNew));
+
+        let RentResult::Wait(mut first) = rent(&mut inner, now) else {
+            panic!("Expected to wait");
+        };
+        let RentResult::Wait(mut second) = rent(&mut inner, now) else {
+            panic!("Expected to wait");
+        };
+
+        inner.return_object(object(1, now, now));
+
+        let Ok(Handoff::Object(object)) = first.try_recv() else {
+            panic!("The first waiter has to get the object");
+        };
+        assert_eq!(object.value, 1);
+        assert!(second.try_recv().is_err());
+
+        // The object is broken - the second waiter gets its slot.
+        inner.release_slot();
+        assert!(matches!(second.try_recv(), Ok(Handoff::CreateNew)));
+        assert_eq!(inner.get_created_amount(), 1);
+    }
+
+    #[test]
+    fn gone_waiter_is_skipped() {
+        let now = Instant::now();
+        let mut inner = ObjectPoolInner::new(limits(1));
+
+        rent(&mut inner, now);
+        let RentResult::Wait(gone) = rent(&mut inner, now) else {
+            panic!("Expected to wait");
+        };
+        drop(gone);
+
+        inner.return_object(object(1, now, now));
+        assert_eq!(inner.get_idle_amount(), 1);
+        assert!(matches!(rent(&mut inner, now), RentResult::Rented(_)));
+    }
+
+    #[test]
+    fn expired_objects_are_evicted() {
+        let now = Instant::now();
+        let at = |seconds: u64| now + Duration::from_secs(seconds);
+        let mut inner = ObjectPoolInner::new(limits(3));
+
+        for _ in 0..3 {
+            rent(&mut inner, now);
+        }
+
+        // Idle for too long.
+        inner.return_object(object(1, now, now));
+        assert_eq!(inner.evict_expired(at(12)), 1);
+        assert_eq!(inner.get_created_amount(), 2);
+
+        inner.return_object(object(2, now, at(55)));
+        inner.return_object(object(3, at(50), at(55)));
+
+        // The second one lives for too long.
+        let RentResult::Rented(object) = rent(&mut inner, at(61)) else {
+            panic!("Expected an object");
+        };
+        assert_eq!(object.value, 3);
+        assert_eq!(inner.get_created_amount(), 1);
+    }
+
+    #[test]
+    fn average_wait_is_computed_over_every_acquisition() {
+        let mut inner: ObjectPoolInner<u32> = ObjectPoolInner::new(limits(1));
+        assert_eq!(inner.get_stats().average_wait, Duration::ZERO);
+
+        inner.add_acquired(Duration::from_millis(10));
+        inner.add_acquired(Duration::from_millis(20));
+        inner.add_acquired(Duration::ZERO);
+        assert_eq!(inner.get_stats().average_wait, Duration::from_millis(10));
+
+        // Does not fit into u32 - the average must not be taken over its lower half.
+        inner.acquired = 1 << 32;
+        inner.total_wait = Duration::from_secs(1 << 32);
+        assert_eq!(inner.get_stats().average_wait, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn idle_reserve_does_not_take_slots_ahead_of_waiters() {
+        let now = Instant::now();
+        let mut inner: ObjectPoolInner<u32> = ObjectPoolInner::new(ObjectsPoolLimits {
+            min_idle: 1,
+            ..limits(2)
+        });
+
+        let (waiter, receiver) = oneshot::channel();
+        inner.waiters.push_back(waiter);
+        assert!(!inner.reserve_for_idle(now));
+
+        // A gone waiter does not count.
+        drop(receiver);
+        assert!(inner.reserve_for_idle(now));
+        assert_eq!(inner.get_created_amount(), 1);
     }
 }
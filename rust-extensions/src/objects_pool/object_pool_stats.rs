@@ -0,0 +1,19 @@
+use std::time::Duration;
+
+/// A snapshot of the [`super::ObjectsPool`] - for the metrics.
+#[derive(Debug, Clone, Default)]
+pub struct ObjectsPoolStats {
+    /// Every object alive - idle, rented or being created.
+    pub total: usize,
+    pub idle: usize,
+    /// Rented or being created.
+    pub in_use: usize,
+    /// Callers waiting for an object to get free.
+    pub waiters: usize,
+    pub creation_failures: u64,
+    pub acquire_timeouts: u64,
+    pub acquired: u64,
+    /// The average time `get_element` took - the waiting in the queue, the
+    /// creation and the validation included.
+    pub average_wait: Duration,
+}
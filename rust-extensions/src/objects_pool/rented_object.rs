@@ -1,32 +1,52 @@
 use std::sync::Arc;
 
-use parking_lot::Mutex;
-
-use super::ObjectPoolInner;
+use super::object_pool_inner::{ObjectsPoolShared, PooledObject};
 
+/// The object taken from the [`super::ObjectsPool`]. It goes back to the pool on
+/// drop - unless it is broken, outlived the max lifetime or is discarded.
 pub struct RentedObject<T: Sync + Send + 'static> {
-    value: Option<T>,
-    inner: Arc<Mutex<ObjectPoolInner<T>>>,
+    object: Option<PooledObject<T>>,
+    pool: Arc<ObjectsPoolShared<T>>,
 }
 
 impl<T: Sync + Send + 'static> RentedObject<T> {
-    pub fn new(inner: Arc<Mutex<ObjectPoolInner<T>>>, value: T) -> Self {
+    pub(super) fn new(pool: Arc<ObjectsPoolShared<T>>, object: PooledObject<T>) -> Self {
         Self {
-            value: Some(value),
-            inner,
+            object: Some(object),
+            pool,
         }
     }
 
     pub fn get_value(&self) -> &T {
-        self.value.as_ref().expect("Somehow value went to None")
+        &self
+            .object
+            .as_ref()
+            .expect("Somehow value went to None")
+            .value
+    }
+
+    pub fn get_value_mut(&mut self) -> &mut T {
+        &mut self
+            .object
+            .as_mut()
+            .expect("Somehow value went to None")
+            .value
+    }
+
+    /// Drops the object instead of returning it to the pool - for the object
+    /// which is known to be broken. Its slot goes to the next waiter.
+    pub fn discard(mut self) {
+        if let Some(object) = self.object.take() {
+            self.pool.discard(object);
+        }
     }
 }
 
 impl<T: Sync + Send + 'static> Drop for RentedObject<T> {
     fn drop(&mut self) {
-        let value = self.value.take().unwrap();
-        let mut inner = self.inner.lock();
-        inner.return_element(value);
+        if let Some(object) = self.object.take() {
+            self.pool.return_object(object);
+        }
     }
 }
 
@@ -35,3 +55,9 @@ impl<T: Sync + Send + 'static> AsRef<T> for RentedObject<T> {
         self.get_value()
     }
 }
+
+impl<T: Sync + Send + 'static> AsMut<T> for RentedObject<T> {
+    fn as_mut(&mut self) -> &mut T {
+        self.get_value_mut()
+    }
+}
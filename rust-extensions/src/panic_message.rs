@@ -0,0 +1,14 @@
+use std::any::Any;
+
+/// The message of a caught panic - what `panic!` was called with.
+pub(crate) fn get_panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        return message.to_string();
+    }
+
+    if let Some(message) = payload.downcast_ref::<String>() {
+        return message.clone();
+    }
+
+    "Unknown panic".to_string()
+}
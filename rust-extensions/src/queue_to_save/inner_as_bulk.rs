@@ -1,11 +1,13 @@
-use std::time::Duration;
+use std::{collections::VecDeque, time::Duration};
 
 use parking_lot::Mutex;
 
-use crate::{queue_to_save::async_waker::*, StrOrString};
+use crate::{queue_to_save::async_waker::*, StrOrString, TaskCompletion};
+
+use super::persist_tracker::*;
 
 pub struct QueueToSaveInnerAsBulk<T> {
-    queue: Mutex<(Vec<T>, AsyncWaker)>,
+    queue: Mutex<(VecDeque<PendingItem<T>>, AsyncWaker, PersistTracker)>,
 
     pub(crate) max_chunk_size: usize,
     pub(crate) timeout: Duration,
@@ -15,7 +17,7 @@ pub struct QueueToSaveInnerAsBulk<T> {
 impl<T> QueueToSaveInnerAsBulk<T> {
     pub fn new(name: StrOrString<'static>) -> Self {
         Self {
-            queue: Default::default(),
+            queue: Mutex::new((VecDeque::new(), AsyncWaker::default(), PersistTracker::new())),
             max_chunk_size: 50,
             timeout: Duration::from_secs(10),
             name,
@@ -23,13 +25,35 @@ impl<T> QueueToSaveInnerAsBulk<T> {
     }
     pub(crate) fn enqueue(&self, items: impl Iterator<Item = T>) {
         let mut queue = self.queue.lock();
-        queue.0.extend(items);
+
+        for itm in items {
+            let pending = queue.2.create_pending_item(itm, None);
+            queue.0.push_back(pending);
+        }
+
         queue.1.wake();
     }
 
     pub(crate) fn enqueue_single(&self, item: T) {
+        self.enqueue_with_awaiter(item, None);
+    }
+
+    pub(crate) fn enqueue_single_and_wait(
+        &self,
+        item: T,
+        task_completion: TaskCompletion<(), QueueToSaveError>,
+    ) {
+        self.enqueue_with_awaiter(item, Some(task_completion));
+    }
+
+    fn enqueue_with_awaiter(
+        &self,
+        item: T,
+        awaiter: Option<TaskCompletion<(), QueueToSaveError>>,
+    ) {
         let mut queue = self.queue.lock();
-        queue.0.push(item);
+        let pending = queue.2.create_pending_item(item, awaiter);
+        queue.0.push_back(pending);
         queue.1.wake();
     }
 
@@ -37,7 +61,15 @@ impl<T> QueueToSaveInnerAsBulk<T> {
         self.queue.lock().0.len()
     }
 
-    pub(crate) async fn dequeue(&self) -> Vec<T> {
+    pub(crate) fn flush(&self) -> QueueToSaveFlushAwaiter {
+        self.queue.lock().2.flush()
+    }
+
+    pub(crate) fn processed(&self, receipt: PersistReceipt, result: Result<(), QueueToSaveError>) {
+        self.queue.lock().2.processed(receipt, result);
+    }
+
+    pub(crate) async fn dequeue(&self) -> (Vec<T>, PersistReceipt) {
         loop {
             match self.try_dequeue() {
                 Ok(values) => {
@@ -50,28 +82,22 @@ impl<T> QueueToSaveInnerAsBulk<T> {
         }
     }
 
-    fn try_dequeue(&self) -> Result<Vec<T>, AsyncWakerAwaiter> {
+    fn try_dequeue(&self) -> Result<(Vec<T>, PersistReceipt), AsyncWakerAwaiter> {
         let mut write_access = self.queue.lock();
 
-        if write_access.0.len() == 0 {
+        if write_access.0.is_empty() {
             return Err(write_access.1.get_awaiter());
         }
 
-        if write_access.0.len() <= self.max_chunk_size {
-            return Ok(std::mem::take(&mut write_access.0));
-        }
-
-        let mut result = Vec::with_capacity(self.max_chunk_size);
+        let amount = write_access.0.len().min(self.max_chunk_size);
 
-        while write_access.0.len() > 0 {
-            if result.len() >= self.max_chunk_size {
-                break;
-            }
+        let mut receipt = PersistReceipt::new();
+        let mut result = Vec::with_capacity(amount);
 
-            let item = write_access.0.remove(0);
-            result.push(item);
+        for pending in write_access.0.drain(..amount) {
+            result.push(receipt.take(pending));
         }
 
-        Ok(result)
+        Ok((result, receipt))
     }
 }
@@ -2,10 +2,12 @@ use std::{collections::VecDeque, time::Duration};
 
 use parking_lot::Mutex;
 
-use crate::{queue_to_save::async_waker::*, StrOrString};
+use crate::{queue_to_save::async_waker::*, StrOrString, TaskCompletion};
+
+use super::persist_tracker::*;
 
 pub struct QueueToSaveInnerAsSingle<T> {
-    queue: Mutex<(VecDeque<T>, AsyncWaker)>,
+    queue: Mutex<(VecDeque<PendingItem<T>>, AsyncWaker, PersistTracker)>,
 
     pub(crate) timeout: Duration,
     pub(crate) name: StrOrString<'static>,
@@ -14,7 +16,7 @@ pub struct QueueToSaveInnerAsSingle<T> {
 impl<T> QueueToSaveInnerAsSingle<T> {
     pub fn new(name: StrOrString<'static>) -> Self {
         Self {
-            queue: Default::default(),
+            queue: Mutex::new((VecDeque::new(), AsyncWaker::default(), PersistTracker::new())),
             timeout: Duration::from_secs(10),
             name,
         }
@@ -23,15 +25,33 @@ impl<T> QueueToSaveInnerAsSingle<T> {
         let mut queue = self.queue.lock();
 
         for itm in items {
-            queue.0.push_back(itm);
+            let pending = queue.2.create_pending_item(itm, None);
+            queue.0.push_back(pending);
         }
 
         queue.1.wake();
     }
 
     pub(crate) fn enqueue_single(&self, item: T) {
+        self.enqueue_with_awaiter(item, None);
+    }
+
+    pub(crate) fn enqueue_single_and_wait(
+        &self,
+        item: T,
+        task_completion: TaskCompletion<(), QueueToSaveError>,
+    ) {
+        self.enqueue_with_awaiter(item, Some(task_completion));
+    }
+
+    fn enqueue_with_awaiter(
+        &self,
+        item: T,
+        awaiter: Option<TaskCompletion<(), QueueToSaveError>>,
+    ) {
         let mut queue = self.queue.lock();
-        queue.0.push_back(item);
+        let pending = queue.2.create_pending_item(item, awaiter);
+        queue.0.push_back(pending);
         queue.1.wake();
     }
 
@@ -39,7 +59,15 @@ impl<T> QueueToSaveInnerAsSingle<T> {
         self.queue.lock().0.len()
     }
 
-    pub(crate) async fn dequeue(&self) -> T {
+    pub(crate) fn flush(&self) -> QueueToSaveFlushAwaiter {
+        self.queue.lock().2.flush()
+    }
+
+    pub(crate) fn processed(&self, receipt: PersistReceipt, result: Result<(), QueueToSaveError>) {
+        self.queue.lock().2.processed(receipt, result);
+    }
+
+    pub(crate) async fn dequeue(&self) -> (T, PersistReceipt) {
         loop {
             match self.try_dequeue() {
                 Ok(values) => {
@@ -52,11 +80,15 @@ impl<T> QueueToSaveInnerAsSingle<T> {
         }
     }
 
-    fn try_dequeue(&self) -> Result<T, AsyncWakerAwaiter> {
+    fn try_dequeue(&self) -> Result<(T, PersistReceipt), AsyncWakerAwaiter> {
         let mut write_access = self.queue.lock();
 
         match write_access.0.pop_front() {
-            Some(result) => Ok(result),
+            Some(pending) => {
+                let mut receipt = PersistReceipt::new();
+                let item = receipt.take(pending);
+                Ok((item, receipt))
+            }
             None => Err(write_access.1.get_awaiter()),
         }
     }
@@ -6,3 +6,5 @@ mod async_waker;
 mod queue_to_save;
 pub use queue_to_save::*;
 mod inner_as_bulk;
+pub(crate) mod persist_tracker;
+pub use persist_tracker::{QueueToSaveAwaiter, QueueToSaveError, QueueToSaveFlushAwaiter};
@@ -0,0 +1,247 @@
+use std::{collections::BTreeSet, time::Duration};
+
+use crate::{TaskCompletion, TaskCompletionAwaiter};
+
+/// Why the chunk with the awaited item was not persisted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueueToSaveError {
+    /// The handler did not finish the chunk within the timeout of the queue.
+    Timeout(Duration),
+    /// The handler panicked while handling the chunk.
+    Panic,
+    /// The queue was dropped before the item was handled.
+    Dropped,
+}
+
+/// Completed as soon as the chunk containing the item given to `enqueue_and_wait`
+/// is handled.
+pub type QueueToSaveAwaiter = TaskCompletionAwaiter<(), QueueToSaveError>;
+
+/// Completed as soon as everything enqueued before `flush` is handled.
+pub type QueueToSaveFlushAwaiter = TaskCompletionAwaiter<(), ()>;
+
+/// The item waiting in a queue together with its sequence number and the callers
+/// waiting for it to be persisted.
+pub(crate) struct PendingItem<T> {
+    pub item: T,
+    pub seq: u64,
+    pub awaiters: Vec<TaskCompletion<(), QueueToSaveError>>,
+}
+
+/// Turns the outcome of the spawned handler invocation into the result reported
+/// to the callers waiting for the chunk.
+pub(crate) fn to_persist_result(
+    result: &Result<Result<(), tokio::time::error::Elapsed>, tokio::task::JoinError>,
+    timeout: Duration,
+) -> Result<(), QueueToSaveError> {
+    match result {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(_)) => Err(QueueToSaveError::Timeout(timeout)),
+        Err(_) => Err(QueueToSaveError::Panic),
+    }
+}
+
+/// What has to be reported back to the queue when a chunk is handled: the sequence
+/// numbers of the entries of the chunk and the callers waiting for them.
+pub(crate) struct PersistReceipt {
+    pub seqs: Vec<u64>,
+    pub awaiters: Vec<TaskCompletion<(), QueueToSaveError>>,
+}
+
+impl PersistReceipt {
+    pub fn new() -> Self {
+        Self {
+            seqs: Vec::new(),
+            awaiters: Vec::new(),
+        }
+    }
+
+    pub fn add(
+        &mut self,
+        seq: u64,
+        awaiters: impl Iterator<Item = TaskCompletion<(), QueueToSaveError>>,
+    ) {
+        self.seqs.push(seq);
+        self.awaiters.extend(awaiters);
+    }
+
+    /// Registers the pending item in the receipt and gives the item itself away.
+    pub fn take<T>(&mut self, pending: PendingItem<T>) -> T {
+        self.add(pending.seq, pending.awaiters.into_iter());
+        pending.item
+    }
+}
+
+/// Keeps track of the entries which are enqueued but not handled yet - pending or
+/// in flight - so `flush` knows when everything enqueued before it is done.
+///
+/// Every new entry of the queue gets the next sequence number. An entry which
+/// replaces a pending one (same ID) keeps the number of the replaced entry - the
+/// replaced content is persisted together with it.
+pub(crate) struct PersistTracker {
+    next_seq: u64,
+    outstanding: BTreeSet<u64>,
+    flush_awaiters: Vec<(u64, TaskCompletion<(), ()>)>,
+}
+
+impl PersistTracker {
+    pub fn new() -> Self {
+        Self {
+            next_seq: 0,
+            outstanding: BTreeSet::new(),
+            flush_awaiters: Vec::new(),
+        }
+    }
+
+    pub fn register_entry(&mut self) -> u64 {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.outstanding.insert(seq);
+        seq
+    }
+
+    pub fn create_pending_item<T>(
+        &mut self,
+        item: T,
+        awaiter: Option<TaskCompletion<(), QueueToSaveError>>,
+    ) -> PendingItem<T> {
+        PendingItem {
+            item,
+            seq: self.register_entry(),
+            awaiters: awaiter.into_iter().collect(),
+        }
+    }
+
+    pub fn create_awaiter() -> (TaskCompletion<(), QueueToSaveError>, QueueToSaveAwaiter) {
+        let mut task_completion = TaskCompletion::new();
+        task_completion.set_drop_error(QueueToSaveError::Dropped);
+        let awaiter = task_completion.get_awaiter();
+        (task_completion, awaiter)
+    }
+
+    /// Returns the awaiter completed when every entry registered so far is handled.
+    pub fn flush(&mut self) -> QueueToSaveFlushAwaiter {
+        if self.outstanding.is_empty() {
+            return TaskCompletionAwaiter::create_completed(Ok(()));
+        }
+
+        let mut task_completion = TaskCompletion::new();
+        // Dropped queue will never handle anything - the flush is over.
+        task_completion.set_drop_error(());
+        let awaiter = task_completion.get_awaiter();
+        self.flush_awaiters.push((self.next_seq, task_completion));
+        awaiter
+    }
+
+    /// The chunk is handled - either successfully or not.
+    pub fn processed(&mut self, receipt: PersistReceipt, result: Result<(), QueueToSaveError>) {
+        for seq in receipt.seqs {
+            self.outstanding.remove(&seq);
+        }
+
+        for mut awaiter in receipt.awaiters {
+            // The caller may not wait anymore - nothing to report then.
+            let _ = match result {
+                Ok(()) => awaiter.try_set_ok(()),
+                Err(err) => awaiter.try_set_error(err),
+            };
+        }
+
+        if self.flush_awaiters.is_empty() {
+            return;
+        }
+
+        let min_outstanding = self.outstanding.first().copied().unwrap_or(self.next_seq);
+
+        let mut i = 0;
+        while i < self.flush_awaiters.len() {
+            if self.flush_awaiters[i].0 <= min_outstanding {
+                let (_, mut task_completion) = self.flush_awaiters.swap_remove(i);
+                let _ = task_completion.try_set_ok(());
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    fn receipt(seqs: &[u64]) -> PersistReceipt {
+        let mut result = PersistReceipt::new();
+        for seq in seqs {
+            result.add(*seq, std::iter::empty());
+        }
+        result
+    }
+
+    #[test]
+    fn flush_of_empty_tracker_is_completed_at_once() {
+        create_runtime().block_on(async {
+            let mut tracker = PersistTracker::new();
+            tracker.flush().get_result().await.unwrap();
+        });
+    }
+
+    #[test]
+    fn flush_waits_only_for_entries_registered_before_it() {
+        create_runtime().block_on(async {
+            let mut tracker = PersistTracker::new();
+            let first = tracker.register_entry();
+            let second = tracker.register_entry();
+
+            let flush = tracker.flush();
+            let after_flush = tracker.register_entry();
+
+            tracker.processed(receipt(&[second]), Ok(()));
+            assert!(tracker.flush_awaiters.len() == 1);
+
+            tracker.processed(receipt(&[first]), Err(QueueToSaveError::Panic));
+            assert!(tracker.flush_awaiters.is_empty());
+            flush.get_result().await.unwrap();
+
+            tracker.processed(receipt(&[after_flush]), Ok(()));
+        });
+    }
+
+    #[test]
+    fn awaiters_get_the_result_of_the_chunk() {
+        create_runtime().block_on(async {
+            let mut tracker = PersistTracker::new();
+
+            let (ok_completion, ok_awaiter) = PersistTracker::create_awaiter();
+            let (err_completion, err_awaiter) = PersistTracker::create_awaiter();
+
+            let mut ok_receipt = PersistReceipt::new();
+            ok_receipt.add(tracker.register_entry(), std::iter::once(ok_completion));
+
+            let mut err_receipt = PersistReceipt::new();
+            err_receipt.add(tracker.register_entry(), std::iter::once(err_completion));
+
+            tracker.processed(ok_receipt, Ok(()));
+            let timeout = Err(QueueToSaveError::Timeout(Duration::from_secs(1)));
+            tracker.processed(err_receipt, timeout);
+
+            assert_eq!(ok_awaiter.get_result().await, Ok(()));
+            assert_eq!(err_awaiter.get_result().await, timeout);
+        });
+    }
+
+    #[test]
+    fn dropped_completion_reports_dropped() {
+        create_runtime().block_on(async {
+            let (completion, awaiter) = PersistTracker::create_awaiter();
+            drop(completion);
+            assert_eq!(awaiter.get_result().await, Err(QueueToSaveError::Dropped));
+        });
+    }
+}
@@ -2,7 +2,12 @@ use std::sync::Arc;
 
 use parking_lot::Mutex;
 
-use crate::{queue_to_save::inner_as_single::QueueToSaveInnerAsSingle, Logger, StrOrString};
+use super::persist_tracker::*;
+
+use crate::{
+    health::HealthReporter, queue_to_save::inner_as_single::QueueToSaveInnerAsSingle,
+    supervisor::Supervisor, Logger, StrOrString,
+};
 
 enum HandlerStatus<T> {
     None,
@@ -13,6 +18,7 @@ enum HandlerStatus<T> {
 pub struct QueueToSave<T: Send + Sync + 'static> {
     inner: Arc<QueueToSaveInnerAsSingle<T>>,
     handler: Mutex<HandlerStatus<T>>,
+    health: Mutex<Option<Arc<HealthReporter>>>,
 }
 
 impl<T: Send + Sync + 'static> QueueToSave<T> {
@@ -20,6 +26,7 @@ impl<T: Send + Sync + 'static> QueueToSave<T> {
         Self {
             inner: Arc::new(QueueToSaveInnerAsSingle::new(name.into())),
             handler: Mutex::new(HandlerStatus::None),
+            health: Mutex::new(None),
         }
     }
     pub fn enqueue(&self, items: impl Iterator<Item = T>) {
@@ -30,6 +37,21 @@ impl<T: Send + Sync + 'static> QueueToSave<T> {
         self.inner.enqueue_single(item);
     }
 
+    /// Enqueues the item and returns the awaiter which is completed as soon as the
+    /// handler is done with the chunk containing it: `Ok` if the handler finished
+    /// in time, the reason otherwise.
+    pub fn enqueue_and_wait(&self, item: T) -> QueueToSaveAwaiter {
+        let (task_completion, awaiter) = PersistTracker::create_awaiter();
+        self.inner.enqueue_single_and_wait(item, task_completion);
+        awaiter
+    }
+
+    /// Resolves as soon as everything enqueued before the call is handled -
+    /// successfully or not. Items enqueued after the call are not waited for.
+    pub async fn flush(&self) {
+        let _ = self.inner.flush().get_result().await;
+    }
+
     pub fn register_events_handler(
         &self,
         events_handle: Arc<dyn QueueToSaveEventsHandler<T> + Send + Sync + 'static>,
@@ -50,29 +72,62 @@ impl<T: Send + Sync + 'static> QueueToSave<T> {
         self.inner.queue_len()
     }
 
-    pub fn start(&self, logger: Arc<dyn Logger + Send + Sync + 'static>) {
+    /// Every handled chunk is a heartbeat, every panicked or timed out one is an
+    /// error. Has to be set before the queue is started.
+    pub fn set_health_reporter(&self, reporter: Arc<HealthReporter>) {
+        *self.health.lock() = Some(reporter);
+    }
+
+    fn take_handler(&self) -> Arc<dyn QueueToSaveEventsHandler<T> + Send + Sync + 'static> {
         let mut write_access = self.handler.lock();
 
-        match &*write_access {
+        let handler = match &*write_access {
             HandlerStatus::None => {
                 panic!(
                     "Event handler is not registered in QueueToSave {}",
                     self.inner.name
                 );
             }
-            HandlerStatus::Some(handler) => {
-                tokio::spawn(queue_to_save_loop(
-                    self.inner.clone(),
-                    handler.clone(),
-                    logger,
-                ));
-            }
+            HandlerStatus::Some(handler) => handler.clone(),
             HandlerStatus::Working => {
                 panic!("QueueToSave {} is already started", self.inner.name);
             }
-        }
+        };
 
         *write_access = HandlerStatus::Working;
+        handler
+    }
+
+    pub fn start(&self, logger: Arc<dyn Logger + Send + Sync + 'static>) {
+        let handler = self.take_handler();
+        let health = self.health.lock().clone();
+        tokio::spawn(queue_to_save_loop(
+            self.inner.clone(),
+            handler,
+            logger,
+            health,
+        ));
+    }
+
+    /// Hands the loop over to the supervisor: it is started together with the
+    /// supervisor and restarted if it dies.
+    pub fn start_supervised(
+        &self,
+        supervisor: &Supervisor,
+        logger: Arc<dyn Logger + Send + Sync + 'static>,
+    ) {
+        let inner = self.inner.clone();
+        let handler = self.take_handler();
+        let health = self.health.lock().clone();
+
+        supervisor.add_child_fn(format!("QueueToSave {}", inner.name), move || {
+            queue_to_save_loop(
+                inner.clone(),
+                handler.clone(),
+                logger.clone(),
+                health.clone(),
+            )
+        });
     }
 }
 
@@ -85,11 +140,12 @@ async fn queue_to_save_loop<T: Send + Sync + 'static>(
     inner: Arc<QueueToSaveInnerAsSingle<T>>,
     handler: Arc<dyn QueueToSaveEventsHandler<T> + Send + Sync + 'static>,
     logger: Arc<dyn Logger + Send + Sync + 'static>,
+    health: Option<Arc<HealthReporter>>,
 ) {
     println!("Queue to save {} is started", inner.name.as_str());
     let timeout = inner.timeout;
     loop {
-        let events = inner.dequeue().await;
+        let (events, receipt) = inner.dequeue().await;
 
         let handler = handler.clone();
         let feature = tokio::spawn(async move {
@@ -98,7 +154,10 @@ async fn queue_to_save_loop<T: Send + Sync + 'static>(
             tokio::time::timeout(timeout, future).await
         });
 
-        let result = match feature.await {
+        let result = feature.await;
+        inner.processed(receipt, to_persist_result(&result, timeout));
+
+        let result = match result {
             Ok(value) => value,
             Err(_) => {
                 let msg = format!(
@@ -106,6 +165,10 @@ async fn queue_to_save_loop<T: Send + Sync + 'static>(
                     inner.name.as_str()
                 );
 
+                if let Some(health) = health.as_ref() {
+                    health.report_error(msg.as_str());
+                }
+
                 logger.write_error("QueueToSave.loop".to_string(), msg, None.into());
                 continue;
             }
@@ -118,7 +181,13 @@ async fn queue_to_save_loop<T: Send + Sync + 'static>(
                 inner.name.as_str()
             );
 
+            if let Some(health) = health.as_ref() {
+                health.report_error(msg.as_str());
+            }
+
             logger.write_error("QueueToSave.loop".to_string(), msg, None.into());
+        } else if let Some(health) = health.as_ref() {
+            health.heartbeat();
         }
     }
 }
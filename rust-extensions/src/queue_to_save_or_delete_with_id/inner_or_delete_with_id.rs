@@ -2,7 +2,7 @@ use std::{collections::HashMap, hash::Hash, time::Duration};
 
 use parking_lot::Mutex;
 
-use crate::StrOrString;
+use crate::{queue_to_save::persist_tracker::*, StrOrString, TaskCompletion};
 
 use super::async_waker::*;
 use super::upsert_or_delete::UpsertOrDelete;
@@ -17,12 +17,18 @@ enum PendingState<T> {
     Delete,
 }
 
+type PendingQueue<ID, T> = (
+    HashMap<ID, PendingItem<PendingState<T>>>,
+    AsyncWaker,
+    PersistTracker,
+);
+
 pub struct QueueToSaveOrDeleteInnerWithId<ID, T>
 where
     ID: Hash + Eq + Clone,
     T: PersistObjectId<ID>,
 {
-    queue: Mutex<(HashMap<ID, PendingState<T>>, AsyncWaker)>,
+    queue: Mutex<PendingQueue<ID, T>>,
 
     pub(crate) max_chunk_size: usize,
     pub(crate) timeout: Duration,
@@ -36,7 +42,7 @@ where
 {
     pub fn new(name: StrOrString<'static>) -> Self {
         Self {
-            queue: Mutex::new((HashMap::new(), AsyncWaker::default())),
+            queue: Mutex::new((HashMap::new(), AsyncWaker::default(), PersistTracker::new())),
             max_chunk_size: 50,
             timeout: Duration::from_secs(10),
             name,
@@ -47,33 +53,85 @@ where
         let mut queue = self.queue.lock();
         for item in items {
             let id = item.get_persist_object_id().clone();
-            queue.0.insert(id, PendingState::Upsert(item));
+            Self::insert(&mut queue, id, PendingState::Upsert(item), None);
         }
         queue.1.wake();
     }
 
     pub(crate) fn enqueue_single(&self, item: T) {
-        let mut queue = self.queue.lock();
         let id = item.get_persist_object_id().clone();
-        queue.0.insert(id, PendingState::Upsert(item));
-        queue.1.wake();
+        self.enqueue_state(id, PendingState::Upsert(item), None);
+    }
+
+    pub(crate) fn enqueue_single_and_wait(
+        &self,
+        item: T,
+        task_completion: TaskCompletion<(), QueueToSaveError>,
+    ) {
+        let id = item.get_persist_object_id().clone();
+        self.enqueue_state(id, PendingState::Upsert(item), Some(task_completion));
     }
 
     pub(crate) fn enqueue_delete(&self, id: ID) {
+        self.enqueue_state(id, PendingState::Delete, None);
+    }
+
+    pub(crate) fn enqueue_delete_and_wait(
+        &self,
+        id: ID,
+        task_completion: TaskCompletion<(), QueueToSaveError>,
+    ) {
+        self.enqueue_state(id, PendingState::Delete, Some(task_completion));
+    }
+
+    fn enqueue_state(
+        &self,
+        id: ID,
+        state: PendingState<T>,
+        awaiter: Option<TaskCompletion<(), QueueToSaveError>>,
+    ) {
         let mut queue = self.queue.lock();
-        queue.0.insert(id, PendingState::Delete);
+        Self::insert(&mut queue, id, state, awaiter);
         queue.1.wake();
     }
 
     pub(crate) fn enqueue_delete_multiple(&self, ids: impl Iterator<Item = ID>) {
         let mut queue = self.queue.lock();
         for id in ids {
-            queue.0.insert(id, PendingState::Delete);
+            Self::insert(&mut queue, id, PendingState::Delete, None);
         }
         queue.1.wake();
     }
 
-    pub(crate) async fn dequeue(&self) -> Vec<UpsertOrDelete<ID, T>> {
+    /// The state replacing the pending one of the same ID inherits its sequence
+    /// number and its awaiters - they are persisted by the newer state.
+    fn insert(
+        queue: &mut PendingQueue<ID, T>,
+        id: ID,
+        state: PendingState<T>,
+        awaiter: Option<TaskCompletion<(), QueueToSaveError>>,
+    ) {
+        match queue.0.get_mut(&id) {
+            Some(pending) => {
+                pending.item = state;
+                pending.awaiters.extend(awaiter);
+            }
+            None => {
+                let pending = queue.2.create_pending_item(state, awaiter);
+                queue.0.insert(id, pending);
+            }
+        }
+    }
+
+    pub(crate) fn flush(&self) -> QueueToSaveFlushAwaiter {
+        self.queue.lock().2.flush()
+    }
+
+    pub(crate) fn processed(&self, receipt: PersistReceipt, result: Result<(), QueueToSaveError>) {
+        self.queue.lock().2.processed(receipt, result);
+    }
+
+    pub(crate) async fn dequeue(&self) -> (Vec<UpsertOrDelete<ID, T>>, PersistReceipt) {
         loop {
             match self.try_dequeue() {
                 Ok(values) => {
@@ -86,20 +144,24 @@ where
         }
     }
 
-    fn try_dequeue(&self) -> Result<Vec<UpsertOrDelete<ID, T>>, AsyncWakerAwaiter> {
+    fn try_dequeue(
+        &self,
+    ) -> Result<(Vec<UpsertOrDelete<ID, T>>, PersistReceipt), AsyncWakerAwaiter> {
         let mut write_access = self.queue.lock();
 
         if write_access.0.is_empty() {
             return Err(write_access.1.get_awaiter());
         }
 
+        let mut receipt = PersistReceipt::new();
+
         if write_access.0.len() <= self.max_chunk_size {
             let result = std::mem::take(&mut write_access.0)
                 .into_iter()
-                .map(|(id, state)| state.into_upsert_or_delete(id))
+                .map(|(id, pending)| receipt.take(pending).into_upsert_or_delete(id))
                 .collect();
 
-            return Ok(result);
+            return Ok((result, receipt));
         }
 
         let keys: Vec<ID> = write_access
@@ -111,12 +173,12 @@ where
 
         let mut result = Vec::with_capacity(keys.len());
         for key in keys {
-            if let Some(state) = write_access.0.remove(&key) {
-                result.push(state.into_upsert_or_delete(key));
+            if let Some(pending) = write_access.0.remove(&key) {
+                result.push(receipt.take(pending).into_upsert_or_delete(key));
             }
         }
 
-        Ok(result)
+        Ok((result, receipt))
     }
 }
 
@@ -157,7 +219,7 @@ mod tests {
         queue.enqueue_single(Obj { id: 2, value: "b" });
         queue.enqueue_delete(1);
 
-        let mut result = queue.try_dequeue().ok().unwrap();
+        let mut result = queue.try_dequeue().ok().unwrap().0;
         result.sort_by_key(|itm| *itm.get_id());
 
         assert_eq!(result.len(), 2);
@@ -173,7 +235,7 @@ mod tests {
         queue.enqueue_delete(1);
         queue.enqueue_single(Obj { id: 1, value: "a" });
 
-        let result = queue.try_dequeue().ok().unwrap();
+        let result = queue.try_dequeue().ok().unwrap().0;
 
         assert_eq!(result.len(), 1);
         assert_eq!(result[0].unwrap_as_upsert().value, "a");
@@ -185,7 +247,7 @@ mod tests {
 
         queue.enqueue_delete(5);
 
-        let result = queue.try_dequeue().ok().unwrap();
+        let result = queue.try_dequeue().ok().unwrap().0;
 
         assert_eq!(result.len(), 1);
         assert_eq!(*result[0].unwrap_as_delete(), 5);
@@ -205,13 +267,13 @@ mod tests {
         queue.enqueue((0..5).map(|id| Obj { id, value: "a" }));
         queue.enqueue_delete(3);
 
-        let chunk = queue.try_dequeue().ok().unwrap();
+        let chunk = queue.try_dequeue().ok().unwrap().0;
         assert_eq!(chunk.len(), 2);
 
-        let chunk = queue.try_dequeue().ok().unwrap();
+        let chunk = queue.try_dequeue().ok().unwrap().0;
         assert_eq!(chunk.len(), 2);
 
-        let chunk = queue.try_dequeue().ok().unwrap();
+        let chunk = queue.try_dequeue().ok().unwrap().0;
         assert_eq!(chunk.len(), 1);
 
         assert!(queue.try_dequeue().is_err());
@@ -226,7 +288,7 @@ mod tests {
         queue.enqueue_delete(3);
 
         let (mut to_upsert, mut to_delete) =
-            UpsertOrDelete::split(queue.try_dequeue().ok().unwrap());
+            UpsertOrDelete::split(queue.try_dequeue().ok().unwrap().0);
 
         to_upsert.sort_by_key(|itm| itm.id);
         to_delete.sort();
@@ -2,7 +2,9 @@ use std::{hash::Hash, sync::Arc};
 
 use parking_lot::Mutex;
 
-use crate::{Logger, StrOrString};
+use crate::{
+    queue_to_save::persist_tracker::*, health::HealthReporter, supervisor::Supervisor, Logger, StrOrString,
+};
 
 use super::{
     inner_or_delete_with_id::QueueToSaveOrDeleteInnerWithId, upsert_or_delete::UpsertOrDelete,
@@ -22,6 +24,7 @@ where
 {
     inner: Arc<QueueToSaveOrDeleteInnerWithId<ID, T>>,
     handler: Mutex<HandlerStatus<ID, T>>,
+    health: Mutex<Option<Arc<HealthReporter>>>,
 }
 
 impl<ID, T> QueueToSaveOrDeleteWithId<ID, T>
@@ -33,6 +36,7 @@ where
         Self {
             inner: Arc::new(QueueToSaveOrDeleteInnerWithId::new(name.into())),
             handler: Mutex::new(HandlerStatus::None),
+            health: Mutex::new(None),
         }
     }
 
@@ -44,6 +48,18 @@ where
         self.inner.enqueue_single(item);
     }
 
+    /// Enqueues the object and returns the awaiter which is completed as soon as
+    /// the handler is done with the chunk containing its ID: `Ok` if the handler
+    /// finished in time, the reason otherwise.
+    ///
+    /// If the pending state of the ID is replaced before it is handled, the
+    /// awaiter is completed by the chunk containing the newer state.
+    pub fn enqueue_and_wait(&self, item: T) -> QueueToSaveAwaiter {
+        let (task_completion, awaiter) = PersistTracker::create_awaiter();
+        self.inner.enqueue_single_and_wait(item, task_completion);
+        awaiter
+    }
+
     /// Marks the object with the given ID as the one to be deleted.
     ///
     /// A pending upsert of the same ID - if any - is dropped right here: there is no
@@ -52,10 +68,24 @@ where
         self.inner.enqueue_delete(id);
     }
 
+    /// The same as [`Self::enqueue_delete`], but returns the awaiter completed as
+    /// soon as the chunk containing the ID is handled.
+    pub fn enqueue_delete_and_wait(&self, id: ID) -> QueueToSaveAwaiter {
+        let (task_completion, awaiter) = PersistTracker::create_awaiter();
+        self.inner.enqueue_delete_and_wait(id, task_completion);
+        awaiter
+    }
+
     pub fn enqueue_delete_multiple(&self, ids: impl Iterator<Item = ID>) {
         self.inner.enqueue_delete_multiple(ids);
     }
 
+    /// Resolves as soon as everything enqueued before the call is handled -
+    /// successfully or not. Items enqueued after the call are not waited for.
+    pub async fn flush(&self) {
+        let _ = self.inner.flush().get_result().await;
+    }
+
     pub fn register_events_handler(
         &self,
         events_handle: Arc<dyn QueueToSaveOrDeleteWithIdEventsHandler<ID, T> + Send + Sync + 'static>,
@@ -68,32 +98,68 @@ where
         self.inner.name.as_str()
     }
 
-    pub fn start(&self, logger: Arc<dyn Logger + Send + Sync + 'static>) {
+    /// Every handled chunk is a heartbeat, every panicked or timed out one is an
+    /// error. Has to be set before the queue is started.
+    pub fn set_health_reporter(&self, reporter: Arc<HealthReporter>) {
+        *self.health.lock() = Some(reporter);
+    }
+
+    fn take_handler(&self) -> Arc<dyn QueueToSaveOrDeleteWithIdEventsHandler<ID, T> + Send + Sync + 'static> {
         let mut write_access = self.handler.lock();
 
-        match &*write_access {
+        let handler = match &*write_access {
             HandlerStatus::None => {
                 panic!(
                     "Event handler is not registered in QueueToSaveOrDeleteWithId {}",
                     self.inner.name
                 );
             }
-            HandlerStatus::Some(handler) => {
-                tokio::spawn(queue_to_save_or_delete_with_id_loop(
-                    self.inner.clone(),
-                    handler.clone(),
-                    logger,
-                ));
-            }
+            HandlerStatus::Some(handler) => handler.clone(),
             HandlerStatus::Working => {
                 panic!(
                     "QueueToSaveOrDeleteWithId {} is already started",
                     self.inner.name
                 );
             }
-        }
+        };
 
         *write_access = HandlerStatus::Working;
+        handler
+    }
+
+    pub fn start(&self, logger: Arc<dyn Logger + Send + Sync + 'static>) {
+        let handler = self.take_handler();
+        let health = self.health.lock().clone();
+        tokio::spawn(queue_to_save_or_delete_with_id_loop(
+            self.inner.clone(),
+            handler,
+            logger,
+            health,
+        ));
+    }
+
+    /// Hands the loop over to the supervisor: it is started together with the
+    /// supervisor and restarted if it dies.
+    pub fn start_supervised(
+        &self,
+        supervisor: &Supervisor,
+        logger: Arc<dyn Logger + Send + Sync + 'static>,
+    ) {
+        let inner = self.inner.clone();
+        let handler = self.take_handler();
+        let health = self.health.lock().clone();
+
+        supervisor.add_child_fn(
+            format!("QueueToSaveOrDeleteWithId {}", inner.name),
+            move || {
+                queue_to_save_or_delete_with_id_loop(
+                    inner.clone(),
+                    handler.clone(),
+                    logger.clone(),
+                    health.clone(),
+                )
+            },
+        );
     }
 }
 
@@ -107,6 +173,7 @@ async fn queue_to_save_or_delete_with_id_loop<ID, T>(
     inner: Arc<QueueToSaveOrDeleteInnerWithId<ID, T>>,
     handler: Arc<dyn QueueToSaveOrDeleteWithIdEventsHandler<ID, T> + Send + Sync + 'static>,
     logger: Arc<dyn Logger + Send + Sync + 'static>,
+    health: Option<Arc<HealthReporter>>,
 ) where
     ID: Hash + Eq + Clone + Send + Sync + 'static,
     T: PersistObjectId<ID> + Send + Sync + 'static,
@@ -117,7 +184,7 @@ async fn queue_to_save_or_delete_with_id_loop<ID, T>(
     );
     let timeout = inner.timeout;
     loop {
-        let events = inner.dequeue().await;
+        let (events, receipt) = inner.dequeue().await;
 
         let handler = handler.clone();
         let feature = tokio::spawn(async move {
@@ -126,7 +193,10 @@ async fn queue_to_save_or_delete_with_id_loop<ID, T>(
             tokio::time::timeout(timeout, future).await
         });
 
-        let result = match feature.await {
+        let result = feature.await;
+        inner.processed(receipt, to_persist_result(&result, timeout));
+
+        let result = match result {
             Ok(value) => value,
             Err(_) => {
                 let msg = format!(
@@ -155,6 +225,8 @@ async fn queue_to_save_or_delete_with_id_loop<ID, T>(
                 msg,
                 None.into(),
             );
+        } else if let Some(health) = health.as_ref() {
+            health.heartbeat();
         }
     }
 }
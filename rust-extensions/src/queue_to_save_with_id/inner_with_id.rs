@@ -1,22 +1,35 @@
-use std::{collections::HashMap, hash::Hash, time::Duration};
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
 
 use parking_lot::Mutex;
 
-use crate::StrOrString;
+use crate::{queue_to_save::persist_tracker::*, StrOrString, TaskCompletion};
 
 use super::async_waker::*;
 use super::persist_object_id::PersistObjectId;
 
+type PendingQueue<ID, T> = (HashMap<ID, PendingItem<T>>, AsyncWaker, PersistTracker);
+
+/// A single lane of the [`super::QueueToSaveWithId`]. Not partitioned queue
+/// has exactly one lane; a partitioned one has a lane per partition, and every
+/// lane is served by its own loop.
 pub struct QueueToSaveInnerWithId<ID, T>
 where
     ID: Hash + Eq + Clone,
     T: PersistObjectId<ID>,
 {
-    queue: Mutex<(HashMap<ID, T>, AsyncWaker)>,
+    queue: Mutex<PendingQueue<ID, T>>,
+    /// Amount of items of the chunk which is being handled right now.
+    in_flight: AtomicUsize,
 
     pub(crate) max_chunk_size: usize,
     pub(crate) timeout: Duration,
     pub(crate) name: StrOrString<'static>,
+    pub(crate) lane_no: Option<usize>,
 }
 
 impl<ID, T> QueueToSaveInnerWithId<ID, T>
@@ -24,32 +37,98 @@ where
     ID: Hash + Eq + Clone,
     T: PersistObjectId<ID>,
 {
-    pub fn new(name: StrOrString<'static>) -> Self {
+    pub fn new(name: StrOrString<'static>, lane_no: Option<usize>) -> Self {
         Self {
-            queue: Mutex::new((HashMap::new(), AsyncWaker::default())),
+            queue: Mutex::new((HashMap::new(), AsyncWaker::default(), PersistTracker::new())),
+            in_flight: AtomicUsize::new(0),
             max_chunk_size: 50,
             timeout: Duration::from_secs(10),
             name,
+            lane_no,
+        }
+    }
+
+    /// The name to be used in logs: the name of the queue, plus the lane number
+    /// if the queue is partitioned.
+    pub(crate) fn get_display_name(&self) -> String {
+        match self.lane_no {
+            Some(lane_no) => format!("{}#{}", self.name.as_str(), lane_no),
+            None => self.name.as_str().to_string(),
         }
     }
 
+    pub(crate) fn queue_len(&self) -> usize {
+        self.queue.lock().0.len()
+    }
+
+    pub(crate) fn get_in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn set_in_flight(&self, value: usize) {
+        self.in_flight.store(value, Ordering::SeqCst);
+    }
+
     pub(crate) fn enqueue(&self, items: impl Iterator<Item = T>) {
         let mut queue = self.queue.lock();
         for item in items {
-            let id = item.get_persist_object_id().clone();
-            queue.0.insert(id, item);
+            Self::insert(&mut queue, item, None);
         }
         queue.1.wake();
     }
 
     pub(crate) fn enqueue_single(&self, item: T) {
+        self.enqueue_with_awaiter(item, None);
+    }
+
+    pub(crate) fn enqueue_single_and_wait(
+        &self,
+        item: T,
+        task_completion: TaskCompletion<(), QueueToSaveError>,
+    ) {
+        self.enqueue_with_awaiter(item, Some(task_completion));
+    }
+
+    fn enqueue_with_awaiter(
+        &self,
+        item: T,
+        awaiter: Option<TaskCompletion<(), QueueToSaveError>>,
+    ) {
         let mut queue = self.queue.lock();
-        let id = item.get_persist_object_id().clone();
-        queue.0.insert(id, item);
+        Self::insert(&mut queue, item, awaiter);
         queue.1.wake();
     }
 
-    pub(crate) async fn dequeue(&self) -> Vec<T> {
+    /// The item replacing the pending one with the same ID inherits its sequence
+    /// number and its awaiters - they are persisted by the newer item.
+    fn insert(
+        queue: &mut PendingQueue<ID, T>,
+        item: T,
+        awaiter: Option<TaskCompletion<(), QueueToSaveError>>,
+    ) {
+        let id = item.get_persist_object_id().clone();
+
+        match queue.0.get_mut(&id) {
+            Some(pending) => {
+                pending.item = item;
+                pending.awaiters.extend(awaiter);
+            }
+            None => {
+                let pending = queue.2.create_pending_item(item, awaiter);
+                queue.0.insert(id, pending);
+            }
+        }
+    }
+
+    pub(crate) fn flush(&self) -> QueueToSaveFlushAwaiter {
+        self.queue.lock().2.flush()
+    }
+
+    pub(crate) fn processed(&self, receipt: PersistReceipt, result: Result<(), QueueToSaveError>) {
+        self.queue.lock().2.processed(receipt, result);
+    }
+
+    pub(crate) async fn dequeue(&self) -> (Vec<T>, PersistReceipt) {
         loop {
             match self.try_dequeue() {
                 Ok(values) => {
@@ -62,15 +141,22 @@ where
         }
     }
 
-    fn try_dequeue(&self) -> Result<Vec<T>, AsyncWakerAwaiter> {
+    fn try_dequeue(&self) -> Result<(Vec<T>, PersistReceipt), AsyncWakerAwaiter> {
         let mut write_access = self.queue.lock();
 
         if write_access.0.is_empty() {
             return Err(write_access.1.get_awaiter());
         }
 
+        let mut receipt = PersistReceipt::new();
+
         if write_access.0.len() <= self.max_chunk_size {
-            return Ok(std::mem::take(&mut write_access.0).into_values().collect());
+            let result = std::mem::take(&mut write_access.0)
+                .into_values()
+                .map(|pending| receipt.take(pending))
+                .collect();
+
+            return Ok((result, receipt));
         }
 
         let keys: Vec<ID> = write_access
@@ -82,11 +168,11 @@ where
 
         let mut result = Vec::with_capacity(keys.len());
         for key in keys {
-            if let Some(item) = write_access.0.remove(&key) {
-                result.push(item);
+            if let Some(pending) = write_access.0.remove(&key) {
+                result.push(receipt.take(pending));
             }
         }
 
-        Ok(result)
+        Ok((result, receipt))
     }
 }
@@ -1,8 +1,12 @@
-use std::{hash::Hash, sync::Arc};
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hash},
+    sync::Arc,
+};
 
 use parking_lot::Mutex;
 
-use crate::{Logger, StrOrString};
+use crate::{queue_to_save::persist_tracker::*, health::HealthReporter, supervisor::Supervisor, Logger, StrOrString};
 
 use super::{inner_with_id::QueueToSaveInnerWithId, persist_object_id::PersistObjectId};
 
@@ -12,13 +16,24 @@ enum HandlerStatus<T> {
     Working,
 }
 
+/// Collects objects by their IDs - the object enqueued later replaces the pending
+/// one with the same ID - and hands them to the handler in chunks.
+///
+/// By default the queue has a single lane, so there is exactly one handler
+/// invocation at a time. A queue created by [`Self::new_partitioned`] hashes IDs
+/// into several lanes - every lane has its own pending objects, its own chunks
+/// and its own loop, so the handler is invoked for different lanes in parallel.
+/// An ID always goes to the same lane, so the updates of the same ID are still
+/// handled one by one in the order they were enqueued.
 pub struct QueueToSaveWithId<ID, T>
 where
     ID: Hash + Eq + Clone + Send + Sync + 'static,
     T: PersistObjectId<ID> + Send + Sync + 'static,
 {
-    inner: Arc<QueueToSaveInnerWithId<ID, T>>,
+    lanes: Vec<Arc<QueueToSaveInnerWithId<ID, T>>>,
+    hash_builder: RandomState,
     handler: Mutex<HandlerStatus<T>>,
+    health: Mutex<Option<Arc<HealthReporter>>>,
 }
 
 impl<ID, T> QueueToSaveWithId<ID, T>
@@ -28,17 +43,117 @@ where
 {
     pub fn new(name: impl Into<StrOrString<'static>>) -> Self {
         Self {
-            inner: Arc::new(QueueToSaveInnerWithId::new(name.into())),
+            lanes: vec![Arc::new(QueueToSaveInnerWithId::new(name.into(), None))],
+            hash_builder: RandomState::new(),
             handler: Mutex::new(HandlerStatus::None),
+            health: Mutex::new(None),
+        }
+    }
+
+    /// Creates the queue with `lanes_amount` lanes handled in parallel.
+    pub fn new_partitioned(name: impl Into<StrOrString<'static>>, lanes_amount: usize) -> Self {
+        let name: StrOrString<'static> = name.into();
+
+        if lanes_amount == 0 {
+            panic!("QueueToSaveWithId {} must have at least one lane", name);
+        }
+
+        let lanes = (0..lanes_amount)
+            .map(|lane_no| {
+                Arc::new(QueueToSaveInnerWithId::new(
+                    name.as_str().to_string().into(),
+                    Some(lane_no),
+                ))
+            })
+            .collect();
+
+        Self {
+            lanes,
+            hash_builder: RandomState::new(),
+            handler: Mutex::new(HandlerStatus::None),
+            health: Mutex::new(None),
         }
     }
 
     pub fn enqueue(&self, items: impl Iterator<Item = T>) {
-        self.inner.enqueue(items);
+        if self.lanes.len() == 1 {
+            self.lanes[0].enqueue(items);
+            return;
+        }
+
+        let mut by_lanes: Vec<Vec<T>> = (0..self.lanes.len()).map(|_| Vec::new()).collect();
+
+        for item in items {
+            let lane_no = self.get_lane_no(item.get_persist_object_id());
+            by_lanes[lane_no].push(item);
+        }
+
+        for (lane_no, items) in by_lanes.into_iter().enumerate() {
+            if !items.is_empty() {
+                self.lanes[lane_no].enqueue(items.into_iter());
+            }
+        }
     }
 
     pub fn enqueue_single(&self, item: T) {
-        self.inner.enqueue_single(item);
+        let lane_no = self.get_lane_no(item.get_persist_object_id());
+        self.lanes[lane_no].enqueue_single(item);
+    }
+
+    /// Enqueues the item and returns the awaiter which is completed as soon as the
+    /// handler is done with the chunk containing it: `Ok` if the handler finished
+    /// in time, the reason otherwise.
+    ///
+    /// If the item is replaced by a newer one with the same ID before it is
+    /// handled, the awaiter is completed by the chunk containing the newer one.
+    pub fn enqueue_and_wait(&self, item: T) -> QueueToSaveAwaiter {
+        let (task_completion, awaiter) = PersistTracker::create_awaiter();
+        let lane_no = self.get_lane_no(item.get_persist_object_id());
+        self.lanes[lane_no].enqueue_single_and_wait(item, task_completion);
+        awaiter
+    }
+
+    /// Resolves as soon as everything enqueued before the call is handled by
+    /// every lane - successfully or not. Items enqueued after the call are not
+    /// waited for.
+    pub async fn flush(&self) {
+        let awaiters: Vec<_> = self.lanes.iter().map(|lane| lane.flush()).collect();
+
+        for awaiter in awaiters {
+            let _ = awaiter.get_result().await;
+        }
+    }
+
+    /// The lane the object with the given ID goes to.
+    pub fn get_lane_no(&self, id: &ID) -> usize {
+        if self.lanes.len() == 1 {
+            return 0;
+        }
+
+        (self.hash_builder.hash_one(id) % self.lanes.len() as u64) as usize
+    }
+
+    pub fn get_lanes_amount(&self) -> usize {
+        self.lanes.len()
+    }
+
+    /// Amount of the items which are waiting in all the lanes right now.
+    ///
+    /// The chunks which are being handled at the moment are already dequeued -
+    /// they are not counted here.
+    pub fn queue_len(&self) -> usize {
+        self.lanes.iter().map(|lane| lane.queue_len()).sum()
+    }
+
+    /// Amount of the items which are waiting in the given lane right now.
+    pub fn lane_queue_len(&self, lane_no: usize) -> usize {
+        self.lanes[lane_no].queue_len()
+    }
+
+    /// Amount of the items of the chunk which the handler is busy with in the
+    /// given lane right now. `0` means the lane is idle.
+    pub fn lane_in_flight(&self, lane_no: usize) -> usize {
+        self.lanes[lane_no].get_in_flight()
     }
 
     pub fn register_events_handler(
@@ -50,35 +165,78 @@ where
     }
 
     pub fn get_name(&self) -> &str {
-        self.inner.name.as_str()
+        self.lanes[0].name.as_str()
     }
 
-    pub fn start(&self, logger: Arc<dyn Logger + Send + Sync + 'static>) {
+    /// One reporter for all the lanes: every handled chunk is a heartbeat, every
+    /// panicked or timed out one is an error. Has to be set before the queue is
+    /// started.
+    pub fn set_health_reporter(&self, reporter: Arc<HealthReporter>) {
+        *self.health.lock() = Some(reporter);
+    }
+
+    fn take_handler(&self) -> Arc<dyn QueueToSaveWithIdEventsHandler<T> + Send + Sync + 'static> {
         let mut write_access = self.handler.lock();
 
-        match &*write_access {
+        let handler = match &*write_access {
             HandlerStatus::None => {
                 panic!(
                     "Event handler is not registered in QueueToSaveWithId {}",
-                    self.inner.name
+                    self.get_name()
                 );
             }
-            HandlerStatus::Some(handler) => {
-                tokio::spawn(queue_to_save_with_id_loop(
-                    self.inner.clone(),
-                    handler.clone(),
-                    logger,
-                ));
-            }
+            HandlerStatus::Some(handler) => handler.clone(),
             HandlerStatus::Working => {
-                panic!(
-                    "QueueToSaveWithId {} is already started",
-                    self.inner.name
-                );
+                panic!("QueueToSaveWithId {} is already started", self.get_name());
             }
-        }
+        };
 
         *write_access = HandlerStatus::Working;
+        handler
+    }
+
+    pub fn start(&self, logger: Arc<dyn Logger + Send + Sync + 'static>) {
+        let handler = self.take_handler();
+        let health = self.health.lock().clone();
+
+        for lane in self.lanes.iter() {
+            tokio::spawn(queue_to_save_with_id_loop(
+                lane.clone(),
+                handler.clone(),
+                logger.clone(),
+                health.clone(),
+            ));
+        }
+    }
+
+    /// Hands the loops over to the supervisor: they are started together with the
+    /// supervisor and restarted if they die. Every lane is a separate child.
+    pub fn start_supervised(
+        &self,
+        supervisor: &Supervisor,
+        logger: Arc<dyn Logger + Send + Sync + 'static>,
+    ) {
+        let handler = self.take_handler();
+        let health = self.health.lock().clone();
+
+        for lane in self.lanes.iter() {
+            let lane = lane.clone();
+            let handler = handler.clone();
+            let logger = logger.clone();
+            let health = health.clone();
+
+            supervisor.add_child_fn(
+                format!("QueueToSaveWithId {}", lane.get_display_name()),
+                move || {
+                    queue_to_save_with_id_loop(
+                        lane.clone(),
+                        handler.clone(),
+                        logger.clone(),
+                        health.clone(),
+                    )
+                },
+            );
+        }
     }
 }
 
@@ -91,14 +249,16 @@ async fn queue_to_save_with_id_loop<ID, T>(
     inner: Arc<QueueToSaveInnerWithId<ID, T>>,
     handler: Arc<dyn QueueToSaveWithIdEventsHandler<T> + Send + Sync + 'static>,
     logger: Arc<dyn Logger + Send + Sync + 'static>,
+    health: Option<Arc<HealthReporter>>,
 ) where
     ID: Hash + Eq + Clone + Send + Sync + 'static,
     T: PersistObjectId<ID> + Send + Sync + 'static,
 {
-    println!("QueueToSaveWithId {} is started", inner.name.as_str());
+    println!("QueueToSaveWithId {} is started", inner.get_display_name());
     let timeout = inner.timeout;
     loop {
-        let events = inner.dequeue().await;
+        let (events, receipt) = inner.dequeue().await;
+        inner.set_in_flight(events.len());
 
         let handler = handler.clone();
         let feature = tokio::spawn(async move {
@@ -107,14 +267,22 @@ async fn queue_to_save_with_id_loop<ID, T>(
             tokio::time::timeout(timeout, future).await
         });
 
-        let result = match feature.await {
+        let result = feature.await;
+        inner.set_in_flight(0);
+        inner.processed(receipt, to_persist_result(&result, timeout));
+
+        let result = match result {
             Ok(value) => value,
             Err(_) => {
                 let msg = format!(
                     "Panic at QueueToSaveWithIdEventsHandler named {}",
-                    inner.name.as_str()
+                    inner.get_display_name()
                 );
 
+                if let Some(health) = health.as_ref() {
+                    health.report_error(msg.as_str());
+                }
+
                 logger.write_error("QueueToSaveWithId.loop".to_string(), msg, None.into());
                 continue;
             }
@@ -124,10 +292,16 @@ async fn queue_to_save_with_id_loop<ID, T>(
             let msg = format!(
                 "Timeout {:?} at QueueToSaveWithIdEventsHandler named {}",
                 inner.timeout,
-                inner.name.as_str()
+                inner.get_display_name()
             );
 
+            if let Some(health) = health.as_ref() {
+                health.report_error(msg.as_str());
+            }
+
             logger.write_error("QueueToSaveWithId.loop".to_string(), msg, None.into());
+        } else if let Some(health) = health.as_ref() {
+            health.heartbeat();
         }
     }
 }
@@ -245,4 +419,256 @@ mod tests {
             assert_eq!(two.value, "b");
         });
     }
+
+    /// Records the order values of every ID are handled in, and the amount of
+    /// the handler invocations running at the same time.
+    #[derive(Default)]
+    struct LanesState {
+        handled: parking_lot::Mutex<std::collections::HashMap<u32, Vec<&'static str>>>,
+        parallel_now: std::sync::atomic::AtomicUsize,
+        max_parallel: std::sync::atomic::AtomicUsize,
+        total: std::sync::atomic::AtomicUsize,
+    }
+
+    struct SlowHandler {
+        state: Arc<LanesState>,
+    }
+
+    #[async_trait::async_trait]
+    impl QueueToSaveWithIdEventsHandler<Obj> for SlowHandler {
+        async fn execute(&self, items: Vec<Obj>) {
+            use std::sync::atomic::Ordering;
+
+            let parallel_now = self.state.parallel_now.fetch_add(1, Ordering::SeqCst) + 1;
+            self.state.max_parallel.fetch_max(parallel_now, Ordering::SeqCst);
+
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+
+            let amount = items.len();
+            {
+                let mut handled = self.state.handled.lock();
+                for item in items {
+                    handled.entry(item.id).or_default().push(item.value);
+                }
+            }
+
+            self.state.parallel_now.fetch_sub(1, Ordering::SeqCst);
+            self.state.total.fetch_add(amount, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn the_same_id_always_goes_to_the_same_lane() {
+        let queue: QueueToSaveWithId<u32, Obj> = QueueToSaveWithId::new_partitioned("test", 4);
+        assert_eq!(queue.get_lanes_amount(), 4);
+
+        for id in 0..100 {
+            let lane_no = queue.get_lane_no(&id);
+            assert!(lane_no < 4);
+            assert_eq!(queue.get_lane_no(&id), lane_no);
+        }
+
+        queue.enqueue((0..100).map(|id| Obj { id, value: "a" }));
+        assert_eq!(queue.queue_len(), 100);
+
+        let by_lanes: usize = (0..4).map(|lane_no| queue.lane_queue_len(lane_no)).sum();
+        assert_eq!(by_lanes, 100);
+    }
+
+    #[test]
+    fn not_partitioned_queue_has_a_single_lane() {
+        let queue: QueueToSaveWithId<u32, Obj> = QueueToSaveWithId::new("test");
+        assert_eq!(queue.get_lanes_amount(), 1);
+        assert_eq!(queue.get_lane_no(&42), 0);
+    }
+
+    #[test]
+    fn lanes_are_handled_in_parallel_and_id_order_is_preserved() {
+        use std::sync::atomic::Ordering;
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let queue: QueueToSaveWithId<u32, Obj> =
+                QueueToSaveWithId::new_partitioned("test-lanes", 4);
+
+            let state = Arc::new(LanesState::default());
+            queue.register_events_handler(Arc::new(SlowHandler {
+                state: state.clone(),
+            }));
+
+            queue.enqueue((0..40).map(|id| Obj { id, value: "a" }));
+            queue.start(Arc::new(NoopLogger));
+
+            // The first chunks are in flight - the next values have to be handled
+            // after them.
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            let in_flight: usize = (0..4).map(|lane_no| queue.lane_in_flight(lane_no)).sum();
+            assert!(in_flight > 0);
+
+            queue.enqueue((0..40).map(|id| Obj { id, value: "b" }));
+
+            for _ in 0..400 {
+                if state.total.load(Ordering::SeqCst) >= 80 {
+                    break;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            }
+
+            assert_eq!(state.total.load(Ordering::SeqCst), 80);
+            assert!(state.max_parallel.load(Ordering::SeqCst) > 1);
+
+            let handled = state.handled.lock();
+            for id in 0..40 {
+                assert_eq!(handled.get(&id).unwrap(), &vec!["a", "b"]);
+            }
+        });
+    }
+
+    /// Panics on the chunks containing the value `"panic"`.
+    struct PanickingOnValueHandler {
+        state: Arc<LanesState>,
+    }
+
+    #[async_trait::async_trait]
+    impl QueueToSaveWithIdEventsHandler<Obj> for PanickingOnValueHandler {
+        async fn execute(&self, items: Vec<Obj>) {
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+
+            if items.iter().any(|item| item.value == "panic") {
+                panic!("Can not save");
+            }
+
+            SlowHandler {
+                state: self.state.clone(),
+            }
+            .execute(items)
+            .await;
+        }
+    }
+
+    #[test]
+    fn enqueue_and_wait_reports_the_outcome_of_the_chunk() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let queue: QueueToSaveWithId<u32, Obj> = QueueToSaveWithId::new("test-and-wait");
+            queue.register_events_handler(Arc::new(PanickingOnValueHandler {
+                state: Arc::new(LanesState::default()),
+            }));
+            queue.start(Arc::new(NoopLogger));
+
+            let ok = queue.enqueue_and_wait(Obj { id: 1, value: "a" });
+            assert_eq!(ok.get_result().await, Ok(()));
+
+            let failed = queue.enqueue_and_wait(Obj { id: 2, value: "panic" });
+            assert_eq!(
+                failed.get_result().await,
+                Err(crate::QueueToSaveError::Panic)
+            );
+        });
+    }
+
+    #[test]
+    fn replaced_item_is_awaited_through_the_newer_one() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let queue: QueueToSaveWithId<u32, Obj> = QueueToSaveWithId::new("test-replaced");
+            let state = Arc::new(LanesState::default());
+            queue.register_events_handler(Arc::new(SlowHandler {
+                state: state.clone(),
+            }));
+
+            let first = queue.enqueue_and_wait(Obj { id: 1, value: "a" });
+            let second = queue.enqueue_and_wait(Obj { id: 1, value: "b" });
+
+            queue.start(Arc::new(NoopLogger));
+
+            assert_eq!(first.get_result().await, Ok(()));
+            assert_eq!(second.get_result().await, Ok(()));
+            assert_eq!(state.handled.lock().get(&1).unwrap(), &vec!["b"]);
+        });
+    }
+
+    #[test]
+    fn flush_waits_for_everything_enqueued_before_it() {
+        use std::sync::atomic::Ordering;
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let queue: QueueToSaveWithId<u32, Obj> =
+                QueueToSaveWithId::new_partitioned("test-flush", 3);
+            let state = Arc::new(LanesState::default());
+            queue.register_events_handler(Arc::new(SlowHandler {
+                state: state.clone(),
+            }));
+
+            // Nothing is enqueued - nothing to wait for.
+            queue.flush().await;
+
+            queue.start(Arc::new(NoopLogger));
+            queue.enqueue((0..200).map(|id| Obj { id, value: "a" }));
+
+            queue.flush().await;
+
+            assert_eq!(state.total.load(Ordering::SeqCst), 200);
+            assert_eq!(queue.queue_len(), 0);
+        });
+    }
+
+    #[test]
+    fn handled_chunks_are_reported_to_the_health_registry() {
+        use crate::{
+            health::{HealthRegistry, HealthStatus},
+            AppStates,
+        };
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let registry = HealthRegistry::new(Arc::new(AppStates::create_initialized()));
+
+            let queue: QueueToSaveWithId<u32, Obj> = QueueToSaveWithId::new("test-health");
+            queue.set_health_reporter(registry.register("test-health"));
+            queue.register_events_handler(Arc::new(PanickingOnValueHandler {
+                state: Arc::new(LanesState::default()),
+            }));
+            queue.start(Arc::new(NoopLogger));
+
+            let get_status = || registry.get_snapshot().components[0].status;
+
+            let _ = queue
+                .enqueue_and_wait(Obj { id: 1, value: "panic" })
+                .get_result()
+                .await;
+            // The awaiter is completed before the loop reports the chunk.
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            assert_eq!(get_status(), HealthStatus::Degraded);
+
+            let _ = queue
+                .enqueue_and_wait(Obj { id: 2, value: "a" })
+                .get_result()
+                .await;
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            assert_eq!(get_status(), HealthStatus::Healthy);
+            assert_eq!(registry.get_snapshot().components[0].errors, 1);
+        });
+    }
 }
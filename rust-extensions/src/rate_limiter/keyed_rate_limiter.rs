@@ -0,0 +1,179 @@
+use std::{collections::HashMap, hash::Hash, time::Duration};
+
+use parking_lot::Mutex;
+
+use crate::date_time::DateTimeAsMicroseconds;
+
+use super::{token_bucket_state::TokenBucketState, TokenBucketSettings};
+
+struct KeyedBucket {
+    state: TokenBucketState,
+    last_used: DateTimeAsMicroseconds,
+}
+
+struct KeyedBuckets<TKey> {
+    buckets: HashMap<TKey, KeyedBucket>,
+    last_eviction: DateTimeAsMicroseconds,
+}
+
+/// A [`super::TokenBucket`] per key - per client ID, per partner - all of them
+/// with the same settings.
+///
+/// The bucket of the key is created on its first call. The bucket which has not
+/// been used for `idle_timeout` and is full again is evicted - it would be
+/// created exactly the same on the next call. The eviction runs on the calls
+/// themselves, at most once per `idle_timeout`, or explicitly with
+/// [`Self::evict_idle_at`].
+pub struct KeyedRateLimiter<TKey: Hash + Eq + Clone> {
+    settings: TokenBucketSettings,
+    idle_timeout: Duration,
+    inner: Mutex<KeyedBuckets<TKey>>,
+}
+
+impl<TKey: Hash + Eq + Clone> KeyedRateLimiter<TKey> {
+    pub fn new(settings: TokenBucketSettings, idle_timeout: Duration) -> Self {
+        Self {
+            settings,
+            idle_timeout,
+            inner: Mutex::new(KeyedBuckets {
+                buckets: HashMap::new(),
+                last_eviction: DateTimeAsMicroseconds::new(0),
+            }),
+        }
+    }
+
+    pub fn try_acquire(&self, key: &TKey) -> bool {
+        self.try_acquire_at(key, DateTimeAsMicroseconds::now())
+    }
+
+    pub fn try_acquire_at(&self, key: &TKey, now: DateTimeAsMicroseconds) -> bool {
+        let mut inner = self.inner.lock();
+        self.evict_if_time(&mut inner, now);
+        self.get_bucket(&mut inner, key, now)
+            .state
+            .try_take(&self.settings, now)
+    }
+
+    /// Waits until a token of the key is available and takes it.
+    pub async fn acquire(&self, key: &TKey) {
+        loop {
+            let wait = {
+                let mut inner = self.inner.lock();
+                let now = DateTimeAsMicroseconds::now();
+                self.evict_if_time(&mut inner, now);
+
+                let bucket = self.get_bucket(&mut inner, key, now);
+
+                if bucket.state.try_take(&self.settings, now) {
+                    return;
+                }
+
+                bucket.state.get_wait(&self.settings, now)
+            };
+
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    fn get_bucket<'s>(
+        &self,
+        inner: &'s mut KeyedBuckets<TKey>,
+        key: &TKey,
+        now: DateTimeAsMicroseconds,
+    ) -> &'s mut KeyedBucket {
+        let bucket = inner
+            .buckets
+            .entry(key.clone())
+            .or_insert_with(|| KeyedBucket {
+                state: TokenBucketState::new(&self.settings, now),
+                last_used: now,
+            });
+
+        bucket.last_used = now;
+        bucket
+    }
+
+    fn evict_if_time(&self, inner: &mut KeyedBuckets<TKey>, now: DateTimeAsMicroseconds) {
+        if now
+            .duration_since(inner.last_eviction)
+            .as_positive_or_zero()
+            < self.idle_timeout
+        {
+            return;
+        }
+
+        self.evict(inner, now);
+    }
+
+    fn evict(&self, inner: &mut KeyedBuckets<TKey>, now: DateTimeAsMicroseconds) {
+        inner.last_eviction = now;
+
+        inner.buckets.retain(|_, bucket| {
+            let idle = now.duration_since(bucket.last_used).as_positive_or_zero();
+            idle < self.idle_timeout
+                || bucket.state.get_available(&self.settings, now) < self.settings.capacity
+        });
+    }
+
+    /// Evicts the buckets of the keys which are idle at `now`.
+    pub fn evict_idle_at(&self, now: DateTimeAsMicroseconds) {
+        let mut inner = self.inner.lock();
+        self.evict(&mut inner, now);
+    }
+
+    /// Amount of the keys which have a bucket right now.
+    pub fn get_keys_amount(&self) -> usize {
+        self.inner.lock().buckets.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(millis: i64) -> DateTimeAsMicroseconds {
+        DateTimeAsMicroseconds::new(millis * 1000)
+    }
+
+    fn create_limiter() -> KeyedRateLimiter<&'static str> {
+        KeyedRateLimiter::new(
+            TokenBucketSettings::new(2, Duration::from_millis(100)),
+            Duration::from_secs(10),
+        )
+    }
+
+    #[test]
+    fn every_key_has_its_own_bucket() {
+        let limiter = create_limiter();
+
+        assert!(limiter.try_acquire_at(&"client-1", at(0)));
+        assert!(limiter.try_acquire_at(&"client-1", at(0)));
+        assert!(!limiter.try_acquire_at(&"client-1", at(0)));
+
+        assert!(limiter.try_acquire_at(&"client-2", at(0)));
+        assert!(limiter.try_acquire_at(&"client-1", at(100)));
+        assert_eq!(limiter.get_keys_amount(), 2);
+    }
+
+    #[test]
+    fn idle_and_refilled_keys_are_evicted() {
+        let limiter = KeyedRateLimiter::new(
+            TokenBucketSettings::new(2, Duration::from_secs(60)),
+            Duration::from_secs(10),
+        );
+
+        assert!(limiter.try_acquire_at(&"idle", at(0)));
+        assert!(limiter.try_acquire_at(&"busy", at(0)));
+
+        // Idle, but not refilled yet - evicting it would give the key a free burst.
+        limiter.evict_idle_at(at(20_000));
+        assert_eq!(limiter.get_keys_amount(), 2);
+
+        assert!(limiter.try_acquire_at(&"busy", at(55_000)));
+
+        // The eviction runs on the call itself, once per idle timeout: "idle" is
+        // full and idle, "busy" is not refilled yet.
+        assert!(limiter.try_acquire_at(&"busy", at(65_000)));
+        assert_eq!(limiter.get_keys_amount(), 1);
+    }
+}
@@ -0,0 +1,8 @@
+mod keyed_rate_limiter;
+mod sliding_window_counter;
+mod token_bucket;
+mod token_bucket_state;
+
+pub use keyed_rate_limiter::KeyedRateLimiter;
+pub use sliding_window_counter::SlidingWindowCounter;
+pub use token_bucket::{TokenBucket, TokenBucketSettings};
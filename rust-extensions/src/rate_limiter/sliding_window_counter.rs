@@ -0,0 +1,134 @@
+use std::time::Duration;
+
+use parking_lot::Mutex;
+
+use crate::date_time::DateTimeAsMicroseconds;
+
+struct SlidingWindowState {
+    window_started: i64,
+    current: u64,
+    previous: u64,
+}
+
+/// Allows up to `limit` calls within any `window`.
+///
+/// The counter keeps the amount of calls of the current fixed window and of the
+/// previous one only, and estimates the calls within the sliding window by
+/// weighting the previous window with the part of it the sliding window still
+/// covers. Unlike a fixed window it does not let `2 * limit` calls through around
+/// the window boundary, and unlike the log of calls it costs the same for any
+/// limit.
+pub struct SlidingWindowCounter {
+    limit: u64,
+    window: Duration,
+    state: Mutex<SlidingWindowState>,
+}
+
+impl SlidingWindowCounter {
+    pub fn new(limit: u64, window: Duration) -> Self {
+        Self {
+            limit,
+            window,
+            state: Mutex::new(SlidingWindowState {
+                window_started: 0,
+                current: 0,
+                previous: 0,
+            }),
+        }
+    }
+
+    fn get_window_micros(&self) -> i64 {
+        (self.window.as_micros() as i64).max(1)
+    }
+
+    fn roll(&self, state: &mut SlidingWindowState, now: DateTimeAsMicroseconds) {
+        let window = self.get_window_micros();
+        let window_started = now.unix_microseconds - now.unix_microseconds.rem_euclid(window);
+
+        if window_started <= state.window_started {
+            return;
+        }
+
+        state.previous = if window_started - state.window_started == window {
+            state.current
+        } else {
+            0
+        };
+
+        state.current = 0;
+        state.window_started = window_started;
+    }
+
+    fn estimate(&self, state: &SlidingWindowState, now: DateTimeAsMicroseconds) -> u64 {
+        let window = self.get_window_micros();
+        let elapsed = now.unix_microseconds - state.window_started;
+        let previous_share = (window - elapsed).max(0) as u128;
+
+        let previous = state.previous as u128 * previous_share / window as u128;
+
+        previous as u64 + state.current
+    }
+
+    pub fn try_acquire(&self) -> bool {
+        self.try_acquire_at(DateTimeAsMicroseconds::now())
+    }
+
+    pub fn try_acquire_at(&self, now: DateTimeAsMicroseconds) -> bool {
+        let mut state = self.state.lock();
+        self.roll(&mut state, now);
+
+        if self.estimate(&state, now) >= self.limit {
+            return false;
+        }
+
+        state.current += 1;
+        true
+    }
+
+    /// The estimated amount of calls within the window ending at `now`.
+    pub fn get_count_at(&self, now: DateTimeAsMicroseconds) -> u64 {
+        let mut state = self.state.lock();
+        self.roll(&mut state, now);
+        self.estimate(&state, now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(millis: i64) -> DateTimeAsMicroseconds {
+        DateTimeAsMicroseconds::new(millis * 1000)
+    }
+
+    #[test]
+    fn limit_within_a_window() {
+        let counter = SlidingWindowCounter::new(3, Duration::from_secs(1));
+
+        assert!(counter.try_acquire_at(at(100)));
+        assert!(counter.try_acquire_at(at(200)));
+        assert!(counter.try_acquire_at(at(300)));
+        assert!(!counter.try_acquire_at(at(900)));
+        assert_eq!(counter.get_count_at(at(900)), 3);
+    }
+
+    #[test]
+    fn previous_window_is_weighted_by_the_part_still_covered() {
+        let counter = SlidingWindowCounter::new(4, Duration::from_secs(1));
+
+        for _ in 0..4 {
+            assert!(counter.try_acquire_at(at(900)));
+        }
+
+        // A fixed window would let 4 more calls through right after the boundary.
+        assert!(!counter.try_acquire_at(at(1_000)));
+
+        // A quarter into the new window 3 of the previous 4 calls still count.
+        assert_eq!(counter.get_count_at(at(1_250)), 3);
+        assert!(counter.try_acquire_at(at(1_250)));
+        assert!(!counter.try_acquire_at(at(1_250)));
+
+        // The window before the previous one counts for nothing.
+        assert_eq!(counter.get_count_at(at(3_000)), 0);
+    }
+}
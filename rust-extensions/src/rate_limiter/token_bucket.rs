@@ -0,0 +1,167 @@
+use std::time::Duration;
+
+use parking_lot::Mutex;
+
+use crate::date_time::DateTimeAsMicroseconds;
+
+use super::token_bucket_state::TokenBucketState;
+
+/// The bucket holds up to `capacity` tokens and gets a new one every
+/// `refill_every` - so `capacity` calls may burst at once, and then the calls go at
+/// the rate of one per `refill_every`.
+#[derive(Debug, Clone, Copy)]
+pub struct TokenBucketSettings {
+    pub capacity: u64,
+    pub refill_every: Duration,
+}
+
+impl TokenBucketSettings {
+    pub fn new(capacity: u64, refill_every: Duration) -> Self {
+        if capacity == 0 {
+            panic!("Token bucket capacity must be greater than 0");
+        }
+
+        Self {
+            capacity,
+            refill_every,
+        }
+    }
+
+    /// `rate` calls per second with bursts of up to `rate` calls.
+    pub fn per_second(rate: u64) -> Self {
+        Self::new(rate, Duration::from_micros(1_000_000 / rate.max(1)))
+    }
+
+    pub(super) fn get_refill_every_micros(&self) -> u64 {
+        (self.refill_every.as_micros() as u64).max(1)
+    }
+}
+
+/// Throttles the calls to a partner: every call takes a token, the tokens are
+/// refilled at the constant rate.
+///
+/// The `_at` methods take the current moment as a parameter - the rest of them use
+/// [`DateTimeAsMicroseconds::now`].
+pub struct TokenBucket {
+    settings: TokenBucketSettings,
+    state: Mutex<TokenBucketState>,
+}
+
+impl TokenBucket {
+    pub fn new(settings: TokenBucketSettings) -> Self {
+        Self {
+            state: Mutex::new(TokenBucketState::new(
+                &settings,
+                DateTimeAsMicroseconds::now(),
+            )),
+            settings,
+        }
+    }
+
+    pub fn get_settings(&self) -> &TokenBucketSettings {
+        &self.settings
+    }
+
+    pub fn try_acquire(&self) -> bool {
+        self.try_acquire_at(DateTimeAsMicroseconds::now())
+    }
+
+    pub fn try_acquire_at(&self, now: DateTimeAsMicroseconds) -> bool {
+        self.state.lock().try_take(&self.settings, now)
+    }
+
+    /// Waits until a token is available and takes it. The callers waiting at the
+    /// same time are not queued - the one which wakes up first gets the token.
+    pub async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock();
+                let now = DateTimeAsMicroseconds::now();
+
+                if state.try_take(&self.settings, now) {
+                    return;
+                }
+
+                state.get_wait(&self.settings, now)
+            };
+
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    pub fn get_available_at(&self, now: DateTimeAsMicroseconds) -> u64 {
+        self.state.lock().get_available(&self.settings, now)
+    }
+
+    /// How long [`Self::acquire`] would wait at the given moment.
+    pub fn get_wait_at(&self, now: DateTimeAsMicroseconds) -> Duration {
+        self.state.lock().get_wait(&self.settings, now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(millis: i64) -> DateTimeAsMicroseconds {
+        DateTimeAsMicroseconds::new(millis * 1000)
+    }
+
+    fn create_bucket(capacity: u64, refill_every_ms: u64) -> TokenBucket {
+        let settings = TokenBucketSettings::new(capacity, Duration::from_millis(refill_every_ms));
+
+        TokenBucket {
+            state: Mutex::new(TokenBucketState::new(&settings, at(0))),
+            settings,
+        }
+    }
+
+    #[test]
+    fn burst_up_to_capacity_then_the_refill_rate() {
+        let bucket = create_bucket(3, 100);
+
+        assert!(bucket.try_acquire_at(at(0)));
+        assert!(bucket.try_acquire_at(at(0)));
+        assert!(bucket.try_acquire_at(at(0)));
+        assert!(!bucket.try_acquire_at(at(0)));
+
+        assert_eq!(bucket.get_wait_at(at(40)), Duration::from_millis(60));
+        assert!(!bucket.try_acquire_at(at(99)));
+        assert!(bucket.try_acquire_at(at(100)));
+        assert!(!bucket.try_acquire_at(at(150)));
+
+        // The part of the interval before a token is produced is not lost.
+        assert!(bucket.try_acquire_at(at(200)));
+    }
+
+    #[test]
+    fn refill_never_exceeds_the_capacity() {
+        let bucket = create_bucket(2, 100);
+
+        assert!(bucket.try_acquire_at(at(0)));
+        assert!(bucket.try_acquire_at(at(0)));
+
+        assert_eq!(bucket.get_available_at(at(10_000)), 2);
+        assert!(bucket.try_acquire_at(at(10_000)));
+        assert!(bucket.try_acquire_at(at(10_000)));
+        assert!(!bucket.try_acquire_at(at(10_000)));
+    }
+
+    #[test]
+    fn acquire_waits_for_the_token() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let bucket = TokenBucket::new(TokenBucketSettings::new(1, Duration::from_millis(50)));
+
+            bucket.acquire().await;
+            let started = std::time::Instant::now();
+            bucket.acquire().await;
+
+            assert!(started.elapsed() >= Duration::from_millis(40));
+        });
+    }
+}
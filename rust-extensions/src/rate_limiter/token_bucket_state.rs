@@ -0,0 +1,92 @@
+use std::time::Duration;
+
+use crate::date_time::DateTimeAsMicroseconds;
+
+use super::TokenBucketSettings;
+
+/// The tokens of a single bucket. The time is always given from outside - so the
+/// bucket behaves exactly the same in the tests as it does against the wall clock.
+pub(super) struct TokenBucketState {
+    available: u64,
+    last_refill: DateTimeAsMicroseconds,
+}
+
+impl TokenBucketState {
+    pub fn new(settings: &TokenBucketSettings, now: DateTimeAsMicroseconds) -> Self {
+        Self {
+            available: settings.capacity,
+            last_refill: now,
+        }
+    }
+
+    fn refill(&mut self, settings: &TokenBucketSettings, now: DateTimeAsMicroseconds) {
+        if self.available >= settings.capacity {
+            // A full bucket does not save the tokens up - the time spent full counts
+            // for nothing.
+            self.last_refill = now;
+            return;
+        }
+
+        let elapsed = now.duration_since(self.last_refill).as_positive_or_zero();
+        let refill_every = settings.get_refill_every_micros();
+        let tokens = elapsed.as_micros() as u64 / refill_every;
+
+        if tokens == 0 {
+            return;
+        }
+
+        self.available = (self.available + tokens).min(settings.capacity);
+
+        if self.available == settings.capacity {
+            self.last_refill = now;
+        } else {
+            // The part of the interval which has not produced a token yet is kept.
+            self.last_refill = self
+                .last_refill
+                .add(Duration::from_micros(tokens * refill_every));
+        }
+    }
+
+    pub fn try_take(
+        &mut self,
+        settings: &TokenBucketSettings,
+        now: DateTimeAsMicroseconds,
+    ) -> bool {
+        self.refill(settings, now);
+
+        if self.available == 0 {
+            return false;
+        }
+
+        self.available -= 1;
+        true
+    }
+
+    /// How long it takes until the next token is available.
+    pub fn get_wait(
+        &mut self,
+        settings: &TokenBucketSettings,
+        now: DateTimeAsMicroseconds,
+    ) -> Duration {
+        self.refill(settings, now);
+
+        if self.available > 0 {
+            return Duration::ZERO;
+        }
+
+        let next_token_at = self
+            .last_refill
+            .add(Duration::from_micros(settings.get_refill_every_micros()));
+
+        next_token_at.duration_since(now).as_positive_or_zero()
+    }
+
+    pub fn get_available(
+        &mut self,
+        settings: &TokenBucketSettings,
+        now: DateTimeAsMicroseconds,
+    ) -> u64 {
+        self.refill(settings, now);
+        self.available
+    }
+}
@@ -0,0 +1,11 @@
+mod supervised_task;
+mod supervisor;
+mod supervisor_health;
+mod supervisor_strategy;
+
+pub use supervised_task::SupervisedTask;
+pub use supervisor::Supervisor;
+pub use supervisor_health::{
+    SupervisedChildExit, SupervisedChildHealth, SupervisorHealth, SupervisorStatus,
+};
+pub use supervisor_strategy::{RestartIntensity, SupervisorStrategy};
@@ -0,0 +1,17 @@
+/// A long living task owned by the [`super::Supervisor`].
+///
+/// `run` is expected to live until the application is shutting down. If it returns
+/// or panics before that, the task is considered dead and `run` is called again on
+/// the same instance - so everything the task has to continue with must live in
+/// `self`, not in the future of the previous `run`.
+///
+/// A task which is done on purpose - an `EventsLoop` asked to stop - reports it
+/// with `is_finished` and is not restarted.
+#[async_trait::async_trait]
+pub trait SupervisedTask: Send + Sync + 'static {
+    async fn run(&self);
+
+    fn is_finished(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,542 @@
+use std::{
+    collections::VecDeque, future::Future, panic::AssertUnwindSafe, sync::Arc, time::Instant,
+};
+
+use futures::FutureExt;
+use parking_lot::Mutex;
+use tokio::task::JoinSet;
+
+use crate::{
+    date_time::DateTimeAsMicroseconds, panic_message::get_panic_message, ApplicationStates, Logger,
+    StrOrString,
+};
+
+use super::{
+    RestartIntensity, SupervisedChildExit, SupervisedChildHealth, SupervisedTask, SupervisorHealth,
+    SupervisorStatus, SupervisorStrategy,
+};
+
+struct SupervisedChild {
+    name: String,
+    task: Arc<dyn SupervisedTask>,
+}
+
+/// The child which runs the future created by the closure - for the tasks which
+/// are not components of this crate.
+struct SupervisedFn<TFn> {
+    create_future: TFn,
+}
+
+#[async_trait::async_trait]
+impl<TFuture, TFn> SupervisedTask for SupervisedFn<TFn>
+where
+    TFuture: Future<Output = ()> + Send + 'static,
+    TFn: Fn() -> TFuture + Send + Sync + 'static,
+{
+    async fn run(&self) {
+        (self.create_future)().await;
+    }
+}
+
+struct SupervisorState {
+    status: SupervisorStatus,
+    children: Vec<SupervisedChildHealth>,
+}
+
+/// Owns long living tasks - the readers of `EventsLoop`s, `BackgroundExecutor`s
+/// and queue-to-save loops, or any [`SupervisedTask`] - and restarts them when
+/// they die while the application is not shutting down.
+///
+/// Children are added before the supervisor is started - the components do it
+/// themselves in their `start_supervised`. A child which dies is restarted
+/// according to the [`SupervisorStrategy`]; if restarts happen more often than the
+/// [`RestartIntensity`] allows, the supervisor stops every child and reports
+/// [`SupervisorStatus::Failed`] - it is up to the liveness probe polling
+/// [`Supervisor::get_health`] to get the process restarted then.
+pub struct Supervisor {
+    name: Arc<String>,
+    strategy: SupervisorStrategy,
+    intensity: RestartIntensity,
+    pending_children: Mutex<Option<Vec<SupervisedChild>>>,
+    state: Arc<Mutex<SupervisorState>>,
+}
+
+impl Supervisor {
+    pub fn new(name: impl Into<StrOrString<'static>>, strategy: SupervisorStrategy) -> Self {
+        Self {
+            name: Arc::new(name.into().to_string()),
+            strategy,
+            intensity: RestartIntensity::default(),
+            pending_children: Mutex::new(Some(Vec::new())),
+            state: Arc::new(Mutex::new(SupervisorState {
+                status: SupervisorStatus::NotStarted,
+                children: Vec::new(),
+            })),
+        }
+    }
+
+    pub fn set_restart_intensity(mut self, intensity: RestartIntensity) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    pub fn get_name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn add_child(&self, name: impl Into<StrOrString<'static>>, task: Arc<dyn SupervisedTask>) {
+        let name = name.into().to_string();
+
+        let mut pending_children = self.pending_children.lock();
+
+        let Some(children) = pending_children.as_mut() else {
+            panic!(
+                "Can not add child {} to supervisor {} - it is already started",
+                name, self.name
+            );
+        };
+
+        children.push(SupervisedChild { name, task });
+    }
+
+    /// Adds the child which runs the future created by `create_future` - a fresh
+    /// one on every restart.
+    pub fn add_child_fn<TFuture, TFn>(
+        &self,
+        name: impl Into<StrOrString<'static>>,
+        create_future: TFn,
+    ) where
+        TFuture: Future<Output = ()> + Send + 'static,
+        TFn: Fn() -> TFuture + Send + Sync + 'static,
+    {
+        self.add_child(name, Arc::new(SupervisedFn { create_future }));
+    }
+
+    pub fn start(
+        &self,
+        app_states: Arc<dyn ApplicationStates + Send + Sync + 'static>,
+        logger: Arc<dyn Logger + Send + Sync + 'static>,
+    ) {
+        let children = self.pending_children.lock().take();
+
+        let Some(children) = children else {
+            panic!("Supervisor {} is already started", self.name);
+        };
+
+        {
+            let mut state = self.state.lock();
+            state.status = SupervisorStatus::Running;
+            state.children = children
+                .iter()
+                .map(|child| SupervisedChildHealth {
+                    name: child.name.clone(),
+                    is_running: false,
+                    restarts: 0,
+                    last_exit: None,
+                    last_exit_at: None,
+                })
+                .collect();
+        }
+
+        tokio::spawn(supervisor_loop(
+            SupervisorLoop {
+                name: self.name.clone(),
+                strategy: self.strategy,
+                intensity: self.intensity,
+                children,
+                state: self.state.clone(),
+                restarts: VecDeque::new(),
+            },
+            app_states,
+            logger,
+        ));
+    }
+
+    pub fn get_status(&self) -> SupervisorStatus {
+        self.state.lock().status
+    }
+
+    pub fn get_health(&self) -> SupervisorHealth {
+        let state = self.state.lock();
+
+        SupervisorHealth {
+            name: self.name.to_string(),
+            status: state.status,
+            children: state.children.clone(),
+        }
+    }
+}
+
+struct SupervisorLoop {
+    name: Arc<String>,
+    strategy: SupervisorStrategy,
+    intensity: RestartIntensity,
+    children: Vec<SupervisedChild>,
+    state: Arc<Mutex<SupervisorState>>,
+    restarts: VecDeque<Instant>,
+}
+
+impl SupervisorLoop {
+    fn spawn_child(&self, join_set: &mut JoinSet<(usize, Option<String>)>, index: usize) {
+        let task = self.children[index].task.clone();
+
+        join_set.spawn(async move {
+            let result = AssertUnwindSafe(task.run()).catch_unwind().await;
+            (
+                index,
+                result
+                    .err()
+                    .map(|payload| get_panic_message(payload.as_ref())),
+            )
+        });
+
+        self.state.lock().children[index].is_running = true;
+    }
+
+    fn child_exited(&self, index: usize, exit: Option<SupervisedChildExit>) {
+        let mut state = self.state.lock();
+        let child = &mut state.children[index];
+        child.is_running = false;
+
+        if exit.is_some() {
+            child.last_exit = exit;
+            child.last_exit_at = Some(DateTimeAsMicroseconds::now());
+        }
+    }
+
+    /// Registers the restart which is about to happen. Returns `false` if it
+    /// exceeds the restart intensity.
+    fn register_restart(&mut self) -> bool {
+        let now = Instant::now();
+
+        while let Some(oldest) = self.restarts.front() {
+            if now.duration_since(*oldest) > self.intensity.period {
+                self.restarts.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        if self.restarts.len() >= self.intensity.max_restarts {
+            return false;
+        }
+
+        self.restarts.push_back(now);
+        true
+    }
+
+    fn set_status(&self, status: SupervisorStatus) {
+        self.state.lock().status = status;
+    }
+}
+
+async fn supervisor_loop(
+    mut supervisor: SupervisorLoop,
+    app_states: Arc<dyn ApplicationStates + Send + Sync + 'static>,
+    logger: Arc<dyn Logger + Send + Sync + 'static>,
+) {
+    let mut join_set = JoinSet::new();
+
+    for index in 0..supervisor.children.len() {
+        supervisor.spawn_child(&mut join_set, index);
+    }
+
+    while let Some(result) = join_set.join_next().await {
+        // Aborted by ourselves - the child is already accounted for.
+        let Ok((index, panic_message)) = result else {
+            continue;
+        };
+
+        if app_states.is_shutting_down()
+            || (panic_message.is_none() && supervisor.children[index].task.is_finished())
+        {
+            supervisor.child_exited(index, None);
+            continue;
+        }
+
+        let exit = match panic_message {
+            Some(message) => SupervisedChildExit::Panicked(message),
+            None => SupervisedChildExit::Returned,
+        };
+
+        logger.write_error(
+            format!("Supervisor {}", supervisor.name.as_str()),
+            format!(
+                "Child {} is dead: {:?}",
+                supervisor.children[index].name, exit
+            ),
+            None,
+        );
+
+        supervisor.child_exited(index, Some(exit));
+
+        if !supervisor.register_restart() {
+            logger.write_fatal_error(
+                format!("Supervisor {}", supervisor.name.as_str()),
+                format!(
+                    "More than {} restarts within {:?}. Giving up",
+                    supervisor.intensity.max_restarts, supervisor.intensity.period
+                ),
+                None,
+            );
+
+            stop_all(&supervisor, &mut join_set).await;
+            supervisor.set_status(SupervisorStatus::Failed);
+            return;
+        }
+
+        match supervisor.strategy {
+            SupervisorStrategy::OneForOne => {
+                increment_restarts(&supervisor, index);
+                supervisor.spawn_child(&mut join_set, index);
+            }
+            SupervisorStrategy::OneForAll => {
+                stop_all(&supervisor, &mut join_set).await;
+
+                for index in 0..supervisor.children.len() {
+                    increment_restarts(&supervisor, index);
+                    supervisor.spawn_child(&mut join_set, index);
+                }
+            }
+        }
+    }
+
+    supervisor.set_status(SupervisorStatus::Stopped);
+}
+
+async fn stop_all(supervisor: &SupervisorLoop, join_set: &mut JoinSet<(usize, Option<String>)>) {
+    join_set.abort_all();
+
+    // Waiting until the aborted children are really gone - the ones restarted
+    // after must not meet them alive.
+    while join_set.join_next().await.is_some() {}
+
+    for index in 0..supervisor.children.len() {
+        supervisor.child_exited(index, None);
+    }
+}
+
+fn increment_restarts(supervisor: &SupervisorLoop, index: usize) {
+    supervisor.state.lock().children[index].restarts += 1;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use crate::{AppStates, Logger};
+
+    use super::*;
+
+    struct TestLogger;
+
+    impl Logger for TestLogger {
+        fn write_info(&self, _: String, _: String, _: Option<HashMap<String, String>>) {}
+        fn write_warning(&self, _: String, _: String, _: Option<HashMap<String, String>>) {}
+        fn write_error(&self, _: String, _: String, _: Option<HashMap<String, String>>) {}
+        fn write_fatal_error(&self, _: String, _: String, _: Option<HashMap<String, String>>) {}
+        fn write_debug_info(&self, _: String, _: String, _: Option<HashMap<String, String>>) {}
+    }
+
+    fn rt() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    /// Dies `deaths` times (panicking), then lives forever.
+    struct DyingTask {
+        runs: AtomicUsize,
+        deaths: usize,
+    }
+
+    #[async_trait::async_trait]
+    impl SupervisedTask for DyingTask {
+        async fn run(&self) {
+            let run_no = self.runs.fetch_add(1, Ordering::SeqCst);
+
+            if run_no < self.deaths {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+                panic!("Died at run {}", run_no);
+            }
+
+            loop {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+            }
+        }
+    }
+
+    struct LivingTask {
+        runs: AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl SupervisedTask for LivingTask {
+        async fn run(&self) {
+            self.runs.fetch_add(1, Ordering::SeqCst);
+            loop {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+            }
+        }
+    }
+
+    async fn wait_until(condition: impl Fn() -> bool) {
+        for _ in 0..400 {
+            if condition() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        panic!("Condition is not met");
+    }
+
+    #[test]
+    fn one_for_one_restarts_only_the_dead_child() {
+        rt().block_on(async {
+            let supervisor = Supervisor::new("test-one-for-one", SupervisorStrategy::OneForOne);
+
+            let dying = Arc::new(DyingTask {
+                runs: AtomicUsize::new(0),
+                deaths: 2,
+            });
+            let living = Arc::new(LivingTask {
+                runs: AtomicUsize::new(0),
+            });
+
+            supervisor.add_child("dying", dying.clone());
+            supervisor.add_child("living", living.clone());
+            supervisor.start(
+                Arc::new(AppStates::create_initialized()),
+                Arc::new(TestLogger),
+            );
+
+            wait_until(|| dying.runs.load(Ordering::SeqCst) == 3).await;
+            tokio::time::sleep(Duration::from_millis(20)).await;
+
+            assert_eq!(living.runs.load(Ordering::SeqCst), 1);
+
+            let health = supervisor.get_health();
+            assert!(health.is_healthy());
+            assert_eq!(health.children[0].restarts, 2);
+            assert_eq!(
+                health.children[0].last_exit,
+                Some(SupervisedChildExit::Panicked("Died at run 1".to_string()))
+            );
+            assert_eq!(health.children[1].restarts, 0);
+        });
+    }
+
+    #[test]
+    fn one_for_all_restarts_every_child() {
+        rt().block_on(async {
+            let supervisor = Supervisor::new("test-one-for-all", SupervisorStrategy::OneForAll);
+
+            let dying = Arc::new(DyingTask {
+                runs: AtomicUsize::new(0),
+                deaths: 1,
+            });
+            let living = Arc::new(LivingTask {
+                runs: AtomicUsize::new(0),
+            });
+
+            supervisor.add_child("dying", dying.clone());
+            supervisor.add_child("living", living.clone());
+            supervisor.start(
+                Arc::new(AppStates::create_initialized()),
+                Arc::new(TestLogger),
+            );
+
+            wait_until(|| living.runs.load(Ordering::SeqCst) == 2).await;
+            wait_until(|| supervisor.get_health().is_healthy()).await;
+
+            assert_eq!(dying.runs.load(Ordering::SeqCst), 2);
+            let health = supervisor.get_health();
+            assert_eq!(health.children[1].restarts, 1);
+        });
+    }
+
+    #[test]
+    fn exceeded_restart_intensity_fails_the_supervisor() {
+        rt().block_on(async {
+            let supervisor = Supervisor::new("test-intensity", SupervisorStrategy::OneForOne)
+                .set_restart_intensity(RestartIntensity::new(2, Duration::from_secs(60)));
+
+            let dying = Arc::new(DyingTask {
+                runs: AtomicUsize::new(0),
+                deaths: usize::MAX,
+            });
+            let living = Arc::new(LivingTask {
+                runs: AtomicUsize::new(0),
+            });
+
+            supervisor.add_child("dying", dying.clone());
+            supervisor.add_child("living", living.clone());
+            supervisor.start(
+                Arc::new(AppStates::create_initialized()),
+                Arc::new(TestLogger),
+            );
+
+            wait_until(|| supervisor.get_status() == SupervisorStatus::Failed).await;
+
+            // The first run and the two allowed restarts.
+            assert_eq!(dying.runs.load(Ordering::SeqCst), 3);
+
+            let health = supervisor.get_health();
+            assert!(!health.is_healthy());
+            assert!(!health.children[1].is_running);
+        });
+    }
+
+    #[test]
+    fn children_exiting_on_shutdown_are_not_restarted() {
+        rt().block_on(async {
+            let app_states = Arc::new(AppStates::create_initialized());
+            let supervisor = Supervisor::new("test-shutdown", SupervisorStrategy::OneForOne);
+
+            let runs = Arc::new(AtomicUsize::new(0));
+
+            supervisor.add_child_fn("exits-on-shutdown", {
+                let runs = runs.clone();
+                let app_states = app_states.clone();
+                move || {
+                    let runs = runs.clone();
+                    let app_states = app_states.clone();
+                    async move {
+                        runs.fetch_add(1, Ordering::SeqCst);
+                        while !app_states.is_shutting_down() {
+                            tokio::time::sleep(Duration::from_millis(1)).await;
+                        }
+                    }
+                }
+            });
+
+            supervisor.start(app_states.clone(), Arc::new(TestLogger));
+
+            wait_until(|| supervisor.get_health().is_healthy()).await;
+            app_states.set_shutting_down();
+
+            wait_until(|| supervisor.get_status() == SupervisorStatus::Stopped).await;
+            assert_eq!(runs.load(Ordering::SeqCst), 1);
+            assert!(supervisor.get_health().children[0].last_exit.is_none());
+        });
+    }
+
+    #[test]
+    fn health_is_serialized_for_the_liveness_endpoint() {
+        let supervisor = Supervisor::new("test-health", SupervisorStrategy::OneForOne);
+        supervisor.add_child_fn("child", || async {});
+
+        let health = serde_json::to_value(supervisor.get_health()).unwrap();
+
+        assert_eq!(health["name"], "test-health");
+        assert_eq!(health["status"], "NotStarted");
+    }
+}
@@ -0,0 +1,48 @@
+use serde::Serialize;
+
+use crate::date_time::DateTimeAsMicroseconds;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum SupervisorStatus {
+    NotStarted,
+    Running,
+    /// The restart intensity is exceeded - the children are stopped and nobody
+    /// restarts them anymore.
+    Failed,
+    /// The application is shut down and so are the children.
+    Stopped,
+}
+
+/// How the child ended up dead.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum SupervisedChildExit {
+    /// `run` returned while the application was not shutting down.
+    Returned,
+    /// `run` panicked - the panic message is kept if it was a string.
+    Panicked(String),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SupervisedChildHealth {
+    pub name: String,
+    pub is_running: bool,
+    pub restarts: usize,
+    pub last_exit: Option<SupervisedChildExit>,
+    pub last_exit_at: Option<DateTimeAsMicroseconds>,
+}
+
+/// The snapshot given by [`super::Supervisor::get_health`] - ready to be serialized
+/// as the answer of a liveness endpoint.
+#[derive(Debug, Clone, Serialize)]
+pub struct SupervisorHealth {
+    pub name: String,
+    pub status: SupervisorStatus,
+    pub children: Vec<SupervisedChildHealth>,
+}
+
+impl SupervisorHealth {
+    /// The supervisor is alive and every child is running.
+    pub fn is_healthy(&self) -> bool {
+        self.status == SupervisorStatus::Running && self.children.iter().all(|itm| itm.is_running)
+    }
+}
@@ -0,0 +1,35 @@
+use std::time::Duration;
+
+/// What is restarted when a child of the [`super::Supervisor`] dies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SupervisorStrategy {
+    /// Only the dead child is restarted.
+    OneForOne,
+    /// Every child is stopped and all of them are started again - for the children
+    /// which can not live without each other.
+    OneForAll,
+}
+
+/// The supervisor gives up if it has to restart more than `max_restarts` times
+/// within `period` - a child which dies right after every restart is not going to
+/// get better by restarting it forever.
+#[derive(Debug, Clone, Copy)]
+pub struct RestartIntensity {
+    pub max_restarts: usize,
+    pub period: Duration,
+}
+
+impl RestartIntensity {
+    pub fn new(max_restarts: usize, period: Duration) -> Self {
+        Self {
+            max_restarts,
+            period,
+        }
+    }
+}
+
+impl Default for RestartIntensity {
+    fn default() -> Self {
+        Self::new(3, Duration::from_secs(5))
+    }
+}
@@ -1,5 +1,7 @@
+mod shared_task_completion;
 mod task_completion;
 mod task_completion_awaiter;
 
+pub use shared_task_completion::SharedTaskCompletion;
 pub use task_completion::{TaskCompletion, TaskCompletionError};
 pub use task_completion_awaiter::{CompletionEvent, TaskCompletionAwaiter};
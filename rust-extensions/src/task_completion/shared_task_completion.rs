@@ -0,0 +1,160 @@
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use tokio::sync::oneshot::Sender;
+
+use super::{CompletionEvent, TaskCompletionAwaiter};
+
+enum SharedState<OkResult, ErrorResult> {
+    Pending(Vec<Sender<CompletionEvent<OkResult, ErrorResult>>>),
+    Completed(Result<OkResult, ErrorResult>),
+}
+
+struct SharedInner<OkResult, ErrorResult> {
+    state: Mutex<SharedState<OkResult, ErrorResult>>,
+}
+
+/// A [`super::TaskCompletion`] which any amount of awaiters can wait for.
+///
+/// Clones share the same completion, so one of them can set the result while the others
+/// hand out awaiters. Every awaiter gets its own clone of the result; an awaiter taken
+/// after the result was set gets it right away. If the last clone is dropped without a
+/// result, every awaiter panics with `"Task is dropped"` - as with `TaskCompletion`.
+pub struct SharedTaskCompletion<OkResult: Clone, ErrorResult: Clone> {
+    inner: Arc<SharedInner<OkResult, ErrorResult>>,
+}
+
+impl<OkResult: Clone, ErrorResult: Clone> Clone for SharedTaskCompletion<OkResult, ErrorResult> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<OkResult: Clone, ErrorResult: Clone> SharedTaskCompletion<OkResult, ErrorResult> {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(SharedInner {
+                state: Mutex::new(SharedState::Pending(Vec::new())),
+            }),
+        }
+    }
+
+    pub fn get_awaiter(&self) -> TaskCompletionAwaiter<OkResult, ErrorResult> {
+        let mut state = self.inner.state.lock();
+
+        match &mut *state {
+            SharedState::Pending(senders) => {
+                let (sender, receiver) = tokio::sync::oneshot::channel();
+                senders.push(sender);
+                TaskCompletionAwaiter::new(receiver)
+            }
+            SharedState::Completed(result) => {
+                TaskCompletionAwaiter::create_completed(result.clone())
+            }
+        }
+    }
+
+    pub fn is_completed(&self) -> bool {
+        matches!(&*self.inner.state.lock(), SharedState::Completed(_))
+    }
+
+    /// Panics if the result is already set.
+    pub fn set_ok(&self, result: OkResult) {
+        if !self.try_set_result(Ok(result)) {
+            panic!("You are trying to set Ok as a result for a second time");
+        }
+    }
+
+    /// Panics if the result is already set.
+    pub fn set_error(&self, error: ErrorResult) {
+        if !self.try_set_result(Err(error)) {
+            panic!("You are trying to set error as a result for a second time");
+        }
+    }
+
+    /// Returns `false` if the result is already set - the new one is dropped then.
+    pub fn try_set_result(&self, result: Result<OkResult, ErrorResult>) -> bool {
+        let senders = {
+            let mut state = self.inner.state.lock();
+
+            if let SharedState::Completed(_) = &*state {
+                return false;
+            }
+
+            match std::mem::replace(&mut *state, SharedState::Completed(result.clone())) {
+                SharedState::Pending(senders) => senders,
+                SharedState::Completed(_) => Vec::new(),
+            }
+        };
+
+        for sender in senders {
+            // The awaiter could have been dropped meanwhile - nobody to tell then.
+            let _ = sender.send(match &result {
+                Ok(ok) => CompletionEvent::Ok(ok.clone()),
+                Err(err) => CompletionEvent::Error(err.clone()),
+            });
+        }
+
+        true
+    }
+}
+
+impl<OkResult: Clone, ErrorResult: Clone> Default for SharedTaskCompletion<OkResult, ErrorResult> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<OkResult, ErrorResult> Drop for SharedInner<OkResult, ErrorResult> {
+    fn drop(&mut self) {
+        if let SharedState::Pending(senders) = &mut *self.state.lock() {
+            for sender in senders.drain(..) {
+                let _ = sender.send(CompletionEvent::Panic("Task is dropped".to_string()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SharedTaskCompletion;
+
+    fn rt() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn every_awaiter_gets_the_result() {
+        rt().block_on(async {
+            let completion: SharedTaskCompletion<u32, String> = SharedTaskCompletion::new();
+
+            let first = completion.get_awaiter();
+            let second = completion.clone().get_awaiter();
+
+            completion.set_ok(5);
+            assert!(!completion.try_set_result(Ok(6)));
+
+            assert_eq!(first.get_result().await, Ok(5));
+            assert_eq!(second.get_result().await, Ok(5));
+
+            // Taken after the result was set.
+            assert_eq!(completion.get_awaiter().get_result().await, Ok(5));
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Task is dropped")]
+    fn dropped_without_a_result_panics_the_awaiters() {
+        rt().block_on(async {
+            let completion: SharedTaskCompletion<u32, String> = SharedTaskCompletion::new();
+            let awaiter = completion.get_awaiter();
+            drop(completion);
+            let _ = awaiter.get_result().await;
+        });
+    }
+}
@@ -30,20 +30,36 @@ impl<OkResult, ErrorResult> TaskCompletion<OkResult, ErrorResult> {
         new_result
     }
 
+    /// The awaiter was dropped before it got the result - so there is no point producing
+    /// it any more, setting it fails.
+    pub fn is_cancelled(&self) -> bool {
+        match self.sender.as_ref() {
+            Some(sender) => sender.is_closed(),
+            None => false,
+        }
+    }
+
+    /// Resolves once the awaiter is dropped without the result - never, if the result is
+    /// already set.
+    pub async fn cancelled(&mut self) {
+        match self.sender.as_mut() {
+            Some(sender) => sender.closed().await,
+            None => std::future::pending().await,
+        }
+    }
+
     pub fn set_drop_error(&mut self, error: ErrorResult) {
         self.drop_error = Some(error);
     }
 
+    /// Does nothing if the awaiter is gone - [`Self::is_cancelled`]: nobody waits for
+    /// the result after a `get_result_with_timeout` gave up or a `select` picked another
+    /// one. Panics if the result is set for the second time.
     pub fn set_ok(&mut self, result: OkResult) {
         match self.sender.take() {
-            Some(sender) => match sender.send(CompletionEvent::Ok(result)) {
-                Ok(_) => {
-                    return;
-                }
-                Err(_) => {
-                    panic!("Can not set Ok result to the task completion.");
-                }
-            },
+            Some(sender) => {
+                let _ = sender.send(CompletionEvent::Ok(result));
+            }
             None => {
                 panic!("You are trying to set Ok as a result for a second time");
             }
@@ -70,13 +86,11 @@ impl<OkResult, ErrorResult> TaskCompletion<OkResult, ErrorResult> {
         }
     }
 
+    /// Does nothing if the awaiter is gone - the same as [`Self::set_ok`].
     pub fn set_error(&mut self, result: ErrorResult) {
         match self.sender.take() {
             Some(sender) => {
-                let result = sender.send(CompletionEvent::Error(result));
-                if let Err(_) = result {
-                    panic!("Can not set Error result to the task completion. ");
-                }
+                let _ = sender.send(CompletionEvent::Error(result));
             }
             None => {
                 panic!("You are trying to set error as a result for a second time");
@@ -84,13 +98,11 @@ impl<OkResult, ErrorResult> TaskCompletion<OkResult, ErrorResult> {
         }
     }
 
+    /// Does nothing if the awaiter is gone - the same as [`Self::set_ok`].
     pub fn set_panic(&mut self, message: String) {
         match self.sender.take() {
             Some(sender) => {
-                let result = sender.send(CompletionEvent::Panic(message));
-                if let Err(_) = result {
-                    panic!("Can not set Error result to the task completion. ");
-                }
+                let _ = sender.send(CompletionEvent::Panic(message));
             }
             None => {
                 panic!("You are trying to set error as a result for a second time");
@@ -1,3 +1,6 @@
+use std::time::Duration;
+
+use futures::future::select_all;
 use tokio::sync::oneshot::Receiver;
 
 #[derive(Clone, Debug)]
@@ -44,4 +47,116 @@ impl<OkResult, ErrorResult> TaskCompletionAwaiter<OkResult, ErrorResult> {
             TaskCompletionAwaiter::Completed(completed) => completed,
         }
     }
+
+    /// Same as [`Self::get_result`], but gives up after `timeout`. `None` means the
+    /// result has not come in time - the awaiter is dropped then, so the producer sees
+    /// the cancellation.
+    pub async fn get_result_with_timeout(
+        self,
+        timeout: Duration,
+    ) -> Option<Result<OkResult, ErrorResult>> {
+        tokio::time::timeout(timeout, self.get_result()).await.ok()
+    }
+
+    /// Waits for the first of `awaiters` to complete and returns its index together with
+    /// its result. The rest are dropped, so their producers see the cancellation.
+    ///
+    /// Panics if `awaiters` is empty, and - like [`Self::get_result`] - if the first
+    /// one to complete was dropped without a result.
+    pub async fn select(awaiters: Vec<Self>) -> (usize, Result<OkResult, ErrorResult>) {
+        if awaiters.is_empty() {
+            panic!("There are no task completion awaiters to select from");
+        }
+
+        let futures = awaiters
+            .into_iter()
+            .map(|awaiter| Box::pin(awaiter.get_result()));
+
+        let (result, index, _) = select_all(futures).await;
+        (index, result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::TaskCompletionAwaiter;
+    use crate::TaskCompletion;
+
+    fn rt() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn dropped_awaiter_cancels_the_task_completion() {
+        rt().block_on(async {
+            let mut completion: TaskCompletion<u32, String> = TaskCompletion::new();
+            let awaiter = completion.get_awaiter();
+
+            assert!(!completion.is_cancelled());
+
+            let timed_out = awaiter
+                .get_result_with_timeout(Duration::from_millis(20))
+                .await;
+            assert!(timed_out.is_none());
+
+            completion.cancelled().await;
+            assert!(completion.is_cancelled());
+            assert!(completion.try_set_ok(1).is_err());
+        });
+    }
+
+    #[test]
+    fn result_set_after_the_awaiter_gave_up_is_dropped() {
+        rt().block_on(async {
+            let mut completion: TaskCompletion<u32, String> = TaskCompletion::new();
+            let awaiter = completion.get_awaiter();
+
+            let timed_out = awaiter
+                .get_result_with_timeout(Duration::from_millis(20))
+                .await;
+            assert!(timed_out.is_none());
+
+            // Nobody waits for it any more - not a reason to crash the producer.
+            completion.set_ok(1);
+        });
+    }
+
+    #[test]
+    fn received_result_does_not_cancel() {
+        rt().block_on(async {
+            let mut completion: TaskCompletion<u32, String> = TaskCompletion::new();
+            let awaiter = completion.get_awaiter();
+
+            completion.set_ok(1);
+            let result = awaiter
+                .get_result_with_timeout(Duration::from_secs(1))
+                .await;
+
+            assert_eq!(result, Some(Ok(1)));
+            assert!(!completion.is_cancelled());
+        });
+    }
+
+    #[test]
+    fn select_returns_the_first_completed() {
+        rt().block_on(async {
+            let mut slow: TaskCompletion<u32, String> = TaskCompletion::new();
+            let mut fast: TaskCompletion<u32, String> = TaskCompletion::new();
+
+            let awaiters = vec![slow.get_awaiter(), fast.get_awaiter()];
+            fast.set_error("failed".to_string());
+
+            let (index, result) = TaskCompletionAwaiter::select(awaiters).await;
+
+            assert_eq!(index, 1);
+            assert_eq!(result, Err("failed".to_string()));
+            // The one which lost is dropped.
+            assert!(slow.is_cancelled());
+        });
+    }
 }
@@ -2,3 +2,5 @@ mod tokio_queue;
 pub use tokio_queue::*;
 mod tokio_queue_published;
 pub use tokio_queue_published::*;
+mod tokio_queue_writer;
+pub use tokio_queue_writer::*;
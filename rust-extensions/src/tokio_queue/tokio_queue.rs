@@ -2,20 +2,95 @@ use std::{sync::Arc, task::Poll};
 
 use super::*;
 
+/// The reading side of an in-process byte pipe: [`tokio::io::AsyncRead`] and
+/// [`tokio::io::AsyncBufRead`] over the bytes the publishers enqueue or write.
+///
+/// The reader takes the enqueued bytes in one go - it swaps its own drained buffer with
+/// the queue's one, so `fill_buf` hands out the publishers' bytes without copying them.
+/// A bounded queue caps the bytes waiting in the queue; the chunk the reader has taken
+/// already does not count.
 pub struct TokioQueue {
     inner: Arc<TokioQueuePublish>,
+    read_buffer: Vec<u8>,
+    read_pos: usize,
 }
 
 impl TokioQueue {
+    /// Unbounded queue.
     pub fn new() -> Self {
+        Self::create(None)
+    }
+
+    /// The [`TokioQueueWriter`]s wait once `capacity` bytes are waiting for the reader.
+    /// Panics if `capacity` is zero.
+    pub fn new_bounded(capacity: usize) -> Self {
+        if capacity == 0 {
+            panic!("TokioQueue capacity must be above zero");
+        }
+
+        Self::create(Some(capacity))
+    }
+
+    fn create(capacity: Option<usize>) -> Self {
         Self {
-            inner: Arc::default(),
+            inner: Arc::new(TokioQueuePublish::new(capacity)),
+            read_buffer: Vec::new(),
+            read_pos: 0,
         }
     }
 
     pub fn get_publisher(&self) -> Arc<TokioQueuePublish> {
         self.inner.clone()
     }
+
+    pub fn get_writer(&self) -> TokioQueueWriter {
+        self.inner.get_writer()
+    }
+
+    /// `Ready(true)` once there are bytes in `read_buffer`, `Ready(false)` on EOF.
+    fn poll_take(&mut self, cx: &mut std::task::Context<'_>) -> Poll<bool> {
+        if self.read_pos < self.read_buffer.len() {
+            return Poll::Ready(true);
+        }
+
+        let mut queue_access = self.inner.state.lock();
+
+        if queue_access.buffer.is_empty() {
+            if queue_access.closed {
+                return Poll::Ready(false);
+            }
+
+            // Register the waker while holding the lock to avoid races,
+            // then drop the lock before returning Pending.
+            self.inner.waker.register(cx.waker());
+            return Poll::Pending;
+        }
+
+        self.read_buffer.clear();
+        self.read_pos = 0;
+        std::mem::swap(&mut self.read_buffer, &mut queue_access.buffer);
+
+        let writer_wakers = std::mem::take(&mut queue_access.writer_wakers);
+        drop(queue_access);
+
+        for waker in writer_wakers {
+            waker.wake();
+        }
+
+        Poll::Ready(true)
+    }
+}
+
+impl Default for TokioQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for TokioQueue {
+    fn drop(&mut self) {
+        self.inner.release_reader();
+    }
 }
 
 impl tokio::io::AsyncRead for TokioQueue {
@@ -24,25 +99,188 @@ impl tokio::io::AsyncRead for TokioQueue {
         cx: &mut std::task::Context<'_>,
         buf: &mut tokio::io::ReadBuf<'_>,
     ) -> std::task::Poll<std::io::Result<()>> {
-        let mut queue_access = self.inner.queue.lock();
-
-        if queue_access.is_empty() {
-            // Register the waker while holding the lock to avoid races,
-            // then re-check and drop the lock before returning Pending.
-            self.inner.waker.register(cx.waker());
+        let this = self.get_mut();
 
-            if queue_access.is_empty() {
-                drop(queue_access);
-                return Poll::Pending;
-            }
+        match this.poll_take(cx) {
+            Poll::Ready(true) => {}
+            // EOF - nothing put into `buf`.
+            Poll::Ready(false) => return Poll::Ready(Ok(())),
+            Poll::Pending => return Poll::Pending,
         }
 
-        let available = queue_access.len();
-        let to_copy = available.min(buf.remaining());
+        let available = &this.read_buffer[this.read_pos..];
+        let to_copy = available.len().min(buf.remaining());
 
-        buf.put_slice(&queue_access[..to_copy]);
-        queue_access.drain(..to_copy);
+        buf.put_slice(&available[..to_copy]);
+        this.read_pos += to_copy;
 
         Poll::Ready(Ok(()))
     }
 }
+
+impl tokio::io::AsyncBufRead for TokioQueue {
+    fn poll_fill_buf(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<std::io::Result<&[u8]>> {
+        let this = self.get_mut();
+
+        match this.poll_take(cx) {
+            Poll::Ready(true) => Poll::Ready(Ok(&this.read_buffer[this.read_pos..])),
+            Poll::Ready(false) => Poll::Ready(Ok(&[])),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn consume(self: std::pin::Pin<&mut Self>, amt: usize) {
+        let this = self.get_mut();
+        this.read_pos = (this.read_pos + amt).min(this.read_buffer.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+    use super::*;
+
+    fn create_runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn close_gives_eof_after_the_enqueued_bytes() {
+        create_runtime().block_on(async {
+            let mut queue = TokioQueue::new();
+            let publisher = queue.get_publisher();
+
+            publisher.enqueue(b"hello ");
+            publisher.enqueue(b"world");
+            publisher.close();
+            publisher.enqueue(b"dropped");
+
+            let mut result = String::new();
+            queue.read_to_string(&mut result).await.unwrap();
+            assert_eq!(result, "hello world");
+        });
+    }
+
+    #[test]
+    fn bounded_writer_waits_for_space() {
+        create_runtime().block_on(async {
+            let mut queue = TokioQueue::new_bounded(4);
+            let mut writer = queue.get_writer();
+            let publisher = queue.get_publisher();
+
+            let writing = tokio::spawn(async move {
+                writer.write_all(b"0123456789").await.unwrap();
+                publisher.close();
+            });
+
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            assert!(!writing.is_finished());
+            assert_eq!(queue.get_publisher().len(), 4);
+
+            let mut result = Vec::new();
+            queue.read_to_end(&mut result).await.unwrap();
+            assert_eq!(result, b"0123456789");
+            writing.await.unwrap();
+        });
+    }
+
+    #[test]
+    fn dropped_writer_does_not_close_the_queue() {
+        create_runtime().block_on(async {
+            let mut queue = TokioQueue::new_bounded(1024);
+            let publisher = queue.get_publisher();
+            let mut first = queue.get_writer();
+            let mut second = first.clone();
+
+            first.write_all(b"first\n").await.unwrap();
+            drop(first);
+            assert!(!publisher.is_closed());
+
+            second.write_all(b"second\n").await.unwrap();
+            second.shutdown().await.unwrap();
+            assert!(second.write_all(b"late").await.is_err());
+            assert!(!publisher.is_closed());
+
+            publisher.enqueue(b"enqueued\n");
+            publisher.close();
+
+            let mut lines = Vec::new();
+            let mut line = String::new();
+
+            while queue.read_line(&mut line).await.unwrap() > 0 {
+                lines.push(std::mem::take(&mut line));
+            }
+
+            assert_eq!(lines, vec!["first\n", "second\n", "enqueued\n"]);
+        });
+    }
+
+    #[test]
+    fn fill_buf_hands_out_the_enqueued_bytes() {
+        create_runtime().block_on(async {
+            let mut queue = TokioQueue::new();
+            queue.get_publisher().enqueue(b"abc");
+
+            assert_eq!(queue.fill_buf().await.unwrap(), b"abc");
+            queue.consume(2);
+            assert_eq!(queue.fill_buf().await.unwrap(), b"c");
+            queue.consume(1);
+
+            queue.get_publisher().enqueue(b"de");
+            assert_eq!(queue.fill_buf().await.unwrap(), b"de");
+        });
+    }
+    #[test]
+    fn dropped_reader_breaks_the_pipe() {
+        create_runtime().block_on(async {
+            let queue = TokioQueue::new_bounded(4);
+            let mut waiting = queue.get_writer();
+            let mut writer = queue.get_writer();
+
+            writer.write_all(b"0123").await.unwrap();
+
+            let writing = tokio::spawn(async move { waiting.write_all(b"45").await });
+
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            assert!(!writing.is_finished());
+
+            drop(queue);
+
+            let err = writing.await.unwrap().unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::BrokenPipe);
+
+            let err = writer.write_all(b"6").await.unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::BrokenPipe);
+        });
+    }
+
+    #[test]
+    fn pending_writer_registers_its_waker_once() {
+        let queue = TokioQueue::new_bounded(1);
+        let mut writer = queue.get_writer();
+        let waker = futures::task::noop_waker();
+        let mut cx = std::task::Context::from_waker(&waker);
+
+        assert!(matches!(
+            std::pin::Pin::new(&mut writer).poll_write(&mut cx, b"0"),
+            Poll::Ready(Ok(1))
+        ));
+
+        for _ in 0..3 {
+            assert!(std::pin::Pin::new(&mut writer)
+                .poll_write(&mut cx, b"1")
+                .is_pending());
+        }
+
+        assert_eq!(queue.get_publisher().state.lock().writer_wakers.len(), 1);
+    }
+}
@@ -1,26 +1,104 @@
+use std::{sync::Arc, task::Waker};
+
 use parking_lot::Mutex;
 
 use futures::task::AtomicWaker;
 
+use super::TokioQueueWriter;
+
+pub(crate) struct TokioQueueState {
+    pub buffer: Vec<u8>,
+    pub closed: bool,
+    /// Writers waiting for space in a bounded queue.
+    pub writer_wakers: Vec<Waker>,
+}
+
+/// The publishing side of a [`super::TokioQueue`].
+///
+/// [`TokioQueuePublish::enqueue`] never waits - it is there for the producers which can
+/// not `.await`, and it ignores the capacity. The producers which can wait for space use
+/// a [`TokioQueueWriter`] instead.
 pub struct TokioQueuePublish {
-    pub(crate) queue: Mutex<Vec<u8>>,
+    pub(crate) state: Mutex<TokioQueueState>,
+    pub(crate) capacity: Option<usize>,
     pub(crate) waker: AtomicWaker,
 }
 
 impl TokioQueuePublish {
+    pub(crate) fn new(capacity: Option<usize>) -> Self {
+        Self {
+            state: Mutex::new(TokioQueueState {
+                buffer: Vec::new(),
+                closed: false,
+                writer_wakers: Vec::new(),
+            }),
+            capacity,
+            waker: AtomicWaker::new(),
+        }
+    }
+
+    /// Once the queue is closed the payload is dropped - as writing into a closed pipe.
     pub fn enqueue(&self, payload: &[u8]) {
-        let mut write_access = self.queue.lock();
-        write_access.extend_from_slice(payload);
+        let mut write_access = self.state.lock();
+
+        if write_access.closed {
+            return;
+        }
+
+        write_access.buffer.extend_from_slice(payload);
         drop(write_access);
         self.waker.wake();
     }
+
+    /// The reader gets EOF once it has read everything enqueued before. Closing it again
+    /// is a no-op.
+    pub fn close(&self) {
+        let writer_wakers = {
+            let mut write_access = self.state.lock();
+            write_access.closed = true;
+            std::mem::take(&mut write_access.writer_wakers)
+        };
+
+        self.waker.wake();
+
+        // They fail with `BrokenPipe` now instead of waiting for space forever.
+        for waker in writer_wakers {
+            waker.wake();
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.state.lock().closed
+    }
+
+    /// `None` for an unbounded queue.
+    pub fn get_capacity(&self) -> Option<usize> {
+        self.capacity
+    }
+
+    /// Amount of bytes waiting for the reader.
+    pub fn len(&self) -> usize {
+        self.state.lock().buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get_writer(self: &Arc<Self>) -> TokioQueueWriter {
+        TokioQueueWriter::new(self.clone())
+    }
+
+    /// The reader is dropped: nobody reads the bytes waiting in the queue, and the
+    /// writers fail with `BrokenPipe` instead of waiting for space forever.
+    pub(crate) fn release_reader(&self) {
+        std::mem::take(&mut self.state.lock().buffer);
+        self.close();
+    }
 }
 
 impl Default for TokioQueuePublish {
     fn default() -> Self {
-        Self {
-            queue: Mutex::default(),
-            waker: AtomicWaker::new(),
-        }
+        Self::new(None)
     }
 }
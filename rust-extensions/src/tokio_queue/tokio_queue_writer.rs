@@ -0,0 +1,89 @@
+use std::{sync::Arc, task::Poll};
+
+use super::TokioQueuePublish;
+
+/// A publisher of a [`super::TokioQueue`] which waits for space in a bounded queue.
+///
+/// There can be any amount of them - cloning one is the same as
+/// [`TokioQueuePublish::get_writer`]. Shutting one down stops only that writer: the
+/// reader gets EOF once the queue is [`TokioQueuePublish::close`]d, so the other writers
+/// and `enqueue` keep publishing. Writing into a closed queue fails with `BrokenPipe`.
+pub struct TokioQueueWriter {
+    inner: Arc<TokioQueuePublish>,
+    shut_down: bool,
+}
+
+impl TokioQueueWriter {
+    pub(crate) fn new(inner: Arc<TokioQueuePublish>) -> Self {
+        Self {
+            inner,
+            shut_down: false,
+        }
+    }
+}
+
+impl Clone for TokioQueueWriter {
+    fn clone(&self) -> Self {
+        Self::new(self.inner.clone())
+    }
+}
+
+impl tokio::io::AsyncWrite for TokioQueueWriter {
+    fn poll_write(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        if self.shut_down {
+            return Poll::Ready(Err(std::io::ErrorKind::BrokenPipe.into()));
+        }
+
+        let mut write_access = self.inner.state.lock();
+
+        if write_access.closed {
+            return Poll::Ready(Err(std::io::ErrorKind::BrokenPipe.into()));
+        }
+
+        let free = match self.inner.capacity {
+            Some(capacity) => capacity.saturating_sub(write_access.buffer.len()),
+            None => buf.len(),
+        };
+
+        if free == 0 && !buf.is_empty() {
+            // Registered under the lock: the reader takes the bytes out and wakes us
+            // under the same lock, so the wake up can not slip in between.
+            let waker = cx.waker();
+
+            if !write_access
+                .writer_wakers
+                .iter()
+                .any(|registered| registered.will_wake(waker))
+            {
+                write_access.writer_wakers.push(waker.clone());
+            }
+            return Poll::Pending;
+        }
+
+        let written = free.min(buf.len());
+        write_access.buffer.extend_from_slice(&buf[..written]);
+        drop(write_access);
+
+        self.inner.waker.wake();
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        self.get_mut().shut_down = true;
+        Poll::Ready(Ok(()))
+    }
+}